@@ -1,38 +1,54 @@
 use std::collections::HashMap;
+use std::io::{self, BufReader};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
 
 use asm_lsp::types::LspClient;
 
 use asm_lsp::handle::{
-    handle_completion_request, handle_diagnostics, handle_did_change_text_document_notification,
+    handle_assemble_project_request, handle_completion_request, handle_diagnostics,
+    handle_did_change_configuration_notification, handle_did_change_text_document_notification,
     handle_did_close_text_document_notification, handle_did_open_text_document_notification,
-    handle_document_symbols_request, handle_goto_def_request, handle_hover_request,
-    handle_references_request, handle_signature_help_request,
+    handle_document_outline_request, handle_document_symbols_request, handle_explain_range_request,
+    handle_goto_def_request, handle_hover_request, handle_instructions_writing_register_request,
+    handle_linked_editing_range_request, handle_list_backends_request,
+    handle_on_type_formatting_request, handle_prepare_rename_request, handle_references_request,
+    handle_rename_request, handle_semantic_tokens_full_request, handle_signature_help_request,
+    handle_workspace_symbol_request,
 };
 use asm_lsp::{
-    get_compile_cmds, get_completes, get_config, get_include_dirs, instr_filter_targets,
-    populate_name_to_directive_map, populate_name_to_instruction_map,
-    populate_name_to_register_map, Arch, Assembler, Config, Instruction, NameToInfoMaps, TreeStore,
+    apply_empty_config_fallback, get_compile_cmds, get_config, get_config_include_dirs,
+    get_defines, get_include_dirs, get_modeline_config, get_modeline_unloaded_docs,
+    get_semantic_tokens_legend, read_config_file, send_empty_resp, AssembleProject, ClosedTrees,
+    Config, ConfigOverrides, DocumentOutline, ExplainRange, InstructionsWritingRegister,
+    ListBackends, LoadedDocs, ServerState, TreeStore, WorkspaceIndex,
 };
 
 use compile_commands::{CompilationDatabase, SourceFile};
 use lsp_types::notification::{
-    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    DidChangeConfiguration, DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    DidSaveTextDocument, Exit, Notification as _, ShowMessage, TelemetryEvent,
 };
 use lsp_types::request::{
     Completion, DocumentDiagnosticRequest, DocumentSymbolRequest, GotoDefinition, HoverRequest,
-    References, SignatureHelpRequest,
+    LinkedEditingRange, OnTypeFormatting, PrepareRenameRequest, References, Rename,
+    SemanticTokensFullRequest, SignatureHelpRequest, WorkspaceSymbolRequest,
 };
 use lsp_types::{
-    CompletionItem, CompletionItemKind, CompletionOptions, CompletionOptionsCompletionItem,
-    DiagnosticOptions, DiagnosticServerCapabilities, HoverProviderCapability, InitializeParams,
-    OneOf, PositionEncodingKind, ServerCapabilities, SignatureHelpOptions,
-    TextDocumentSyncCapability, TextDocumentSyncKind, WorkDoneProgressOptions,
+    CompletionOptions, CompletionOptionsCompletionItem, DiagnosticOptions,
+    DiagnosticServerCapabilities, DocumentOnTypeFormattingOptions, HoverProviderCapability,
+    InitializeParams, LinkedEditingRangeServerCapabilities, MessageType, OneOf,
+    PositionEncodingKind, RenameOptions, SemanticTokensFullOptions, SemanticTokensOptions,
+    SemanticTokensServerCapabilities, ServerCapabilities, ShowMessageParams, SignatureHelpOptions,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Uri, WorkDoneProgressOptions,
 };
 
 use anyhow::Result;
-use log::{error, info};
-use lsp_server::{Connection, Message, Notification, Request, RequestId};
+use flexi_logger::LoggerHandle;
+use log::{error, info, warn};
+use lsp_server::{Connection, IoThreads, Message, Notification, Request, RequestId};
 use lsp_textdocument::TextDocuments;
 
 /// Entry point of the server. Connects to the client, loads documentation resources,
@@ -40,7 +56,8 @@ use lsp_textdocument::TextDocuments;
 ///
 /// # Errors
 ///
-/// Returns `Err` if the server fails to connect to the lsp client
+/// Returns `Err` if the command line arguments are invalid, or the server fails
+/// to connect to the lsp client
 ///
 /// # Panics
 ///
@@ -49,13 +66,23 @@ pub fn main() -> Result<()> {
     // initialisation -----------------------------------------------------------------------------
     // Set up logging. Because `stdio_transport` gets a lock on stdout and stdin, we must have our
     // logging only write out to stderr.
-    flexi_logger::Logger::try_with_str("info")?.start()?;
+    let logger = flexi_logger::Logger::try_with_str("info")?.start()?;
 
     // LSP server initialisation ------------------------------------------------------------------
     info!("Starting asm_lsp...");
 
     // Create the transport
-    let (connection, _io_threads) = Connection::stdio();
+    let args = parse_args(std::env::args().skip(1))?;
+    // An explicitly given config is loaded up front, so a bad path fails loudly
+    // rather than being silently replaced by the usual search
+    let config_path = args.config_path.or_else(|| {
+        std::env::var_os("ASM_LSP_CONFIG")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    let explicit_config = config_path.as_deref().map(read_config_file).transpose()?;
+    info!("Connecting over {:?}", args.transport);
+    let (connection, io_threads) = connect(&args.transport)?;
 
     // specify UTF-16 encoding for compatibility with lsp-textdocument
     let position_encoding = Some(PositionEncodingKind::UTF16);
@@ -79,7 +106,8 @@ pub fn main() -> Result<()> {
 
     let signature_help_provider = Some(SignatureHelpOptions {
         trigger_characters: None,
-        retrigger_characters: None,
+        // move the active directive argument along
+        retrigger_characters: Some(vec![String::from(",")]),
         work_done_progress_options: WorkDoneProgressOptions {
             work_done_progress: Some(false),
         },
@@ -87,6 +115,16 @@ pub fn main() -> Result<()> {
 
     let references_provider = Some(OneOf::Left(true));
 
+    // labels and their references, edited together as the label is typed
+    let linked_editing_range_provider = Some(LinkedEditingRangeServerCapabilities::Simple(true));
+
+    let rename_provider = Some(OneOf::Right(RenameOptions {
+        prepare_provider: Some(true),
+        work_done_progress_options: WorkDoneProgressOptions {
+            work_done_progress: None,
+        },
+    }));
+
     let diagnostic_provider = Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
         identifier: Some(String::from("asm-lsp")),
         inter_file_dependencies: true,
@@ -96,6 +134,20 @@ pub fn main() -> Result<()> {
         },
     }));
 
+    let semantic_tokens_provider = Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+        SemanticTokensOptions {
+            legend: get_semantic_tokens_legend(),
+            full: Some(SemanticTokensFullOptions::Bool(true)),
+            ..Default::default()
+        },
+    ));
+
+    // indent the line following a label
+    let document_on_type_formatting_provider = Some(DocumentOnTypeFormattingOptions {
+        first_trigger_character: String::from("\n"),
+        more_trigger_character: None,
+    });
+
     let capabilities = ServerCapabilities {
         position_encoding,
         hover_provider,
@@ -105,7 +157,13 @@ pub fn main() -> Result<()> {
         text_document_sync,
         document_symbol_provider: Some(OneOf::Left(true)),
         references_provider,
+        rename_provider,
+        linked_editing_range_provider,
         diagnostic_provider,
+        semantic_tokens_provider,
+        document_on_type_formatting_provider,
+        // answered only if the `workspace_index` option is set, which isn't known yet
+        workspace_symbol_provider: Some(OneOf::Left(true)),
         ..ServerCapabilities::default()
     };
     let server_capabilities = serde_json::to_value(capabilities).unwrap();
@@ -113,356 +171,211 @@ pub fn main() -> Result<()> {
 
     let params: InitializeParams = serde_json::from_value(initialization_params).unwrap();
     info!("Client initialization params: {:?}", params);
-    let mut config = get_config(&params);
+    let compile_cmds = get_compile_cmds(&params).unwrap_or_default();
+    info!("Loaded compile commands: {:?}", compile_cmds);
+    let (mut config, mut compile_cmd_overrides) =
+        get_config(&params, &compile_cmds, explicit_config);
     info!("Server Configuration: {:?}", config);
+    info!(
+        "Assemblers detected from compile commands: {:?}",
+        compile_cmd_overrides
+    );
     if let Some(ref client_info) = params.client_info {
         if client_info.name.eq("helix") {
             info!("Helix LSP client detected");
             config.client = Some(LspClient::Helix);
         }
     }
+    if config.is_empty() {
+        let fell_back = apply_empty_config_fallback(&mut config);
+        warn_empty_config(&connection, fell_back)?;
+    }
+    set_log_level(&logger, &config);
 
-    let mut names_to_info = NameToInfoMaps::default();
-    // create a map of &Instruction_name -> &Instruction - Use that in user queries
-    // The Instruction(s) themselves are stored in a vector and we only keep references to the
-    // former map
-    let x86_instructions = if config.instruction_sets.x86.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let x86_instrs = include_bytes!("../serialized/opcodes/x86");
-        let instrs = bincode::deserialize::<Vec<Instruction>>(x86_instrs)?
-            .into_iter()
-            .map(|instruction| {
-                // filter out assemblers by user config
-                instr_filter_targets(&instruction, &config)
-            })
-            .filter(|instruction| !instruction.forms.is_empty())
-            .collect();
-        info!(
-            "x86 instruction set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        instrs
-    } else {
-        Vec::new()
-    };
-
-    let x86_64_instructions = if config.instruction_sets.x86_64.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let x86_64_instrs = include_bytes!("../serialized/opcodes/x86_64");
-        let instrs = bincode::deserialize::<Vec<Instruction>>(x86_64_instrs)?
-            .into_iter()
-            .map(|instruction| {
-                // filter out assemblers by user config
-                instr_filter_targets(&instruction, &config)
-            })
-            .filter(|instruction| !instruction.forms.is_empty())
-            .collect();
-        info!(
-            "x86-64 instruction set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        instrs
-    } else {
-        Vec::new()
-    };
-
-    let z80_instructions = if config.instruction_sets.z80.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let z80_instrs = include_bytes!("../serialized/opcodes/z80");
-        let instrs = bincode::deserialize::<Vec<Instruction>>(z80_instrs)?
-            .into_iter()
-            .map(|instruction| {
-                // filter out assemblers by user config
-                instr_filter_targets(&instruction, &config)
-            })
-            .filter(|instruction| !instruction.forms.is_empty())
-            .collect();
-        info!(
-            "z80 instruction set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        instrs
-    } else {
-        Vec::new()
-    };
-
-    let arm_instructions = if config.instruction_sets.arm.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let arm_instrs = include_bytes!("../serialized/opcodes/arm");
-        // NOTE: Actually, the arm file are all arm64 so we needed to get
-        // the arm32 versions then do the below
-        // NOTE: No need to filter these instructions by assembler
-        // like we do for x86/x86_64, as our ARM docs don't contain any
-        // assembler-specific information (yet)
-        let instrs = bincode::deserialize::<Vec<Instruction>>(arm_instrs)?;
-        info!(
-            "arm instruction set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        instrs
-    } else {
-        Vec::new()
-    };
-
-    let arm64_instructions = if config.instruction_sets.arm64.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        // TODO: change to arm64 after arm32 has been added
-        let arm_instrs = include_bytes!("../serialized/opcodes/arm");
-        // NOTE: Actually, the arm file are all arm64 so we needed to get
-        // the arm32 versions then do the below
-        // NOTE: No need to filter these instructions by assembler
-        // like we do for x86/x86_64, as our ARM docs don't contain any
-        // assembler-specific information (yet)
-        let instrs = bincode::deserialize::<Vec<Instruction>>(arm_instrs)?;
-        info!(
-            "arm instruction set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        instrs
-    } else {
-        Vec::new()
-    };
-
-    let riscv_instructions = if config.instruction_sets.riscv.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let riscv_instrs = include_bytes!("../serialized/opcodes/riscv");
-        // NOTE: No need to filter these instructions by assembler like we do for
-        // x86/x86_64, as our RISCV docs don't contain any assembler-specific information (yet)
-        let instrs = bincode::deserialize::<Vec<Instruction>>(riscv_instrs)?;
-        info!(
-            "riscv instruction set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        instrs
-    } else {
-        Vec::new()
-    };
-
-    populate_name_to_instruction_map(
-        Arch::X86,
-        &x86_instructions,
-        &mut names_to_info.instructions,
-    );
-    populate_name_to_instruction_map(
-        Arch::X86_64,
-        &x86_64_instructions,
-        &mut names_to_info.instructions,
-    );
-    populate_name_to_instruction_map(
-        Arch::Z80,
-        &z80_instructions,
-        &mut names_to_info.instructions,
-    );
-    populate_name_to_instruction_map(
-        Arch::ARM,
-        &arm_instructions,
-        &mut names_to_info.instructions,
-    );
-    populate_name_to_instruction_map(
-        Arch::ARM64,
-        &arm64_instructions,
-        &mut names_to_info.instructions,
-    );
-    populate_name_to_instruction_map(
-        Arch::RISCV,
-        &riscv_instructions,
-        &mut names_to_info.instructions,
-    );
-
-    // create a map of &Register_name -> &Register - Use that in user queries
-    // The Register(s) themselves are stored in a vector and we only keep references to the
-    // former map
-    let x86_registers = if config.instruction_sets.x86.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let regs_x86 = include_bytes!("../serialized/registers/x86");
-        let regs = bincode::deserialize(regs_x86)?;
-        info!(
-            "x86 register set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        regs
-    } else {
-        Vec::new()
-    };
-
-    let x86_64_registers = if config.instruction_sets.x86_64.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let regs_x86_64 = include_bytes!("../serialized/registers/x86_64");
-        let regs = bincode::deserialize(regs_x86_64)?;
-        info!(
-            "x86-64 register set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        regs
-    } else {
-        Vec::new()
-    };
+    // Open documents and the like outlive a reload of the documentation
+    let mut docs = DocumentState::default();
+    docs.workspace_index.update_config(&config);
+    loop {
+        // The documentation itself is owned by `loaded_docs`, the lookup maps and
+        // completion items in `state` only keep references into it
+        let loaded_docs = LoadedDocs::load(&config)?;
+        let state = ServerState::new(&loaded_docs, &config);
 
-    let z80_registers = if config.instruction_sets.z80.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let regs_z80 = include_bytes!("../serialized/registers/z80");
-        let regs = bincode::deserialize(regs_z80)?;
-        info!(
-            "z80 register set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        regs
-    } else {
-        Vec::new()
-    };
+        let mut include_dirs = get_include_dirs(&compile_cmds);
+        include_dirs
+            .entry(SourceFile::All)
+            .or_default()
+            .extend(get_config_include_dirs(&config));
+        let defines = get_defines(&compile_cmds);
 
-    let arm_registers = if config.instruction_sets.arm.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let regs_arm = include_bytes!("../serialized/registers/arm");
-        let regs = bincode::deserialize(regs_arm)?;
-        info!(
-            "arm register set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        regs
-    } else {
-        Vec::new()
-    };
+        let exit = main_loop(
+            &connection,
+            Some(&logger),
+            &mut config,
+            &state,
+            &compile_cmds,
+            &mut compile_cmd_overrides,
+            &include_dirs,
+            &defines,
+            &mut docs,
+        )?;
+        if exit == LoopExit::Shutdown {
+            break;
+        }
+        info!("Enabled instruction sets, assemblers, or include dirs changed, reloading...");
+    }
 
-    let arm64_registers = if config.instruction_sets.arm64.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let regs_arm64 = include_bytes!("../serialized/registers/arm64");
-        let regs = bincode::deserialize(regs_arm64)?;
-        info!(
-            "arm register set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        regs
-    } else {
-        Vec::new()
-    };
+    // The writer thread runs until every sender to it is gone, and `connection`
+    // holds one of those, so it has to be dropped before we can join
+    drop(connection);
+    io_threads.join()?;
 
-    let riscv_registers = if config.instruction_sets.riscv.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let regs_riscv = include_bytes!("../serialized/registers/riscv");
-        let regs = bincode::deserialize(regs_riscv)?;
-        info!(
-            "riscv register set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        regs
-    } else {
-        Vec::new()
-    };
+    info!("Shutting down asm_lsp");
+    Ok(())
+}
 
-    populate_name_to_register_map(Arch::X86, &x86_registers, &mut names_to_info.registers);
-    populate_name_to_register_map(
-        Arch::X86_64,
-        &x86_64_registers,
-        &mut names_to_info.registers,
-    );
-    populate_name_to_register_map(Arch::Z80, &z80_registers, &mut names_to_info.registers);
-    populate_name_to_register_map(Arch::ARM, &arm_registers, &mut names_to_info.registers);
-    populate_name_to_register_map(Arch::ARM64, &arm64_registers, &mut names_to_info.registers);
-    populate_name_to_register_map(Arch::RISCV, &riscv_registers, &mut names_to_info.registers);
+/// Documents opened by the client, along with their parse trees and configs
+#[derive(Default)]
+struct DocumentState {
+    text_store: TextDocuments,
+    tree_store: TreeStore,
+    closed_trees: ClosedTrees,
+    config_overrides: ConfigOverrides,
+    /// The configs of the files opened without a project root, from the root
+    /// found for them when they were opened
+    root_configs: ConfigOverrides,
+    /// The labels of every file in the project
+    workspace_index: WorkspaceIndexState,
+    /// The instruction sets and assemblers each open file's modeline enables
+    /// without their documentation being loaded, as last reported to the user
+    modeline_warnings: HashMap<Uri, Vec<String>>,
+}
 
-    let gas_directives = if config.assemblers.gas.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let gas_dirs = include_bytes!("../serialized/directives/gas");
-        let dirs = bincode::deserialize(gas_dirs)?;
-        info!(
-            "Gas directive set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        dirs
-    } else {
-        Vec::new()
-    };
+/// The index of the labels of every file in the project, built on a worker
+/// thread so that startup doesn't wait on a walk of the whole project
+#[derive(Default)]
+enum WorkspaceIndexState {
+    /// The `workspace_index` option is off, or there's no project root to index
+    #[default]
+    Off,
+    /// The index is still being built. Files saved in the meantime are indexed
+    /// again once it's done, the worker may have read them before the save
+    Building {
+        receiver: Receiver<WorkspaceIndex>,
+        saved: Vec<Uri>,
+    },
+    Ready(WorkspaceIndex),
+}
 
-    let masm_directives = if config.assemblers.masm.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let masm_dirs = include_bytes!("../serialized/directives/masm");
-        let dirs = bincode::deserialize(masm_dirs)?;
-        info!(
-            "MASM directive set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        dirs
-    } else {
-        Vec::new()
-    };
+impl WorkspaceIndexState {
+    /// Starts building the index, or drops it, to match the `workspace_index`
+    /// option in `config`
+    fn update_config(&mut self, config: &Config) {
+        if !config.opts.workspace_index.unwrap_or(false) {
+            *self = Self::Off;
+            return;
+        }
+        let (Self::Off, Some(root)) = (&self, config.project_root.clone()) else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        let spawned = thread::Builder::new()
+            .name("workspace-index".to_string())
+            .spawn(move || {
+                // the receiver is gone if the option was turned off in the meantime
+                _ = sender.send(WorkspaceIndex::build(&root));
+            });
+        match spawned {
+            Ok(_) => {
+                *self = Self::Building {
+                    receiver,
+                    saved: Vec::new(),
+                };
+            }
+            Err(e) => error!("Failed to spawn workspace index thread - Error: {e}"),
+        }
+    }
 
-    let nasm_directives = if config.assemblers.nasm.unwrap_or(false) {
-        let start = std::time::Instant::now();
-        let nasm_dirs = include_bytes!("../serialized/directives/nasm");
-        let dirs = bincode::deserialize(nasm_dirs)?;
-        info!(
-            "Nasm directive set loaded in {}ms",
-            start.elapsed().as_millis()
-        );
-        dirs
-    } else {
-        Vec::new()
-    };
+    /// Returns the index if it's finished building. Until then, requests are
+    /// answered from the open documents alone
+    fn get(&mut self, text_store: &TextDocuments) -> Option<&WorkspaceIndex> {
+        if let Self::Building { receiver, saved } = self {
+            match receiver.try_recv() {
+                Ok(mut index) => {
+                    for uri in saved.iter() {
+                        if let Some(doc) = text_store.get_document(uri) {
+                            index.update_file(uri, doc.get_content(None));
+                        }
+                    }
+                    *self = Self::Ready(index);
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    error!("Workspace index thread exited without building the index");
+                    *self = Self::Off;
+                }
+            }
+        }
 
-    populate_name_to_directive_map(
-        Assembler::Gas,
-        &gas_directives,
-        &mut names_to_info.directives,
-    );
-    populate_name_to_directive_map(
-        Assembler::Masm,
-        &masm_directives,
-        &mut names_to_info.directives,
-    );
-    populate_name_to_directive_map(
-        Assembler::Nasm,
-        &nasm_directives,
-        &mut names_to_info.directives,
-    );
+        match self {
+            Self::Ready(index) => Some(index),
+            Self::Off | Self::Building { .. } => None,
+        }
+    }
 
-    let instr_completion_items = get_completes(
-        &names_to_info.instructions,
-        Some(CompletionItemKind::OPERATOR),
-    );
-    let reg_completion_items =
-        get_completes(&names_to_info.registers, Some(CompletionItemKind::VARIABLE));
-    let directive_completion_items = get_completes(
-        &names_to_info.directives,
-        Some(CompletionItemKind::OPERATOR),
-    );
+    /// Indexes the file at `uri` again after it's been saved
+    fn update_file(&mut self, uri: &Uri, text_store: &TextDocuments) {
+        match self {
+            Self::Ready(index) => {
+                if let Some(doc) = text_store.get_document(uri) {
+                    index.update_file(uri, doc.get_content(None));
+                }
+            }
+            Self::Building { saved, .. } => saved.push(uri.clone()),
+            Self::Off => {}
+        }
+    }
+}
 
-    let compile_cmds = get_compile_cmds(&params).unwrap_or_default();
-    info!("Loaded compile commands: {:?}", compile_cmds);
-    let include_dirs = get_include_dirs(&compile_cmds);
-
-    main_loop(
-        &connection,
-        &config,
-        &names_to_info,
-        &instr_completion_items,
-        &directive_completion_items,
-        &reg_completion_items,
-        &compile_cmds,
-        &include_dirs,
-    )?;
-
-    // HACK: the `writer` thread of `connection` hangs on joining more often than
-    // not. Need to investigate this further, but for now just skipping the join
-    // (and thus allowing the process to exit) is fine
-    // _io_threads.join()?;
+/// Why `main_loop` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopExit {
+    /// The client shut the server down
+    Shutdown,
+    /// The enabled instruction sets, assemblers, or include dirs changed, so the
+    /// documentation and include search paths have to be loaded again
+    Reload,
+}
 
-    info!("Shutting down asm_lsp");
-    Ok(())
+/// Applies the `log_level` option to `logger`
+fn set_log_level(logger: &LoggerHandle, config: &Config) {
+    if let Some(ref level) = config.opts.log_level {
+        if let Err(e) = logger.parse_new_spec(level) {
+            error!("Invalid log level `{level}` - Error: {e}");
+        }
+    }
 }
 
 fn main_loop(
     connection: &Connection,
-    config: &Config,
-    names_to_info: &NameToInfoMaps,
-    instruction_completion_items: &[CompletionItem],
-    directive_completion_items: &[CompletionItem],
-    register_completion_items: &[CompletionItem],
+    logger: Option<&LoggerHandle>,
+    config: &mut Config,
+    state: &ServerState,
     compile_cmds: &CompilationDatabase,
+    compile_cmd_overrides: &mut ConfigOverrides,
     include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
-) -> Result<()> {
-    let mut text_store = TextDocuments::new();
-    let mut tree_store = TreeStore::new();
+    defines: &HashMap<SourceFile, HashMap<String, String>>,
+    docs: &mut DocumentState,
+) -> Result<LoopExit> {
+    let DocumentState {
+        text_store,
+        tree_store,
+        closed_trees,
+        config_overrides,
+        root_configs,
+        workspace_index,
+        modeline_warnings,
+    } = docs;
 
     info!("Starting asm_lsp loop...");
     for msg in &connection.receiver {
@@ -471,118 +384,283 @@ fn main_loop(
             Message::Request(req) => {
                 if connection.handle_shutdown(&req)? {
                     info!("Recieved shutdown request");
-                    return Ok(());
+                    return Ok(LoopExit::Shutdown);
                 }
-                if let Ok((id, params)) = cast_req::<HoverRequest>(req.clone()) {
+                let method = req.method.clone();
+                // a modeline can turn off all features for a file
+                if get_request_uri(&req)
+                    .is_some_and(|uri| config.get_config(&uri, config_overrides).disabled)
+                {
+                    send_empty_resp(connection, req.id, config)?;
+                    info!(
+                        "Request for disabled file serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, params)) = cast_req::<HoverRequest>(req.clone()) {
+                    let uri = &params.text_document_position_params.text_document.uri;
+                    let config = config.get_config(uri, config_overrides);
                     handle_hover_request(
                         connection,
                         id,
                         config,
                         &params,
-                        &text_store,
-                        &mut tree_store,
-                        names_to_info,
+                        text_store,
+                        tree_store,
+                        &state.names_to_info,
                         include_dirs,
+                        defines,
+                        compile_cmds,
                     )?;
                     info!(
                         "Hover request serviced in {}ms",
                         start.elapsed().as_millis()
                     );
                 } else if let Ok((id, params)) = cast_req::<Completion>(req.clone()) {
+                    let uri = &params.text_document_position.text_document.uri;
+                    let config = config.get_config(uri, config_overrides);
                     handle_completion_request(
                         connection,
                         id,
                         &params,
                         config,
-                        &text_store,
-                        &mut tree_store,
-                        instruction_completion_items,
-                        directive_completion_items,
-                        register_completion_items,
+                        text_store,
+                        tree_store,
+                        &state.instr_completion_items,
+                        &state.directive_completion_items,
+                        &state.reg_completion_items,
                     )?;
                     info!(
                         "Completion request serviced in {}ms",
                         start.elapsed().as_millis()
                     );
                 } else if let Ok((id, params)) = cast_req::<GotoDefinition>(req.clone()) {
+                    let workspace_index = workspace_index.get(text_store);
+                    let uri = &params.text_document_position_params.text_document.uri;
+                    let config = config.get_config(uri, config_overrides);
                     handle_goto_def_request(
                         connection,
                         id,
                         &params,
                         config,
-                        &text_store,
-                        &mut tree_store,
+                        text_store,
+                        tree_store,
+                        include_dirs,
+                        workspace_index,
                     )?;
                     info!(
                         "Goto definition request serviced in {}ms",
                         start.elapsed().as_millis()
                     );
                 } else if let Ok((id, params)) = cast_req::<DocumentSymbolRequest>(req.clone()) {
+                    let config = config.get_config(&params.text_document.uri, config_overrides);
                     handle_document_symbols_request(
+                        connection, id, &params, config, text_store, tree_store,
+                    )?;
+                    info!(
+                        "Document symbols request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, params)) = cast_req::<WorkspaceSymbolRequest>(req.clone()) {
+                    handle_workspace_symbol_request(
                         connection,
                         id,
                         &params,
                         config,
-                        &text_store,
-                        &mut tree_store,
+                        workspace_index.get(text_store),
                     )?;
                     info!(
-                        "Document symbols request serviced in {}ms",
+                        "Workspace symbol request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, params)) = cast_req::<SemanticTokensFullRequest>(req.clone())
+                {
+                    let config = config.get_config(&params.text_document.uri, config_overrides);
+                    handle_semantic_tokens_full_request(
+                        connection,
+                        id,
+                        &params,
+                        config,
+                        text_store,
+                        tree_store,
+                        &state.names_to_info,
+                    )?;
+                    info!(
+                        "Semantic tokens request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, params)) = cast_req::<PrepareRenameRequest>(req.clone()) {
+                    let config = config.get_config(&params.text_document.uri, config_overrides);
+                    handle_prepare_rename_request(
+                        connection, id, &params, config, text_store, tree_store,
+                    )?;
+                    info!(
+                        "Prepare rename request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, params)) = cast_req::<Rename>(req.clone()) {
+                    let uri = &params.text_document_position.text_document.uri;
+                    let config = config.get_config(uri, config_overrides);
+                    handle_rename_request(
+                        connection,
+                        id,
+                        &params,
+                        config,
+                        text_store,
+                        tree_store,
+                        &state.names_to_info,
+                    )?;
+                    info!(
+                        "Rename request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, params)) = cast_req::<LinkedEditingRange>(req.clone()) {
+                    let uri = &params.text_document_position_params.text_document.uri;
+                    let config = config.get_config(uri, config_overrides);
+                    handle_linked_editing_range_request(
+                        connection,
+                        id,
+                        &params,
+                        config,
+                        text_store,
+                        tree_store,
+                        &state.names_to_info,
+                    )?;
+                    info!(
+                        "Linked editing range request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, params)) = cast_req::<OnTypeFormatting>(req.clone()) {
+                    let uri = &params.text_document_position.text_document.uri;
+                    let config = config.get_config(uri, config_overrides);
+                    handle_on_type_formatting_request(connection, id, &params, config, text_store)?;
+                    info!(
+                        "On type formatting request serviced in {}ms",
                         start.elapsed().as_millis()
                     );
                 } else if let Ok((id, params)) = cast_req::<SignatureHelpRequest>(req.clone()) {
+                    let uri = &params.text_document_position_params.text_document.uri;
+                    let config = config.get_config(uri, config_overrides);
                     handle_signature_help_request(
                         connection,
                         id,
                         &params,
                         config,
-                        &text_store,
-                        &mut tree_store,
-                        &names_to_info.instructions,
+                        text_store,
+                        tree_store,
+                        &state.names_to_info,
                     )?;
                     info!(
                         "Signature help request serviced in {}ms",
                         start.elapsed().as_millis()
                     );
                 } else if let Ok((id, params)) = cast_req::<References>(req.clone()) {
+                    let workspace_index = workspace_index.get(text_store);
+                    let uri = &params.text_document_position.text_document.uri;
+                    let config = config.get_config(uri, config_overrides);
                     handle_references_request(
                         connection,
                         id,
                         &params,
                         config,
-                        &text_store,
-                        &mut tree_store,
+                        text_store,
+                        tree_store,
+                        workspace_index,
                     )?;
                     info!(
                         "References request serviced in {}ms",
                         start.elapsed().as_millis()
                     );
+                } else if let Ok((id, params)) = cast_req::<ExplainRange>(req.clone()) {
+                    let config = config.get_config(&params.text_document.uri, config_overrides);
+                    handle_explain_range_request(
+                        connection,
+                        id,
+                        &params,
+                        config,
+                        text_store,
+                        tree_store,
+                        &state.names_to_info,
+                    )?;
+                    info!(
+                        "Explain range request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, params)) = cast_req::<DocumentOutline>(req.clone()) {
+                    handle_document_outline_request(
+                        connection, id, &params, text_store, tree_store,
+                    )?;
+                    info!(
+                        "Document outline request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, ())) = cast_req::<AssembleProject>(req.clone()) {
+                    // the response is sent from a worker thread once it's ready
+                    handle_assemble_project_request(connection, id, config, compile_cmds)?;
+                } else if let Ok((id, params)) =
+                    cast_req::<InstructionsWritingRegister>(req.clone())
+                {
+                    handle_instructions_writing_register_request(
+                        connection,
+                        id,
+                        &params,
+                        config,
+                        &state.names_to_info,
+                    )?;
+                    info!(
+                        "Instructions writing register request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                } else if let Ok((id, ())) = cast_req::<ListBackends>(req.clone()) {
+                    handle_list_backends_request(connection, id, config)?;
+                    info!(
+                        "List backends request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
                 } else if let Ok((_id, params)) = cast_req::<DocumentDiagnosticRequest>(req.clone())
                 {
-                    // Ok to unwrap, this should never be `None`
-                    if config.opts.diagnostics.unwrap() {
-                        handle_diagnostics(
-                            connection,
-                            &params.text_document.uri,
-                            config,
-                            compile_cmds,
-                        )?;
-                        info!(
-                            "Diagnostics request serviced in {}ms",
-                            start.elapsed().as_millis()
-                        );
-                    }
+                    let config = config.get_config(&params.text_document.uri, config_overrides);
+                    handle_diagnostics(
+                        connection,
+                        &params.text_document.uri,
+                        config,
+                        compile_cmds,
+                    )?;
+                    info!(
+                        "Diagnostics request serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
                 } else {
                     error!("Invalid request format -> {:#?}", req);
                 }
+                if config.opts.telemetry.unwrap_or(false) {
+                    send_request_telemetry(connection, &method, start.elapsed().as_millis())?;
+                }
             }
             Message::Notification(notif) => {
+                // an exit without a preceding shutdown request, the client is
+                // going away regardless
+                if notif.method == Exit::METHOD {
+                    info!("Received exit notification");
+                    return Ok(LoopExit::Shutdown);
+                }
                 if let Ok(params) = cast_notif::<DidOpenTextDocument>(notif.clone()) {
                     handle_did_open_text_document_notification(
                         &params,
-                        &mut text_store,
-                        &mut tree_store,
+                        config,
+                        text_store,
+                        tree_store,
+                        closed_trees,
+                        config_overrides,
+                        compile_cmd_overrides,
+                        root_configs,
                     );
+                    warn_modeline_unloaded_docs(
+                        connection,
+                        &params.text_document.uri,
+                        config,
+                        text_store,
+                        modeline_warnings,
+                    )?;
                     info!(
                         "Did open text document notification serviced in {}ms",
                         start.elapsed().as_millis()
@@ -590,8 +668,19 @@ fn main_loop(
                 } else if let Ok(params) = cast_notif::<DidChangeTextDocument>(notif.clone()) {
                     handle_did_change_text_document_notification(
                         &params,
-                        &mut text_store,
-                        &mut tree_store,
+                        config,
+                        text_store,
+                        tree_store,
+                        config_overrides,
+                        compile_cmd_overrides,
+                        root_configs,
+                    )?;
+                    warn_modeline_unloaded_docs(
+                        connection,
+                        &params.text_document.uri,
+                        config,
+                        text_store,
+                        modeline_warnings,
                     )?;
                     info!(
                         "Did change text document notification serviced in {}ms",
@@ -600,33 +689,63 @@ fn main_loop(
                 } else if let Ok(params) = cast_notif::<DidCloseTextDocument>(notif.clone()) {
                     handle_did_close_text_document_notification(
                         &params,
-                        &mut text_store,
-                        &mut tree_store,
+                        config,
+                        text_store,
+                        tree_store,
+                        closed_trees,
+                        config_overrides,
+                        root_configs,
                     );
+                    modeline_warnings.remove(&params.text_document.uri);
                     info!(
                         "Did close text document notification serviced in {}ms",
                         start.elapsed().as_millis()
                     );
-                } else if let Ok(params) = cast_notif::<DidSaveTextDocument>(notif.clone()) {
-                    // Ok to unwrap, this should never be `None`
-                    if config.opts.diagnostics.unwrap() {
-                        handle_diagnostics(
-                            connection,
-                            &params.text_document.uri,
-                            config,
-                            compile_cmds,
-                        )?;
-                        info!(
-                            "Published diagnostics on save in {}ms",
-                            start.elapsed().as_millis()
-                        );
+                } else if let Ok(params) = cast_notif::<DidChangeConfiguration>(notif.clone()) {
+                    let needs_reload = handle_did_change_configuration_notification(
+                        &params,
+                        config,
+                        text_store,
+                        config_overrides,
+                        compile_cmd_overrides,
+                        root_configs,
+                    );
+                    if let Some(logger) = logger {
+                        set_log_level(logger, config);
                     }
+                    workspace_index.update_config(config);
+                    info!(
+                        "Did change configuration notification serviced in {}ms",
+                        start.elapsed().as_millis()
+                    );
+                    if needs_reload {
+                        return Ok(LoopExit::Reload);
+                    }
+                } else if let Ok(params) = cast_notif::<DidSaveTextDocument>(notif.clone()) {
+                    workspace_index.update_file(&params.text_document.uri, text_store);
+                    let config = config.get_config(&params.text_document.uri, config_overrides);
+                    handle_diagnostics(
+                        connection,
+                        &params.text_document.uri,
+                        config,
+                        compile_cmds,
+                    )?;
+                    info!(
+                        "Published diagnostics on save in {}ms",
+                        start.elapsed().as_millis()
+                    );
                 }
             }
             Message::Response(_resp) => {}
         }
     }
-    Ok(())
+    Ok(LoopExit::Shutdown)
+}
+
+/// Returns the URI of the text document `req` concerns, if any
+fn get_request_uri(req: &Request) -> Option<Uri> {
+    let uri = req.params.get("textDocument")?.get("uri")?.as_str()?;
+    Uri::from_str(uri).ok()
 }
 
 fn cast_req<R>(req: Request) -> Result<(RequestId, R::Params)>
@@ -641,6 +760,239 @@ where
     }
 }
 
+/// The channel the server uses to talk to the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Transport {
+    Stdio,
+    /// Connect to a TCP socket the client listens on at this port
+    Socket(u16),
+    /// Connect to a named pipe (a Unix domain socket on Unix) created by the client
+    Pipe(PathBuf),
+}
+
+/// The server's command line arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Args {
+    transport: Transport,
+    /// Config file to use instead of searching for global and project configs
+    config_path: Option<PathBuf>,
+}
+
+/// Parses the server's command line arguments, i.e. the transport selection and
+/// an optional `--config` path. Both `--flag value` and `--flag=value` forms are
+/// accepted, the latter being what VS Code passes
+///
+/// # Errors
+///
+/// Returns `Err` if an argument is unknown, a value is missing or malformed, or
+/// more than one transport or config is selected
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut transport = None;
+    let mut config_path = None;
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| anyhow::anyhow!("Missing value for `{flag}`"))
+        };
+        let selected = match flag.as_str() {
+            "--stdio" if inline_value.is_none() => Transport::Stdio,
+            "--socket" => {
+                let port = value()?;
+                Transport::Socket(
+                    port.parse()
+                        .map_err(|_| anyhow::anyhow!("Invalid port `{port}` for `--socket`"))?,
+                )
+            }
+            "--pipe" => Transport::Pipe(PathBuf::from(value()?)),
+            "--config" => {
+                let path = PathBuf::from(value()?);
+                if config_path.replace(path).is_some() {
+                    return Err(anyhow::anyhow!("`--config` may only be given once"));
+                }
+                continue;
+            }
+            _ => return Err(anyhow::anyhow!("Unknown argument `{arg}`")),
+        };
+        if let Some(prev) = transport {
+            return Err(anyhow::anyhow!(
+                "Conflicting transports {prev:?} and {selected:?}: only one of `--stdio`, \
+                `--socket`, and `--pipe` may be given"
+            ));
+        }
+        transport = Some(selected);
+    }
+
+    Ok(Args {
+        transport: transport.unwrap_or(Transport::Stdio),
+        config_path,
+    })
+}
+
+/// Handles to the threads moving messages between a `Connection` and the client
+enum IoHandles {
+    Lsp(IoThreads),
+    Pipe {
+        reader: JoinHandle<io::Result<()>>,
+        writer: JoinHandle<io::Result<()>>,
+    },
+}
+
+impl IoHandles {
+    fn join(self) -> io::Result<()> {
+        match self {
+            Self::Lsp(io_threads) => io_threads.join(),
+            Self::Pipe { reader, writer } => {
+                for handle in [reader, writer] {
+                    handle
+                        .join()
+                        .map_err(|_| io::Error::other("Pipe thread panicked"))??;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Connects to the client over `transport`
+///
+/// # Errors
+///
+/// Returns `Err` if the socket or pipe can't be connected to
+fn connect(transport: &Transport) -> io::Result<(Connection, IoHandles)> {
+    match transport {
+        Transport::Stdio => {
+            let (connection, io_threads) = Connection::stdio();
+            Ok((connection, IoHandles::Lsp(io_threads)))
+        }
+        Transport::Socket(port) => {
+            let (connection, io_threads) = Connection::connect(("127.0.0.1", *port))?;
+            Ok((connection, IoHandles::Lsp(io_threads)))
+        }
+        Transport::Pipe(path) => {
+            #[cfg(unix)]
+            let stream = std::os::unix::net::UnixStream::connect(path)?;
+            #[cfg(not(unix))]
+            let stream = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(path)?;
+            let mut write_stream = stream.try_clone()?;
+
+            // lsp-server has no pipe transport, so bridge the pipe to an in-memory
+            // connection the same way its socket transport does
+            let (connection, bridge) = Connection::memory();
+            let Connection { sender, receiver } = bridge;
+            let reader = thread::spawn(move || {
+                let mut buf_read = BufReader::new(stream);
+                while let Some(msg) = Message::read(&mut buf_read)? {
+                    let is_exit =
+                        matches!(&msg, Message::Notification(n) if n.method == Exit::METHOD);
+                    if sender.send(msg).is_err() || is_exit {
+                        break;
+                    }
+                }
+                Ok(())
+            });
+            let writer = thread::spawn(move || {
+                receiver
+                    .into_iter()
+                    .try_for_each(|msg| msg.write(&mut write_stream))
+            });
+            Ok((connection, IoHandles::Pipe { reader, writer }))
+        }
+    }
+}
+
+/// Warns the user that no instruction sets or assemblers are enabled, which
+/// leaves the server with nothing to offer unless it `fell_back` to x86-64 and GAS
+///
+/// # Errors
+///
+/// Returns `Err` if the message fails to send
+fn warn_empty_config(connection: &Connection, fell_back: bool) -> Result<()> {
+    let message = if fell_back {
+        "asm-lsp: No instruction sets or assemblers are enabled, falling back to x86-64 and \
+        GAS. Check the `instruction_sets` and `assemblers` tables in your `.asm-lsp.toml` \
+        config, or set `empty_config_fallback = false` to turn this off."
+    } else {
+        "asm-lsp: No instruction sets or assemblers are enabled, so no language features \
+        will work. Check the `instruction_sets` and `assemblers` tables in your \
+        `.asm-lsp.toml` config."
+    };
+    let params = ShowMessageParams {
+        typ: MessageType::WARNING,
+        message: String::from(message),
+    };
+    let notif = Notification::new(ShowMessage::METHOD.to_string(), params);
+    Ok(connection.sender.send(Message::Notification(notif))?)
+}
+
+/// Warns the user if the modeline of the document at `uri` enables instruction
+/// sets or assemblers that aren't enabled in the project `config`, as their
+/// documentation isn't loaded. A file is only warned about again once what its
+/// modeline enables changes
+///
+/// # Errors
+///
+/// Returns `Err` if the message fails to send
+fn warn_modeline_unloaded_docs(
+    connection: &Connection,
+    uri: &Uri,
+    config: &Config,
+    text_store: &TextDocuments,
+    modeline_warnings: &mut HashMap<Uri, Vec<String>>,
+) -> Result<()> {
+    let unloaded = text_store
+        .get_document(uri)
+        .and_then(|doc| get_modeline_config(doc.get_content(None), config))
+        .map(|file_config| get_modeline_unloaded_docs(&file_config, config))
+        .unwrap_or_default();
+    if modeline_warnings.get(uri).map_or(&[][..], Vec::as_slice) == unloaded.as_slice() {
+        return Ok(());
+    }
+    if unloaded.is_empty() {
+        modeline_warnings.remove(uri);
+        return Ok(());
+    }
+
+    let message = format!(
+        "asm-lsp: The modeline in {} enables {}, which isn't enabled in your \
+        `.asm-lsp.toml` config, so its documentation isn't loaded. Enable it in the \
+        config to get documentation for the file.",
+        uri.path(),
+        unloaded.join(", ")
+    );
+    warn!("{message}");
+    modeline_warnings.insert(uri.clone(), unloaded);
+    let params = ShowMessageParams {
+        typ: MessageType::WARNING,
+        message,
+    };
+    let notif = Notification::new(ShowMessage::METHOD.to_string(), params);
+    Ok(connection.sender.send(Message::Notification(notif))?)
+}
+
+/// Sends a `telemetry/event` notification with the `method` of a request the
+/// server just serviced, and how long it took
+///
+/// # Errors
+///
+/// Returns `Err` if the message fails to send
+fn send_request_telemetry(connection: &Connection, method: &str, elapsed_ms: u128) -> Result<()> {
+    let params = serde_json::json!({
+        "method": method,
+        "elapsedMs": elapsed_ms,
+    });
+    let notif = Notification::new(TelemetryEvent::METHOD.to_string(), params);
+    Ok(connection.sender.send(Message::Notification(notif))?)
+}
+
 fn cast_notif<R>(notif: Notification) -> Result<R::Params>
 where
     R: lsp_types::notification::Notification,
@@ -652,3 +1004,438 @@ where
         Err(e) => Err(anyhow::anyhow!("Error: {e}")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use asm_lsp::{
+        AssembleProject, AssembleProjectResult, Config, ConfigOverrides, ListBackends, ServerState,
+    };
+    use lsp_server::{Connection, Message, Notification, Request, RequestId};
+    use lsp_types::notification::{
+        DidChangeConfiguration, DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized,
+        Notification as _, ShowMessage, TelemetryEvent,
+    };
+    use lsp_types::request::{Initialize, Request as _, Shutdown, WorkspaceSymbolRequest};
+    use lsp_types::{
+        DidChangeConfigurationParams, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+        InitializeParams, InitializedParams, ServerCapabilities, ShowMessageParams,
+        TextDocumentContentChangeEvent, TextDocumentItem, Uri, VersionedTextDocumentIdentifier,
+        WorkspaceSymbolParams, WorkspaceSymbolResponse,
+    };
+
+    use super::{main_loop, parse_args, Args, DocumentState, LoopExit, Transport};
+
+    #[test]
+    fn main_loop_it_exits_after_shutdown_and_exit() {
+        let (server, client) = Connection::memory();
+        let server_thread = std::thread::spawn(move || {
+            let capabilities = serde_json::to_value(ServerCapabilities::default()).unwrap();
+            server.initialize(capabilities).unwrap();
+            main_loop(
+                &server,
+                None,
+                &mut Config::default(),
+                &ServerState::default(),
+                &Vec::new(),
+                &mut ConfigOverrides::new(),
+                &HashMap::new(),
+                &HashMap::new(),
+                &mut DocumentState::default(),
+            )
+        });
+        let timeout = Duration::from_secs(10);
+
+        let init_params = serde_json::to_value(InitializeParams::default()).unwrap();
+        client
+            .sender
+            .send(
+                Request::new(
+                    RequestId::from(1),
+                    Initialize::METHOD.to_string(),
+                    init_params,
+                )
+                .into(),
+            )
+            .unwrap();
+        let Ok(Message::Response(resp)) = client.receiver.recv_timeout(timeout) else {
+            panic!("No response to initialize request");
+        };
+        assert_eq!(resp.id, RequestId::from(1));
+        let initialized = serde_json::to_value(InitializedParams {}).unwrap();
+        client
+            .sender
+            .send(Notification::new(Initialized::METHOD.to_string(), initialized).into())
+            .unwrap();
+
+        client
+            .sender
+            .send(Request::new(RequestId::from(2), Shutdown::METHOD.to_string(), ()).into())
+            .unwrap();
+        let Ok(Message::Response(resp)) = client.receiver.recv_timeout(timeout) else {
+            panic!("No response to shutdown request");
+        };
+        assert_eq!(resp.id, RequestId::from(2));
+        client
+            .sender
+            .send(Notification::new(Exit::METHOD.to_string(), ()).into())
+            .unwrap();
+
+        assert_eq!(server_thread.join().unwrap().unwrap(), LoopExit::Shutdown);
+        // with the server's end of the connection dropped, the channel closes
+        assert!(client.receiver.recv_timeout(timeout).is_err());
+    }
+
+    #[test]
+    fn main_loop_it_reloads_when_instruction_sets_change() {
+        let (server, client) = Connection::memory();
+        let server_thread = std::thread::spawn(move || {
+            let mut config = Config::default();
+            let exit = main_loop(
+                &server,
+                None,
+                &mut config,
+                &ServerState::default(),
+                &Vec::new(),
+                &mut ConfigOverrides::new(),
+                &HashMap::new(),
+                &HashMap::new(),
+                &mut DocumentState::default(),
+            );
+            (exit, config)
+        });
+
+        let settings = serde_json::json!({ "opts": { "diagnostics": false } });
+        let params = serde_json::to_value(DidChangeConfigurationParams { settings }).unwrap();
+        client
+            .sender
+            .send(Notification::new(DidChangeConfiguration::METHOD.to_string(), params).into())
+            .unwrap();
+        let settings = serde_json::json!({ "asm-lsp": { "instruction_sets": { "arm": true } } });
+        let params = serde_json::to_value(DidChangeConfigurationParams { settings }).unwrap();
+        client
+            .sender
+            .send(Notification::new(DidChangeConfiguration::METHOD.to_string(), params).into())
+            .unwrap();
+
+        let (exit, config) = server_thread.join().unwrap();
+        assert_eq!(exit.unwrap(), LoopExit::Reload);
+        assert_eq!(config.opts.diagnostics, Some(false));
+        assert_eq!(config.instruction_sets.arm, Some(true));
+        assert_eq!(config.instruction_sets.x86_64, Some(true));
+    }
+
+    #[test]
+    fn main_loop_it_sends_request_telemetry() {
+        let (server, client) = Connection::memory();
+        let server_thread = std::thread::spawn(move || {
+            let mut config = Config::default();
+            config.opts.telemetry = Some(true);
+            main_loop(
+                &server,
+                None,
+                &mut config,
+                &ServerState::default(),
+                &Vec::new(),
+                &mut ConfigOverrides::new(),
+                &HashMap::new(),
+                &HashMap::new(),
+                &mut DocumentState::default(),
+            )
+        });
+        let timeout = Duration::from_secs(10);
+
+        client
+            .sender
+            .send(Request::new(RequestId::from(1), ListBackends::METHOD.to_string(), ()).into())
+            .unwrap();
+        let Ok(Message::Response(resp)) = client.receiver.recv_timeout(timeout) else {
+            panic!("No response to list backends request");
+        };
+        assert_eq!(resp.id, RequestId::from(1));
+        let Ok(Message::Notification(notif)) = client.receiver.recv_timeout(timeout) else {
+            panic!("No telemetry event for list backends request");
+        };
+        assert_eq!(notif.method, TelemetryEvent::METHOD);
+        assert_eq!(notif.params["method"], ListBackends::METHOD);
+        assert!(notif.params["elapsedMs"].is_u64());
+
+        client
+            .sender
+            .send(Notification::new(Exit::METHOD.to_string(), ()).into())
+            .unwrap();
+        assert_eq!(server_thread.join().unwrap().unwrap(), LoopExit::Shutdown);
+    }
+
+    #[test]
+    fn main_loop_it_responds_to_assemble_project_from_a_worker() {
+        let (server, client) = Connection::memory();
+        let server_thread = std::thread::spawn(move || {
+            main_loop(
+                &server,
+                None,
+                &mut Config::default(),
+                &ServerState::default(),
+                &Vec::new(),
+                &mut ConfigOverrides::new(),
+                &HashMap::new(),
+                &HashMap::new(),
+                &mut DocumentState::default(),
+            )
+        });
+        let timeout = Duration::from_secs(10);
+
+        client
+            .sender
+            .send(Request::new(RequestId::from(1), AssembleProject::METHOD.to_string(), ()).into())
+            .unwrap();
+        let Ok(Message::Response(resp)) = client.receiver.recv_timeout(timeout) else {
+            panic!("No response to assemble project request");
+        };
+        assert_eq!(resp.id, RequestId::from(1));
+        let result: AssembleProjectResult = serde_json::from_value(resp.result.unwrap()).unwrap();
+        assert!(result.diagnostics.is_empty());
+
+        client
+            .sender
+            .send(Notification::new(Exit::METHOD.to_string(), ()).into())
+            .unwrap();
+        assert_eq!(server_thread.join().unwrap().unwrap(), LoopExit::Shutdown);
+    }
+
+    #[test]
+    fn main_loop_it_builds_the_workspace_index_in_the_background() {
+        let root = std::env::temp_dir().join(format!(
+            "asm-lsp-background-workspace-index-test-{}",
+            std::process::id()
+        ));
+        _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("helper.s"), "helper:\n    ret\n").unwrap();
+        let server_root = root.clone();
+        let (server, client) = Connection::memory();
+        let server_thread = std::thread::spawn(move || {
+            let mut config = Config {
+                project_root: Some(server_root),
+                ..Config::default()
+            };
+            main_loop(
+                &server,
+                None,
+                &mut config,
+                &ServerState::default(),
+                &Vec::new(),
+                &mut ConfigOverrides::new(),
+                &HashMap::new(),
+                &HashMap::new(),
+                &mut DocumentState::default(),
+            )
+        });
+        let timeout = Duration::from_secs(10);
+        let mut next_id = 0;
+        let mut symbol_names = || {
+            next_id += 1;
+            let params = WorkspaceSymbolParams {
+                query: String::from("help"),
+                ..Default::default()
+            };
+            client
+                .sender
+                .send(
+                    Request::new(
+                        RequestId::from(next_id),
+                        WorkspaceSymbolRequest::METHOD.to_string(),
+                        params,
+                    )
+                    .into(),
+                )
+                .unwrap();
+            let Ok(Message::Response(resp)) = client.receiver.recv_timeout(timeout) else {
+                panic!("No response to workspace symbol request");
+            };
+            assert_eq!(resp.id, RequestId::from(next_id));
+            // an empty response, while there's no index to search
+            match resp.result.map(serde_json::from_value) {
+                Some(Ok(WorkspaceSymbolResponse::Flat(symbols))) => {
+                    symbols.into_iter().map(|sym| sym.name).collect()
+                }
+                _ => Vec::new(),
+            }
+        };
+
+        assert!(symbol_names().is_empty());
+        let settings = serde_json::json!({ "opts": { "workspace_index": true } });
+        let params = serde_json::to_value(DidChangeConfigurationParams { settings }).unwrap();
+        client
+            .sender
+            .send(Notification::new(DidChangeConfiguration::METHOD.to_string(), params).into())
+            .unwrap();
+        // requests are answered without the index until it's been built
+        let start = std::time::Instant::now();
+        let names = loop {
+            let names: Vec<String> = symbol_names();
+            if !names.is_empty() || start.elapsed() > timeout {
+                break names;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(names, ["helper"]);
+
+        client
+            .sender
+            .send(Notification::new(Exit::METHOD.to_string(), ()).into())
+            .unwrap();
+        assert_eq!(server_thread.join().unwrap().unwrap(), LoopExit::Shutdown);
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn main_loop_it_warns_about_modelines_enabling_unloaded_docs() {
+        let (server, client) = Connection::memory();
+        let server_thread = std::thread::spawn(move || {
+            main_loop(
+                &server,
+                None,
+                &mut Config::default(),
+                &ServerState::default(),
+                &Vec::new(),
+                &mut ConfigOverrides::new(),
+                &HashMap::new(),
+                &HashMap::new(),
+                &mut DocumentState::default(),
+            )
+        });
+        let timeout = Duration::from_secs(10);
+        let uri: Uri = "file:///tmp/modeline.s".parse().unwrap();
+        let open_params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "asm".to_string(),
+                version: 0,
+                text: "# asm-lsp: arch=riscv\n    nop\n".to_string(),
+            },
+        };
+        client
+            .sender
+            .send(Notification::new(DidOpenTextDocument::METHOD.to_string(), open_params).into())
+            .unwrap();
+        let Ok(Message::Notification(notif)) = client.receiver.recv_timeout(timeout) else {
+            panic!("No warning about the modeline");
+        };
+        assert_eq!(notif.method, ShowMessage::METHOD);
+        let params: ShowMessageParams = serde_json::from_value(notif.params).unwrap();
+        assert!(params.message.contains("enables riscv"));
+
+        // editing the file doesn't repeat the warning, nor does switching to an
+        // instruction set that's already loaded
+        for (version, text) in [
+            (1, "# asm-lsp: arch=riscv\n    ret\n"),
+            (2, "# asm-lsp: arch=x86_64\n"),
+        ] {
+            let change_params = DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: text.to_string(),
+                }],
+            };
+            client
+                .sender
+                .send(
+                    Notification::new(DidChangeTextDocument::METHOD.to_string(), change_params)
+                        .into(),
+                )
+                .unwrap();
+        }
+        let params = WorkspaceSymbolParams {
+            query: String::new(),
+            ..Default::default()
+        };
+        client
+            .sender
+            .send(
+                Request::new(
+                    RequestId::from(1),
+                    WorkspaceSymbolRequest::METHOD.to_string(),
+                    params,
+                )
+                .into(),
+            )
+            .unwrap();
+        let Ok(Message::Response(resp)) = client.receiver.recv_timeout(timeout) else {
+            panic!("Expected only the workspace symbol response");
+        };
+        assert_eq!(resp.id, RequestId::from(1));
+
+        client
+            .sender
+            .send(Notification::new(Exit::METHOD.to_string(), ()).into())
+            .unwrap();
+        assert_eq!(server_thread.join().unwrap().unwrap(), LoopExit::Shutdown);
+    }
+
+    fn parse(args: &[&str]) -> anyhow::Result<Args> {
+        parse_args(args.iter().map(ToString::to_string))
+    }
+
+    fn parse_transport(args: &[&str]) -> anyhow::Result<Transport> {
+        parse(args).map(|args| args.transport)
+    }
+
+    #[test]
+    fn parse_transport_it_defaults_to_stdio() {
+        assert_eq!(parse_transport(&[]).unwrap(), Transport::Stdio);
+        assert_eq!(parse_transport(&["--stdio"]).unwrap(), Transport::Stdio);
+    }
+
+    #[test]
+    fn parse_transport_it_accepts_both_value_forms() {
+        assert_eq!(
+            parse_transport(&["--socket", "9257"]).unwrap(),
+            Transport::Socket(9257)
+        );
+        assert_eq!(
+            parse_transport(&["--socket=9257"]).unwrap(),
+            Transport::Socket(9257)
+        );
+        assert_eq!(
+            parse_transport(&["--pipe=/tmp/asm-lsp.sock"]).unwrap(),
+            Transport::Pipe("/tmp/asm-lsp.sock".into())
+        );
+    }
+
+    #[test]
+    fn parse_transport_it_rejects_invalid_args() {
+        assert!(parse_transport(&["--stdio", "--socket", "9257"]).is_err());
+        assert!(parse_transport(&["--socket"]).is_err());
+        assert!(parse_transport(&["--socket", "http"]).is_err());
+        assert!(parse_transport(&["--verbose"]).is_err());
+    }
+
+    #[test]
+    fn parse_args_it_accepts_a_config_path() {
+        assert_eq!(parse(&[]).unwrap().config_path, None);
+        assert_eq!(
+            parse(&["--config", "/ci/asm-lsp.toml"]).unwrap(),
+            Args {
+                transport: Transport::Stdio,
+                config_path: Some("/ci/asm-lsp.toml".into()),
+            }
+        );
+        assert_eq!(
+            parse(&["--socket=9257", "--config=/ci/asm-lsp.toml"]).unwrap(),
+            Args {
+                transport: Transport::Socket(9257),
+                config_path: Some("/ci/asm-lsp.toml".into()),
+            }
+        );
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["--config", "a.toml", "--config", "b.toml"]).is_err());
+    }
+}
@@ -2,26 +2,36 @@ use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::{anyhow, Result};
 use compile_commands::{CompilationDatabase, SourceFile};
-use log::info;
-use lsp_server::{Connection, Message, RequestId, Response};
+use log::{error, info};
+use lsp_server::{Connection, ErrorCode, Message, RequestId, Response};
 use lsp_textdocument::TextDocuments;
 use lsp_types::{
     notification::{
         DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification,
         PublishDiagnostics,
     },
-    CompletionItem, CompletionParams, Diagnostic, DidChangeTextDocumentParams,
-    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentSymbolParams,
-    DocumentSymbolResponse, GotoDefinitionParams, HoverParams, PublishDiagnosticsParams,
-    ReferenceParams, SignatureHelpParams, Uri,
+    CompletionItem, CompletionParams, Diagnostic, DidChangeConfigurationParams,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentOnTypeFormattingParams, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, HoverParams, LinkedEditingRangeParams, PublishDiagnosticsParams,
+    ReferenceParams, RenameParams, SemanticTokensParams, SemanticTokensResult, SignatureHelpParams,
+    TextDocumentPositionParams, Uri, WorkspaceSymbolParams, WorkspaceSymbolResponse,
 };
 use tree_sitter::Parser;
 
 use crate::{
-    apply_compile_cmd, get_comp_resp, get_default_compile_cmd, get_document_symbols,
-    get_goto_def_resp, get_hover_resp, get_ref_resp, get_sig_help_resp, get_word_from_pos_params,
-    send_empty_resp, text_doc_change_to_ts_edit, Config, NameToInfoMaps, NameToInstructionMap,
-    TreeEntry, TreeStore,
+    add_debug_hover_info, add_diagnostics_context, apply_compile_cmd, apply_config_settings,
+    apply_linters, asm_language, canonicalize_uri, dedup_diagnostics, get_arm_state_diagnostics,
+    get_assemble_project_resp, get_backends_resp, get_comp_resp, get_default_compile_cmd,
+    get_document_outline, get_document_symbols, get_explain_range_resp, get_file_compile_cmds,
+    get_goto_def_resp, get_hover_resp, get_linked_editing_range_resp, get_modeline_config,
+    get_on_type_formatting_resp, get_prepare_rename_resp, get_ref_resp, get_register_writers_resp,
+    get_rename_resp, get_root_compile_cmds, get_root_fallback_config, get_semantic_tokens,
+    get_sig_help_resp, get_syntax_diagnostics, get_word_from_pos_params,
+    get_workspace_goto_def_resp, get_workspace_ref_resp, send_empty_resp,
+    text_doc_change_to_ts_edit, uri_to_path, ClosedTrees, Config, ConfigOverrides,
+    DocumentOutlineParams, ExplainRangeParams, InstructionsWritingRegisterParams, NameToInfoMaps,
+    TreeEntry, TreeStore, WorkspaceIndex,
 };
 
 /// Handles hover requests
@@ -42,6 +52,8 @@ pub fn handle_hover_request(
     tree_store: &mut TreeStore,
     names_to_info: &NameToInfoMaps,
     include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
+    defines: &HashMap<SourceFile, HashMap<String, String>>,
+    compile_cmds: &CompilationDatabase,
 ) -> Result<()> {
     let (word, cursor_offset) = if let Some(doc) =
         text_store.get_document(&params.text_document_position_params.text_document.uri)
@@ -51,7 +63,7 @@ pub fn handle_hover_request(
         return send_empty_resp(connection, id, config);
     };
 
-    if let Some(hover_resp) = get_hover_resp(
+    let mut hover_resp = get_hover_resp(
         params,
         config,
         word,
@@ -62,7 +74,14 @@ pub fn handle_hover_request(
         &names_to_info.registers,
         &names_to_info.directives,
         include_dirs,
-    ) {
+        defines,
+        compile_cmds,
+    );
+    if config.opts.debug_hover.unwrap_or(false) {
+        hover_resp = add_debug_hover_info(hover_resp, params, text_store, tree_store);
+    }
+
+    if let Some(hover_resp) = hover_resp {
         let result = serde_json::to_value(hover_resp).unwrap();
         let result = Response {
             id,
@@ -137,11 +156,19 @@ pub fn handle_goto_def_request(
     config: &Config,
     text_store: &TextDocuments,
     tree_store: &mut TreeStore,
+    include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
+    workspace_index: Option<&WorkspaceIndex>,
 ) -> Result<()> {
     let uri = &params.text_document_position_params.text_document.uri;
     if let Some(doc) = text_store.get_document(uri) {
         if let Some(tree_entry) = tree_store.get_mut(uri) {
-            if let Some(def_resp) = get_goto_def_resp(doc, tree_entry, params) {
+            // labels declared in the current file take precedence
+            let def_resp = get_goto_def_resp(doc, tree_entry, params, config, include_dirs)
+                .or_else(|| {
+                    workspace_index
+                        .and_then(|index| get_workspace_goto_def_resp(params, doc, config, index))
+                });
+            if let Some(def_resp) = def_resp {
                 let result = serde_json::to_value(def_resp).unwrap();
                 let result = Response {
                     id,
@@ -193,6 +220,111 @@ pub fn handle_document_symbols_request(
     send_empty_resp(connection, id, config)
 }
 
+/// Handles workspace symbol requests, answered from the workspace index if
+/// the `workspace_index` option is set
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Panics if JSON encoding of a response fails
+pub fn handle_workspace_symbol_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &WorkspaceSymbolParams,
+    config: &Config,
+    workspace_index: Option<&WorkspaceIndex>,
+) -> Result<()> {
+    if let Some(index) = workspace_index {
+        let resp = WorkspaceSymbolResponse::Flat(index.get_symbols(&params.query));
+        let result = serde_json::to_value(resp).unwrap();
+        let result = Response {
+            id,
+            result: Some(result),
+            error: None,
+        };
+        return Ok(connection.sender.send(Message::Response(result))?);
+    }
+
+    send_empty_resp(connection, id, config)
+}
+
+/// Handles full document semantic tokens requests
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Panics if JSON encoding of a response fails
+pub fn handle_semantic_tokens_full_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &SemanticTokensParams,
+    config: &Config,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+    names_to_info: &NameToInfoMaps,
+) -> Result<()> {
+    let uri = &params.text_document.uri;
+    if let Some(doc) = text_store.get_document(uri) {
+        if let Some(tree_entry) = tree_store.get_mut(uri) {
+            if let Some(tokens) = get_semantic_tokens(
+                doc.get_content(None),
+                tree_entry,
+                config,
+                &names_to_info.registers,
+            ) {
+                let resp = SemanticTokensResult::Tokens(tokens);
+                let result = serde_json::to_value(resp).unwrap();
+                let result = Response {
+                    id,
+                    result: Some(result),
+                    error: None,
+                };
+                return Ok(connection.sender.send(Message::Response(result))?);
+            }
+        }
+    }
+
+    send_empty_resp(connection, id, config)
+}
+
+/// Handles on type formatting requests
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Will panic if json serialization of the response fails
+pub fn handle_on_type_formatting_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &DocumentOnTypeFormattingParams,
+    config: &Config,
+    text_store: &TextDocuments,
+) -> Result<()> {
+    let uri = &params.text_document_position.text_document.uri;
+    if let Some(doc) = text_store.get_document(uri) {
+        if let Some(edits) = get_on_type_formatting_resp(doc.get_content(None), params, config) {
+            let result = serde_json::to_value(edits).unwrap();
+            let result = Response {
+                id,
+                result: Some(result),
+                error: None,
+            };
+            return Ok(connection.sender.send(Message::Response(result))?);
+        }
+    }
+
+    send_empty_resp(connection, id, config)
+}
+
 /// Handles signature help requests
 ///
 /// # Errors
@@ -209,7 +341,7 @@ pub fn handle_signature_help_request(
     config: &Config,
     text_store: &TextDocuments,
     tree_store: &mut TreeStore,
-    names_to_instructions: &NameToInstructionMap,
+    names_to_info: &NameToInfoMaps,
 ) -> Result<()> {
     let uri = &params.text_document_position_params.text_document.uri;
     if let Some(doc) = text_store.get_document(uri) {
@@ -218,7 +350,9 @@ pub fn handle_signature_help_request(
                 doc.get_content(None),
                 params,
                 tree_entry,
-                names_to_instructions,
+                &names_to_info.instructions,
+                &names_to_info.directives,
+                config,
             );
 
             if let Some(sig) = sig_resp {
@@ -253,11 +387,15 @@ pub fn handle_references_request(
     config: &Config,
     text_store: &TextDocuments,
     tree_store: &mut TreeStore,
+    workspace_index: Option<&WorkspaceIndex>,
 ) -> Result<()> {
     let uri = &params.text_document_position.text_document.uri;
     if let Some(doc) = text_store.get_document(uri) {
         if let Some(tree_entry) = tree_store.get_mut(uri) {
-            let ref_resp = get_ref_resp(params, doc, tree_entry);
+            let mut ref_resp = get_ref_resp(params, doc, tree_entry);
+            if let Some(index) = workspace_index {
+                ref_resp.extend(get_workspace_ref_resp(params, doc, index));
+            }
             if !ref_resp.is_empty() {
                 let result = serde_json::to_value(&ref_resp).unwrap();
 
@@ -274,6 +412,303 @@ pub fn handle_references_request(
     send_empty_resp(connection, id, config)
 }
 
+/// Handles prepare rename requests
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Will panic if json serialization of the response fails
+pub fn handle_prepare_rename_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &TextDocumentPositionParams,
+    config: &Config,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+) -> Result<()> {
+    if let Some(doc) = text_store.get_document(&params.text_document.uri) {
+        if let Some(tree_entry) = tree_store.get_mut(&params.text_document.uri) {
+            if let Some(range) = get_prepare_rename_resp(params, doc, tree_entry) {
+                let result = serde_json::to_value(range).unwrap();
+                let result = Response {
+                    id,
+                    result: Some(result),
+                    error: None,
+                };
+                return Ok(connection.sender.send(Message::Response(result))?);
+            }
+        }
+    }
+
+    send_empty_resp(connection, id, config)
+}
+
+/// Handles linked editing range requests
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Will panic if json serialization of the response fails
+pub fn handle_linked_editing_range_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &LinkedEditingRangeParams,
+    config: &Config,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+    names_to_info: &NameToInfoMaps,
+) -> Result<()> {
+    let pos_params = &params.text_document_position_params;
+    if let Some(doc) = text_store.get_document(&pos_params.text_document.uri) {
+        if let Some(tree_entry) = tree_store.get_mut(&pos_params.text_document.uri) {
+            if let Some(ranges) =
+                get_linked_editing_range_resp(pos_params, doc, tree_entry, config, names_to_info)
+            {
+                let result = serde_json::to_value(ranges).unwrap();
+                let result = Response {
+                    id,
+                    result: Some(result),
+                    error: None,
+                };
+                return Ok(connection.sender.send(Message::Response(result))?);
+            }
+        }
+    }
+
+    send_empty_resp(connection, id, config)
+}
+
+/// Handles rename requests, responding with an error the client can show if the
+/// rename isn't possible
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Will panic if json serialization of the response fails
+pub fn handle_rename_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &RenameParams,
+    config: &Config,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+    names_to_info: &NameToInfoMaps,
+) -> Result<()> {
+    let uri = &params.text_document_position.text_document.uri;
+    if let Some(doc) = text_store.get_document(uri) {
+        if let Some(tree_entry) = tree_store.get_mut(uri) {
+            let result = match get_rename_resp(params, doc, tree_entry, config, names_to_info) {
+                Ok(edit) => Response {
+                    id,
+                    result: Some(serde_json::to_value(edit).unwrap()),
+                    error: None,
+                },
+                Err(e) => Response::new_err(id, ErrorCode::RequestFailed as i32, e.to_string()),
+            };
+            return Ok(connection.sender.send(Message::Response(result))?);
+        }
+    }
+
+    send_empty_resp(connection, id, config)
+}
+
+/// Handles the custom `asm-lsp/listBackends` request
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Panics if JSON encoding of a response fails
+pub fn handle_list_backends_request(
+    connection: &Connection,
+    id: RequestId,
+    config: &Config,
+) -> Result<()> {
+    let result = serde_json::to_value(get_backends_resp(config)).unwrap();
+    let result = Response {
+        id,
+        result: Some(result),
+        error: None,
+    };
+
+    Ok(connection.sender.send(Message::Response(result))?)
+}
+
+/// Handles the custom `asm-lsp/instructionsWritingRegister` request
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Panics if JSON encoding of a response fails
+pub fn handle_instructions_writing_register_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &InstructionsWritingRegisterParams,
+    config: &Config,
+    names_to_info: &NameToInfoMaps,
+) -> Result<()> {
+    let result = match get_register_writers_resp(params, config, &names_to_info.instructions) {
+        Ok(writers) => Response {
+            id,
+            result: Some(serde_json::to_value(writers).unwrap()),
+            error: None,
+        },
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+    };
+
+    Ok(connection.sender.send(Message::Response(result))?)
+}
+
+/// Handles the custom `asm-lsp/explainRange` request
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Panics if JSON encoding of a response fails
+pub fn handle_explain_range_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &ExplainRangeParams,
+    config: &Config,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+    names_to_info: &NameToInfoMaps,
+) -> Result<()> {
+    let uri = &params.text_document.uri;
+    if let Some(doc) = text_store.get_document(uri) {
+        if let Some(tree_entry) = tree_store.get_mut(uri) {
+            if let Some(explanation) = get_explain_range_resp(
+                doc.get_content(None),
+                tree_entry,
+                params.range,
+                config,
+                &names_to_info.instructions,
+            ) {
+                let result = serde_json::to_value(explanation).unwrap();
+                let result = Response {
+                    id,
+                    result: Some(result),
+                    error: None,
+                };
+                return Ok(connection.sender.send(Message::Response(result))?);
+            }
+        }
+    }
+
+    send_empty_resp(connection, id, config)
+}
+
+/// Handles the custom `asm-lsp/documentOutline` request
+///
+/// A document with no symbols, or that can't be read, gets an empty outline
+///
+/// # Errors
+///
+/// Returns 'Err' if the response fails to send via `connection`
+///
+/// # Panics
+///
+/// Panics if JSON encoding of a response fails, or if the parser fails to set
+/// the language
+pub fn handle_document_outline_request(
+    connection: &Connection,
+    id: RequestId,
+    params: &DocumentOutlineParams,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+) -> Result<()> {
+    let uri = &params.text_document.uri;
+    let outline = if let (Some(doc), Some(tree_entry)) =
+        (text_store.get_document(uri), tree_store.get_mut(uri))
+    {
+        get_document_outline(doc.get_content(None), tree_entry, params)
+    } else {
+        // the document isn't open, so there's nothing to reuse
+        let text = uri_to_path(uri)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        get_document_outline(&text, &mut tree_entry, params)
+    };
+
+    let result = serde_json::to_value(outline).unwrap();
+    let result = Response {
+        id,
+        result: Some(result),
+        error: None,
+    };
+
+    Ok(connection.sender.send(Message::Response(result))?)
+}
+
+/// Handles the custom `asm-lsp/assembleProject` request
+///
+/// Assembling every file in the project can take a while, so it's done on a
+/// worker thread that sends the response once it's finished, leaving the main
+/// loop free to service other requests in the meantime
+///
+/// # Errors
+///
+/// Returns 'Err' if the worker thread fails to spawn
+///
+/// # Panics
+///
+/// Panics if JSON encoding of a response fails
+pub fn handle_assemble_project_request(
+    connection: &Connection,
+    id: RequestId,
+    config: &Config,
+    compile_cmds: &CompilationDatabase,
+) -> Result<()> {
+    let sender = connection.sender.clone();
+    let config = config.clone();
+    let compile_cmds = compile_cmds.clone();
+    std::thread::Builder::new()
+        .name("assemble-project".to_string())
+        .spawn(move || {
+            let start = std::time::Instant::now();
+            let result =
+                serde_json::to_value(get_assemble_project_resp(&config, &compile_cmds)).unwrap();
+            let result = Response {
+                id,
+                result: Some(result),
+                error: None,
+            };
+            if let Err(e) = sender.send(Message::Response(result)) {
+                error!("Failed to send assemble project response - Error: {e}");
+            }
+            info!(
+                "Assemble project request serviced in {}ms",
+                start.elapsed().as_millis()
+            );
+        })?;
+
+    Ok(())
+}
+
 /// Produces diagnostics and sends a `PublishDiagnostics` notification to the client
 /// Diagnostics are only produced for the file specified by `uri`
 ///
@@ -290,32 +725,45 @@ pub fn handle_diagnostics(
     cfg: &Config,
     compile_cmds: &CompilationDatabase,
 ) -> Result<()> {
-    let req_source_path = PathBuf::from(uri.path().as_str());
-
-    let source_entries = compile_cmds.iter().filter(|entry| match entry.file {
-        SourceFile::File(ref file) => {
-            if file.is_absolute() {
-                file.eq(&req_source_path)
-            } else if let Ok(source_path) = file.canonicalize() {
-                source_path.eq(&req_source_path)
-            } else {
-                false
-            }
+    // Diagnostics are produced by running external tools against the file on
+    // disk, so there's nothing we can do for scratch buffers or remote documents
+    let Some(req_source_path) = uri_to_path(uri) else {
+        info!("Skipping diagnostics for non-file URI {}", uri.as_str());
+        return Ok(());
+    };
+
+    // Diagnostics can be muted for a file by its modeline, in which case any
+    // previously published for it are cleared
+    if cfg.disabled || !cfg.opts.diagnostics.unwrap_or(true) {
+        info!("Diagnostics are disabled for {}", uri.as_str());
+        return publish_diagnostics(connection, uri, Vec::new());
+    }
+
+    // Files opened without a project root can still have compile commands in the
+    // root found for them (see `get_root_fallback_config`)
+    let root_cmds;
+    let compile_cmds = match cfg.project_root {
+        Some(ref root) if compile_cmds.is_empty() => {
+            root_cmds = get_root_compile_cmds(root).unwrap_or_default();
+            &root_cmds
         }
-        SourceFile::All => true,
-    });
+        _ => compile_cmds,
+    };
 
     let mut has_entries = false;
     let mut diagnostics: Vec<Diagnostic> = Vec::new();
-    for entry in source_entries {
+    for entry in get_file_compile_cmds(compile_cmds, &req_source_path) {
         has_entries = true;
         apply_compile_cmd(cfg, &mut diagnostics, uri, entry);
     }
 
-    // If no user-provided entries corresponded to the file, just try out
-    // invoking the user-provided compiler (if they gave one), or alternatively
-    // gcc (and clang if that fails) with the source file path as the only argument
-    if !has_entries && cfg.opts.default_diagnostics.unwrap_or(false) {
+    // If no user-provided entries corresponded to the file, just try out the
+    // user's diagnostics command template or compiler (if they gave one), or
+    // alternatively gcc (and clang if that fails) with the source file path as
+    // the only argument
+    if !has_entries
+        && (cfg.opts.diagnostics_command.is_some() || cfg.opts.default_diagnostics.unwrap_or(false))
+    {
         info!(
             "No applicable user-provided commands for {}. Applying default compile command",
             uri.path().as_str()
@@ -328,6 +776,35 @@ pub fn handle_diagnostics(
         );
     }
 
+    apply_linters(cfg, &mut diagnostics, uri);
+
+    let show_context = cfg.opts.diagnostics_context.unwrap_or(false);
+    let is_arm = cfg.instruction_sets.arm.unwrap_or(false);
+    let check_syntax = cfg.opts.syntax_diagnostics.unwrap_or(false);
+    if show_context || is_arm || check_syntax {
+        if let Ok(source) = std::fs::read_to_string(&req_source_path) {
+            if is_arm {
+                diagnostics.append(&mut get_arm_state_diagnostics(&source));
+            }
+            if check_syntax {
+                diagnostics.append(&mut get_syntax_diagnostics(&source));
+            }
+            if show_context {
+                add_diagnostics_context(&mut diagnostics, &source);
+            }
+        }
+    }
+
+    dedup_diagnostics(&mut diagnostics);
+    publish_diagnostics(connection, uri, diagnostics)
+}
+
+/// Sends `diagnostics` for `uri` to the client, replacing any it was sent before
+fn publish_diagnostics(
+    connection: &Connection,
+    uri: &Uri,
+    diagnostics: Vec<Diagnostic>,
+) -> Result<()> {
     let params = PublishDiagnosticsParams {
         uri: uri.clone(),
         diagnostics,
@@ -354,21 +831,43 @@ pub fn handle_diagnostics(
 /// fails to set the language
 pub fn handle_did_open_text_document_notification(
     params: &DidOpenTextDocumentParams,
+    config: &Config,
     text_store: &mut TextDocuments,
     tree_store: &mut TreeStore,
+    closed_trees: &mut ClosedTrees,
+    config_overrides: &mut ConfigOverrides,
+    compile_cmd_overrides: &ConfigOverrides,
+    root_configs: &mut ConfigOverrides,
 ) {
     let raw_params = serde_json::to_value(params).unwrap();
     text_store.listen(DidOpenTextDocument::METHOD, &raw_params);
-
-    let mut parser = Parser::new();
-    parser.set_language(&tree_sitter_asm::language()).unwrap();
-    tree_store.insert(
-        params.text_document.uri.clone(),
-        TreeEntry {
-            tree: parser.parse(&params.text_document.text, None),
-            parser,
-        },
+    let uri = &params.text_document.uri;
+    // the project root is only looked for once, rather than on every change
+    match get_root_fallback_config(uri, config) {
+        Some(root_config) => _ = root_configs.insert(uri.clone(), root_config),
+        None => _ = root_configs.remove(uri),
+    }
+    update_config_override(
+        uri,
+        config,
+        text_store,
+        config_overrides,
+        compile_cmd_overrides,
+        root_configs,
     );
+
+    let tree_entry = closed_trees
+        .take(uri, &params.text_document.text)
+        .unwrap_or_else(|| {
+            let mut parser = Parser::new();
+            parser.set_language(&asm_language()).unwrap();
+            TreeEntry {
+                tree: parser.parse(&params.text_document.text, None),
+                parser,
+                symbols: None,
+            }
+        });
+    tree_store.insert(uri.clone(), tree_entry);
 }
 
 /// Handles did change text document notifications
@@ -384,29 +883,53 @@ pub fn handle_did_open_text_document_notification(
 /// Panics if JSON encoding of a response fails
 pub fn handle_did_change_text_document_notification(
     params: &DidChangeTextDocumentParams,
+    config: &Config,
     text_store: &mut TextDocuments,
     tree_store: &mut TreeStore,
+    config_overrides: &mut ConfigOverrides,
+    compile_cmd_overrides: &ConfigOverrides,
+    root_configs: &ConfigOverrides,
 ) -> Result<()> {
-    let raw_params = serde_json::to_value(params).unwrap();
-    text_store.listen(DidChangeTextDocument::METHOD, &raw_params);
-
     let uri = &params.text_document.uri;
-    if let Some(ref mut doc) = text_store.get_document(uri) {
-        if let Some(tree_entry) = tree_store.get_mut(uri) {
-            if let Some(ref mut curr_tree) = tree_entry.tree {
-                for change in &params.content_changes {
-                    match text_doc_change_to_ts_edit(change, doc) {
-                        Ok(edit) => {
-                            curr_tree.edit(&edit);
-                        }
-                        Err(e) => {
-                            return Err(anyhow!("Bad edit info, failed to edit tree - Error: {e}"));
-                        }
+    // Each change is relative to the document as left by the previous one, so
+    // the tree has to be edited against the document's contents *before* the
+    // change is applied. Otherwise the tree's byte ranges drift out of sync with
+    // the document, and subsequent queries can report nodes past its end
+    for change in &params.content_changes {
+        if let (Some(doc), Some(tree_entry)) =
+            (text_store.get_document(uri), tree_store.get_mut(uri))
+        {
+            tree_entry.symbols = None;
+            if change.range.is_none() {
+                // full document sync, nothing from the old tree can be reused
+                tree_entry.tree = None;
+            } else if let Some(ref mut curr_tree) = tree_entry.tree {
+                match text_doc_change_to_ts_edit(change, doc) {
+                    Ok(edit) => {
+                        curr_tree.edit(&edit);
+                    }
+                    Err(e) => {
+                        return Err(anyhow!("Bad edit info, failed to edit tree - Error: {e}"));
                     }
                 }
             }
         }
+
+        let single_change = DidChangeTextDocumentParams {
+            text_document: params.text_document.clone(),
+            content_changes: vec![change.clone()],
+        };
+        let raw_params = serde_json::to_value(single_change).unwrap();
+        text_store.listen(DidChangeTextDocument::METHOD, &raw_params);
     }
+    update_config_override(
+        uri,
+        config,
+        text_store,
+        config_overrides,
+        compile_cmd_overrides,
+        root_configs,
+    );
 
     Ok(())
 }
@@ -418,10 +941,121 @@ pub fn handle_did_change_text_document_notification(
 /// Panics if JSON encoding of `params` fails
 pub fn handle_did_close_text_document_notification(
     params: &DidCloseTextDocumentParams,
+    config: &Config,
     text_store: &mut TextDocuments,
     tree_store: &mut TreeStore,
+    closed_trees: &mut ClosedTrees,
+    config_overrides: &mut ConfigOverrides,
+    root_configs: &mut ConfigOverrides,
 ) {
+    let uri = &params.text_document.uri;
+    // Keep the tree around in case the document is reopened unchanged
+    let capacity = config
+        .get_config(uri, config_overrides)
+        .opts
+        .keep_closed_trees
+        .unwrap_or(0);
+    if let (Some(doc), Some(tree_entry)) = (text_store.get_document(uri), tree_store.remove(uri)) {
+        if capacity > 0 {
+            let text = doc.get_content(None).to_string();
+            closed_trees.insert(uri.clone(), text, tree_entry, capacity);
+        }
+    }
+
     let raw_params = serde_json::to_value(params).unwrap();
     text_store.listen(DidCloseTextDocument::METHOD, &raw_params);
-    tree_store.remove(&params.text_document.uri);
+    config_overrides.remove(uri);
+    root_configs.remove(uri);
+}
+
+/// Handles did change configuration notifications, applying the client's settings
+/// to `config` and the per-file configs derived from it
+///
+/// Returns `true` if the enabled instruction sets, assemblers, or `include_dirs`
+/// changed, in which case the documentation loaded for them (and the completion
+/// items derived from it, like the GAS suffixed and deprecated instructions) or
+/// the include search paths need to be rebuilt
+pub fn handle_did_change_configuration_notification(
+    params: &DidChangeConfigurationParams,
+    config: &mut Config,
+    text_store: &TextDocuments,
+    config_overrides: &mut ConfigOverrides,
+    compile_cmd_overrides: &mut ConfigOverrides,
+    root_configs: &mut ConfigOverrides,
+) -> bool {
+    let new_config = match apply_config_settings(config, &params.settings) {
+        Ok(new_config) => new_config,
+        Err(e) => {
+            error!("Failed to apply configuration settings - Error: {e}");
+            return false;
+        }
+    };
+    info!("Updated Server Configuration: {:?}", new_config);
+    let needs_reload = new_config.instruction_sets != config.instruction_sets
+        || new_config.assemblers != config.assemblers
+        || new_config.opts.include_dirs != config.opts.include_dirs;
+
+    // the settings go on top of each file's override, which keeps the assembler
+    // detected from its compile command
+    for file_config in compile_cmd_overrides.values_mut() {
+        match apply_config_settings(file_config, &params.settings) {
+            Ok(mut new_file_config) => {
+                new_file_config.assemblers = file_config.assemblers.clone();
+                *file_config = new_file_config;
+            }
+            Err(e) => error!("Failed to apply configuration settings - Error: {e}"),
+        }
+    }
+    *config = new_config;
+    for uri in text_store.documents().keys() {
+        // the fallback configs are derived from `config`, so they're stale now
+        match get_root_fallback_config(uri, config) {
+            Some(root_config) => _ = root_configs.insert(uri.clone(), root_config),
+            None => _ = root_configs.remove(uri),
+        }
+        update_config_override(
+            uri,
+            config,
+            text_store,
+            config_overrides,
+            compile_cmd_overrides,
+            root_configs,
+        );
+    }
+
+    needs_reload
+}
+
+/// Sets or clears the config override for `uri` according to the modeline in
+/// its current contents, if any, falling back to the assembler implied by its
+/// compile command (see `get_compile_cmd_overrides`), then to its entry in
+/// `root_configs` (see `get_root_fallback_config`)
+fn update_config_override(
+    uri: &Uri,
+    config: &Config,
+    text_store: &TextDocuments,
+    config_overrides: &mut ConfigOverrides,
+    compile_cmd_overrides: &ConfigOverrides,
+    root_configs: &ConfigOverrides,
+) {
+    // compile command overrides are keyed by canonical path, so the file is found
+    // even if the client opened it through a symlink
+    let cmd_config = compile_cmd_overrides
+        .get(uri)
+        .or_else(|| compile_cmd_overrides.get(&canonicalize_uri(uri)?));
+    let root_config = root_configs.get(uri);
+    let base_config = cmd_config.or(root_config).unwrap_or(config);
+    match text_store
+        .get_document(uri)
+        .and_then(|doc| get_modeline_config(doc.get_content(None), base_config))
+        .or_else(|| cmd_config.cloned())
+        .or_else(|| root_config.cloned())
+    {
+        Some(file_config) => {
+            config_overrides.insert(uri.clone(), file_config);
+        }
+        None => {
+            config_overrides.remove(uri);
+        }
+    }
 }
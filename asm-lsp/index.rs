@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use lsp_types::{Location, SymbolInformation, SymbolKind, Uri};
+use tree_sitter::Parser;
+
+use crate::{
+    asm_language, canonicalize_uri, get_tree_symbols, path_to_uri, uri_to_path, SymbolIndex,
+};
+
+/// File extensions of the sources indexed by a `WorkspaceIndex`
+const INDEXED_EXTENSIONS: &[&str] = &["s", "S", "sx", "asm", "inc"];
+
+/// Directories left out of a `WorkspaceIndex`, along with hidden ones. They hold
+/// build outputs and dependencies rather than the project's own sources
+const SKIPPED_DIRS: &[&str] = &["target", "build", "node_modules", "vendor", "third_party"];
+
+/// The most files a `WorkspaceIndex` holds, so a huge tree can't stall startup
+const MAX_INDEXED_FILES: usize = 5000;
+
+/// Files larger than this many bytes are left out of a `WorkspaceIndex`, they're
+/// usually generated
+const MAX_INDEXED_FILE_SIZE: u64 = 1 << 20;
+
+/// The labels declared in and identifiers referenced by the assembly source files
+/// under a project's root, for requests that span files
+///
+/// A file's entries are replaced as a whole when it's saved, so an edit never
+/// requires indexing the other files again.
+#[derive(Default)]
+pub struct WorkspaceIndex {
+    root: PathBuf,
+    /// The symbols of each indexed file, by its canonical URI
+    files: HashMap<Uri, SymbolIndex>,
+    /// Where each label is declared, across all indexed files
+    definitions: HashMap<String, Vec<Location>>,
+}
+
+/// Returns `true` if `path` has the extension of an assembly source file
+fn is_indexed_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| INDEXED_EXTENSIONS.iter().any(|asm_ext| ext == *asm_ext))
+}
+
+impl WorkspaceIndex {
+    /// Indexes the assembly source files under `root`, skipping hidden directories
+    /// and those in `SKIPPED_DIRS`, and stopping once `MAX_INDEXED_FILES` have
+    /// been indexed
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the tree-sitter grammar fails to load
+    #[must_use]
+    pub fn build(root: &Path) -> Self {
+        let start = std::time::Instant::now();
+        let mut index = Self {
+            root: root.to_path_buf(),
+            ..Self::default()
+        };
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+
+        let mut dirs = vec![root.to_path_buf()];
+        'walk: while let Some(dir) = dirs.pop() {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    warn!("Failed to index {} - Error {e}", dir.display());
+                    continue;
+                }
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                // symlinked directories aren't followed, they could form a cycle
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_ref()) {
+                        dirs.push(path);
+                    }
+                    continue;
+                }
+                if !is_indexed_file(&path)
+                    || entry
+                        .metadata()
+                        .is_ok_and(|metadata| metadata.len() > MAX_INDEXED_FILE_SIZE)
+                {
+                    continue;
+                }
+                if index.files.len() >= MAX_INDEXED_FILES {
+                    warn!(
+                        "Stopped indexing {} after {MAX_INDEXED_FILES} files",
+                        root.display()
+                    );
+                    break 'walk;
+                }
+                // keyed like `update_file`, which gets URIs from the client
+                let uri = path.canonicalize().ok().and_then(|path| path_to_uri(&path));
+                if let (Ok(text), Some(uri)) = (std::fs::read_to_string(&path), uri) {
+                    index.insert_file(uri, &text, &mut parser);
+                }
+            }
+        }
+
+        info!(
+            "Indexed {} files under {} in {}ms",
+            index.files.len(),
+            root.display(),
+            start.elapsed().as_millis()
+        );
+        index
+    }
+
+    /// Indexes `uri` again from its saved contents `text`, if it's an assembly
+    /// source file under the project root
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the tree-sitter grammar fails to load
+    pub fn update_file(&mut self, uri: &Uri, text: &str) {
+        let uri = canonicalize_uri(uri).unwrap_or_else(|| uri.clone());
+        let is_indexed = uri_to_path(&uri)
+            .is_some_and(|path| path.starts_with(&self.root) && is_indexed_file(&path));
+        if !is_indexed || (!self.files.contains_key(&uri) && self.files.len() >= MAX_INDEXED_FILES)
+        {
+            return;
+        }
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        self.insert_file(uri, text, &mut parser);
+    }
+
+    /// Replaces the entries for `uri` with the symbols of `text`
+    fn insert_file(&mut self, uri: Uri, text: &str, parser: &mut Parser) {
+        self.remove_file(&uri);
+        let Some(tree) = parser.parse(text, None) else {
+            return;
+        };
+        let symbols = get_tree_symbols(text.as_bytes(), &tree, 0);
+        // local labels, e.g. NASM's `.loop` or GAS's `.L1`, can't be referenced
+        // from other files
+        for (name, range) in symbols
+            .labels
+            .iter()
+            .filter(|(name, _)| !name.starts_with('.'))
+        {
+            self.definitions
+                .entry(name.clone())
+                .or_default()
+                .push(Location {
+                    uri: uri.clone(),
+                    range: *range,
+                });
+        }
+        self.files.insert(uri, symbols);
+    }
+
+    /// Drops the entries for `uri`
+    fn remove_file(&mut self, uri: &Uri) {
+        let Some(symbols) = self.files.remove(uri) else {
+            return;
+        };
+        for (name, _) in &symbols.labels {
+            if let Some(locations) = self.definitions.get_mut(name) {
+                locations.retain(|location| location.uri != *uri);
+                if locations.is_empty() {
+                    self.definitions.remove(name);
+                }
+            }
+        }
+    }
+
+    /// Returns where the label `name` is declared, outside of `exclude`, which is
+    /// the open document whose own symbols are used instead
+    #[must_use]
+    pub fn get_definitions(&self, name: &str, exclude: &Uri) -> Vec<Location> {
+        let exclude = canonicalize_uri(exclude).unwrap_or_else(|| exclude.clone());
+        self.definitions
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(|location| location.uri != exclude)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the references to `name` outside of `exclude`, including its
+    /// declarations if `include_declaration` is set
+    #[must_use]
+    pub fn get_references(
+        &self,
+        name: &str,
+        include_declaration: bool,
+        exclude: &Uri,
+    ) -> Vec<Location> {
+        let exclude = canonicalize_uri(exclude).unwrap_or_else(|| exclude.clone());
+        self.files
+            .iter()
+            .filter(|(uri, _)| **uri != exclude)
+            .flat_map(|(uri, symbols)| {
+                symbols
+                    .idents
+                    .iter()
+                    .filter(|ident| ident.name == name)
+                    .filter(|ident| !ident.is_decl || include_declaration)
+                    .map(|ident| Location {
+                        uri: uri.clone(),
+                        range: ident.range,
+                    })
+            })
+            .collect()
+    }
+
+    /// Returns the labels whose names contain `query`, ignoring case, sorted by
+    /// name. Every label matches an empty query
+    #[must_use]
+    pub fn get_symbols(&self, query: &str) -> Vec<SymbolInformation> {
+        let query = query.to_lowercase();
+        let mut names: Vec<&String> = self
+            .definitions
+            .keys()
+            .filter(|name| name.to_lowercase().contains(&query))
+            .collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .flat_map(|name| {
+                self.definitions[name].iter().map(|location| {
+                    #[allow(deprecated)]
+                    SymbolInformation {
+                        name: name.clone(),
+                        kind: SymbolKind::FUNCTION,
+                        tags: None,
+                        deprecated: None,
+                        location: location.clone(),
+                        container_name: None,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the number of indexed files
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
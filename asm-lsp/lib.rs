@@ -1,13 +1,18 @@
 pub mod handle;
+pub mod index;
 pub mod lsp;
 pub mod parser;
+pub mod state;
+pub mod syscalls;
 mod test;
 pub mod types;
 pub mod ustr;
 
+pub use index::WorkspaceIndex;
 pub use lsp::*;
 pub use parser::{
     populate_gas_directives, populate_instructions, populate_name_to_directive_map,
     populate_name_to_instruction_map, populate_name_to_register_map, populate_registers,
 };
+pub use state::{LoadedDocs, ServerState};
 pub use types::*;
@@ -1,11 +1,13 @@
 use crate::ustr;
 use std::collections::{HashMap, HashSet};
-use std::convert::TryFrom;
+use std::ffi::OsStr;
 use std::fs::{create_dir_all, File};
 use std::io::BufRead;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 use anyhow::{anyhow, Result};
 use compile_commands::{CompilationDatabase, CompileArgs, CompileCommand, SourceFile};
@@ -14,23 +16,34 @@ use log::{error, info, log, log_enabled, warn};
 use lsp_server::{Connection, Message, RequestId, Response};
 use lsp_textdocument::{FullTextDocument, TextDocuments};
 use lsp_types::{
-    CompletionItem, CompletionItemKind, CompletionList, CompletionParams, CompletionTriggerKind,
-    Diagnostic, DocumentSymbol, DocumentSymbolParams, Documentation, GotoDefinitionParams,
-    GotoDefinitionResponse, Hover, HoverContents, HoverParams, InitializeParams, Location,
-    MarkupContent, MarkupKind, Position, Range, ReferenceParams, SignatureHelp,
-    SignatureHelpParams, SignatureInformation, SymbolKind, TextDocumentContentChangeEvent,
-    TextDocumentPositionParams, Uri,
+    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionItemTag,
+    CompletionList, CompletionParams, CompletionTextEdit, CompletionTriggerKind, Diagnostic,
+    DiagnosticSeverity, DocumentOnTypeFormattingParams, DocumentSymbol, DocumentSymbolParams,
+    Documentation, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    InitializeParams, LinkedEditingRanges, Location, MarkupContent, MarkupKind,
+    ParameterInformation, ParameterLabel, PartialResultParams, Position, Range, ReferenceParams,
+    RenameParams, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens,
+    SemanticTokensLegend, SignatureHelp, SignatureHelpParams, SignatureInformation, SymbolKind,
+    TextDocumentContentChangeEvent, TextDocumentPositionParams, TextEdit, Uri,
+    WorkDoneProgressParams, WorkspaceEdit,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Deserialize;
+use strum::IntoEnumIterator;
 use symbolic::common::{Language, Name, NameMangling};
 use symbolic_demangle::{Demangle, DemangleOptions};
-use tree_sitter::InputEdit;
+use tree_sitter::{InputEdit, Tree};
 
-use crate::types::Column;
+use crate::types::{parse_arch_name, Column};
 use crate::{
-    Arch, ArchOrAssembler, Assembler, Completable, Config, Hoverable, Instruction, LspClient,
-    NameToInstructionMap, TreeEntry, TreeStore,
+    Arch, ArchOrAssembler, ArmState, AssembleProjectResult, Assembler, Assemblers, BackendInfo,
+    Completable, CompletionData, Config, ConfigOverrides, DemangleStyle, Directive,
+    DocumentOutlineParams, DocumentOutlineResult, Hoverable, IdentRef, Instruction,
+    InstructionForm, InstructionSets, InstructionsWritingRegisterParams, ListBackendsResult,
+    LspClient, NameToDirectiveMap, NameToInfoMaps, NameToInstructionMap, NameToRegisterMap,
+    NasmStruc, NasmStrucMember, OperandType, OutlineSymbol, OutlineSymbolKind, RegisterType,
+    RegisterWriter, SymbolIndex, TreeEntry, TreeStore, WorkspaceIndex,
 };
 
 /// Sends an empty, non-error response to the lsp client via `connection`
@@ -46,47 +59,158 @@ pub fn send_empty_resp(connection: &Connection, id: RequestId, config: &Config)
     };
 
     // Helix shuts the server down when the above empty response is sent,
-    // so send nothing in its case
-    if config.client == Some(LspClient::Helix) {
+    // so send nothing in its case. The client name can't always be relied on
+    // (e.g. behind a proxy), so users can also choose explicitly
+    let suppress = config
+        .opts
+        .suppress_empty_responses
+        .unwrap_or_else(|| config.client == Some(LspClient::Helix));
+    if suppress {
         Ok(())
     } else {
         Ok(connection.sender.send(Message::Response(empty_resp))?)
     }
 }
 
-/// Find the ([start], [end]) indices and the cursor's offset in a word
+/// Returns the local filesystem path backing `uri`, or `None` if `uri` doesn't
+/// use the `file` scheme (e.g. `untitled:` scratch buffers or remote documents)
+#[must_use]
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    if !uri.scheme()?.eq_lowercase("file") {
+        return None;
+    }
+    let path = uri.path().as_estr().decode().into_string_lossy();
+    // file:///C:/foo -> C:/foo
+    if cfg!(windows) {
+        let trimmed = path.trim_start_matches('/');
+        if trimmed.as_bytes().get(1) == Some(&b':') {
+            return Some(PathBuf::from(trimmed));
+        }
+    }
+
+    Some(PathBuf::from(path.as_ref()))
+}
+
+/// Returns a `file` URI for the local filesystem `path`
+#[must_use]
+pub fn path_to_uri(path: &Path) -> Option<Uri> {
+    let path = path.to_str()?;
+    // C:\foo -> /C:/foo
+    let path = if cfg!(windows) {
+        format!("/{}", path.replace('\\', "/"))
+    } else {
+        path.to_string()
+    };
+    Uri::from_str(&format!("file://{}", url_escape::encode_path(&path))).ok()
+}
+
+/// Returns the `file` URI of the canonical path of `uri`, resolving symlinks so
+/// that two URIs naming the same file compare equal
+#[must_use]
+pub fn canonicalize_uri(uri: &Uri) -> Option<Uri> {
+    let path = uri_to_path(uri)?.canonicalize().ok()?;
+    path_to_uri(&path)
+}
+
+/// Converts `character`, an LSP column counted in UTF-16 code units, into a byte
+/// offset into `line`
+///
+/// Tabs and other BMP characters are a single code unit, but astral characters
+/// (e.g. emoji in comments) take two, and anything outside ASCII takes more than
+/// one byte. Offsets past the end of the line are clamped to `line.len()`
+#[must_use]
+pub fn utf16_col_to_byte_col(line: &str, character: u32) -> Column {
+    let mut utf16_col = 0;
+    for (idx, c) in line.char_indices() {
+        if utf16_col >= character as usize {
+            return idx;
+        }
+        utf16_col += c.len_utf16();
+    }
+
+    line.len()
+}
+
+/// Converts `col`, a byte offset into `line`, into an LSP column counted in UTF-16
+/// code units, the reverse of `utf16_col_to_byte_col`
+///
+/// Offsets past the end of the line are clamped to its length
+#[must_use]
+pub fn byte_col_to_utf16_col(line: &str, col: Column) -> u32 {
+    let utf16_col = line
+        .char_indices()
+        .take_while(|(idx, _)| *idx < col)
+        .map(|(_, c)| c.len_utf16())
+        .sum::<usize>();
+
+    u32::try_from(utf16_col).unwrap_or(u32::MAX)
+}
+
+/// Returns the tree-sitter grammar used to parse assembly documents
+///
+/// Every parser and query goes through here, so this is the only place to change
+/// to try out another build of the grammar, e.g. a local checkout of
+/// `tree-sitter-asm` patched in with `[patch.crates-io]`.
+///
+/// The queries depend on these node kinds and fields, which a replacement grammar
+/// needs to keep:
+///
+/// - `instruction`, with its mnemonic as a `word` in the `kind` field
+/// - `meta` (directives), with its name as a `meta_ident` in the `kind` field
+/// - `label`, containing an `ident`
+/// - `ident`, `reg`, `ptr`, `int`, `float`, and `string` operands, where labels
+///   used as operands are parsed as `(ident (reg))`
+#[must_use]
+pub fn asm_language() -> tree_sitter::Language {
+    tree_sitter_asm::language()
+}
+
+/// Converts an LSP `Position` into a tree-sitter `Point`, whose column is a byte
+/// offset into the line
+fn position_to_point(curr_doc: &str, pos: Position) -> tree_sitter::Point {
+    let line = curr_doc.lines().nth(pos.line as usize).unwrap_or_default();
+    tree_sitter::Point {
+        row: pos.line as usize,
+        column: utf16_col_to_byte_col(line, pos.character),
+    }
+}
+
+/// Find the ([start], [end]) byte indices and the cursor's byte offset in a word
 /// on the given line
 ///
+/// `col` is the cursor's LSP column, in UTF-16 code units
+///
 /// Borrowed from RLS
 /// characters besides the default alphanumeric and '_'
 #[must_use]
 pub fn find_word_at_pos(line: &str, col: Column) -> ((Column, Column), usize) {
-    let line_ = format!("{line} ");
     let is_ident_char = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let mut col = utf16_col_to_byte_col(line, u32::try_from(col).unwrap_or(u32::MAX));
+    // The `%` of a GAS register or NASM directive, e.g. `%rax`, belongs to the name
+    // after it
+    if line[col..].starts_with('%') && line[col + 1..].starts_with(is_ident_char) {
+        col += 1;
+    }
 
-    let start = line_
-        .chars()
-        .enumerate()
-        .take(col)
-        .filter(|&(_, c)| !is_ident_char(c))
-        .last()
-        .map_or(0, |(i, _)| i + 1);
+    let start = line[..col]
+        .char_indices()
+        .rfind(|&(_, c)| !is_ident_char(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
 
-    let mut end = line_
-        .chars()
-        .enumerate()
-        .skip(col)
-        .filter(|&(_, c)| !is_ident_char(c));
+    let end = line[col..]
+        .char_indices()
+        .find(|&(_, c)| !is_ident_char(c))
+        .map_or(line.len(), |(i, _)| col + i);
 
-    let end = end.next();
-    ((start, end.map_or(col, |(i, _)| i)), col - start)
+    ((start, end), col - start)
 }
 
 /// Returns the word undernearth the cursor given the specified `TextDocumentPositionParams`
 ///
 /// # Errors
 ///
-/// Will return `Err` if the file cannot be opened
+/// Will return `Err` if the file cannot be opened, or if `uri` doesn't refer
+/// to a local file
 ///
 /// # Panics
 ///
@@ -97,7 +221,9 @@ pub fn get_word_from_file_params(pos_params: &TextDocumentPositionParams) -> Res
     let line = pos_params.position.line as usize;
     let col = pos_params.position.character as usize;
 
-    let filepath = PathBuf::from(uri.as_str());
+    let Some(filepath) = uri_to_path(uri) else {
+        return Err(anyhow!("Not a local file -> {}", uri.as_str()));
+    };
     match filepath.canonicalize() {
         Ok(file) => {
             let file = match File::open(file) {
@@ -121,7 +247,19 @@ pub fn get_word_from_pos_params<'a>(
     doc: &'a FullTextDocument,
     pos_params: &TextDocumentPositionParams,
 ) -> (&'a str, usize) {
-    let line_contents = doc.get_content(Some(Range {
+    let line_contents = get_line_from_pos_params(doc, pos_params);
+    let ((word_start, word_end), cursor_offset) =
+        find_word_at_pos(line_contents, pos_params.position.character as usize);
+    (&line_contents[word_start..word_end], cursor_offset)
+}
+
+/// Returns the contents of the line the cursor is on given the specified
+/// `TextDocumentPositionParams`
+fn get_line_from_pos_params<'a>(
+    doc: &'a FullTextDocument,
+    pos_params: &TextDocumentPositionParams,
+) -> &'a str {
+    doc.get_content(Some(Range {
         start: Position {
             line: pos_params.position.line,
             character: 0,
@@ -130,11 +268,7 @@ pub fn get_word_from_pos_params<'a>(
             line: pos_params.position.line,
             character: u32::MAX,
         },
-    }));
-
-    let ((word_start, word_end), cursor_offset) =
-        find_word_at_pos(line_contents, pos_params.position.character as usize);
-    (&line_contents[word_start..word_end], cursor_offset)
+    }))
 }
 
 /// Fetches default include directories, as well as any additional directories
@@ -145,9 +279,17 @@ pub fn get_word_from_pos_params<'a>(
 #[must_use]
 pub fn get_include_dirs(compile_cmds: &CompilationDatabase) -> HashMap<SourceFile, Vec<PathBuf>> {
     let mut include_map = HashMap::from([(SourceFile::All, Vec::new())]);
+    let mut dialect_args = get_dialect_args_by_entry(compile_cmds);
 
+    // the language and standard flags of an entry targeting every file (e.g. from
+    // `compile_flags.txt`) replace the default C/C++ probe
+    let global_dialect_args = dialect_args
+        .iter()
+        .position(|(source_file, _)| *source_file == SourceFile::All)
+        .map(|idx| dialect_args.swap_remove(idx).1)
+        .unwrap_or_default();
     let global_dirs = include_map.get_mut(&SourceFile::All).unwrap();
-    for dir in get_default_include_dirs() {
+    for dir in get_default_include_dirs(&global_dialect_args) {
         global_dirs.push(dir);
     }
 
@@ -158,59 +300,307 @@ pub fn get_include_dirs(compile_cmds: &CompilationDatabase) -> HashMap<SourceFil
             .or_insert_with(|| vec![dir.to_owned()]);
     }
 
+    // files compiled with a specific language or standard can have a different
+    // set of system include directories, so probe again with their flags
+    let mut probed: HashMap<Vec<String>, Vec<PathBuf>> = HashMap::new();
+    for (source_file, dialect_args) in dialect_args {
+        let dialect_dirs = probed
+            .entry(dialect_args)
+            .or_insert_with_key(|dialect_args| get_default_include_dirs(dialect_args));
+        let global_dirs = include_map[&SourceFile::All].clone();
+        let dirs = include_map.entry(source_file).or_default();
+        for dir in dialect_dirs.iter() {
+            if !global_dirs.contains(dir) && !dirs.contains(dir) {
+                dirs.push(dir.clone());
+            }
+        }
+    }
+
     info!("Include directory map: {:?}", include_map);
 
     include_map
 }
 
+/// Returns the user's configured `include_dirs`, with relative paths resolved
+/// against the project root. Directories that don't exist are skipped with a
+/// warning
+#[must_use]
+pub fn get_config_include_dirs(config: &Config) -> Vec<PathBuf> {
+    let Some(ref dirs) = config.opts.include_dirs else {
+        return Vec::new();
+    };
+
+    dirs.iter()
+        .filter_map(|dir| {
+            let full_path = match config.project_root {
+                Some(ref root) if dir.is_relative() => root.join(dir),
+                _ => dir.clone(),
+            };
+            match full_path.canonicalize() {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    warn!(
+                        "Skipping include directory {} - Error: {e}",
+                        full_path.display()
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
 /// Returns a vector of default #include directories
+///
+/// If `dialect_args` (e.g. `-x assembler-with-cpp`, `-std=gnu11`) are given, the
+/// compilers are probed with them, otherwise as both C and C++
 #[must_use]
-fn get_default_include_dirs() -> Vec<PathBuf> {
+fn get_default_include_dirs(dialect_args: &[String]) -> Vec<PathBuf> {
     let mut include_dirs = HashSet::new();
-    // repeat "cpp" and "clang" so that each command can be run with
-    // both set of args specified in `cmd_args`
-    let cmds = &["cpp", "cpp", "clang", "clang"];
-    let cmd_args = &[
-        ["-v", "-E", "-x", "c", "/dev/null", "-o", "/dev/null"],
-        ["-v", "-E", "-x", "c++", "/dev/null", "-o", "/dev/null"],
-    ];
+    let probe_args: Vec<Vec<&str>> = if dialect_args.is_empty() {
+        vec![vec!["-x", "c"], vec!["-x", "c++"]]
+    } else {
+        vec![dialect_args.iter().map(String::as_str).collect()]
+    };
 
-    for (cmd, args) in cmds.iter().zip(cmd_args.iter().cycle()) {
-        if let Ok(cmd_output) = std::process::Command::new(cmd)
-            .args(args)
-            .stderr(std::process::Stdio::piped())
-            .output()
+    for cmd in ["cpp", "clang"] {
+        for args in &probe_args {
+            let Ok(cmd_output) = std::process::Command::new(cmd)
+                .args(["-v", "-E"])
+                .args(args)
+                .args(["/dev/null", "-o", "/dev/null"])
+                .stderr(std::process::Stdio::piped())
+                .output()
+            else {
+                continue;
+            };
+            if !cmd_output.status.success() {
+                continue;
+            }
+            let output_str: String = ustr::get_string(cmd_output.stderr);
+
+            output_str
+                .lines()
+                .skip_while(|line| !line.contains("#include \"...\" search starts here:"))
+                .skip(1)
+                .take_while(|line| {
+                    !(line.contains("End of search list.")
+                        || line.contains("#include <...> search starts here:"))
+                })
+                .filter_map(|line| PathBuf::from(line.trim()).canonicalize().ok())
+                .for_each(|path| {
+                    include_dirs.insert(path);
+                });
+
+            output_str
+                .lines()
+                .skip_while(|line| !line.contains("#include <...> search starts here:"))
+                .skip(1)
+                .take_while(|line| !line.contains("End of search list."))
+                .filter_map(|line| PathBuf::from(line.trim()).canonicalize().ok())
+                .for_each(|path| {
+                    include_dirs.insert(path);
+                });
+        }
+    }
+
+    include_dirs.iter().cloned().collect::<Vec<PathBuf>>()
+}
+
+/// Returns the include directories specified by the compiler arguments `args`,
+/// in the order they're given. The returned paths may be relative
+///
+/// Picks up `-I`, `-isystem`, and `-iquote` in both their joined (`-Idir`) and
+/// separated (`-I dir`) forms, along with the system include directories under
+/// a `--sysroot` or `-isysroot`
+fn get_include_args<'a>(args: impl IntoIterator<Item = &'a str>) -> Vec<PathBuf> {
+    const DIR_FLAGS: [&str; 3] = ["-I", "-isystem", "-iquote"];
+    const SYSROOT_FLAGS: [&str; 2] = ["--sysroot", "-isysroot"];
+
+    let mut dirs = Vec::new();
+    let add_sysroot = |sysroot: &str, dirs: &mut Vec<PathBuf>| {
+        let sysroot = PathBuf::from(sysroot);
+        for sub_dir in ["usr/include", "usr/local/include"] {
+            let dir = sysroot.join(sub_dir);
+            // relative paths are checked when they're canonicalized by the caller
+            if dir.is_relative() || dir.is_dir() {
+                dirs.push(dir);
+            }
+        }
+    };
+
+    // the flag preceding the current arg, if it was given without its directory
+    let mut pending_flag: Option<&str> = None;
+    for arg in args {
+        if let Some(flag) = pending_flag.take() {
+            if SYSROOT_FLAGS.contains(&flag) {
+                add_sysroot(arg, &mut dirs);
+            } else {
+                dirs.push(PathBuf::from(arg));
+            }
+        } else if DIR_FLAGS.contains(&arg) || SYSROOT_FLAGS.contains(&arg) {
+            // -Irelative is stored as two separate args if parsed from `compile_flags.txt`
+            pending_flag = Some(arg);
+        } else if let Some(sysroot) = arg.strip_prefix("--sysroot=") {
+            add_sysroot(sysroot, &mut dirs);
+        } else if let Some(sysroot) = arg.strip_prefix("-isysroot") {
+            add_sysroot(sysroot, &mut dirs);
+        } else if let Some(dir) = DIR_FLAGS.iter().find_map(|flag| arg.strip_prefix(flag)) {
+            dirs.push(PathBuf::from(dir));
+        }
+    }
+
+    dirs
+}
+
+/// Returns the language (`-x`) and standard (`-std=`) flags in the compiler
+/// arguments `args`, normalized into the form they're passed to the compiler's
+/// include directory probe. Only the last of each flag is kept, as with the
+/// compiler itself
+///
+/// If only a C++ standard is given, `-x c++` is added so the probe doesn't treat
+/// `/dev/null` as C
+fn get_dialect_args<'a>(args: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut lang = None;
+    let mut std = None;
+    // whether the previous arg was `-x`, given without its language
+    let mut pending_lang = false;
+    for arg in args {
+        if pending_lang {
+            pending_lang = false;
+            lang = Some(arg);
+        } else if arg == "-x" {
+            pending_lang = true;
+        } else if let Some(arg_lang) = arg.strip_prefix("-x") {
+            lang = Some(arg_lang);
+        } else if let Some(arg_std) = arg
+            .strip_prefix("-std=")
+            .or_else(|| arg.strip_prefix("--std="))
         {
-            if cmd_output.status.success() {
-                let output_str: String = ustr::get_string(cmd_output.stderr);
-
-                output_str
-                    .lines()
-                    .skip_while(|line| !line.contains("#include \"...\" search starts here:"))
-                    .skip(1)
-                    .take_while(|line| {
-                        !(line.contains("End of search list.")
-                            || line.contains("#include <...> search starts here:"))
-                    })
-                    .filter_map(|line| PathBuf::from(line.trim()).canonicalize().ok())
-                    .for_each(|path| {
-                        include_dirs.insert(path);
-                    });
+            std = Some(arg_std);
+        }
+    }
 
-                output_str
-                    .lines()
-                    .skip_while(|line| !line.contains("#include <...> search starts here:"))
-                    .skip(1)
-                    .take_while(|line| !line.contains("End of search list."))
-                    .filter_map(|line| PathBuf::from(line.trim()).canonicalize().ok())
-                    .for_each(|path| {
-                        include_dirs.insert(path);
-                    });
+    // `-x none` goes back to guessing the language from the file extension
+    let lang = lang
+        .filter(|lang| *lang != "none")
+        .or_else(|| std.filter(|std| std.contains("++")).map(|_| "c++"));
+    let mut dialect_args = Vec::new();
+    if let Some(lang) = lang {
+        dialect_args.push("-x".to_string());
+        dialect_args.push(lang.to_string());
+    }
+    if let Some(std) = std {
+        dialect_args.push(format!("-std={std}"));
+    }
+
+    dialect_args
+}
+
+/// Returns the dialect flags (see `get_dialect_args`) of each entry in `compile_cmds`
+/// that specifies any
+fn get_dialect_args_by_entry(compile_cmds: &CompilationDatabase) -> Vec<(SourceFile, Vec<String>)> {
+    compile_cmds
+        .iter()
+        .filter_map(|entry| {
+            let source_file = get_entry_source_file(entry)?;
+            let dialect_args = match entry.arguments {
+                Some(CompileArgs::Flags(ref args) | CompileArgs::Arguments(ref args)) => {
+                    get_dialect_args(args.iter().map(|arg| arg.trim()))
+                }
+                None => entry
+                    .args_from_cmd()
+                    .map(|args| get_dialect_args(args.iter().map(String::as_str)))
+                    .unwrap_or_default(),
+            };
+            (!dialect_args.is_empty()).then_some((source_file, dialect_args))
+        })
+        .collect()
+}
+
+/// Returns the symbols defined by the compiler arguments `args`, along with their values
+///
+/// Picks up preprocessor defines (`-DNAME`, `-D NAME=value`) and GAS symbol definitions
+/// (`--defsym NAME=value`), including those passed through to the assembler with `-Wa,`.
+/// Defines without a value are given the value `1`, as they are by the compiler
+fn get_define_args<'a>(args: impl IntoIterator<Item = &'a str>) -> Vec<(String, String)> {
+    let mut defines = Vec::new();
+    let mut add_define = |def: &str| {
+        let (name, value) = def.split_once('=').unwrap_or((def, "1"));
+        if !name.is_empty() {
+            defines.push((name.to_string(), value.to_string()));
+        }
+    };
+
+    // whether the previous arg was a flag given without its definition
+    let mut pending_flag = false;
+    for arg in args {
+        if pending_flag {
+            pending_flag = false;
+            add_define(arg);
+        } else if arg == "-D" || arg == "--defsym" {
+            pending_flag = true;
+        } else if let Some(assembler_args) = arg.strip_prefix("-Wa,") {
+            for (name, value) in get_define_args(assembler_args.split(',')) {
+                add_define(&format!("{name}={value}"));
             }
+        } else if let Some(def) = arg.strip_prefix("--defsym=") {
+            add_define(def);
+        } else if let Some(def) = arg.strip_prefix("-D") {
+            add_define(def);
         }
     }
 
-    include_dirs.iter().cloned().collect::<Vec<PathBuf>>()
+    defines
+}
+
+/// Returns the source file targeted by the compilation database entry `entry`, with
+/// relative paths resolved against the entry's directory
+fn get_entry_source_file(entry: &CompileCommand) -> Option<SourceFile> {
+    match &entry.file {
+        SourceFile::All => Some(SourceFile::All),
+        SourceFile::File(file) => {
+            if file.is_absolute() {
+                Some(entry.file.clone())
+            } else {
+                let dir = entry.directory.canonicalize().ok()?;
+                dir.join(file).canonicalize().ok().map(SourceFile::File)
+            }
+        }
+    }
+}
+
+/// Returns a map of source files to the symbols defined for them on the command
+/// line (see `get_define_args`), as specified by `compile_cmds`
+///
+/// These symbols are considered defined when working with the corresponding files
+#[must_use]
+pub fn get_defines(
+    compile_cmds: &CompilationDatabase,
+) -> HashMap<SourceFile, HashMap<String, String>> {
+    let mut define_map: HashMap<SourceFile, HashMap<String, String>> = HashMap::new();
+
+    for entry in compile_cmds {
+        let Some(source_file) = get_entry_source_file(entry) else {
+            continue;
+        };
+        let defines = match entry.arguments {
+            Some(CompileArgs::Flags(ref args) | CompileArgs::Arguments(ref args)) => {
+                get_define_args(args.iter().map(|arg| arg.trim()))
+            }
+            None => entry
+                .args_from_cmd()
+                .map(|args| get_define_args(args.iter().map(String::as_str)))
+                .unwrap_or_default(),
+        };
+        if !defines.is_empty() {
+            define_map.entry(source_file).or_default().extend(defines);
+        }
+    }
+
+    info!("Command line define map: {:?}", define_map);
+
+    define_map
 }
 
 /// Returns a vector of source files and their associated additional include directories,
@@ -224,20 +614,10 @@ fn get_additional_include_dirs(compile_cmds: &CompilationDatabase) -> Vec<(Sourc
             continue;
         };
 
-        let source_file = match &entry.file {
-            SourceFile::All => SourceFile::All,
-            SourceFile::File(file) => {
-                if file.is_absolute() {
-                    entry.file.clone()
-                } else if let Ok(dir) = entry_dir.join(file).canonicalize() {
-                    SourceFile::File(dir)
-                } else {
-                    continue;
-                }
-            }
+        let Some(source_file) = get_entry_source_file(entry) else {
+            continue;
         };
 
-        let mut check_dir = false;
         if let Some(args) = &entry.arguments {
             // `arguments` run as the compilation step for the translation unit `file`
             // We will try to canonicalize non-absolute paths as relative to `file`,
@@ -245,54 +625,29 @@ fn get_additional_include_dirs(compile_cmds: &CompilationDatabase) -> Vec<(Sourc
             // add the include directory and issue a warning in this case
             match args {
                 CompileArgs::Flags(args) | CompileArgs::Arguments(args) => {
-                    for arg in args.iter().map(|arg| arg.trim()) {
-                        if check_dir {
-                            // current arg is preceeded by lone '-I'
-                            let dir = PathBuf::from(arg);
-                            if dir.is_absolute() {
-                                additional_dirs.push((source_file.clone(), dir));
-                            } else if let SourceFile::File(ref source_path) = source_file {
-                                if let Ok(full_include_path) = source_path.join(dir).canonicalize()
-                                {
-                                    additional_dirs.push((source_file.clone(), full_include_path));
-                                }
-                            } else {
-                                warn!("Additional relative include directories cannot be extracted for a compilation database entry targeting 'All'");
-                            }
-                            check_dir = false;
-                        } else if arg.eq("-I") {
-                            // -Irelative is stored as two separate args if parsed from `compile_flags.txt`
-                            check_dir = true;
-                        } else if arg.len() > 2 && arg.starts_with("-I") {
-                            // '-Irelative'
-                            let dir = PathBuf::from(&arg[2..]);
-                            if dir.is_absolute() {
-                                additional_dirs.push((source_file.clone(), dir));
-                            } else if let SourceFile::File(ref source_path) = source_file {
-                                if let Ok(full_include_path) = source_path.join(dir).canonicalize()
-                                {
-                                    additional_dirs.push((source_file.clone(), full_include_path));
-                                }
-                            } else {
-                                warn!("Additional relative include directories cannot be extracted for a compilation database entry targeting 'All'");
+                    for dir in get_include_args(args.iter().map(|arg| arg.trim())) {
+                        if dir.is_absolute() {
+                            additional_dirs.push((source_file.clone(), dir));
+                        } else if let SourceFile::File(ref source_path) = source_file {
+                            if let Ok(full_include_path) = source_path.join(dir).canonicalize() {
+                                additional_dirs.push((source_file.clone(), full_include_path));
                             }
+                        } else {
+                            warn!("Additional relative include directories cannot be extracted for a compilation database entry targeting 'All'");
                         }
                     }
                 }
             }
         } else if entry.command.is_some() {
             if let Some(args) = entry.args_from_cmd() {
-                for arg in args {
-                    if arg.starts_with("-I") && arg.len() > 2 {
-                        // "All paths specified in the `command` or `file` fields must be either absolute or relative to..." the `directory` field
-                        let incl_path = PathBuf::from(&arg[2..]);
-                        if incl_path.is_absolute() {
-                            additional_dirs.push((source_file.clone(), incl_path));
-                        } else {
-                            let dir = entry_dir.join(incl_path);
-                            if let Ok(full_include_path) = dir.canonicalize() {
-                                additional_dirs.push((source_file.clone(), full_include_path));
-                            }
+                // "All paths specified in the `command` or `file` fields must be either absolute or relative to..." the `directory` field
+                for incl_path in get_include_args(args.iter().map(String::as_str)) {
+                    if incl_path.is_absolute() {
+                        additional_dirs.push((source_file.clone(), incl_path));
+                    } else {
+                        let dir = entry_dir.join(incl_path);
+                        if let Ok(full_include_path) = dir.canonicalize() {
+                            additional_dirs.push((source_file.clone(), full_include_path));
                         }
                     }
                 }
@@ -309,23 +664,22 @@ fn get_additional_include_dirs(compile_cmds: &CompilationDatabase) -> Vec<(Sourc
 ///
 /// If both are present, `compile_commands.json` will override `compile_flags.txt`
 pub fn get_compile_cmds(params: &InitializeParams) -> Option<CompilationDatabase> {
-    if let Some(mut path) = get_project_root(params) {
-        // Check the project root directory first
-        let db = get_compilation_db_files(&path);
-        if db.is_some() {
-            return db;
-        }
+    get_root_compile_cmds(&get_project_root(params)?)
+}
 
-        // "The convention is to name the file compile_commands.json and put it at the top of the
-        // build directory."
-        path.push("build");
-        let db = get_compilation_db_files(&path);
-        if db.is_some() {
-            return db;
-        }
+/// Attempts to find either the `compile_commands.json` or `compile_flags.txt`
+/// file in `root` or its build directory, as in `get_compile_cmds`
+#[must_use]
+pub fn get_root_compile_cmds(root: &Path) -> Option<CompilationDatabase> {
+    // Check the project root directory first
+    let db = get_compilation_db_files(root);
+    if db.is_some() {
+        return db;
     }
 
-    None
+    // "The convention is to name the file compile_commands.json and put it at the top of the
+    // build directory."
+    get_compilation_db_files(&root.join("build"))
 }
 
 fn get_compilation_db_files(path: &Path) -> Option<CompilationDatabase> {
@@ -345,9 +699,85 @@ fn get_compilation_db_files(path: &Path) -> Option<CompilationDatabase> {
     None
 }
 
+/// Returns the assembler implied by the program `compile_cmd` invokes, if any
+///
+/// e.g. `nasm` implies NASM, `ml64.exe` MASM, and `go tool asm` Go. GNU `as` and
+/// C compilers, including cross toolchain variants like `arm-none-eabi-gcc`,
+/// imply GAS
+#[must_use]
+pub fn get_compile_cmd_assembler(compile_cmd: &CompileCommand) -> Option<Assembler> {
+    let args = match compile_cmd.arguments {
+        Some(CompileArgs::Arguments(ref args)) => args.clone(),
+        // `compile_flags.txt` entries don't name a program
+        Some(CompileArgs::Flags(_)) => return None,
+        None => compile_cmd.args_from_cmd()?,
+    };
+    let program = Path::new(args.first()?)
+        .file_name()?
+        .to_str()?
+        .to_ascii_lowercase();
+    let program = program.strip_suffix(".exe").unwrap_or(&program);
+    // cross toolchains and versioned installs add a target prefix or version
+    // suffix, e.g. `riscv64-unknown-elf-as` or `gcc-13`
+    program.split('-').find_map(|tool| match tool {
+        "nasm" | "yasm" => Some(Assembler::Nasm),
+        "ml" | "ml64" | "jwasm" | "uasm" => Some(Assembler::Masm),
+        "go" if args
+            .get(1..3)
+            .is_some_and(|sub_cmd| sub_cmd == ["tool", "asm"]) =>
+        {
+            Some(Assembler::Go)
+        }
+        "as" | "gas" | "gcc" | "cc" | "clang" | "g++" | "c++" | "clang++" => Some(Assembler::Gas),
+        _ => None,
+    })
+}
+
+/// Returns a config for each file whose compile command implies an assembler,
+/// with only that assembler enabled
+///
+/// Returns an empty map if `config` specifies any assemblers itself
+#[must_use]
+pub fn get_compile_cmd_overrides(
+    config: &Config,
+    compile_cmds: &CompilationDatabase,
+) -> ConfigOverrides {
+    let mut overrides = ConfigOverrides::new();
+    if !config.is_assembler_unset() {
+        return overrides;
+    }
+    for entry in compile_cmds {
+        let SourceFile::File(ref file) = entry.file else {
+            continue;
+        };
+        let Some(assembler) = get_compile_cmd_assembler(entry) else {
+            continue;
+        };
+        // Key by the canonical path so lookups match regardless of symlinks in
+        // either the compile command or the client's URI
+        let path = entry.directory.join(file);
+        let path = path.canonicalize().unwrap_or(path);
+        let Some(uri) = path_to_uri(&path) else {
+            continue;
+        };
+        let mut file_config = config.clone();
+        for asm in Assembler::iter() {
+            file_config.set_assembler_enabled(asm, asm == assembler);
+        }
+        overrides.insert(uri, file_config);
+    }
+
+    overrides
+}
+
 /// Returns a default `CompileCommand` for the provided `uri`.
 ///
-/// - If the user specified a compiler in their config, it will be used.
+/// - If the user specified a `diagnostics_command` in their config, it will be
+///   used with each `{file}` replaced by the source file's path. If the template
+///   has no `{file}` placeholder, the path is appended instead
+/// - Otherwise, if the user mapped one of the file's enabled arches to an assembler
+///   in `assemblers_by_arch`, it will be used.
+/// - Otherwise, if the user specified a compiler in their config, it will be used.
 /// - Otherwise, the command will be constructed with a single flag consisting of
 ///   the provided `uri`
 ///
@@ -355,11 +785,42 @@ fn get_compilation_db_files(path: &Path) -> Option<CompilationDatabase> {
 /// uninitialized to avoid unnecessary allocations. If you're using this function
 /// in a new place, please reconsider this assumption
 pub fn get_default_compile_cmd(uri: &Uri, cfg: &Config) -> CompileCommand {
+    let source_path = uri_to_path(uri)
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    if let Some(ref template) = cfg.opts.diagnostics_command {
+        let mut arguments: Vec<String> = template
+            .split_whitespace()
+            .map(|arg| arg.replace("{file}", &source_path))
+            .collect();
+        if !template.contains("{file}") {
+            arguments.push(source_path);
+        }
+        return CompileCommand {
+            file: SourceFile::All, // Field isn't checked when called, intentionally left in odd state here
+            directory: PathBuf::new(), // Field isn't checked when called, intentionally left uninitialized here
+            arguments: Some(CompileArgs::Arguments(arguments)),
+            command: None,
+            output: None,
+        };
+    }
+    if let Some(assembler) = cfg.get_arch_assembler() {
+        return CompileCommand {
+            file: SourceFile::All, // Field isn't checked when called, intentionally left in odd state here
+            directory: PathBuf::new(), // Field isn't checked when called, intentionally left uninitialized here
+            arguments: Some(CompileArgs::Arguments(vec![
+                assembler.to_string(),
+                source_path,
+            ])),
+            command: None,
+            output: None,
+        };
+    }
     cfg.opts.compiler.as_ref().map_or_else(
         || CompileCommand {
             file: SourceFile::All, // Field isn't checked when called, intentionally left in odd state here
             directory: PathBuf::new(), // Field isn't checked when called, intentionally left uninitialized here
-            arguments: Some(CompileArgs::Flags(vec![uri.path().to_string()])),
+            arguments: Some(CompileArgs::Flags(vec![source_path.clone()])),
             command: None,
             output: None,
         },
@@ -368,7 +829,7 @@ pub fn get_default_compile_cmd(uri: &Uri, cfg: &Config) -> CompileCommand {
             directory: PathBuf::new(), // Field isn't checked when called, intentionally left uninitialized here
             arguments: Some(CompileArgs::Arguments(vec![
                 compiler.to_string(),
-                uri.path().to_string(),
+                source_path.clone(),
             ])),
             command: None,
             output: None,
@@ -376,87 +837,497 @@ pub fn get_default_compile_cmd(uri: &Uri, cfg: &Config) -> CompileCommand {
     )
 }
 
+/// Returns the entries of `compile_cmds` that apply to the source file at `path`
+pub fn get_file_compile_cmds<'a>(
+    compile_cmds: &'a CompilationDatabase,
+    path: &'a Path,
+) -> impl Iterator<Item = &'a CompileCommand> {
+    compile_cmds.iter().filter(move |entry| match entry.file {
+        SourceFile::File(ref file) => {
+            if file.is_absolute() {
+                file.eq(path)
+            } else if let Ok(source_path) = file.canonicalize() {
+                source_path.eq(path)
+            } else {
+                false
+            }
+        }
+        SourceFile::All => true,
+    })
+}
+
 /// Attempts to run the given compile command and parses the resulting output. Any
 /// relevant output will be translated into a `Diagnostic` object and pushed into
 /// `diagnostics`
+///
+/// Commands given only as flags don't name a compiler, so only the first of the
+/// configured compilers (`gcc`, then `clang`, by default) that launches is run.
+/// They're alternatives to one another rather than separate checks: each accepts
+/// the same flags and would report the same problems, so running the rest would
+/// only duplicate the diagnostics.
 pub fn apply_compile_cmd(
     cfg: &Config,
     diagnostics: &mut Vec<Diagnostic>,
     uri: &Uri,
     compile_cmd: &CompileCommand,
 ) {
-    // TODO: Consolidate this logic, a little tricky because we need to capture
-    // compile_cmd.arguments by reference, but we get an owned Vec out of args_from_cmd()...
-    if let Some(ref args) = compile_cmd.arguments {
-        match args {
-            CompileArgs::Flags(flags) => {
-                let compilers = cfg
-                    .opts
-                    .compiler
-                    .as_ref()
-                    .map_or_else(|| vec!["gcc", "clang"], |compiler| vec![compiler.as_str()]);
-
-                for compiler in compilers {
-                    match Command::new(compiler) // default or user-supplied compiler
-                        .args(flags) // user supplied args
-                        .arg(uri.path().as_str()) // the source file in question
-                        .output()
-                    {
-                        Ok(result) => {
-                            let output_str = ustr::get_string(result.stderr);
-                            get_diagnostics(diagnostics, &output_str);
-                        }
-                        Err(e) => {
-                            warn!("Failed to launch compile command process with {compiler} -- Error: {e}");
-                        }
-                    };
-                }
-            }
-            CompileArgs::Arguments(arguments) => {
-                if arguments.len() < 2 {
-                    return;
-                }
-                let output = match Command::new(&arguments[0]).args(&arguments[1..]).output() {
-                    Ok(result) => result,
-                    Err(e) => {
-                        error!("Failed to launch compile command process -- Error: {e}");
-                        return;
-                    }
-                };
-                let output_str = ustr::get_string(output.stderr);
-                get_diagnostics(diagnostics, &output_str);
-            }
+    let Some(source_path) = uri_to_path(uri) else {
+        return;
+    };
+    // Relative paths in a compile command are relative to its `directory`. The
+    // default compile command doesn't have one, so fall back to the project root
+    let working_dir = if compile_cmd.directory.as_os_str().is_empty() {
+        cfg.project_root.as_deref()
+    } else {
+        Some(compile_cmd.directory.as_path())
+    };
+    // Only needed to find the length of lines in line-only diagnostics
+    let source = if cfg.opts.diagnostics_full_line.unwrap_or(true) {
+        std::fs::read_to_string(&source_path).ok()
+    } else {
+        None
+    };
+    let cmd_args;
+    let (programs, args): (Vec<&str>, Vec<&OsStr>) = match compile_cmd.arguments {
+        Some(CompileArgs::Flags(ref flags)) => (
+            get_compilers(cfg),
+            flags
+                .iter()
+                .map(OsStr::new)
+                .chain(std::iter::once(source_path.as_os_str()))
+                .collect(),
+        ),
+        Some(CompileArgs::Arguments(ref arguments)) => {
+            let Some(cmd) = with_fallbacks(cfg, arguments) else {
+                return;
+            };
+            cmd
         }
-    } else if let Some(args) = compile_cmd.args_from_cmd() {
-        if args.len() < 2 {
-            return;
+        None => {
+            cmd_args = compile_cmd.args_from_cmd();
+            let Some(cmd) = cmd_args
+                .as_deref()
+                .and_then(|args| with_fallbacks(cfg, args))
+            else {
+                return;
+            };
+            cmd
         }
-        let output = match Command::new(&args[0]).args(&args[1..]).output() {
-            Ok(result) => result,
-            Err(e) => {
-                error!("Failed to launch compile command process -- Error: {e}");
+    };
+    run_compile_cmd(
+        cfg,
+        diagnostics,
+        &programs,
+        &args,
+        working_dir,
+        source.as_deref(),
+    );
+}
+
+/// Runs `args` with each of `programs` in turn, parsing diagnostics from the
+/// stderr of the first that launches into `diagnostics`
+///
+/// The first program we're able to run wins, running the rest would just report
+/// the same problems again
+fn run_compile_cmd(
+    cfg: &Config,
+    diagnostics: &mut Vec<Diagnostic>,
+    programs: &[&str],
+    args: &[&OsStr],
+    working_dir: Option<&Path>,
+    source: Option<&str>,
+) {
+    for program in programs {
+        let mut cmd = Command::new(program);
+        if let Some(dir) = working_dir {
+            cmd.current_dir(dir);
+        }
+        match cmd.args(args).output() {
+            Ok(result) => {
+                let output_str = ustr::get_string(result.stderr);
+                get_diagnostics(diagnostics, &output_str, source, cfg);
                 return;
             }
-        };
-        let output_str = ustr::get_string(output.stderr);
-        get_diagnostics(diagnostics, &output_str);
+            Err(e) => {
+                warn!("Failed to launch compile command process with {program} -- Error: {e}");
+            }
+        }
     }
+    error!("Failed to launch any compile command process for {programs:?}");
 }
 
-/// Attempts to parse `tool_output`, translating it into `Diagnostic` objects
-/// and placing them into `diagnostics`
-///
-/// Looks for diagnostics of the following form:
+/// Runs each of the user's configured `linters` against the file at `uri`,
+/// parsing diagnostics from their stdout and stderr into `diagnostics`
 ///
-/// <file name>:<line number>: Error: <Error message>
-///
-/// As more assemblers are incorporated, this can be updated
-///
-/// # Panics
-fn get_diagnostics(diagnostics: &mut Vec<Diagnostic>, tool_output: &str) {
-    static DIAG_REG_LINE_COLUMN: Lazy<Regex> =
-        Lazy::new(|| Regex::new(r"^.*:(\d+):(\d+):\s+(.*)$").unwrap());
-    static DIAG_REG_LINE_ONLY: Lazy<Regex> =
+/// Each linter is given as a command line, split on whitespace, to which the
+/// source file's path is appended. Linters that fail to launch are skipped
+pub fn apply_linters(cfg: &Config, diagnostics: &mut Vec<Diagnostic>, uri: &Uri) {
+    let Some(ref linters) = cfg.opts.linters else {
+        return;
+    };
+    let Some(source_path) = uri_to_path(uri) else {
+        return;
+    };
+    // Only needed to find the length of lines in line-only diagnostics
+    let source = if cfg.opts.diagnostics_full_line.unwrap_or(true) {
+        std::fs::read_to_string(&source_path).ok()
+    } else {
+        None
+    };
+    for linter in linters {
+        let mut args = linter.split_whitespace();
+        let Some(program) = args.next() else {
+            continue;
+        };
+        let mut cmd = Command::new(program);
+        if let Some(ref dir) = cfg.project_root {
+            cmd.current_dir(dir);
+        }
+        match cmd.args(args).arg(&source_path).output() {
+            Ok(result) => {
+                let start = diagnostics.len();
+                get_diagnostics(
+                    diagnostics,
+                    &ustr::get_string(result.stdout),
+                    source.as_deref(),
+                    cfg,
+                );
+                get_diagnostics(
+                    diagnostics,
+                    &ustr::get_string(result.stderr),
+                    source.as_deref(),
+                    cfg,
+                );
+                // let the user know which linter reported what
+                for diag in &mut diagnostics[start..] {
+                    diag.source = Some(program.to_string());
+                }
+            }
+            Err(e) => {
+                warn!("Failed to launch linter {program} -- Error: {e}");
+            }
+        }
+    }
+}
+
+/// Returns the compilers to try, in order, when invoking a command without an
+/// explicitly specified compiler. This is the user-supplied compiler if one was
+/// given, and `gcc` and then `clang` otherwise
+fn get_compilers(cfg: &Config) -> Vec<&str> {
+    cfg.opts
+        .compiler
+        .as_ref()
+        .map_or_else(|| vec!["gcc", "clang"], |compiler| vec![compiler.as_str()])
+}
+
+/// Splits a compile command line into the programs to try running, its own
+/// followed by the fallback compilers in case it can't be launched, and its
+/// arguments
+///
+/// Only a gcc or clang driver is given fallbacks, any other program's arguments
+/// (e.g. `nasm`'s) wouldn't mean the same thing to them
+fn with_fallbacks<'a>(
+    cfg: &'a Config,
+    cmd: &'a [String],
+) -> Option<(Vec<&'a str>, Vec<&'a OsStr>)> {
+    let (program, args) = cmd.split_first()?;
+    if args.is_empty() {
+        return None;
+    }
+    let mut programs = vec![program.as_str()];
+    if is_gcc_like(program) {
+        programs.extend(
+            get_compilers(cfg)
+                .into_iter()
+                .filter(|compiler| compiler != program),
+        );
+    }
+    Some((programs, args.iter().map(OsStr::new).collect()))
+}
+
+/// Returns whether `program` is a gcc or clang driver, e.g. `/usr/bin/cc`,
+/// `clang++`, or `arm-none-eabi-gcc-12`
+fn is_gcc_like(program: &str) -> bool {
+    let name = Path::new(program)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or(program);
+    matches!(name, "cc" | "c++" | "cc.exe" | "c++.exe")
+        || ["gcc", "g++", "clang"]
+            .iter()
+            .any(|driver| name.contains(driver))
+}
+
+/// How a program is asked for an assembler listing
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ListingKind {
+    /// A gcc driver, which passes `-al` through to the assembler with `-Wa,-al`
+    Driver,
+    /// GNU `as` itself, which takes `-al` directly
+    Assembler,
+}
+
+/// Returns how to get an assembler listing out of `program`, or `None` if it
+/// can't produce one (e.g. `nasm`, or `clang`'s integrated assembler)
+fn get_listing_kind(program: &str) -> Option<ListingKind> {
+    let name = Path::new(program)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or(program);
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    if name.contains("clang") {
+        None
+    } else if is_gcc_like(program) {
+        Some(ListingKind::Driver)
+    } else if name == "as" || name == "gas" || name.ends_with("-as") {
+        Some(ListingKind::Assembler)
+    } else {
+        None
+    }
+}
+
+/// Returns the programs to try, in order, when assembling an instruction for its
+/// encoding, along with how to get a listing out of each
+///
+/// These are picked from `compile_cmd` just as `apply_compile_cmd` would, leaving
+/// out any that can't produce an `as -al` listing
+fn get_encoding_programs(cfg: &Config, compile_cmd: &CompileCommand) -> Vec<(String, ListingKind)> {
+    let cmd_args;
+    let programs = match compile_cmd.arguments {
+        Some(CompileArgs::Flags(_)) => get_compilers(cfg),
+        Some(CompileArgs::Arguments(ref arguments)) => with_fallbacks(cfg, arguments)
+            .map(|(programs, _)| programs)
+            .unwrap_or_default(),
+        None => {
+            cmd_args = compile_cmd.args_from_cmd();
+            cmd_args
+                .as_deref()
+                .and_then(|args| with_fallbacks(cfg, args))
+                .map(|(programs, _)| programs)
+                .unwrap_or_default()
+        }
+    };
+    programs
+        .into_iter()
+        .filter_map(|program| Some((program.to_string(), get_listing_kind(program)?)))
+        .collect()
+}
+
+/// Returns the flags selecting the assembler mode for the configured architecture,
+/// i.e. 32-bit mode if x86 is enabled without x86-64
+fn get_encoding_mode_args(cfg: &Config, kind: ListingKind) -> &'static [&'static str] {
+    let is_x86_32 =
+        cfg.instruction_sets.x86.unwrap_or(false) && !cfg.instruction_sets.x86_64.unwrap_or(false);
+    match kind {
+        _ if !is_x86_32 => &[],
+        ListingKind::Driver => &["-m32"],
+        ListingKind::Assembler => &["--32"],
+    }
+}
+
+/// The most instruction encodings `get_instr_encoding` keeps before starting over
+const MAX_CACHED_ENCODINGS: usize = 256;
+
+/// Attempts to assemble the single instruction `line`, returning the resulting
+/// machine code bytes as a space-separated hex string (e.g. "B8 01 00 00 00")
+///
+/// The instruction is assembled with the programs from `compile_cmd` (see
+/// `get_encoding_programs`), in the mode of the configured architecture. Any lines
+/// in `prelude` (e.g. `.code32`) are placed before the instruction so it's
+/// assembled in the same mode as the source file. For x86 sources written for NASM
+/// or MASM, Intel syntax is assumed unless the prelude picks a syntax itself.
+/// Returns `None` if the instruction can't be assembled by any of these programs
+///
+/// Results are cached by the assembled source, so hovering the same line again
+/// doesn't wait on another compiler run
+///
+/// # Panics
+///
+/// Will panic if the cache's lock is poisoned
+#[must_use]
+pub fn get_instr_encoding(
+    cfg: &Config,
+    compile_cmd: &CompileCommand,
+    prelude: &[&str],
+    line: &str,
+) -> Option<String> {
+    static ENCODING_CACHE: Lazy<Mutex<HashMap<String, Option<String>>>> =
+        Lazy::new(|| Mutex::new(HashMap::new()));
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let programs = get_encoding_programs(cfg, compile_cmd);
+    if programs.is_empty() {
+        return None;
+    }
+
+    let is_x86 =
+        cfg.instruction_sets.x86.unwrap_or(false) || cfg.instruction_sets.x86_64.unwrap_or(false);
+    let is_intel = !cfg.assemblers.gas.unwrap_or(false)
+        && (cfg.assemblers.nasm.unwrap_or(false) || cfg.assemblers.masm.unwrap_or(false));
+    let picks_syntax = prelude
+        .iter()
+        .any(|line| line.starts_with(".intel_syntax") || line.starts_with(".att_syntax"));
+    let mut lines = Vec::with_capacity(prelude.len() + 2);
+    if is_x86 && is_intel && !picks_syntax {
+        lines.push(".intel_syntax noprefix");
+    }
+    lines.extend_from_slice(prelude);
+    // the instruction is always the last line of the source file
+    let line_num = lines.len() + 1;
+    lines.push(line.trim());
+    let mut source = lines.join("\n");
+    source.push('\n');
+
+    let cache_key = format!(
+        "{}\n{source}",
+        programs
+            .iter()
+            .map(|(program, kind)| format!(
+                "{program} {}",
+                get_encoding_mode_args(cfg, *kind).join(" ")
+            ))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    if let Some(encoding) = ENCODING_CACHE.lock().unwrap().get(&cache_key) {
+        return encoding.clone();
+    }
+
+    let tmp_dir = std::env::temp_dir();
+    let src_path = tmp_dir.join(format!(
+        "asm-lsp-encoding-{}-{}.s",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let obj_path = src_path.with_extension("o");
+    if let Err(e) = std::fs::write(&src_path, source) {
+        warn!("Failed to write temporary file for instruction encoding -- Error: {e}");
+        return None;
+    }
+
+    let mut encoding = None;
+    for (program, kind) in programs {
+        let listing_args: &[&str] = match kind {
+            ListingKind::Driver => &["-c", "-x", "assembler", "-Wa,-al"],
+            ListingKind::Assembler => &["-al"],
+        };
+        match Command::new(&program)
+            .args(listing_args)
+            .args(get_encoding_mode_args(cfg, kind))
+            .arg("-o")
+            .arg(&obj_path)
+            .arg(&src_path)
+            .output()
+        {
+            Ok(result) => {
+                if result.status.success() {
+                    let listing = ustr::get_string(result.stdout);
+                    encoding = get_listing_encoding(&listing, line_num);
+                }
+                break;
+            }
+            Err(e) => {
+                warn!("Failed to launch encoding process with {program} -- Error: {e}");
+            }
+        }
+    }
+
+    _ = std::fs::remove_file(&src_path);
+    _ = std::fs::remove_file(&obj_path);
+
+    let mut cache = ENCODING_CACHE.lock().unwrap();
+    if cache.len() >= MAX_CACHED_ENCODINGS {
+        cache.clear();
+    }
+    cache.insert(cache_key, encoding.clone());
+    encoding
+}
+
+/// Extracts the encoded bytes for source line `line_num` (1-indexed) from an
+/// assembler listing, as produced by `as -al`
+///
+/// Listing lines are of the form `<line number> <address> <hex bytes> <source>`,
+/// with long encodings continuing on subsequent lines without an address
+#[must_use]
+pub fn get_listing_encoding(listing: &str, line_num: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    for line in listing.lines() {
+        let Some((num, rest)) = line.trim_start().split_once(' ') else {
+            continue;
+        };
+        if num.parse::<usize>().ok() != Some(line_num) {
+            continue;
+        }
+        // skip over the address, which is left blank on continuation lines
+        let (Some(addr), Some(rest)) = (rest.get(..4), rest.get(5..)) else {
+            continue;
+        };
+        if !addr.bytes().all(|c| c == b' ' || c.is_ascii_hexdigit()) {
+            continue;
+        }
+        // the source text follows a tab, make sure we don't pick it up by mistake
+        let hex = rest.split('\t').next().unwrap_or_default().trim();
+        if hex.is_empty() || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        bytes.extend((0..hex.len()).step_by(2).filter_map(|i| hex.get(i..i + 2)));
+    }
+
+    if bytes.is_empty() {
+        None
+    } else {
+        Some(bytes.join(" "))
+    }
+}
+
+/// Returns the severity keyword (see `DIAGNOSTIC_KEYWORDS`) a tool's message starts with
+fn get_diagnostic_keyword(msg: &str) -> Option<&'static str> {
+    let (prefix, _) = msg.split_once(':')?;
+    match prefix.trim().to_ascii_lowercase().as_str() {
+        "error" | "fatal error" | "fatal" => Some("error"),
+        "warning" => Some("warning"),
+        "note" | "info" => Some("note"),
+        _ => None,
+    }
+}
+
+/// Attempts to parse `tool_output`, translating it into `Diagnostic` objects
+/// and placing them into `diagnostics`
+///
+/// Looks for diagnostics of the following form:
+///
+/// <file name>:<line number>: Error: <Error message>
+///
+/// As more assemblers are incorporated, this can be updated
+///
+/// Some tools only report a line number. If `source` is provided, such diagnostics
+/// span the whole of the corresponding line, otherwise they're given a zero-width
+/// range at its start
+///
+/// Messages starting with a severity keyword (e.g. `error` for GAS' `Error: junk at
+/// end of line`) are given the severity `cfg` maps it to, and are dropped if it's
+/// mapped to `none`
+///
+/// # Panics
+///
+/// Will panic if one of the diagnostic regexes fails to compile
+pub fn get_diagnostics(
+    diagnostics: &mut Vec<Diagnostic>,
+    tool_output: &str,
+    source: Option<&str>,
+    cfg: &Config,
+) {
+    let severity = |msg: &str| {
+        get_diagnostic_keyword(msg).map_or(Some(None), |keyword| {
+            cfg.get_diagnostic_level(keyword)
+                .to_lsp_severity()
+                .map(Some)
+        })
+    };
+
+    static DIAG_REG_LINE_COLUMN: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^.*:(\d+):(\d+):\s+(.*)$").unwrap());
+    static DIAG_REG_LINE_ONLY: Lazy<Regex> =
         Lazy::new(|| Regex::new(r"^.*:(\d+):\s+(.*)$").unwrap());
 
     // TODO: Consolidate/ clean this up...regexes are hard
@@ -474,19 +1345,25 @@ fn get_diagnostics(diagnostics: &mut Vec<Diagnostic>, tool_output: &str) {
                     continue;
                 };
                 let err_msg = &caps[3];
-                diagnostics.push(Diagnostic::new_simple(
-                    Range {
-                        start: Position {
-                            line: line_number - 1,
-                            character: column_number,
-                        },
-                        end: Position {
-                            line: line_number - 1,
-                            character: column_number,
+                let Some(severity) = severity(err_msg) else {
+                    continue;
+                };
+                diagnostics.push(Diagnostic {
+                    severity,
+                    ..Diagnostic::new_simple(
+                        Range {
+                            start: Position {
+                                line: line_number - 1,
+                                character: column_number,
+                            },
+                            end: Position {
+                                line: line_number - 1,
+                                character: column_number,
+                            },
                         },
-                    },
-                    String::from(err_msg),
-                ));
+                        String::from(err_msg),
+                    )
+                });
                 continue;
             }
         }
@@ -503,23 +1380,64 @@ fn get_diagnostics(diagnostics: &mut Vec<Diagnostic>, tool_output: &str) {
                 continue;
             };
             let err_msg = &caps[2];
-            diagnostics.push(Diagnostic::new_simple(
-                Range {
-                    start: Position {
-                        line: line_number - 1,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: line_number - 1,
-                        character: 0,
+            let Some(severity) = severity(err_msg) else {
+                continue;
+            };
+            let line_len = source
+                .and_then(|source| source.lines().nth(line_number as usize - 1))
+                .map_or(0, |line| line.encode_utf16().count() as u32);
+            diagnostics.push(Diagnostic {
+                severity,
+                ..Diagnostic::new_simple(
+                    Range {
+                        start: Position {
+                            line: line_number - 1,
+                            character: 0,
+                        },
+                        end: Position {
+                            line: line_number - 1,
+                            character: line_len,
+                        },
                     },
-                },
-                String::from(err_msg),
-            ));
+                    String::from(err_msg),
+                )
+            });
+        }
+    }
+}
+
+/// Appends the source line each of `diagnostics` refers to onto its message, so
+/// the message makes sense on its own in clients that don't show it inline
+pub fn add_diagnostics_context(diagnostics: &mut [Diagnostic], source: &str) {
+    let lines: Vec<&str> = source.lines().collect();
+    for diag in diagnostics {
+        let line_num = diag.range.start.line as usize;
+        if let Some(line) = lines.get(line_num).map(|line| line.trim()) {
+            if !line.is_empty() {
+                diag.message = format!("{}\n{} | {line}", diag.message, line_num + 1);
+            }
         }
     }
 }
 
+/// Sorts `diagnostics` by position and removes any duplicate entries, i.e.
+/// those sharing the same range, message, and severity
+///
+/// Duplicates can arise when multiple compile commands apply to the same file,
+/// so this should be called once all commands have been run
+pub fn dedup_diagnostics(diagnostics: &mut Vec<Diagnostic>) {
+    diagnostics.sort_by(|a, b| {
+        (a.range.start, a.range.end, &a.message, a.severity).cmp(&(
+            b.range.start,
+            b.range.end,
+            &b.message,
+            b.severity,
+        ))
+    });
+    diagnostics
+        .dedup_by(|a, b| a.range == b.range && a.message == b.message && a.severity == b.severity);
+}
+
 /// Function allowing us to connect tree sitter's logging with the log crate
 #[allow(clippy::needless_pass_by_value)]
 pub fn tree_sitter_logger(log_type: tree_sitter::LogType, message: &str) {
@@ -537,10 +1455,12 @@ pub fn tree_sitter_logger(log_type: tree_sitter::LogType, message: &str) {
 
 /// Convert an `lsp_types::TextDocumentContentChangeEvent` to a `tree_sitter::InputEdit`
 ///
+/// `doc` must be the document's contents *before* `change` is applied, as the
+/// byte offsets of the edited range are computed against it
+///
 /// # Errors
 ///
-/// Returns `Err` if `change.range` is `None`, or if a `usize`->`u32` numeric conversion
-/// failed
+/// Returns `Err` if `change.range` is `None`
 pub fn text_doc_change_to_ts_edit(
     change: &TextDocumentContentChangeEvent,
     doc: &FullTextDocument,
@@ -550,25 +1470,36 @@ pub fn text_doc_change_to_ts_edit(
     let end = range.end;
 
     let start_byte = doc.offset_at(start) as usize;
+    // tree-sitter columns are byte offsets into the line, LSP columns are UTF-16
+    let line_start_byte = |line| doc.offset_at(Position { line, character: 0 }) as usize;
+    let start_col = start_byte - line_start_byte(start.line);
+    let old_end_byte = doc.offset_at(end) as usize;
     let new_end_byte = start_byte + change.text.len();
-    let new_end_pos = doc.position_at(u32::try_from(new_end_byte)?);
+    // the new text isn't in `doc` yet, so we find where it ends by walking it
+    let new_end_position = change.text.rfind('\n').map_or_else(
+        || tree_sitter::Point {
+            row: start.line as usize,
+            column: start_col + change.text.len(),
+        },
+        |last_newline| tree_sitter::Point {
+            row: start.line as usize + change.text.matches('\n').count(),
+            column: change.text.len() - last_newline - 1,
+        },
+    );
 
     Ok(tree_sitter::InputEdit {
         start_byte,
-        old_end_byte: doc.offset_at(end) as usize,
+        old_end_byte,
         new_end_byte,
         start_position: tree_sitter::Point {
             row: start.line as usize,
-            column: start.character as usize,
+            column: start_col,
         },
         old_end_position: tree_sitter::Point {
             row: end.line as usize,
-            column: end.character as usize,
-        },
-        new_end_position: tree_sitter::Point {
-            row: new_end_pos.line as usize,
-            column: new_end_pos.character as usize,
+            column: old_end_byte - line_start_byte(end.line),
         },
+        new_end_position,
     })
 }
 
@@ -580,7 +1511,7 @@ pub fn get_completes<T: Completable, U: ArchOrAssembler>(
     kind: Option<CompletionItemKind>,
 ) -> Vec<CompletionItem> {
     map.iter()
-        .map(|((_arch_or_asm, name), item_info)| {
+        .map(|((arch_or_asm, name), item_info)| {
             let value = format!("{item_info}");
 
             CompletionItem {
@@ -590,61 +1521,265 @@ pub fn get_completes<T: Completable, U: ArchOrAssembler>(
                     kind: MarkupKind::Markdown,
                     value,
                 })),
+                // lets items from one architecture or assembler be told apart
+                data: serde_json::to_value(CompletionData {
+                    arch: Some(arch_or_asm.as_ref().to_string()),
+                    ..Default::default()
+                })
+                .ok(),
                 ..Default::default()
             }
         })
         .collect()
 }
 
+/// Returns the `CompletionData` attached to `item`, or the default if it has none
+fn get_comp_data(item: &CompletionItem) -> CompletionData {
+    item.data
+        .as_ref()
+        .and_then(|data| CompletionData::deserialize(data).ok())
+        .unwrap_or_default()
+}
+
+/// Applies `update` to the `CompletionData` attached to `item`, keeping whatever
+/// else was set on it
+fn update_comp_data(item: &mut CompletionItem, update: impl FnOnce(&mut CompletionData)) {
+    let mut data = get_comp_data(item);
+    update(&mut data);
+    item.data = serde_json::to_value(data).ok();
+}
+
+/// Marks the completions for registers that can only be used in 64-bit mode, so
+/// they can be left out when completing in 16 or 32-bit code
+pub fn tag_64bit_only_completes(items: &mut [CompletionItem], register_map: &NameToRegisterMap) {
+    for item in items {
+        let only_64bit = [Arch::X86, Arch::X86_64]
+            .into_iter()
+            .filter_map(|arch| register_map.get(&(arch, item.label.as_str())))
+            .any(|reg| reg.only_64bit);
+        if only_64bit {
+            update_comp_data(item, |data| data.only_64bit = true);
+        }
+    }
+}
+
+fn is_64bit_only_comp(item: &CompletionItem) -> bool {
+    get_comp_data(item).only_64bit
+}
+
+/// Tags the instruction completions for obsolete instructions as deprecated, along
+/// with those for 32-bit only instructions if x86-64 is enabled
+pub fn tag_deprecated_completes(
+    items: &mut [CompletionItem],
+    instruction_map: &NameToInstructionMap,
+    config: &Config,
+) {
+    let targets_64bit = config.instruction_sets.x86_64.unwrap_or(false);
+    for item in items {
+        let is_deprecated = Arch::iter()
+            .filter_map(|arch| instruction_map.get(&(arch, item.label.as_str())))
+            .any(|instr| instr.deprecated.is_some() || (targets_64bit && instr.invalid_64bit));
+        if is_deprecated {
+            item.tags = Some(vec![CompletionItemTag::DEPRECATED]);
+        }
+    }
+}
+
 #[must_use]
-pub fn get_hover_resp<T: Hoverable, U: Hoverable, V: Hoverable>(
+pub fn get_hover_resp<U: Hoverable, V: Hoverable>(
     params: &HoverParams,
     config: &Config,
     word: &str,
     cursor_offset: usize,
     text_store: &TextDocuments,
     tree_store: &mut TreeStore,
-    instruction_map: &HashMap<(Arch, &str), T>,
+    instruction_map: &NameToInstructionMap,
     register_map: &HashMap<(Arch, &str), U>,
     directive_map: &HashMap<(Assembler, &str), V>,
     include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
+    defines: &HashMap<SourceFile, HashMap<String, String>>,
+    compile_cmds: &CompilationDatabase,
 ) -> Option<Hover> {
-    let instr_lookup = lookup_hover_resp_by_arch(word, instruction_map);
-    if instr_lookup.is_some() {
-        return instr_lookup;
+    // Words in comments and strings are just text, but a string can still name an
+    // included file
+    if is_in_comment_or_string(params, text_store, tree_store) {
+        return get_included_file_resp(params, "", text_store, include_dirs);
+    }
+
+    if config.instruction_sets.x86.unwrap_or(false)
+        || config.instruction_sets.x86_64.unwrap_or(false)
+    {
+        let decorator_resp = get_evex_decorator_resp(params, text_store);
+        if decorator_resp.is_some() {
+            return decorator_resp;
+        }
+        // `rip` itself gets the register's docs
+        if !word.eq_ignore_ascii_case("rip") {
+            let lea_resp = get_lea_rip_resp(params, text_store, tree_store);
+            if lea_resp.is_some() {
+                return lea_resp;
+            }
+        }
+    }
+
+    if config.instruction_sets.arm.unwrap_or(false)
+        || config.instruction_sets.arm64.unwrap_or(false)
+    {
+        let literal_resp = get_literal_pool_resp(params, text_store, tree_store);
+        if literal_resp.is_some() {
+            return literal_resp;
+        }
+        // `lsl` and friends are instructions too, but not in operand position
+        let shift_resp = get_arm_shift_resp(params, text_store);
+        if shift_resp.is_some() {
+            return shift_resp;
+        }
+    }
+
+    if !word.is_empty() && word.chars().count() < config.opts.min_hover_word_len.unwrap_or(1) {
+        return None;
+    }
+
+    // The cursor is on whitespace or punctuation, which can only be part of a
+    // constant expression or an included file's name
+    if word.is_empty() {
+        if config.assemblers.gas.unwrap_or(false) {
+            let expr_resp = get_expr_resp(params, text_store, defines);
+            if expr_resp.is_some() {
+                return expr_resp;
+            }
+        }
+        return get_included_file_resp(params, word, text_store, include_dirs);
+    }
+
+    if config.assemblers.gas.unwrap_or(false) {
+        let numeric_label_resp = get_numeric_label_resp(params, text_store);
+        if numeric_label_resp.is_some() {
+            return numeric_label_resp;
+        }
+    }
+
+    let gas_suffix = get_gas_size_suffix(word, config, instruction_map);
+    let m68k_suffix = if gas_suffix.is_none() {
+        get_m68k_size_suffix(word, config, instruction_map)
+    } else {
+        None
+    };
+    let instr_word = gas_suffix.or(m68k_suffix).map_or(word, |(base, _)| base);
+    let instr_resp = if config.prefers_go_names() {
+        lookup_go_instr_hover_resp(instr_word, instruction_map, config)
+    } else {
+        lookup_hover_resp_by_arch(instr_word, instruction_map, config)
+    };
+    if let Some(mut instr_resp) = instr_resp {
+        // MASM mnemonics are case-insensitive, the documented name is shown, e.g.
+        // `mov` for `MOV`
+        if let HoverContents::Markup(ref mut content) = instr_resp.contents {
+            if config.assemblers.masm.unwrap_or(false)
+                && instr_word != instr_word.to_ascii_lowercase()
+            {
+                content.value.push_str("\n\nMatched `");
+                content.value.push_str(instr_word);
+                content.value.push_str("` case-insensitively");
+            }
+        }
+        if let (Some((_, bits)), HoverContents::Markup(ref mut content)) =
+            (gas_suffix, &mut instr_resp.contents)
+        {
+            content.value.push_str("\n\nSize suffix: `");
+            content.value.push_str(&word[instr_word.len()..]);
+            content.value.push_str("` (");
+            content.value.push_str(bits);
+            content.value.push_str("-bit operands)");
+        }
+        if let (Some((_, desc)), HoverContents::Markup(ref mut content)) =
+            (m68k_suffix, &mut instr_resp.contents)
+        {
+            content.value.push_str("\n\nSize suffix: `");
+            content.value.push_str(&word[instr_word.len()..]);
+            content.value.push_str("` (");
+            content.value.push_str(desc);
+            content.value.push(')');
+        }
+        if config.opts.show_encoding.unwrap_or(false) {
+            if let (Some(encoding), HoverContents::Markup(ref mut content)) = (
+                get_encoding_resp(params, config, word, text_store, compile_cmds),
+                &mut instr_resp.contents,
+            ) {
+                content.value.push_str("\n\nEncoding: `");
+                content.value.push_str(&encoding);
+                content.value.push('`');
+            }
+        }
+        if config.opts.show_opcodes.unwrap_or(false) {
+            if let (Some(table), HoverContents::Markup(ref mut content)) = (
+                get_opcodes_table(instr_word, instruction_map, config),
+                &mut instr_resp.contents,
+            ) {
+                content.value.push_str("\n\n");
+                content.value.push_str(&table);
+            }
+        }
+        if config.instruction_sets.arm.unwrap_or(false) {
+            if let (Some(note), HoverContents::Markup(ref mut content)) = (
+                get_arm_state_note(params, word, text_store),
+                &mut instr_resp.contents,
+            ) {
+                content.value.push_str("\n\n");
+                content.value.push_str(&note);
+            }
+        }
+        return Some(instr_resp);
+    }
+
+    if config.instruction_sets.arm.unwrap_or(false) {
+        let state_resp = get_arm_state_resp(params, word, text_store);
+        if state_resp.is_some() {
+            return state_resp;
+        }
     }
 
     // directive lookup
     {
         if config.assemblers.gas.unwrap_or(false) || config.assemblers.masm.unwrap_or(false) {
             // all gas directives have a '.' prefix, some masm directives do
-            let directive_lookup = lookup_hover_resp_by_assembler(word, directive_map);
+            let directive_lookup = lookup_hover_resp_by_assembler(word, directive_map, config);
             if directive_lookup.is_some() {
                 return directive_lookup;
             }
         } else if config.assemblers.nasm.unwrap_or(false) {
-            // most nasm directives have no prefix, 2 have a '.' prefix
-            let directive_lookup = lookup_hover_resp_by_assembler(word, directive_map);
-            if directive_lookup.is_some() {
-                return directive_lookup;
+            // most nasm directives have no prefix, 2 have a '.' prefix (which is part
+            // of `word`), and some have a '%' prefix (which isn't)
+            let percent_prefixed = format!("%{word}");
+            let mut candidates = Vec::with_capacity(3);
+            if has_percent_prefix(params, text_store) {
+                candidates.push(percent_prefixed.clone());
             }
-            // Some nasm directives have a % prefix
-            let prefixed = format!("%{word}");
-            let directive_lookup = lookup_hover_resp_by_assembler(&prefixed, directive_map);
-            if directive_lookup.is_some() {
-                return directive_lookup;
+            candidates.push(word.to_string());
+            if config.opts.directive_prefix_fallback.unwrap_or(true) {
+                candidates.push(percent_prefixed);
+                if !word.starts_with('.') {
+                    candidates.push(format!(".{word}"));
+                }
+            }
+            for candidate in candidates {
+                let directive_lookup =
+                    lookup_hover_resp_by_assembler(&candidate, directive_map, config);
+                if directive_lookup.is_some() {
+                    return directive_lookup;
+                }
             }
         }
     }
 
     let reg_lookup = if config.instruction_sets.arm64.unwrap_or(false) {
         word.find('.').map_or_else(
-            || lookup_hover_resp_by_arch(&word[0..], register_map),
+            || lookup_hover_resp_by_arch(&word[0..], register_map, config),
             |dot| {
                 if cursor_offset <= dot {
                     // main vector register info on ARM64
                     let main_register = &word[0..dot];
-                    lookup_hover_resp_by_arch(main_register, register_map)
+                    lookup_hover_resp_by_arch(main_register, register_map, config)
                 } else {
                     // if Vector = V21.2D -> lower Register = D21
                     // lower vector register info on ARM64
@@ -654,16 +1789,50 @@ pub fn get_hover_resp<T: Hoverable, U: Hoverable, V: Hoverable>(
                     lower_register.push_str(&word[reg_letter..]);
                     let reg_num = 1..dot;
                     lower_register.push_str(&word[reg_num]);
-                    lookup_hover_resp_by_arch(&lower_register, register_map)
+                    lookup_hover_resp_by_arch(&lower_register, register_map, config)
                 }
             },
         )
     } else {
-        lookup_hover_resp_by_arch(word, register_map)
+        lookup_hover_resp_by_arch(word, register_map, config)
     };
 
-    if reg_lookup.is_some() {
-        return reg_lookup;
+    if let Some(mut reg_resp) = reg_lookup {
+        // other arches have registers of the same name, e.g. ARM's `sp`
+        if get_hover_arches(word, register_map, config)
+            .iter()
+            .any(|arch| matches!(arch, Arch::X86 | Arch::X86_64))
+        {
+            if let (Some(note), HoverContents::Markup(ref mut content)) = (
+                get_partial_register_note(word, config),
+                &mut reg_resp.contents,
+            ) {
+                content.value.push_str("\n\n");
+                content.value.push_str(&note);
+            }
+        }
+        return Some(reg_resp);
+    }
+
+    let interrupt_resp = get_interrupt_resp(params, config, word, text_store);
+    if interrupt_resp.is_some() {
+        return interrupt_resp;
+    }
+
+    if config.opts.syscall_hover.unwrap_or(false) {
+        let syscall_resp = get_syscall_resp(params, config, word, text_store);
+        if syscall_resp.is_some() {
+            return syscall_resp;
+        }
+    }
+
+    if config.assemblers.nasm.unwrap_or(false) {
+        let struc_resp = text_store
+            .get_document(&params.text_document_position_params.text_document.uri)
+            .and_then(|doc| get_nasm_struc_resp(word, doc.get_content(None)));
+        if struc_resp.is_some() {
+            return struc_resp;
+        }
     }
 
     let label_data = get_label_resp(
@@ -676,99 +1845,558 @@ pub fn get_hover_resp<T: Hoverable, U: Hoverable, V: Hoverable>(
         return label_data;
     }
 
-    let demang = get_demangle_resp(word);
-    if demang.is_some() {
-        return demang;
+    // after labels, which are free to be named e.g. `c1`
+    if config.instruction_sets.arm.unwrap_or(false) {
+        let coproc_resp = get_arm_coproc_resp(word);
+        if coproc_resp.is_some() {
+            return coproc_resp;
+        }
     }
 
-    let include_path = get_include_resp(
-        &params.text_document_position_params.text_document.uri,
+    let alias_resp = get_alias_resp(word, config);
+    if alias_resp.is_some() {
+        return alias_resp;
+    }
+
+    let define_resp = get_define_resp(
         word,
-        include_dirs,
+        &params.text_document_position_params.text_document.uri,
+        defines,
     );
-    if include_path.is_some() {
-        return include_path;
+    if define_resp.is_some() {
+        return define_resp;
     }
 
-    None
+    let demang = get_demangle_resp(word, config);
+    if demang.is_some() {
+        return demang;
+    }
+
+    get_included_file_resp(params, word, text_store, include_dirs)
 }
 
-fn lookup_hover_resp_by_arch<T: Hoverable>(
-    word: &str,
-    map: &HashMap<(Arch, &str), T>,
-) -> Option<Hover> {
-    // ensure hovered text is always lowercase
-    let hovered_text = word.to_ascii_lowercase();
-    // switch over to vec?
-    let (x86_resp, x86_64_resp, z80_resp, arm_resp, arm64_resp, riscv_resp) =
-        search_for_hoverable_by_arch(&hovered_text, map);
-    match (
-        x86_resp.is_some(),
-        x86_64_resp.is_some(),
-        z80_resp.is_some(),
-        arm_resp.is_some(),
-        arm64_resp.is_some(),
-        riscv_resp.is_some(),
-    ) {
-        (true, _, _, _, _, _)
-        | (_, true, _, _, _, _)
-        | (_, _, true, _, _, _)
-        | (_, _, _, true, _, _)
-        | (_, _, _, _, true, _)
-        | (_, _, _, _, _, true) => {
-            let mut value = String::new();
-            if let Some(x86_resp) = x86_resp {
-                value += &format!("{x86_resp}");
-            }
-            if let Some(x86_64_resp) = x86_64_resp {
-                value += &format!(
-                    "{}{}",
-                    if value.is_empty() { "" } else { "\n\n" },
-                    x86_64_resp
-                );
-            }
-            if let Some(z80_resp) = z80_resp {
-                value += &format!("{}{}", if value.is_empty() { "" } else { "\n\n" }, z80_resp);
-            }
-            if let Some(arm_resp) = arm_resp {
-                value += &format!("{}{}", if value.is_empty() { "" } else { "\n\n" }, arm_resp);
-            }
-            if let Some(arm64_resp) = arm64_resp {
-                value += &format!(
-                    "{}{}",
-                    if value.is_empty() { "" } else { "\n\n" },
-                    arm64_resp
-                );
-            }
-            if let Some(riscv_resp) = riscv_resp {
-                value += &format!(
-                    "{}{}",
-                    if value.is_empty() { "" } else { "\n\n" },
-                    riscv_resp
-                );
+/// How many lines after a syscall number is loaded to look for the instruction
+/// making the syscall
+const SYSCALL_LOOKAHEAD_LINES: usize = 8;
+
+/// Parses an integer literal in decimal, `0x`-prefixed hex, or NASM's `h`-suffixed
+/// hex
+fn parse_int_literal(word: &str) -> Option<u32> {
+    let lower = word.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = lower.strip_suffix('h') {
+        // NASM requires a leading digit, e.g. `0ffh`
+        hex.starts_with(|c: char| c.is_ascii_digit())
+            .then(|| u32::from_str_radix(hex, 16).ok())?
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// Returns the first two operands of `line`, if it's a `mov` (or `li`) that could
+/// load an immediate into a register, lowercased and without their `$`, `#`, and
+/// `%` prefixes
+///
+/// e.g. `mov eax, 60`, `movl $60, %eax`, `mov x8, #93 // exit`, `li a7, 93`
+fn get_mov_operands(line: &str) -> Option<(String, String)> {
+    let (mnemonic, operands) = line.trim().split_once(char::is_whitespace)?;
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    if !(mnemonic.starts_with("mov") || mnemonic == "li") {
+        return None;
+    }
+    let mut operands = operands
+        .split(',')
+        .filter_map(|op| op.split_whitespace().next())
+        .map(|op| op.trim_start_matches(['$', '#', '%']).to_ascii_lowercase());
+
+    Some((operands.next()?, operands.next()?))
+}
+
+/// Returns the architecture whose Linux syscall ABI traps with the instruction on
+/// `line` and takes the syscall number in `number_reg`, if any
+fn get_syscall_arch(line: &str, number_reg: &str) -> Option<Arch> {
+    let mut tokens = line.split_whitespace();
+    let mnemonic = tokens.next()?.to_ascii_lowercase();
+    let operand = tokens
+        .next()
+        .map(|op| op.trim_start_matches(['$', '#']).to_ascii_lowercase());
+    match (mnemonic.as_str(), number_reg) {
+        ("syscall", "rax" | "eax") => Some(Arch::X86_64),
+        ("int", "eax") if operand.is_some_and(|op| op == "0x80" || op == "80h") => Some(Arch::X86),
+        ("svc" | "swi", "r7") => Some(Arch::ARM),
+        ("svc", "x8" | "w8") => Some(Arch::ARM64),
+        ("ecall", "a7" | "x17") => Some(Arch::RISCV),
+        _ => None,
+    }
+}
+
+/// Returns hover info for the Linux syscall numbered `word`, if it's the
+/// immediate loaded into the syscall number register by a `mov` (or `li`)
+/// shortly before a `syscall`/`int 0x80`/`svc`/`ecall` instruction
+fn get_syscall_resp(
+    params: &HoverParams,
+    config: &Config,
+    word: &str,
+    text_store: &TextDocuments,
+) -> Option<Hover> {
+    let number = parse_int_literal(word)?;
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let mut lines = doc
+        .get_content(None)
+        .lines()
+        .skip(pos_params.position.line as usize);
+
+    let (first, second) = get_mov_operands(lines.next()?)?;
+    let word = word.to_ascii_lowercase();
+    let number_reg = if first == word {
+        // AT&T syntax puts the destination last
+        second
+    } else if second == word {
+        first
+    } else {
+        return None;
+    };
+
+    let arch = lines
+        .take(SYSCALL_LOOKAHEAD_LINES)
+        .find_map(|line| get_syscall_arch(line.trim(), &number_reg))?;
+    // 32-bit syscalls can also be made from x86-64 code
+    let is_enabled =
+        config.is_isa_enabled(arch) || (arch == Arch::X86 && config.is_isa_enabled(Arch::X86_64));
+    if !is_enabled {
+        return None;
+    }
+    let (name, syscall_params) = crate::syscalls::get_syscall(arch, number)?;
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "**{name}** ({} Linux syscall {number})\n\n```c\n{name}({syscall_params})\n```",
+                arch.display_name()
+            ),
+        }),
+        range: None,
+    })
+}
+
+/// Returns a description of x86 software interrupt `vector`, for `int`
+fn get_x86_interrupt_desc(vector: u32) -> String {
+    match vector {
+        0x3 => String::from("Breakpoint exception, as raised by a debugger's `int3`"),
+        0x10 => String::from("BIOS video services, with the function number in `ah`"),
+        0x13 => String::from("BIOS disk services, with the function number in `ah`"),
+        0x15 => String::from("BIOS system services, with the function number in `ah`"),
+        0x16 => String::from("BIOS keyboard services, with the function number in `ah`"),
+        0x21 => String::from("MS-DOS services, with the function number in `ah`"),
+        0x80 => String::from(
+            "Legacy Linux syscall interrupt, using the 32-bit ABI even from 64-bit code. \
+            The syscall number is taken from `eax`, and its arguments from `ebx`, `ecx`, \
+            `edx`, `esi`, `edi`, and `ebp`",
+        ),
+        _ => format!(
+            "Software interrupt {vector:#x}, handled by entry {vector} of the interrupt \
+            descriptor table"
+        ),
+    }
+}
+
+/// Returns the syscall number loaded into one of `number_regs` by a `mov` (or
+/// `li`) in the few lines before the last of `lines`
+fn find_syscall_number(lines: &[&str], number_regs: &[&str]) -> Option<u32> {
+    lines
+        .iter()
+        .rev()
+        .skip(1)
+        .take(SYSCALL_LOOKAHEAD_LINES)
+        .find_map(|line| {
+            let (first, second) = get_mov_operands(line)?;
+            if number_regs.contains(&first.as_str()) {
+                parse_int_literal(&second)
+            } else if number_regs.contains(&second.as_str()) {
+                // AT&T syntax puts the destination last
+                parse_int_literal(&first)
+            } else {
+                None
             }
-            Some(Hover {
-                contents: HoverContents::Markup(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value,
-                }),
-                range: None,
+        })
+}
+
+/// Returns hover info for the immediate operand of an `int` (x86) or `svc`/`swi`
+/// (ARM) instruction, explaining what the interrupt or supervisor call does
+///
+/// If the `syscall_hover` option is set, the Linux syscall made by the instruction
+/// is noted as well
+fn get_interrupt_resp(
+    params: &HoverParams,
+    config: &Config,
+    word: &str,
+    text_store: &TextDocuments,
+) -> Option<Hover> {
+    let imm = parse_int_literal(word)?;
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let lines: Vec<&str> = doc
+        .get_content(None)
+        .lines()
+        .take(pos_params.position.line as usize + 1)
+        .collect();
+    let line = lines.last()?;
+    let stmt = line.split([';', '@']).next()?.split("//").next()?;
+    let stmt = stmt
+        .split_once(':')
+        .filter(|(label, _)| !label.contains(char::is_whitespace))
+        .map_or(stmt, |(_, rest)| rest)
+        .trim();
+    let (mnemonic, operand) = stmt.split_once(char::is_whitespace)?;
+    let operand = operand.trim().trim_start_matches(['$', '#']);
+    if !operand.eq_ignore_ascii_case(word) {
+        return None;
+    }
+
+    let x86_enabled = config.is_isa_enabled(Arch::X86) || config.is_isa_enabled(Arch::X86_64);
+    let (desc, syscall_arch, number_regs): (String, Arch, &[&str]) =
+        match mnemonic.to_ascii_lowercase().as_str() {
+            "int" if x86_enabled => (get_x86_interrupt_desc(imm), Arch::X86, &["eax"]),
+            "svc" | "swi" if config.is_isa_enabled(Arch::ARM) => (
+                String::from(
+                    "Supervisor call, trapping into the kernel. Linux (EABI) ignores the \
+                    immediate, taking the syscall number from `r7` and its arguments from \
+                    `r0`-`r6`",
+                ),
+                Arch::ARM,
+                &["r7"],
+            ),
+            "svc" if config.is_isa_enabled(Arch::ARM64) => (
+                String::from(
+                    "Supervisor call, trapping into the kernel. Linux ignores the immediate, \
+                    taking the syscall number from `x8` and its arguments from `x0`-`x5`",
+                ),
+                Arch::ARM64,
+                &["x8", "w8"],
+            ),
+            _ => return None,
+        };
+
+    let mut value = format!("**{} {operand}**\n\n{desc}", mnemonic.to_ascii_lowercase());
+    let makes_syscall = syscall_arch != Arch::X86 || imm == 0x80;
+    if makes_syscall && config.opts.syscall_hover.unwrap_or(false) {
+        if let Some((name, number)) = find_syscall_number(&lines, number_regs).and_then(|number| {
+            crate::syscalls::get_syscall(syscall_arch, number).map(|(name, _)| (name, number))
+        }) {
+            value = format!(
+                "{value}\n\nSyscall: **{name}** ({} Linux syscall {number})",
+                syscall_arch.display_name()
+            );
+        }
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Returns hover info for the file named in the `.include`/`.incbin` on the
+/// cursor's line, or for the file named `word` if there isn't one
+fn get_included_file_resp(
+    params: &HoverParams,
+    word: &str,
+    text_store: &TextDocuments,
+    include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
+) -> Option<Hover> {
+    let uri = &params.text_document_position_params.text_document.uri;
+    // prefer the quoted name, which may contain characters that end a word
+    let included = text_store
+        .get_document(uri)
+        .and_then(|doc| {
+            get_included_file(get_line_from_pos_params(
+                doc,
+                &params.text_document_position_params,
+            ))
+        })
+        .or_else(|| {
+            // a bare word is only looked for in the include directories
+            (!word.is_empty()).then_some(IncludedFile {
+                name: word,
+                is_incbin: false,
+                is_quoted: false,
             })
+        })?;
+
+    get_include_resp(uri, &included, include_dirs)
+}
+
+/// Adds the kind and byte range of the tree-sitter node under the cursor to `hover`
+///
+/// The node info is separated from any documentation `hover` already contains. If
+/// there was no documentation for the hovered word, a hover containing only the
+/// node info is returned instead. Intended for diagnosing grammar and query issues,
+/// see the `debug_hover` option
+pub fn add_debug_hover_info(
+    hover: Option<Hover>,
+    params: &HoverParams,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+) -> Option<Hover> {
+    let uri = &params.text_document_position_params.text_document.uri;
+    let pos = params.text_document_position_params.position;
+    let debug_info = text_store.get_document(uri).and_then(|doc| {
+        let curr_doc = doc.get_content(None);
+        let tree_entry = tree_store.get_mut(uri)?;
+        tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
+        let point = position_to_point(curr_doc, pos);
+        let node = tree_entry
+            .tree
+            .as_ref()?
+            .root_node()
+            .descendant_for_point_range(point, point)?;
+        let parent_kind = node.parent().map_or("none", |parent| parent.kind());
+        Some(format!(
+            "*tree-sitter*: `{}` (parent `{parent_kind}`), bytes {}..{}",
+            node.kind(),
+            node.start_byte(),
+            node.end_byte()
+        ))
+    })?;
+
+    let Some(mut hover) = hover else {
+        return Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: debug_info,
+            }),
+            range: None,
+        });
+    };
+    if let HoverContents::Markup(ref mut content) = hover.contents {
+        content.value.push_str("\n\n---\n\n");
+        content.value.push_str(&debug_info);
+    }
+
+    Some(hover)
+}
+
+/// Renders a markdown table of the opcode encodings of each of `word`'s forms,
+/// preferring the x86-64 documentation over x86's if both are enabled
+fn get_opcodes_table(
+    word: &str,
+    instruction_map: &NameToInstructionMap,
+    config: &Config,
+) -> Option<String> {
+    let word = word.to_ascii_lowercase();
+    let instr = [Arch::X86_64, Arch::X86]
+        .into_iter()
+        .filter(|&arch| config.is_isa_enabled(arch))
+        .find_map(|arch| instruction_map.get(&(arch, word.as_str())))?;
+
+    let mut rows = Vec::new();
+    for form in &instr.forms {
+        let operands = form
+            .operands
+            .iter()
+            .map(|op| op.type_.as_ref())
+            .collect::<Vec<&str>>()
+            .join(", ");
+        for encoding in &form.encodings {
+            let row = format!("| `{} {operands}` | `{encoding}` |", instr.name);
+            if !rows.contains(&row) {
+                rows.push(row);
+            }
         }
-        _ => {
-            // don't know of this word
-            None
+    }
+    if rows.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "**Encodings**\n\n| Form | Opcode |\n| --- | --- |\n{}",
+        rows.join("\n")
+    ))
+}
+
+/// Assembles the hovered line if it consists of a single instruction with `word`
+/// as its mnemonic, returning the resulting encoding
+fn get_encoding_resp(
+    params: &HoverParams,
+    config: &Config,
+    word: &str,
+    text_store: &TextDocuments,
+    compile_cmds: &CompilationDatabase,
+) -> Option<String> {
+    // directives that change how the instruction on the hovered line is assembled
+    const MODE_DIRECTIVES: [&str; 6] = [
+        ".intel_syntax",
+        ".att_syntax",
+        ".code16",
+        ".code32",
+        ".code64",
+        ".arch",
+    ];
+
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let line_num = pos_params.position.line as usize;
+    let mut lines = doc.get_content(None).lines();
+    let prelude: Vec<&str> = lines
+        .by_ref()
+        .take(line_num)
+        .map(str::trim)
+        .filter(|line| MODE_DIRECTIVES.iter().any(|dir| line.starts_with(dir)))
+        .collect();
+
+    // strip trailing comments, and only assemble lines that start with the mnemonic
+    let line = lines.next()?;
+    let instr = line.split(['#', ';']).next()?.trim();
+    let mnemonic = instr.split_whitespace().next()?;
+    if !mnemonic.eq_ignore_ascii_case(word) {
+        return None;
+    }
+
+    // assemble with the file's own compile command if it has one, as diagnostics do
+    let uri = &pos_params.text_document.uri;
+    let source_path = uri_to_path(uri);
+    let root_cmds;
+    let compile_cmds = match config.project_root {
+        Some(ref root) if compile_cmds.is_empty() => {
+            root_cmds = get_root_compile_cmds(root).unwrap_or_default();
+            &root_cmds
+        }
+        _ => compile_cmds,
+    };
+    let default_cmd = get_default_compile_cmd(uri, config);
+    let compile_cmd = source_path
+        .as_deref()
+        .and_then(|path| get_file_compile_cmds(compile_cmds, path).next())
+        .unwrap_or(&default_cmd);
+
+    get_instr_encoding(config, compile_cmd, &prelude, instr)
+}
+
+/// Looks up `word` in `map` for each architecture enabled in `config`, joining the
+/// matching entries into a single hover. If the `hover_single_arch` option is set,
+/// only the first match is shown, in the order x86, x86-64, z80, arm, arm64, riscv, 6502,
+/// m68k
+fn lookup_hover_resp_by_arch<T: Hoverable>(
+    word: &str,
+    map: &HashMap<(Arch, &str), T>,
+    config: &Config,
+) -> Option<Hover> {
+    // ensure hovered text is always lowercase
+    let hovered_text = word.to_ascii_lowercase();
+    let value = get_hover_arches(&hovered_text, map, config)
+        .into_iter()
+        .filter_map(|arch| map.get(&(arch, hovered_text.as_str())))
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    if value.is_empty() {
+        // don't know of this word
+        return None;
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Returns the architectures whose entries for `word` in `map` are shown by
+/// `lookup_hover_resp_by_arch`, in the order they're shown
+fn get_hover_arches<T: Hoverable>(
+    word: &str,
+    map: &HashMap<(Arch, &str), T>,
+    config: &Config,
+) -> Vec<Arch> {
+    let hovered_text = word.to_ascii_lowercase();
+    // a file's modeline can narrow down the arches loaded for the project
+    let mut resps: Vec<(Option<&T>, Arch)> =
+        <[Option<&T>; 8]>::from(search_for_hoverable_by_arch(&hovered_text, map))
+            .into_iter()
+            .zip(SEARCH_ARCH_ORDER)
+            .collect();
+    sort_by_arch_priority(&mut resps, config);
+    let mut arches: Vec<Arch> = resps
+        .into_iter()
+        .filter(|&(resp, arch)| resp.is_some() && config.is_isa_enabled(arch))
+        .map(|(_, arch)| arch)
+        .collect();
+    if config.opts.hover_single_arch.unwrap_or(false) {
+        arches.truncate(1);
+    }
+
+    arches
+}
+
+/// Returns hover documentation for the instruction `word` as written in Go
+/// assembly, for the `prefer_go_names` option
+///
+/// Go names like `MOVOU` are looked up as is, before falling back to the usual
+/// lowercase lookup. Only the forms Go has a name for are shown
+fn lookup_go_instr_hover_resp(
+    word: &str,
+    instruction_map: &NameToInstructionMap,
+    config: &Config,
+) -> Option<Hover> {
+    let upper = word.to_ascii_uppercase();
+    let lower = word.to_ascii_lowercase();
+    let instrs: Vec<(Arch, Instruction)> = SEARCH_ARCH_ORDER
+        .into_iter()
+        .filter_map(|arch| {
+            let instr = instruction_map
+                .get(&(arch, upper.as_str()))
+                .or_else(|| instruction_map.get(&(arch, lower.as_str())))?;
+            Some((arch, get_go_forms(instr, &upper)))
+        })
+        .collect();
+    let go_map: HashMap<(Arch, &str), &Instruction> = instrs
+        .iter()
+        .map(|(arch, instr)| ((*arch, lower.as_str()), instr))
+        .collect();
+
+    lookup_hover_resp_by_arch(&lower, &go_map, config)
+}
+
+/// Returns `instr` with only the forms that have a Go name, without their GAS
+/// names, and with those named `go_name` first. Instructions Go has no names for
+/// at all are returned as is
+fn get_go_forms(instr: &Instruction, go_name: &str) -> Instruction {
+    let mut instr = instr.clone();
+    if instr.forms.iter().any(|form| form.go_name.is_some()) {
+        instr.forms.retain(|form| form.go_name.is_some());
+        for form in &mut instr.forms {
+            form.gas_name = None;
         }
+        instr
+            .forms
+            .sort_by_key(|form| form.go_name.as_deref() != Some(go_name));
     }
+
+    instr
 }
 
 fn lookup_hover_resp_by_assembler<T: Hoverable>(
     word: &str,
     map: &HashMap<(Assembler, &str), T>,
+    config: &Config,
 ) -> Option<Hover> {
     let hovered_directive = word.to_ascii_lowercase();
     let (gas_resp, go_resp, masm_resp, nasm_resp) =
         search_for_hoverable_by_assembler(&hovered_directive, map);
+    // a file's modeline can narrow down the assemblers loaded for the project
+    let enabled = |assembler| config.is_assembler_enabled(assembler);
+    let gas_resp = gas_resp.filter(|_| enabled(Assembler::Gas));
+    let go_resp = go_resp.filter(|_| enabled(Assembler::Go));
+    let masm_resp = masm_resp.filter(|_| enabled(Assembler::Masm));
+    let nasm_resp = nasm_resp.filter(|_| enabled(Assembler::Nasm));
 
     match (
         gas_resp.is_some(),
@@ -817,6 +2445,19 @@ fn lookup_hover_resp_by_assembler<T: Hoverable>(
     }
 }
 
+/// Returns `true` if the word at the cursor is written with a `%` prefix, e.g.
+/// `%define`, which `find_word_at_pos` leaves out of the word itself
+fn has_percent_prefix(params: &HoverParams, text_store: &TextDocuments) -> bool {
+    let pos_params = &params.text_document_position_params;
+    let Some(doc) = text_store.get_document(&pos_params.text_document.uri) else {
+        return false;
+    };
+    let line = get_line_from_pos_params(doc, pos_params);
+    let ((start, _), _) = find_word_at_pos(line, pos_params.position.character as usize);
+
+    line[..start].ends_with('%')
+}
+
 /// Returns the data associated with a given label `word`
 fn get_label_resp(
     word: &str,
@@ -831,7 +2472,7 @@ fn get_label_resp(
             if let Some(ref tree) = tree_entry.tree {
                 static QUERY_LABEL_DATA: Lazy<tree_sitter::Query> = Lazy::new(|| {
                     tree_sitter::Query::new(
-                        &tree_sitter_asm::language(),
+                        &asm_language(),
                         "(
                             (label (ident) @label)
                             .
@@ -853,10 +2494,7 @@ fn get_label_resp(
 
                 for match_ in matches_iter {
                     let caps = match_.captures;
-                    if caps.len() != 2
-                        || caps[0].node.end_byte() >= curr_doc.len()
-                        || caps[1].node.end_byte() >= curr_doc.len()
-                    {
+                    if caps.len() != 2 {
                         continue;
                     }
                     let label_text = caps[0].node.utf8_text(curr_doc);
@@ -883,90 +2521,1776 @@ fn get_label_resp(
     None
 }
 
-fn get_demangle_resp(word: &str) -> Option<Hover> {
-    let name = Name::new(word, NameMangling::Mangled, Language::Unknown);
-    let demangled = name.demangle(DemangleOptions::complete());
-    if let Some(demang) = demangled {
-        let value = demang;
-        return Some(Hover {
-            contents: HoverContents::Markup(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value,
-            }),
-            range: None,
-        });
-    }
-
-    None
+/// Splits a label definition, e.g. `loop:` or `1:`, off the start of `line`,
+/// returning the label and the rest of the line
+fn split_label_def(line: &str) -> Option<(&str, &str)> {
+    let (label, rest) = line.trim_start().split_once(':')?;
+    (!label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '$' | '@')))
+    .then_some((label, rest))
 }
 
-fn get_include_resp(
-    source_file: &Uri,
-    filename: &str,
-    include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
-) -> Option<Hover> {
-    let mut paths = String::new();
-
-    type DirIter<'a> = Box<dyn Iterator<Item = &'a PathBuf> + 'a>;
-    let mut dir_iter = include_dirs.get(&SourceFile::All).map_or_else(
-        || Box::new(std::iter::empty()) as DirIter,
-        |dirs| Box::new(dirs.iter()) as DirIter,
-    );
+/// Counts the `Nf` and `Nb` references resolving to the GAS numeric label
+/// definition under the cursor, e.g. `1:`
+///
+/// Numeric labels tend to be reused in every routine, so only the region between
+/// the enclosing named labels is scanned
+fn get_numeric_label_resp(params: &HoverParams, text_store: &TextDocuments) -> Option<Hover> {
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let lines: Vec<&str> = doc.get_content(None).lines().collect();
+    let row = pos_params.position.line as usize;
+    let line = lines.get(row)?;
+    let (label, _) = split_label_def(line)?;
+    if !label.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let label_start = line.len() - line.trim_start().len();
+    let col = utf16_col_to_byte_col(line, pos_params.position.character);
+    if col < label_start || col > label_start + label.len() {
+        return None;
+    }
 
-    if let Ok(src_path) = PathBuf::from(source_file.as_str()).canonicalize() {
-        if let Some(dirs) = include_dirs.get(&SourceFile::File(src_path)) {
-            dir_iter = Box::new(dir_iter.chain(dirs.iter()));
-        }
+    fn named_label(line: &str) -> Option<&str> {
+        split_label_def(line)
+            .map(|(name, _)| name)
+            .filter(|name| !name.chars().all(|c| c.is_ascii_digit()))
     }
+    let scope_start = lines[..row]
+        .iter()
+        .rposition(|line| named_label(line).is_some());
+    let scope_end = lines[row..]
+        .iter()
+        .position(|line| named_label(line).is_some())
+        .map_or(lines.len(), |offset| row + offset);
 
-    for dir in dir_iter {
-        match std::fs::read_dir(dir) {
-            Ok(dir_reader) => {
-                for file in dir_reader {
-                    match file {
-                        Ok(f) => {
-                            if f.file_name() == filename {
-                                paths += &format!("file://{}\n", f.path().display());
-                            }
-                        }
-                        Err(e) => {
-                            error!(
-                                "Failed to read item in {} - Error {e}",
-                                dir.as_path().display()
-                            );
-                        }
-                    };
+    let fwd_ref = format!("{label}f");
+    let back_ref = format!("{label}b");
+    let (mut fwd_count, mut back_count) = (0, 0);
+    for (i, line) in lines
+        .iter()
+        .enumerate()
+        .take(scope_end)
+        .skip(scope_start.map_or(0, |start| start + 1))
+    {
+        let stmt = match split_label_def(line) {
+            Some((def, rest)) if def == label => {
+                // `Nf` before an earlier definition resolves to that one, and `Nb`
+                // after a later definition resolves to it instead
+                match i.cmp(&row) {
+                    std::cmp::Ordering::Less => fwd_count = 0,
+                    std::cmp::Ordering::Equal => {}
+                    std::cmp::Ordering::Greater => break,
                 }
+                rest
             }
-            Err(e) => {
-                error!(
-                    "Failed to create directory reader for {} - Error {e}",
-                    dir.as_path().display()
-                );
+            Some((_, rest)) => rest,
+            None => line,
+        };
+        let stmt = stmt
+            .split(['#', ';'])
+            .next()
+            .and_then(|stmt| stmt.split("//").next())
+            .unwrap_or("");
+        for token in stmt.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+            if i < row && token == fwd_ref {
+                fwd_count += 1;
+            } else if i >= row && token == back_ref {
+                back_count += 1;
             }
         }
     }
 
-    if paths.is_empty() {
-        None
+    let scope = scope_start
+        .and_then(|start| named_label(lines[start]))
+        .map(|name| format!(" in `{name}`"))
+        .unwrap_or_default();
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "Numeric label `{label}`{scope}\n\n + `{fwd_ref}` references: {fwd_count}\n + `{back_ref}` references: {back_count}"
+            ),
+        }),
+        range: None,
+    })
+}
+
+/// Returns the symbol of an ARM literal pool operand, e.g. `my_data` in
+/// `ldr r0, =my_data`, if the cursor is on it (`=` included)
+///
+/// `character` is the cursor's LSP column, in UTF-16 code units
+fn get_literal_pool_symbol(line: &str, character: u32) -> Option<&str> {
+    let col = utf16_col_to_byte_col(line, character);
+    let sym_start = if line[col..].starts_with('=') {
+        col + 1
     } else {
-        Some(Hover {
-            contents: HoverContents::Markup(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: paths,
-            }),
-            range: None,
-        })
+        find_word_at_pos(line, character as usize).0 .0
+    };
+    let before_sym = line[..sym_start].strip_suffix('=')?;
+    // only operands, which rules out `.equ`-style assignments like `name = value`
+    if !before_sym.trim_end().ends_with(',') {
+        return None;
+    }
+    let sym_len = line[sym_start..]
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.' || c == '$'))
+        .unwrap_or(line.len() - sym_start);
+    let sym = &line[sym_start..sym_start + sym_len];
+    // `=0x1234` loads a constant rather than a symbol's address
+    if sym.is_empty() || sym.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
     }
+
+    Some(sym)
 }
 
-/// Filter out duplicate completion suggestions
-fn filtered_comp_list(comps: &[CompletionItem]) -> Vec<CompletionItem> {
-    let mut seen = HashSet::new();
+/// Returns hover info for ARM literal pool operands like `=my_data`, which are
+/// loaded from a constant the assembler places in a nearby literal pool
+fn get_literal_pool_resp(
+    params: &HoverParams,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+) -> Option<Hover> {
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let line = get_line_from_pos_params(doc, pos_params);
+    let sym = get_literal_pool_symbol(line, pos_params.position.character)?;
+
+    let note = format!(
+        "`={sym}` loads the address of `{sym}` through the literal pool: the assembler \
+        stores the address in a pool placed after the code (at the next `.ltorg`, or the \
+        end of the section), and turns the `ldr` into a PC-relative load of that entry"
+    );
+    let value = match get_label_resp(sym, &pos_params.text_document.uri, text_store, tree_store) {
+        Some(Hover {
+            contents: HoverContents::Markup(label_data),
+            ..
+        }) => format!("{}\n\n{note}", label_data.value),
+        _ => note,
+    };
 
-    comps
-        .iter()
-        .filter(|comp_item| {
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Returns the displacement of a RIP-relative memory operand, e.g. `label + 8` in
+/// `[rip + label + 8]`, `[rel label + 8]`, or `label+8(%rip)`
+fn get_rip_relative_disp(operand: &str) -> Option<String> {
+    // AT&T syntax
+    if let Some((disp, base)) = operand
+        .strip_suffix(')')
+        .and_then(|operand| operand.rsplit_once('('))
+    {
+        return base
+            .trim()
+            .eq_ignore_ascii_case("%rip")
+            .then(|| disp.trim().to_string());
+    }
+
+    // Intel syntax, possibly with a size like `qword ptr` in front
+    let inner = operand[operand.find('[')? + 1..].strip_suffix(']')?.trim();
+    if inner
+        .get(..4)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("rel "))
+    {
+        return Some(inner[4..].trim().to_string());
+    }
+    let mut disp = Vec::new();
+    let mut has_rip = false;
+    for term in inner.split('+').map(str::trim) {
+        if term.eq_ignore_ascii_case("rip") {
+            has_rip = true;
+        } else {
+            disp.push(term);
+        }
+    }
+
+    has_rip.then(|| disp.join(" + "))
+}
+
+/// Returns the displacement of the RIP-relative memory operand of the `lea` on
+/// `line`, if the cursor (at byte column `col`) is in it
+fn get_lea_rip_disp(line: &str, col: usize) -> Option<String> {
+    let code = line.split([';', '#']).next().unwrap_or(line);
+    let trimmed = code.trim_start();
+    let mnemonic_start = code.len() - trimmed.len();
+    let mnemonic_len = trimmed.find(char::is_whitespace)?;
+    if !matches!(
+        trimmed[..mnemonic_len].to_ascii_lowercase().as_str(),
+        "lea" | "leaq" | "leal" | "leaw"
+    ) {
+        return None;
+    }
+
+    // find the operand the cursor is in, commas inside of `[...]` or `(...)` don't
+    // separate operands
+    let ops_start = mnemonic_start + mnemonic_len;
+    let mut operand_start = ops_start;
+    let mut depth = 0i32;
+    for (idx, c) in code[ops_start..]
+        .char_indices()
+        .map(|(idx, c)| (idx + ops_start, c))
+        .chain(std::iter::once((code.len(), ',')))
+    {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth -= 1,
+            ',' if depth <= 0 => {
+                if (operand_start..idx).contains(&col) {
+                    return get_rip_relative_disp(code[operand_start..idx].trim());
+                }
+                operand_start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Returns hover info for the RIP-relative memory operand of a `lea`, e.g.
+/// `[rip + label]` or `label(%rip)`, along with what's known about the label
+fn get_lea_rip_resp(
+    params: &HoverParams,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+) -> Option<Hover> {
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let line = get_line_from_pos_params(doc, pos_params);
+    let col = utf16_col_to_byte_col(line, pos_params.position.character);
+    let disp = get_lea_rip_disp(line, col)?;
+    let disp = if disp.is_empty() { "0" } else { disp.as_str() };
+
+    let mut value = format!(
+        "RIP-relative address: `{disp}` is assembled as an offset from `rip`, the address \
+        of the next instruction, so `lea` computes the address without accessing memory \
+        and the code stays position-independent"
+    );
+    let label = disp
+        .split(['+', '-', '*', ' '])
+        .map(|term| term.split('@').next().unwrap_or(term).trim())
+        .find(|term| term.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '.'));
+    if let Some(label) = label {
+        let def_line = doc
+            .get_content(None)
+            .lines()
+            .position(|line| split_label_def(line).is_some_and(|(name, _)| name == label));
+        if let Some(def_line) = def_line {
+            value.push_str("\n\nLabel `");
+            value.push_str(label);
+            value.push_str("` is defined on line ");
+            value.push_str(&(def_line + 1).to_string());
+            if let Some(Hover {
+                contents: HoverContents::Markup(label_data),
+                ..
+            }) = get_label_resp(label, &pos_params.text_document.uri, text_store, tree_store)
+            {
+                value.push_str(": ");
+                value.push_str(&label_data.value);
+            }
+        } else {
+            value.push_str("\n\n`");
+            value.push_str(label);
+            value.push_str("` isn't defined in this file, the linker resolves it");
+        }
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// The x86 general purpose registers by their 64, 32, 16, and 8-bit names, along
+/// with the name of bits 8-15 for those that have one
+pub const X86_GP_REGISTER_FAMILIES: [(&str, &str, &str, &str, Option<&str>); 16] = [
+    ("rax", "eax", "ax", "al", Some("ah")),
+    ("rbx", "ebx", "bx", "bl", Some("bh")),
+    ("rcx", "ecx", "cx", "cl", Some("ch")),
+    ("rdx", "edx", "dx", "dl", Some("dh")),
+    ("rsi", "esi", "si", "sil", None),
+    ("rdi", "edi", "di", "dil", None),
+    ("rbp", "ebp", "bp", "bpl", None),
+    ("rsp", "esp", "sp", "spl", None),
+    ("r8", "r8d", "r8w", "r8b", None),
+    ("r9", "r9d", "r9w", "r9b", None),
+    ("r10", "r10d", "r10w", "r10b", None),
+    ("r11", "r11d", "r11w", "r11b", None),
+    ("r12", "r12d", "r12w", "r12b", None),
+    ("r13", "r13d", "r13w", "r13b", None),
+    ("r14", "r14d", "r14w", "r14b", None),
+    ("r15", "r15d", "r15w", "r15b", None),
+];
+
+/// Returns a note on which bits of its full register the x86 general purpose
+/// register `word` covers, and what writing it does to the rest of them
+///
+/// The full register is the 64-bit one if x86-64 is enabled, and the 32-bit one
+/// otherwise. Full registers, and those only valid in 64-bit mode when x86-64
+/// isn't enabled, get no note.
+fn get_partial_register_note(word: &str, config: &Config) -> Option<String> {
+    let name = word.trim_start_matches('%').to_ascii_lowercase();
+    let name = name.as_str();
+    let &(r64, r32, r16, r8, r8_high) =
+        X86_GP_REGISTER_FAMILIES
+            .iter()
+            .find(|(r64, r32, r16, r8, r8_high)| {
+                [*r64, *r32, *r16, *r8].contains(&name) || *r8_high == Some(name)
+            })?;
+    let long_mode = config.instruction_sets.x86_64.unwrap_or(false);
+    // r8-r15, and the low bytes of rsi, rdi, rbp, and rsp, need a REX prefix
+    let only_64bit = r64.as_bytes()[1].is_ascii_digit() || (name == r8 && r8_high.is_none());
+    if !long_mode && only_64bit {
+        return None;
+    }
+    let full = if long_mode { r64 } else { r32 };
+    let bits = if name == full {
+        return None;
+    } else if name == r32 {
+        "0-31"
+    } else if name == r16 {
+        "0-15"
+    } else if name == r8 {
+        "0-7"
+    } else {
+        "8-15"
+    };
+
+    let effect = if name == r32 {
+        // long mode zero extends 32-bit results, breaking any dependency on the
+        // register's previous value
+        format!("zeroes bits 32-63 of `{full}`")
+    } else {
+        format!(
+            "merges the result into `{full}`, leaving its other bits unchanged, so the \
+            write depends on the previous value of `{full}`"
+        )
+    };
+    let mut note =
+        format!("Partial register: `{name}` is bits {bits} of `{full}`. Writing `{name}` {effect}");
+    if long_mode && Some(name) == r8_high {
+        note.push_str(". `");
+        note.push_str(name);
+        note.push_str("` can't be used in an instruction with a REX prefix");
+    }
+    Some(note)
+}
+
+/// Returns `true` if `name` is one of the AVX-512 opmask registers `k0`-`k7`
+fn is_mask_register(name: &str) -> bool {
+    let name = name.trim_start_matches('%');
+    name.len() == 2
+        && name.starts_with(['k', 'K'])
+        && name.as_bytes()[1].is_ascii_digit()
+        && name.as_bytes()[1] <= b'7'
+}
+
+/// Returns the byte range of the contents of the `{...}` operand decorator the
+/// cursor is in, if any
+fn get_evex_decorator_at(line: &str, col: usize) -> Option<(usize, usize)> {
+    let before = line.get(..col)?;
+    let start = before.rfind('{')? + 1;
+    if before[start..].contains('}') {
+        return None;
+    }
+    let end = col + line[col..].find(['{', '}'])?;
+    if line.as_bytes()[end] != b'}' {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+/// Returns hover info for the AVX-512 (EVEX) operand decorators, i.e. write masks
+/// like `{k1}`, zero-masking `{z}`, embedded rounding `{rn-sae}`, and broadcasts
+/// like `{1to16}`
+fn get_evex_decorator_resp(params: &HoverParams, text_store: &TextDocuments) -> Option<Hover> {
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let line = get_line_from_pos_params(doc, pos_params);
+    let col = utf16_col_to_byte_col(line, pos_params.position.character);
+    let (start, end) = get_evex_decorator_at(line, col)?;
+    let decorator = line[start..end].trim().to_ascii_lowercase();
+
+    let value = match decorator.trim_start_matches('%') {
+        "k0" => "**Write mask** `{k0}`\n\n`k0` can't be used as a write mask, its encoding \
+            means the instruction is unmasked"
+            .to_string(),
+        mask @ ("k1" | "k2" | "k3" | "k4" | "k5" | "k6" | "k7") => format!(
+            "**Write mask** `{{{decorator}}}`\n\nOnly the destination elements whose bit in \
+            `{mask}` is set are written. The others keep their previous value, or are zeroed \
+            if `{{z}}` is also given"
+        ),
+        "z" => "**Zero-masking** `{z}`\n\nDestination elements whose write mask bit is clear \
+            are zeroed instead of keeping their previous value"
+            .to_string(),
+        "sae" => "**Suppress all exceptions** `{sae}`\n\nNo floating-point exceptions are \
+            reported for this instruction"
+            .to_string(),
+        rounding @ ("rn-sae" | "rd-sae" | "ru-sae" | "rz-sae") => {
+            let mode = match rounding {
+                "rn-sae" => "to nearest (even)",
+                "rd-sae" => "down (toward -∞)",
+                "ru-sae" => "up (toward +∞)",
+                _ => "toward zero",
+            };
+            format!(
+                "**Embedded rounding** `{{{rounding}}}`\n\nRounds {mode} for this instruction \
+                only, overriding `MXCSR.RC`. Implies `{{sae}}`, so no floating-point exceptions \
+                are reported"
+            )
+        }
+        broadcast => {
+            let count = broadcast
+                .strip_prefix("1to")?
+                .parse::<u8>()
+                .ok()
+                .filter(|count| [2, 4, 8, 16, 32].contains(count))?;
+            format!(
+                "**Embedded broadcast** `{{{broadcast}}}`\n\nThe single element loaded from the \
+                memory operand is repeated across all {count} elements of the vector"
+            )
+        }
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Returns hover info for ARM coprocessor operands, i.e. the coprocessor names
+/// `p0`-`p15` and coprocessor registers `c0`-`c15` used by `mcr`/`mrc` and friends
+fn get_arm_coproc_resp(word: &str) -> Option<Hover> {
+    let word = word.to_ascii_lowercase();
+    let (prefix, num) = word.split_at_checked(1)?;
+    // reject things like `c01`
+    if num.len() > 1 && num.starts_with('0') {
+        return None;
+    }
+    let num = num.parse::<u8>().ok().filter(|&num| num <= 15)?;
+    let value = match prefix {
+        "p" => {
+            let usage = match num {
+                15 => " (System control coprocessor, CP15)",
+                14 => " (Debug coprocessor, CP14)",
+                10 | 11 => " (Floating-point and Advanced SIMD)",
+                _ => "",
+            };
+            format!("P{num} [arm]\nCoprocessor {num}{usage}")
+        }
+        "c" => format!(
+            "C{num} [arm]\nCoprocessor register {num}, selects a register of the target coprocessor \
+            together with the opcode operands of `mcr`/`mrc`"
+        ),
+        _ => return None,
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// 32-bit ARM mnemonics that can only be assembled in one instruction set state,
+/// along with a short description
+const ARM_STATE_EXCLUSIVE: [(&str, ArmState, &str); 8] = [
+    ("cbz", ArmState::Thumb, "Compare and Branch on Zero"),
+    ("cbnz", ArmState::Thumb, "Compare and Branch on Nonzero"),
+    ("tbb", ArmState::Thumb, "Table Branch Byte"),
+    ("tbh", ArmState::Thumb, "Table Branch Halfword"),
+    ("rsc", ArmState::Arm, "Reverse Subtract with Carry"),
+    (
+        "rscs",
+        ArmState::Arm,
+        "Reverse Subtract with Carry, setting flags",
+    ),
+    ("swp", ArmState::Arm, "Swap"),
+    ("swpb", ArmState::Arm, "Swap Byte"),
+];
+
+/// Checks if `stmt` is an `.arm`/`.thumb` (or `.code 32`/`.code 16`) directive,
+/// returning the state it switches to
+fn get_arm_state_directive(stmt: &str) -> Option<ArmState> {
+    let mut tokens = stmt.split_whitespace();
+    match tokens.next()?.to_ascii_lowercase().as_str() {
+        ".arm" => Some(ArmState::Arm),
+        ".thumb" => Some(ArmState::Thumb),
+        ".code" => match tokens.next()? {
+            "32" => Some(ArmState::Arm),
+            "16" => Some(ArmState::Thumb),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Strips any comment and leading label from an ARM source line
+fn get_arm_stmt(line: &str) -> &str {
+    let stmt = line
+        .split('@')
+        .next()
+        .and_then(|stmt| stmt.split("//").next())
+        .unwrap_or_default()
+        .trim();
+    stmt.split_once(':')
+        .filter(|(label, _)| !label.contains(char::is_whitespace))
+        .map_or(stmt, |(_, rest)| rest.trim_start())
+}
+
+/// Returns the x86 operating mode (16, 32, or 64) set by `stmt` if it's a mode
+/// directive, like GAS's `.code32` or NASM's `bits 32`
+fn get_x86_mode_directive(stmt: &str) -> Option<u8> {
+    let stmt = stmt
+        .split([';', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    let mode = match stmt.split_whitespace().collect::<Vec<&str>>()[..] {
+        [".code16" | ".code16gcc" | "use16"] | ["bits", "16"] => 16,
+        [".code32" | "use32"] | ["bits", "32"] => 32,
+        [".code64" | "use64"] | ["bits", "64"] => 64,
+        _ => return None,
+    };
+
+    Some(mode)
+}
+
+/// Returns the x86 operating mode in effect at `line`, as set by the last mode
+/// directive before it, or `None` if there isn't one
+#[must_use]
+pub fn get_x86_mode(curr_doc: &str, line: usize) -> Option<u8> {
+    curr_doc
+        .lines()
+        .take(line)
+        .filter_map(get_x86_mode_directive)
+        .last()
+}
+
+/// Returns the instruction set state in effect at `line`, as set by the last
+/// state directive before it, or `None` if there isn't one
+#[must_use]
+pub fn get_arm_state(curr_doc: &str, line: usize) -> Option<ArmState> {
+    curr_doc
+        .lines()
+        .take(line)
+        .filter_map(|line| get_arm_state_directive(get_arm_stmt(line)))
+        .last()
+}
+
+/// The condition code suffixes of 32-bit ARM instructions, e.g. the `eq` in
+/// `moveq`, along with the flags they test
+pub const ARM_CONDITION_CODES: [(&str, &str); 17] = [
+    ("eq", "Equal (Z set)"),
+    ("ne", "Not equal (Z clear)"),
+    ("cs", "Carry set (C set)"),
+    ("hs", "Unsigned higher or same (C set)"),
+    ("cc", "Carry clear (C clear)"),
+    ("lo", "Unsigned lower (C clear)"),
+    ("mi", "Negative (N set)"),
+    ("pl", "Positive or zero (N clear)"),
+    ("vs", "Overflow (V set)"),
+    ("vc", "No overflow (V clear)"),
+    ("hi", "Unsigned higher (C set and Z clear)"),
+    ("ls", "Unsigned lower or same (C clear or Z set)"),
+    ("ge", "Signed greater or equal (N == V)"),
+    ("lt", "Signed less (N != V)"),
+    ("gt", "Signed greater (Z clear and N == V)"),
+    ("le", "Signed less or equal (Z set or N != V)"),
+    ("al", "Always"),
+];
+
+/// 32-bit ARM mnemonics that take a condition code suffix
+const ARM_CONDITIONAL_MNEMONICS: [&str; 39] = [
+    "adc", "add", "and", "asr", "b", "bic", "bl", "blx", "bx", "cmn", "cmp", "eor", "ldm", "ldr",
+    "ldrb", "ldrh", "ldrsb", "ldrsh", "lsl", "lsr", "mla", "mov", "mul", "mvn", "orr", "pop",
+    "push", "ror", "rsb", "rsc", "sbc", "stm", "str", "strb", "strh", "sub", "svc", "teq", "tst",
+];
+
+/// The condition codes of the x86 `jcc`, `setcc`, and `cmovcc` instructions, e.g.
+/// the `ne` in `jne`, along with the flags they test
+pub const X86_CONDITION_CODES: [(&str, &str); 30] = [
+    ("o", "Overflow (OF=1)"),
+    ("no", "Not overflow (OF=0)"),
+    ("b", "Unsigned below (CF=1)"),
+    ("c", "Carry (CF=1)"),
+    ("nae", "Unsigned not above or equal (CF=1)"),
+    ("ae", "Unsigned above or equal (CF=0)"),
+    ("nb", "Unsigned not below (CF=0)"),
+    ("nc", "Not carry (CF=0)"),
+    ("e", "Equal (ZF=1)"),
+    ("z", "Zero (ZF=1)"),
+    ("ne", "Not equal (ZF=0)"),
+    ("nz", "Not zero (ZF=0)"),
+    ("be", "Unsigned below or equal (CF=1 or ZF=1)"),
+    ("na", "Unsigned not above (CF=1 or ZF=1)"),
+    ("a", "Unsigned above (CF=0 and ZF=0)"),
+    ("nbe", "Unsigned not below or equal (CF=0 and ZF=0)"),
+    ("s", "Sign (SF=1)"),
+    ("ns", "Not sign (SF=0)"),
+    ("p", "Parity (PF=1)"),
+    ("pe", "Parity even (PF=1)"),
+    ("np", "Not parity (PF=0)"),
+    ("po", "Parity odd (PF=0)"),
+    ("l", "Signed less (SF!=OF)"),
+    ("nge", "Signed not greater or equal (SF!=OF)"),
+    ("ge", "Signed greater or equal (SF=OF)"),
+    ("nl", "Signed not less (SF=OF)"),
+    ("le", "Signed less or equal (ZF=1 or SF!=OF)"),
+    ("ng", "Signed not greater (ZF=1 or SF!=OF)"),
+    ("g", "Signed greater (ZF=0 and SF=OF)"),
+    ("nle", "Signed not less or equal (ZF=0 and SF=OF)"),
+];
+
+/// x86 mnemonics the condition codes are appended to
+const X86_CONDITIONAL_MNEMONICS: [&str; 3] = ["j", "set", "cmov"];
+
+/// Returns the meaning of the condition code of a conditional `mnemonic` of `arch`,
+/// e.g. `moveq` or `jne`
+fn get_condition_code(arch: Arch, mnemonic: &str) -> Option<&'static str> {
+    let (bases, codes): (&[&str], &[(&str, &str)]) = match arch {
+        Arch::ARM => (&ARM_CONDITIONAL_MNEMONICS, &ARM_CONDITION_CODES),
+        Arch::X86 | Arch::X86_64 => (&X86_CONDITIONAL_MNEMONICS, &X86_CONDITION_CODES),
+        _ => return None,
+    };
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    bases.iter().find_map(|&base| {
+        let cond = mnemonic.strip_prefix(base)?;
+        codes
+            .iter()
+            .find(|(code, _)| *code == cond)
+            .map(|&(_, meaning)| meaning)
+    })
+}
+
+/// Checks if `mnemonic` can only be assembled in one instruction set state,
+/// returning its base mnemonic, that state, and a short description
+///
+/// Condition code and `.w`/`.n` width suffixes are ignored, e.g. `rscne` and
+/// `tbb.w` are recognized
+#[must_use]
+pub fn get_arm_exclusive_state(mnemonic: &str) -> Option<(&'static str, ArmState, &'static str)> {
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let mnemonic = mnemonic
+        .strip_suffix(".w")
+        .or_else(|| mnemonic.strip_suffix(".n"))
+        .unwrap_or(&mnemonic);
+    ARM_STATE_EXCLUSIVE
+        .iter()
+        .find(|(base, _, _)| {
+            mnemonic.strip_prefix(base).is_some_and(|cond| {
+                cond.is_empty() || ARM_CONDITION_CODES.iter().any(|(code, _)| *code == cond)
+            })
+        })
+        .copied()
+}
+
+/// Returns a note on the instruction set state `mnemonic` is assembled in on
+/// the hovered line, or `None` if neither the mnemonic nor the document say
+/// anything about it
+fn get_arm_state_note(
+    params: &HoverParams,
+    mnemonic: &str,
+    text_store: &TextDocuments,
+) -> Option<String> {
+    let pos_params = &params.text_document_position_params;
+    let curr_state = text_store
+        .get_document(&pos_params.text_document.uri)
+        .and_then(|doc| get_arm_state(doc.get_content(None), pos_params.position.line as usize));
+    let exclusive = get_arm_exclusive_state(mnemonic).map(|(_, state, _)| state);
+
+    let mut note = String::new();
+    if let Some(state) = curr_state {
+        note.push_str("Instruction set state: ");
+        note.push_str(state.as_ref());
+    }
+    if let Some(state) = exclusive {
+        if !note.is_empty() {
+            note.push('\n');
+        }
+        note.push_str("Only available in ");
+        note.push_str(state.as_ref());
+        note.push_str(" state");
+    }
+
+    (!note.is_empty()).then_some(note)
+}
+
+/// Returns hover info for 32-bit ARM mnemonics that are exclusive to one
+/// instruction set state and missing from the instruction docs
+fn get_arm_state_resp(
+    params: &HoverParams,
+    word: &str,
+    text_store: &TextDocuments,
+) -> Option<Hover> {
+    let (base, _, description) = get_arm_exclusive_state(word)?;
+    let note = get_arm_state_note(params, word, text_store)?;
+    let mut value = base.to_ascii_uppercase();
+    value.push_str(" [arm]\n");
+    value.push_str(description);
+    value.push_str("\n\n");
+    value.push_str(&note);
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Returns hover info for ARM barrel shifter operators, i.e. the `lsl`, `lsr`,
+/// `asr`, `ror`, and `rrx` that shift the last source operand in operand position,
+/// like in `add r0, r1, r2, lsl #2`
+fn get_arm_shift_resp(params: &HoverParams, text_store: &TextDocuments) -> Option<Hover> {
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let line = get_line_from_pos_params(doc, pos_params);
+    let ((start, end), _) = find_word_at_pos(line, pos_params.position.character as usize);
+    let shift = line[start..end].to_ascii_lowercase();
+    let (name, action, fill) = match shift.as_str() {
+        "lsl" => ("Logical shift left", "shifted left", ", shifting in zeros"),
+        "lsr" => (
+            "Logical shift right",
+            "shifted right",
+            ", shifting in zeros",
+        ),
+        "asr" => (
+            "Arithmetic shift right",
+            "shifted right",
+            ", shifting in copies of the sign bit",
+        ),
+        "ror" => (
+            "Rotate right",
+            "rotated right",
+            ", with the bits shifted out re-entering at the top",
+        ),
+        "rrx" => (
+            "Rotate right with extend",
+            "rotated right by one bit",
+            ", shifting the carry flag into the top bit",
+        ),
+        _ => return None,
+    };
+    // a shift operator always follows the operand it shifts
+    let operand = line[..start]
+        .trim_end()
+        .strip_suffix(',')?
+        .rsplit([',', ' ', '\t'])
+        .next()
+        .filter(|operand| !operand.is_empty())?;
+    let amount = line[end..]
+        .split([',', ']', '@', '/'])
+        .next()
+        .map(str::trim)
+        .filter(|amount| !amount.is_empty() && shift != "rrx")
+        .map(|amount| {
+            if amount.starts_with('#') {
+                format!(" by `{amount}`")
+            } else {
+                format!(" by the value in `{amount}`")
+            }
+        })
+        .unwrap_or_default();
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "{} (shift operator)\n{name}\n\n`{operand}` is {action}{amount}{fill}, \
+                before the instruction uses it",
+                shift.to_ascii_uppercase()
+            ),
+        }),
+        range: None,
+    })
+}
+
+/// Warns about 32-bit ARM instructions used in an instruction set state they
+/// can't be assembled in. Lines before the first state directive aren't checked
+#[must_use]
+pub fn get_arm_state_diagnostics(curr_doc: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut curr_state = None;
+    for (line_num, line) in curr_doc.lines().enumerate() {
+        let stmt = get_arm_stmt(line);
+        if let Some(state) = get_arm_state_directive(stmt) {
+            curr_state = Some(state);
+            continue;
+        }
+        let (Some(curr_state), Some(mnemonic)) = (curr_state, stmt.split_whitespace().next())
+        else {
+            continue;
+        };
+        let Some((_, state, _)) = get_arm_exclusive_state(mnemonic) else {
+            continue;
+        };
+        if state == curr_state {
+            continue;
+        }
+
+        // `stmt` and `mnemonic` are both slices of `line`
+        let start = mnemonic.as_ptr() as usize - line.as_ptr() as usize;
+        let start_col = line[..start].encode_utf16().count() as u32;
+        let mut diag = Diagnostic::new_simple(
+            Range {
+                start: Position {
+                    line: line_num as u32,
+                    character: start_col,
+                },
+                end: Position {
+                    line: line_num as u32,
+                    character: start_col + mnemonic.encode_utf16().count() as u32,
+                },
+            },
+            format!("`{mnemonic}` is only available in {state} state, but the current state is {curr_state}"),
+        );
+        diag.severity = Some(DiagnosticSeverity::WARNING);
+        diag.source = Some("asm-lsp".to_string());
+        diagnostics.push(diag);
+    }
+
+    diagnostics
+}
+
+/// Reports clear syntax errors, i.e. stray or doubled punctuation and memory
+/// operands missing their closing bracket
+///
+/// tree-sitter-asm produces error nodes for plenty of valid syntax it doesn't know
+/// about, so only error nodes made up entirely of `,`, `)`, and `]` are reported,
+/// and brackets are only flagged on lines where they're unbalanced. Lines with
+/// braces or `@` (AVX-512 decorators, ARM register lists and comments) throw the
+/// parser off and are skipped entirely
+///
+/// # Panics
+///
+/// Will panic if the tree-sitter grammar fails to load
+#[must_use]
+pub fn get_syntax_diagnostics(curr_doc: &str) -> Vec<Diagnostic> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&asm_language()).unwrap();
+    let Some(tree) = parser.parse(curr_doc, None) else {
+        return Vec::new();
+    };
+
+    let lines: Vec<&str> = curr_doc.lines().collect();
+    let mut diagnostics = Vec::new();
+    let mut cursor = tree.walk();
+    let mut nodes = vec![tree.root_node()];
+    while let Some(node) = nodes.pop() {
+        if !node.has_error() {
+            continue;
+        }
+        if !node.is_missing() && !node.is_error() {
+            nodes.extend(node.children(&mut cursor));
+            continue;
+        }
+
+        let start = node.start_position();
+        let line = lines.get(start.row).copied().unwrap_or_default();
+        if line.contains(['{', '}', '@']) {
+            continue;
+        }
+        let text = &curr_doc[node.byte_range()];
+        let (start_col, end_col, message) = if node.is_missing() {
+            let (open, close) = match node.kind() {
+                ")" => ('(', ')'),
+                "]" => ('[', ']'),
+                _ => continue,
+            };
+            // indexed GAS operands like `(%rax,%rbx)` are parsed as an operand
+            // missing its `)`, followed by a stray `)`
+            if line.matches(open).count() <= line.matches(close).count() {
+                continue;
+            }
+            // highlight the unclosed operand, e.g. `(%rax`
+            let open_col = line
+                .get(..start.column)
+                .and_then(|before| before.rfind(open))
+                .unwrap_or(start.column);
+            (open_col, start.column, format!("Missing closing `{close}`"))
+        } else {
+            if text.is_empty()
+                || !text
+                    .chars()
+                    .all(|c| matches!(c, ',' | ')' | ']' | ' ' | '\t'))
+            {
+                continue;
+            }
+            if (text.contains(')') && line.matches('(').count() >= line.matches(')').count())
+                || (text.contains(']') && line.matches('[').count() >= line.matches(']').count())
+            {
+                continue;
+            }
+            let text = text.trim();
+            let col = start.column + text.as_ptr() as usize
+                - curr_doc[node.byte_range()].as_ptr() as usize;
+            (col, col + text.len(), format!("Unexpected `{text}`"))
+        };
+
+        let mut diag = Diagnostic::new_simple(
+            Range {
+                start: Position {
+                    line: start.row as u32,
+                    character: line.get(..start_col).unwrap_or(line).encode_utf16().count() as u32,
+                },
+                end: Position {
+                    line: start.row as u32,
+                    character: line.get(..end_col).unwrap_or(line).encode_utf16().count() as u32,
+                },
+            },
+            format!("Syntax error: {message}"),
+        );
+        diag.severity = Some(DiagnosticSeverity::ERROR);
+        diag.source = Some("asm-lsp".to_string());
+        diagnostics.push(diag);
+    }
+    diagnostics.sort_by_key(|diag| (diag.range.start.line, diag.range.start.character));
+
+    diagnostics
+}
+
+/// Checks if `word` is an x86/x86-64 mnemonic with a GAS operand size suffix
+/// (`b`, `w`, `l`, or `q`), returning the unsuffixed mnemonic and the implied
+/// operand size in bits
+///
+/// The suffix is only stripped if the unsuffixed mnemonic is an instruction that
+/// takes operands of that size, and `word` isn't itself the name of a different
+/// instruction (e.g. `movsb` or `movq`)
+#[must_use]
+pub fn get_gas_size_suffix<'a>(
+    word: &'a str,
+    config: &Config,
+    instruction_map: &NameToInstructionMap,
+) -> Option<(&'a str, &'static str)> {
+    if !config.assemblers.gas.unwrap_or(false) || word.len() < 2 || !word.is_ascii() {
+        return None;
+    }
+    let (base, suffix) = word.split_at(word.len() - 1);
+    let (bits, sized_operands): (&str, &[OperandType]) = match suffix.to_ascii_lowercase().as_str()
+    {
+        "b" => ("8", &[OperandType::al, OperandType::r8, OperandType::m8]),
+        "w" => ("16", &[OperandType::ax, OperandType::r16, OperandType::m16]),
+        "l" => (
+            "32",
+            &[OperandType::eax, OperandType::r32, OperandType::m32],
+        ),
+        "q" => (
+            "64",
+            &[OperandType::rax, OperandType::r64, OperandType::m64],
+        ),
+        _ => return None,
+    };
+    let base_lower = base.to_ascii_lowercase();
+    // x87 suffixes select a memory format (e.g. `flds` vs `fldl`) rather than an
+    // integer operand size
+    if base_lower.starts_with('f') {
+        return None;
+    }
+    let word_lower = word.to_ascii_lowercase();
+    // don't shadow real instructions on other architectures, e.g. RISCV's `addw`
+    if [
+        Arch::Z80,
+        Arch::ARM,
+        Arch::ARM64,
+        Arch::RISCV,
+        Arch::Mos6502,
+        Arch::M68k,
+    ]
+    .into_iter()
+    .any(|arch| instruction_map.contains_key(&(arch, word_lower.as_str())))
+    {
+        return None;
+    }
+
+    [Arch::X86, Arch::X86_64].into_iter().find_map(|arch| {
+        let instr = instruction_map.get(&(arch, base_lower.as_str()))?;
+        if instr.name != base_lower {
+            return None;
+        }
+        // `word` may already be registered as one of the instruction's GAS names,
+        // but mustn't belong to another instruction
+        if instruction_map
+            .get(&(arch, word_lower.as_str()))
+            .is_some_and(|other| other.name != instr.name)
+        {
+            return None;
+        }
+        instr
+            .forms
+            .iter()
+            .flat_map(|form| &form.operands)
+            .any(|op| sized_operands.contains(&op.type_))
+            .then_some((base, bits))
+    })
+}
+
+/// Strips an m68k size suffix (`.b`, `.w`, `.l`, or `.s`) from `word`, returning
+/// the lowercased base mnemonic
+fn get_m68k_mnemonic(word: &str) -> Option<String> {
+    let (base, suffix) = word.rsplit_once('.')?;
+    matches!(suffix.to_ascii_lowercase().as_str(), "b" | "w" | "l" | "s")
+        .then(|| base.to_ascii_lowercase())
+}
+
+/// Checks if `word` is an m68k mnemonic with a size suffix (`.b`, `.w`, `.l`, or
+/// `.s`), returning the unsuffixed mnemonic and a description of the suffix
+#[must_use]
+pub fn get_m68k_size_suffix<'a>(
+    word: &'a str,
+    config: &Config,
+    instruction_map: &NameToInstructionMap,
+) -> Option<(&'a str, &'static str)> {
+    if !config.is_isa_enabled(Arch::M68k) {
+        return None;
+    }
+    let base_lower = get_m68k_mnemonic(word)?;
+    if !instruction_map.contains_key(&(Arch::M68k, base_lower.as_str())) {
+        return None;
+    }
+    let (base, suffix) = word.split_at(base_lower.len());
+    let desc = match suffix.to_ascii_lowercase().as_str() {
+        ".b" => "byte, 8 bits",
+        ".w" => "word, 16 bits",
+        ".l" => "long, 32 bits",
+        _ => "short branch, 8-bit displacement",
+    };
+    Some((base, desc))
+}
+
+/// Looks up `word` in the user's configured `aliases`, returning its
+/// documentation if present
+fn get_alias_resp(word: &str, config: &Config) -> Option<Hover> {
+    let value = config.opts.aliases.as_ref()?.get(word)?.clone();
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+fn get_demangle_resp(word: &str, config: &Config) -> Option<Hover> {
+    let name = Name::new(word, NameMangling::Mangled, Language::Unknown);
+    let options = match config.opts.demangle_options.unwrap_or_default() {
+        DemangleStyle::Complete => DemangleOptions::complete(),
+        DemangleStyle::NameOnly => DemangleOptions::name_only(),
+    };
+    let demangled = name.demangle(options);
+    if let Some(demang) = demangled {
+        let value = demang;
+        return Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        });
+    }
+
+    None
+}
+
+/// Returns the value of `word` if it's a symbol defined on the command line for
+/// `source_file` (or for all files) by the compile commands
+fn get_define_resp(
+    word: &str,
+    source_file: &Uri,
+    defines: &HashMap<SourceFile, HashMap<String, String>>,
+) -> Option<Hover> {
+    let value = *get_file_defines(source_file, defines).get(word)?;
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("`{word} = {value}`\n\nDefined by the compile command"),
+        }),
+        range: None,
+    })
+}
+
+/// Returns the symbols defined on the command line for `source_file` (or for all
+/// files) by the compile commands, by name
+fn get_file_defines<'a>(
+    source_file: &Uri,
+    defines: &'a HashMap<SourceFile, HashMap<String, String>>,
+) -> HashMap<&'a str, &'a str> {
+    let file_defines = uri_to_path(source_file)
+        .and_then(|path| path.canonicalize().ok())
+        .and_then(|path| defines.get(&SourceFile::File(path)));
+    // file-specific definitions take precedence
+    defines
+        .get(&SourceFile::All)
+        .into_iter()
+        .chain(file_defines)
+        .flatten()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect()
+}
+
+/// Returns the unit size in bytes of one of NASM's `res*` or `d*` pseudo-instructions,
+/// along with whether it reserves space (`res*`) rather than declaring data
+fn get_nasm_data_unit(directive: &str) -> Option<(u64, bool)> {
+    let lower = directive.to_ascii_lowercase();
+    let (suffix, reserves) = lower
+        .strip_prefix("res")
+        .map(|suffix| (suffix, true))
+        .or_else(|| Some((lower.strip_prefix('d')?, false)))?;
+    let unit = match suffix {
+        "b" => 1,
+        "w" => 2,
+        "d" => 4,
+        "q" => 8,
+        "t" => 10,
+        "o" => 16,
+        "y" => 32,
+        "z" => 64,
+        _ => return None,
+    };
+
+    Some((unit, reserves))
+}
+
+/// Evaluates a NASM count or offset, e.g. the `4 * 8` in `resb 4 * 8`
+fn eval_nasm_count(expr: &str) -> Option<u64> {
+    let expr = expr.trim();
+    parse_int_literal(expr)
+        .map(u64::from)
+        .or_else(|| eval_expr(expr, &HashMap::new(), 0).and_then(|value| u64::try_from(value).ok()))
+}
+
+/// Returns the structure types declared by NASM `struc`/`endstruc` blocks in
+/// `curr_doc`
+///
+/// Member offsets are tracked through `res*` and `d*` declarations and `alignb`.
+/// Once a member's size can't be determined, e.g. because it's given by a constant
+/// defined elsewhere, the offsets of the members after it are unknown
+#[must_use]
+pub fn get_nasm_strucs(curr_doc: &str) -> Vec<NasmStruc> {
+    let mut strucs = Vec::new();
+    // the structure being declared, and the offset of its next member
+    let mut curr: Option<(NasmStruc, Option<u64>)> = None;
+    for (line_num, line) in curr_doc.lines().enumerate() {
+        let stmt = line.split(';').next().unwrap_or_default();
+        let tokens = get_ident_tokens(stmt);
+        let Some(&(first_col, first)) = tokens.first() else {
+            continue;
+        };
+        let line_num = u32::try_from(line_num).unwrap_or(u32::MAX);
+        let range_of = |col: Column, token: &str| Range {
+            start: Position {
+                line: line_num,
+                character: byte_col_to_utf16_col(line, col),
+            },
+            end: Position {
+                line: line_num,
+                character: byte_col_to_utf16_col(line, col + token.len()),
+            },
+        };
+
+        match (first.to_ascii_lowercase().as_str(), curr.as_mut()) {
+            ("struc", None) => {
+                let Some(&(name_col, name)) = tokens.get(1) else {
+                    continue;
+                };
+                // `struc name, offset` starts the first member at `offset`
+                let start = stmt[name_col + name.len()..]
+                    .split_once(',')
+                    .map_or(Some(0), |(_, offset)| eval_nasm_count(offset));
+                curr = Some((
+                    NasmStruc {
+                        name: name.to_string(),
+                        range: range_of(name_col, name),
+                        size: None,
+                        members: Vec::new(),
+                    },
+                    start,
+                ));
+            }
+            ("endstruc", Some(_)) => {
+                if let Some((mut struc, offset)) = curr.take() {
+                    struc.size = offset;
+                    strucs.push(struc);
+                }
+            }
+            (_, Some((struc, offset))) => {
+                let is_directive = |token: &str| {
+                    get_nasm_data_unit(token).is_some() || token.eq_ignore_ascii_case("alignb")
+                };
+                let (label, rest) = if is_directive(first) {
+                    (None, &tokens[..])
+                } else {
+                    (Some((first_col, first)), &tokens[1..])
+                };
+                let Some(&(dir_col, directive)) = rest.first() else {
+                    // a bare label takes up no space
+                    if let Some((name_col, name)) = label {
+                        struc.members.push(NasmStrucMember {
+                            name: name.to_string(),
+                            range: range_of(name_col, name),
+                            offset: *offset,
+                            size: None,
+                        });
+                    }
+                    continue;
+                };
+                let operands = &stmt[dir_col + directive.len()..];
+                if directive.eq_ignore_ascii_case("alignb") {
+                    let align = operands.split(',').next().and_then(eval_nasm_count);
+                    *offset = offset
+                        .zip(align)
+                        .map(|(offset, align)| offset.next_multiple_of(align.max(1)));
+                    continue;
+                }
+                let size = get_nasm_data_unit(directive).and_then(|(unit, reserves)| {
+                    let count = if reserves {
+                        eval_nasm_count(operands)?
+                    } else if operands.contains(['"', '\'', '`']) {
+                        // a string's size depends on its contents
+                        return None;
+                    } else {
+                        u64::try_from(operands.split(',').count()).ok()?
+                    };
+                    unit.checked_mul(count)
+                });
+                if let Some((name_col, name)) = label {
+                    struc.members.push(NasmStrucMember {
+                        name: name.to_string(),
+                        range: range_of(name_col, name),
+                        offset: *offset,
+                        size,
+                    });
+                }
+                *offset = offset
+                    .zip(size)
+                    .and_then(|(offset, size)| offset.checked_add(size));
+            }
+            _ => {}
+        }
+    }
+
+    strucs
+}
+
+/// Returns hover information for a NASM structure type, its `_size` symbol, or
+/// one of its members, e.g. `point.x`
+fn get_nasm_struc_resp(word: &str, curr_doc: &str) -> Option<Hover> {
+    let fmt_bytes = |bytes: Option<u64>| {
+        bytes.map_or_else(
+            || String::from("unknown"),
+            |bytes| format!("{bytes} (0x{bytes:x})"),
+        )
+    };
+    let value = get_nasm_strucs(curr_doc).iter().find_map(|struc| {
+        if let Some(member) = struc.get_member(word) {
+            return Some(format!(
+                "`{word}`: member of struc `{}`\n\nOffset: {}\n\nSize: {}",
+                struc.name,
+                fmt_bytes(member.offset),
+                fmt_bytes(member.size)
+            ));
+        }
+        if word != struc.name && word.strip_suffix("_size") != Some(struc.name.as_str()) {
+            return None;
+        }
+        let mut value = format!("struc `{}`\n\nSize: {}", struc.name, fmt_bytes(struc.size));
+        if !struc.members.is_empty() {
+            value.push_str("\n\n| Member | Offset | Size |\n|---|---|---|");
+            for member in &struc.members {
+                let row = format!(
+                    "\n| `{}` | {} | {} |",
+                    struc.member_symbol(member),
+                    fmt_bytes(member.offset),
+                    fmt_bytes(member.size)
+                );
+                value.push_str(&row);
+            }
+        }
+        Some(value)
+    })?;
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Returns `true` if the hover position is within a comment or string literal
+fn is_in_comment_or_string(
+    params: &HoverParams,
+    text_store: &TextDocuments,
+    tree_store: &mut TreeStore,
+) -> bool {
+    let pos_params = &params.text_document_position_params;
+    let (Some(doc), Some(tree_entry)) = (
+        text_store.get_document(&pos_params.text_document.uri),
+        tree_store.get_mut(&pos_params.text_document.uri),
+    ) else {
+        return false;
+    };
+    let curr_doc = doc.get_content(None);
+    tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
+    let Some(ref tree) = tree_entry.tree else {
+        return false;
+    };
+
+    let point = position_to_point(curr_doc, pos_params.position);
+    let mut node = tree.root_node().descendant_for_point_range(point, point);
+    while let Some(curr) = node {
+        if matches!(curr.kind(), "line_comment" | "block_comment" | "string") {
+            return true;
+        }
+        node = curr.parent();
+    }
+
+    false
+}
+
+/// Checks if the text being typed at `cursor` is part of a comment
+fn is_in_comment(tree: &Tree, cursor: tree_sitter::Point) -> bool {
+    // a line comment ends at the cursor while it's being typed, so look at the
+    // character before the cursor instead
+    let before = tree_sitter::Point {
+        row: cursor.row,
+        column: cursor.column.saturating_sub(1),
+    };
+    let mut node = tree.root_node().descendant_for_point_range(before, before);
+    while let Some(curr) = node {
+        match curr.kind() {
+            "line_comment" => return true,
+            "block_comment" => return cursor < curr.end_position(),
+            _ => {}
+        }
+        node = curr.parent();
+    }
+
+    false
+}
+
+/// Tags offered as completions inside comments when the `comment_tags` option
+/// isn't set
+const DEFAULT_COMMENT_TAGS: &[&str] = &["TODO", "FIXME", "NOTE", "HACK"];
+
+/// Returns completions for the tags configured by the `comment_tags` option
+fn get_comment_tag_comps(config: &Config) -> Vec<CompletionItem> {
+    let tag_comp = |tag: &str| CompletionItem {
+        label: tag.to_string(),
+        kind: Some(CompletionItemKind::TEXT),
+        ..Default::default()
+    };
+    config.opts.comment_tags.as_ref().map_or_else(
+        || {
+            DEFAULT_COMMENT_TAGS
+                .iter()
+                .map(|tag| tag_comp(tag))
+                .collect()
+        },
+        |tags| tags.iter().map(|tag| tag_comp(tag)).collect(),
+    )
+}
+
+/// Maximum depth of nested symbol definitions followed when evaluating an expression
+const EXPR_MAX_DEPTH: usize = 16;
+
+/// Returns the value of the constant expression under the cursor, e.g. `4 * 8` in
+/// `.quad 4 * 8` or `SIZE << 2` in `.set BYTES, SIZE << 2`
+///
+/// Symbols are resolved through the document's `.equ`, `.set`, `.equiv`, `.eqv`,
+/// and `name = value` definitions, and those defined on the command line by the
+/// compile commands (e.g. `-DSIZE=16`). Returns `None` if any symbol can't be
+/// resolved to a constant, e.g. because it's a label
+fn get_expr_resp(
+    params: &HoverParams,
+    text_store: &TextDocuments,
+    defines: &HashMap<SourceFile, HashMap<String, String>>,
+) -> Option<Hover> {
+    let pos_params = &params.text_document_position_params;
+    let doc = text_store.get_document(&pos_params.text_document.uri)?;
+    let line = get_line_from_pos_params(doc, pos_params);
+    let col = utf16_col_to_byte_col(line, pos_params.position.character);
+    let (expr_start, expr) = get_expr_at(line, col)?;
+    // only operators and the whitespace around them make up a bare expression
+    if !line[col..]
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_whitespace() || "+-*/<>()".contains(c))
+        || expr_start > col
+    {
+        return None;
+    }
+
+    // the document's own definitions are seen after the command line's
+    let curr_doc = doc.get_content(None);
+    let mut constants = get_file_defines(&pos_params.text_document.uri, defines);
+    constants.extend(
+        curr_doc
+            .lines()
+            .filter_map(|line| get_constant_def(line.trim())),
+    );
+    let value = eval_expr(expr, &constants, 0)?;
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("`{expr} = {value}` (`{value:#x}`)"),
+        }),
+        range: None,
+    })
+}
+
+/// Returns the byte offset and text of the comma-separated operand of the GAS
+/// statement on `line` that contains byte column `col`
+fn get_expr_at(line: &str, col: Column) -> Option<(Column, &str)> {
+    // skip over any label and the mnemonic or directive
+    let stmt_start = line.find(':').map_or(0, |idx| idx + 1);
+    let stmt = &line[stmt_start..];
+    let args_start = if let Some((_, value)) = get_constant_def(stmt.trim()) {
+        stmt_start + stmt.rfind(value)?
+    } else {
+        let trimmed = stmt.trim_start();
+        let mnemonic_end = trimmed.find(char::is_whitespace)?;
+        stmt_start + (stmt.len() - trimmed.len()) + mnemonic_end
+    };
+    if col < args_start {
+        return None;
+    }
+
+    let mut start = args_start;
+    for arg in line[args_start..].split(',') {
+        let end = start + arg.len();
+        if col <= end {
+            let expr = arg.trim();
+            // AT&T immediates are prefixed with a '$'
+            let expr = expr.strip_prefix('$').unwrap_or(expr).trim_start();
+            return (!expr.is_empty()).then_some((start, expr));
+        }
+        start = end + 1;
+    }
+
+    None
+}
+
+/// Returns the name and value of a GAS symbol definition, e.g. `.equ SIZE, 16` or
+/// `SIZE = 16`
+fn get_constant_def(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = [".equ", ".set", ".equiv", ".eqv"]
+        .iter()
+        .find_map(|directive| {
+            let rest = line.strip_prefix(directive)?;
+            rest.starts_with(char::is_whitespace)
+                .then(|| rest.split_once(','))?
+        })
+        .or_else(|| {
+            let (name, value) = line.split_once('=')?;
+            (!value.starts_with('=')).then_some((name, value))
+        })?;
+    let name = name.trim();
+    let is_symbol = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '$');
+
+    is_symbol.then(|| (name, value.trim()))
+}
+
+/// Evaluates the GAS constant expression `expr`, resolving symbols with `constants`
+///
+/// Supports integer literals, parentheses, unary `-` and `~`, and the binary
+/// operators `*`, `/`, `<<`, `>>`, `+`, and `-`, with GAS' precedence
+fn eval_expr(expr: &str, constants: &HashMap<&str, &str>, depth: usize) -> Option<i64> {
+    if depth > EXPR_MAX_DEPTH {
+        return None;
+    }
+    let tokens = tokenize_expr(expr)?;
+    let mut parser = ExprParser {
+        tokens: &tokens,
+        pos: 0,
+        constants,
+        depth,
+    };
+    let value = parser.parse_sum()?;
+
+    // trailing tokens mean the expression wasn't understood
+    (parser.pos == tokens.len()).then_some(value)
+}
+
+fn tokenize_expr(expr: &str) -> Option<Vec<&str>> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let len = if rest.starts_with("<<") || rest.starts_with(">>") {
+            2
+        } else if rest.starts_with(['+', '-', '*', '/', '~', '(', ')']) {
+            1
+        } else {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.' || c == '$'))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return None;
+            }
+            len
+        };
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+
+    Some(tokens)
+}
+
+struct ExprParser<'a> {
+    tokens: &'a [&'a str],
+    pos: usize,
+    constants: &'a HashMap<&'a str, &'a str>,
+    depth: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn next_if(&mut self, ops: &[&str]) -> Option<&'a str> {
+        let token = *self.tokens.get(self.pos)?;
+        ops.contains(&token).then(|| {
+            self.pos += 1;
+            token
+        })
+    }
+
+    fn parse_sum(&mut self) -> Option<i64> {
+        let mut value = self.parse_product()?;
+        while let Some(op) = self.next_if(&["+", "-"]) {
+            let rhs = self.parse_product()?;
+            value = if op == "+" {
+                value.wrapping_add(rhs)
+            } else {
+                value.wrapping_sub(rhs)
+            };
+        }
+
+        Some(value)
+    }
+
+    fn parse_product(&mut self) -> Option<i64> {
+        let mut value = self.parse_unary()?;
+        while let Some(op) = self.next_if(&["*", "/", "<<", ">>"]) {
+            let rhs = self.parse_unary()?;
+            value = match op {
+                "*" => value.wrapping_mul(rhs),
+                "/" => value.checked_div(rhs)?,
+                "<<" => value.checked_shl(u32::try_from(rhs).ok()?)?,
+                _ => value.checked_shr(u32::try_from(rhs).ok()?)?,
+            };
+        }
+
+        Some(value)
+    }
+
+    fn parse_unary(&mut self) -> Option<i64> {
+        match self.next_if(&["-", "~", "("]) {
+            Some("-") => Some(self.parse_unary()?.wrapping_neg()),
+            Some("~") => Some(!self.parse_unary()?),
+            Some(_) => {
+                let value = self.parse_sum()?;
+                self.next_if(&[")"])?;
+                Some(value)
+            }
+            None => {
+                let token = *self.tokens.get(self.pos)?;
+                self.pos += 1;
+                parse_gas_int(token).or_else(|| {
+                    let definition = self.constants.get(token)?;
+                    eval_expr(definition, self.constants, self.depth + 1)
+                })
+            }
+        }
+    }
+}
+
+/// Parses a GAS integer literal: decimal, `0x` hex, `0b` binary, or `0`-prefixed
+/// octal
+fn parse_gas_int(token: &str) -> Option<i64> {
+    let lower = token.to_ascii_lowercase();
+    let (digits, radix) = [("0x", 16), ("0b", 2)]
+        .iter()
+        .find_map(|&(prefix, radix)| Some((lower.strip_prefix(prefix)?, radix)))
+        .unwrap_or_else(|| match lower.strip_prefix('0') {
+            Some(octal) if !octal.is_empty() => (octal, 8),
+            _ => (&lower, 10),
+        });
+
+    i64::from_str_radix(digits, radix).ok()
+}
+
+/// A file named by an include directive
+struct IncludedFile<'a> {
+    name: &'a str,
+    /// Whether it's included with `.incbin`, i.e. as raw bytes
+    is_incbin: bool,
+    /// Whether the name is quoted rather than in angle brackets, in which case the
+    /// including file's own directory is searched first
+    is_quoted: bool,
+}
+
+/// Returns the file named by a GAS `.include` or `.incbin`, NASM `%include`, or C
+/// preprocessor `#include` directive on `line`
+fn get_included_file(line: &str) -> Option<IncludedFile<'_>> {
+    let line = line.trim_start();
+    let (rest, is_incbin) = line
+        .strip_prefix(".incbin")
+        .map(|rest| (rest, true))
+        .or_else(|| {
+            [".include", "%include", "#include"]
+                .iter()
+                .find_map(|directive| line.strip_prefix(directive))
+                .map(|rest| (rest, false))
+        })?;
+    let rest = rest.trim_start();
+    // only the C preprocessor has `<file>` includes
+    let (name, close, is_quoted) = match rest.strip_prefix('"') {
+        Some(name) => (name, '"', true),
+        None if line.starts_with('#') => (rest.strip_prefix('<')?, '>', false),
+        None => return None,
+    };
+
+    Some(IncludedFile {
+        name: &name[..name.find(close)?],
+        is_incbin,
+        is_quoted,
+    })
+}
+
+/// Searches the include directories applicable to `source_file` for `filename`,
+/// starting with `source_file`'s own directory if `search_src_dir` is set
+fn resolve_include(
+    source_file: &Uri,
+    filename: &str,
+    include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
+    search_src_dir: bool,
+) -> Vec<PathBuf> {
+    // include resolution is relative to the file on disk, nothing to do for
+    // scratch buffers or remote documents
+    let Some(src_path) = uri_to_path(source_file) else {
+        return Vec::new();
+    };
+    let mut paths = Vec::new();
+
+    type DirIter<'a> = Box<dyn Iterator<Item = &'a PathBuf> + 'a>;
+    let src_dir = src_path
+        .parent()
+        .filter(|_| search_src_dir)
+        .map(Path::to_path_buf);
+    let mut dir_iter = include_dirs.get(&SourceFile::All).map_or_else(
+        || Box::new(src_dir.iter()) as DirIter,
+        |dirs| Box::new(src_dir.iter().chain(dirs.iter())) as DirIter,
+    );
+    // the same file may be reachable through several of the directories
+    let mut seen = HashSet::new();
+
+    if let Ok(src_path) = src_path.canonicalize() {
+        if let Some(dirs) = include_dirs.get(&SourceFile::File(src_path)) {
+            dir_iter = Box::new(dir_iter.chain(dirs.iter()));
+        }
+    }
+
+    for dir in dir_iter {
+        match std::fs::read_dir(dir) {
+            Ok(dir_reader) => {
+                for file in dir_reader {
+                    match file {
+                        Ok(f) => {
+                            let path = f.path();
+                            if f.file_name() == filename
+                                && seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone()))
+                            {
+                                paths.push(path);
+                            }
+                        }
+                        Err(e) => {
+                            error!(
+                                "Failed to read item in {} - Error {e}",
+                                dir.as_path().display()
+                            );
+                        }
+                    };
+                }
+            }
+            Err(e) => {
+                error!(
+                    "Failed to create directory reader for {} - Error {e}",
+                    dir.as_path().display()
+                );
+            }
+        }
+    }
+
+    paths
+}
+
+/// Lists the resolved paths of `filename`, along with their sizes in bytes for
+/// binary `.incbin` files
+fn get_include_resp(
+    source_file: &Uri,
+    included: &IncludedFile,
+    include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
+) -> Option<Hover> {
+    let mut paths = String::new();
+    for path in resolve_include(source_file, included.name, include_dirs, included.is_quoted) {
+        paths.push_str("file://");
+        paths.push_str(&path.display().to_string());
+        if included.is_incbin {
+            if let Ok(metadata) = std::fs::metadata(&path) {
+                paths.push_str(" (");
+                paths.push_str(&metadata.len().to_string());
+                paths.push_str(" bytes)");
+            }
+        }
+        paths.push('\n');
+    }
+
+    if paths.is_empty() {
+        None
+    } else {
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: paths,
+            }),
+            range: None,
+        })
+    }
+}
+
+/// Filter out duplicate completion suggestions
+fn filtered_comp_list(comps: &[CompletionItem]) -> Vec<CompletionItem> {
+    let mut seen = HashSet::new();
+
+    comps
+        .iter()
+        .filter(|comp_item| {
             if seen.contains(&comp_item.label) {
                 false
             } else {
@@ -1001,26 +4325,595 @@ fn filtered_comp_list_prefix(comps: &[CompletionItem], prefix: char) -> Vec<Comp
         .collect()
 }
 
-macro_rules! cursor_matches {
-    ($cursor_line:expr,$cursor_char:expr,$query_start:expr,$query_end:expr) => {{
-        $query_start.row == $cursor_line
-            && $query_end.row == $cursor_line
-            && $query_start.column <= $cursor_char
-            && $query_end.column >= $cursor_char
-    }};
-}
+/// Returns the `%` prefixed directive completions, along with the unprefixed NASM
+/// directives (e.g. `bits`), which replace the `%` before the cursor
+///
+/// Directives with both forms, like `include` and `%include`, do the same thing,
+/// so only the prefixed form is offered for those
+fn get_nasm_directive_comps(
+    dir_comps: &[CompletionItem],
+    line: &str,
+    cursor_char: usize,
+    position: Position,
+) -> Vec<CompletionItem> {
+    let mut items = filtered_comp_list_prefix(dir_comps, '%');
+    let Some(prefix_start) = line.get(..cursor_char).and_then(|before| before.rfind('%')) else {
+        return items;
+    };
+    let range = Range {
+        start: Position {
+            line: position.line,
+            character: line[..prefix_start].encode_utf16().count() as u32,
+        },
+        end: position,
+    };
+
+    let prefixed: HashSet<String> = items
+        .iter()
+        .map(|item| item.label[1..].to_ascii_lowercase())
+        .collect();
+    let mut seen = HashSet::new();
+    for item in dir_comps {
+        let is_nasm = get_comp_data(item).arch.as_deref() == Some(Assembler::Nasm.as_ref());
+        if !is_nasm
+            || item.label.starts_with('%')
+            || prefixed.contains(&item.label.to_ascii_lowercase())
+            || !seen.insert(&item.label)
+        {
+            continue;
+        }
+        items.push(CompletionItem {
+            filter_text: Some(format!("%{}", item.label)),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: item.label.clone(),
+            })),
+            ..item.clone()
+        });
+    }
+
+    items
+}
+
+/// If `cursor_line` lies within the body of a GAS `.macro`/`.endm` block, returns
+/// the names of the macro's parameters
+fn get_macro_params(curr_doc: &str, cursor_line: usize) -> Option<Vec<&str>> {
+    let preceding_lines: Vec<&str> = curr_doc.lines().take(cursor_line).collect();
+    for line in preceding_lines.into_iter().rev() {
+        let line = line.trim_start();
+        if line.starts_with(".endm") {
+            return None;
+        }
+        let Some(decl) = line.strip_prefix(".macro") else {
+            continue;
+        };
+        // .macro name arg1, arg2=default, arg3:req
+        let mut tokens = decl
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty());
+        tokens.next()?; // the macro's name
+        return Some(
+            tokens
+                .filter_map(|param| param.split(['=', ':']).next())
+                .filter(|param| !param.is_empty())
+                .collect(),
+        );
+    }
+
+    None
+}
+
+/// Returns completion items for the GAS operand size suffixed variants of x86/x86-64
+/// instructions, e.g. `movb`, `movw`, `movl`, and `movq` for `mov`
+///
+/// Only suffixes implying an operand size the instruction actually takes are
+/// included, and variants that name another instruction (e.g. `movq`) are left to
+/// that instruction's own item. Returns an empty vector if GAS isn't enabled
+#[must_use]
+pub fn get_gas_suffix_completes(
+    instruction_map: &NameToInstructionMap,
+    config: &Config,
+) -> Vec<CompletionItem> {
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    for (&(arch, name), instr) in instruction_map {
+        if !matches!(arch, Arch::X86 | Arch::X86_64) || name != instr.name {
+            continue;
+        }
+        for suffix in ['b', 'w', 'l', 'q'] {
+            let label = format!("{name}{suffix}");
+            let Some((_, bits)) = get_gas_size_suffix(&label, config, instruction_map) else {
+                continue;
+            };
+            if !seen.insert(label.clone()) {
+                continue;
+            }
+            items.push(CompletionItem {
+                label,
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(format!("{name} with {bits}-bit operands")),
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!("{instr}"),
+                })),
+                data: serde_json::to_value(CompletionData {
+                    arch: Some(arch.as_ref().to_string()),
+                    gas_size_suffix: true,
+                    ..Default::default()
+                })
+                .ok(),
+                ..Default::default()
+            });
+        }
+    }
+
+    items
+}
+
+/// Adds completion items for the condition code suffixed variants of 32-bit ARM
+/// instructions, e.g. `moveq` and `movne` for `mov`
+///
+/// The meaning of the condition is noted on these, and on the items for the x86
+/// `jcc`, `setcc`, and `cmovcc` instructions. The x86 variants are documented
+/// instructions of their own, so they already have items. ARM variants that name
+/// another instruction (e.g. SVE's `cmpeq`) are left to that instruction's item
+pub fn add_condition_code_completes(
+    items: &mut Vec<CompletionItem>,
+    instruction_map: &NameToInstructionMap,
+    config: &Config,
+) {
+    let describe = |meaning: &str| {
+        Some(CompletionItemLabelDetails {
+            detail: None,
+            description: Some(meaning.to_string()),
+        })
+    };
+
+    if config.instruction_sets.x86.unwrap_or(false)
+        || config.instruction_sets.x86_64.unwrap_or(false)
+    {
+        // other arches' mnemonics can look conditional too, e.g. z80's `jp`
+        let is_x86 = |item: &CompletionItem| {
+            let arch = get_comp_data(item).arch;
+            arch.as_deref() == Some(Arch::X86.as_ref())
+                || arch.as_deref() == Some(Arch::X86_64.as_ref())
+        };
+        for item in items.iter_mut().filter(|item| is_x86(item)) {
+            if let Some(meaning) = get_condition_code(Arch::X86, &item.label) {
+                item.label_details = describe(meaning);
+            }
+        }
+    }
+
+    if config.instruction_sets.arm.unwrap_or(false) {
+        for base in ARM_CONDITIONAL_MNEMONICS {
+            let Some(instr) = instruction_map.get(&(Arch::ARM, base)) else {
+                continue;
+            };
+            for (cond, meaning) in ARM_CONDITION_CODES {
+                let label = format!("{base}{cond}");
+                if instruction_map.contains_key(&(Arch::ARM, label.as_str())) {
+                    continue;
+                }
+                items.push(CompletionItem {
+                    label,
+                    kind: Some(CompletionItemKind::FUNCTION),
+                    label_details: describe(meaning),
+                    detail: Some(format!("{base} with condition {cond}")),
+                    documentation: Some(Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: format!("{instr}"),
+                    })),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+fn is_gas_suffix_comp(item: &CompletionItem) -> bool {
+    get_comp_data(item).gas_size_suffix
+}
+
+/// Sets `sort_text` on each of `items` so that those of kind `primary` sort ahead
+/// of the rest, keeping them alphabetical within each group
+fn prioritize_comp_kind(items: &mut [CompletionItem], primary: CompletionItemKind) {
+    for item in items {
+        let rank = if item.kind == Some(primary) { '0' } else { '1' };
+        item.sort_text = Some(format!("{rank}{}", item.label));
+    }
+}
+
+/// If the text before `cursor_char` on `line` is a NASM structure's name followed
+/// by a '.', returns a completion for each of the structure's members
+fn get_nasm_struc_member_comps(
+    curr_doc: &str,
+    line: &str,
+    cursor_char: Column,
+) -> Option<Vec<CompletionItem>> {
+    let before_cursor = line.get(..cursor_char)?.strip_suffix('.')?;
+    let name_start = before_cursor
+        .char_indices()
+        .rfind(|&(_, c)| !(c.is_alphanumeric() || c == '_'))
+        .map_or(0, |(idx, c)| idx + c.len_utf8());
+    let name = &before_cursor[name_start..];
+    let struc = get_nasm_strucs(curr_doc)
+        .into_iter()
+        .find(|struc| struc.name == name)?;
+    let items: Vec<CompletionItem> = struc
+        .members
+        .iter()
+        .filter_map(|member| {
+            Some(CompletionItem {
+                label: member.name.strip_prefix('.')?.to_string(),
+                kind: Some(CompletionItemKind::FIELD),
+                detail: member.offset.map(|offset| format!("Offset {offset}")),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    (!items.is_empty()).then_some(items)
+}
+
+fn macro_param_comps(params: &[&str]) -> Vec<CompletionItem> {
+    params
+        .iter()
+        .map(|param| CompletionItem {
+            label: format!("\\{param}"),
+            kind: Some(CompletionItemKind::VARIABLE),
+            detail: Some(String::from("Macro parameter")),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Data directives whose string arguments can contain escape sequences
+const STRING_DATA_DIRECTIVES: &[&str] = &[
+    ".ascii",
+    ".asciz",
+    ".string",
+    ".string8",
+    ".string16",
+    ".string32",
+    ".string64",
+    "db",
+    "dw",
+    "dd",
+    "dq",
+    "dt",
+    "do",
+    "dy",
+    "dz",
+];
+
+/// Escape sequences recognized in double-quoted GAS strings
+const GAS_STRING_ESCAPES: &[(&str, &str)] = &[
+    ("\\n", "Newline"),
+    ("\\t", "Horizontal tab"),
+    ("\\r", "Carriage return"),
+    ("\\b", "Backspace"),
+    ("\\f", "Form feed"),
+    (
+        "\\0",
+        "Null character. Up to three octal digits give any character code, e.g. `\\101`",
+    ),
+    ("\\x", "Hexadecimal character code, e.g. `\\x41`"),
+    ("\\\\", "Backslash"),
+    ("\\\"", "Double quote"),
+];
+
+/// Escape sequences recognized in backquoted NASM strings
+const NASM_STRING_ESCAPES: &[(&str, &str)] = &[
+    ("\\n", "Newline"),
+    ("\\t", "Horizontal tab"),
+    ("\\r", "Carriage return"),
+    ("\\b", "Backspace"),
+    ("\\f", "Form feed"),
+    ("\\v", "Vertical tab"),
+    ("\\a", "Alert (BEL)"),
+    ("\\e", "Escape (ESC)"),
+    (
+        "\\0",
+        "Null character. Up to three octal digits give any character code, e.g. `\\101`",
+    ),
+    ("\\x", "Hexadecimal character code, e.g. `\\x41`"),
+    (
+        "\\u",
+        "UTF-8 encoding of a 4 digit Unicode codepoint, e.g. `\\u263a`",
+    ),
+    (
+        "\\U",
+        "UTF-8 encoding of an 8 digit Unicode codepoint, e.g. `\\U0001f600`",
+    ),
+    ("\\\\", "Backslash"),
+    ("\\`", "Backquote"),
+    ("\\'", "Single quote"),
+    ("\\\"", "Double quote"),
+    ("\\?", "Question mark"),
+];
+
+/// Returns the directive (or instruction) and opening quote of the string literal
+/// containing the cursor, if any
+///
+/// tree-sitter-asm doesn't parse NASM's backquoted strings, or strings left
+/// unterminated while the user is typing, so these are found from the line's
+/// raw text instead
+fn get_string_literal_ctx<'a>(
+    tree: Option<&Tree>,
+    curr_doc: &'a str,
+    cursor_line: usize,
+    cursor_char: usize,
+) -> Option<(&'a str, char)> {
+    if let Some(tree) = tree {
+        static QUERY_STRING: Lazy<tree_sitter::Query> = Lazy::new(|| {
+            tree_sitter::Query::new(
+                &asm_language(),
+                "[
+                    (meta kind: (meta_ident) @directive (string) @string)
+                    (instruction kind: (word) @directive (string) @string)
+                ]",
+            )
+            .unwrap()
+        });
+        let mut line_cursor = tree_sitter::QueryCursor::new();
+        line_cursor.set_point_range(std::ops::Range {
+            start: tree_sitter::Point {
+                row: cursor_line,
+                column: 0,
+            },
+            end: tree_sitter::Point {
+                row: cursor_line,
+                column: usize::MAX,
+            },
+        });
+        for match_ in line_cursor.matches(&QUERY_STRING, tree.root_node(), curr_doc.as_bytes()) {
+            let [directive, string] = match_.captures else {
+                continue;
+            };
+            let start = string.node.start_position();
+            let end = string.node.end_position();
+            if start.row == cursor_line
+                && end.row == cursor_line
+                && start.column < cursor_char
+                && cursor_char < end.column
+            {
+                let directive = directive.node.utf8_text(curr_doc.as_bytes()).ok()?;
+                let quote = curr_doc[string.node.start_byte()..].chars().next()?;
+                return Some((directive, quote));
+            }
+        }
+    }
+
+    let line = curr_doc.lines().nth(cursor_line)?;
+    let before_cursor = line.get(..cursor_char).unwrap_or(line);
+    let mut open_quote = None;
+    let mut escaped = false;
+    for c in before_cursor.chars() {
+        match open_quote {
+            // the rest of the line is a comment
+            None if c == ';' => return None,
+            None if c == '"' || c == '`' => open_quote = Some(c),
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(quote) if c == quote => open_quote = None,
+            _ => {}
+        }
+    }
+    let quote = open_quote?;
+    let mut words = before_cursor.split_whitespace();
+    let mut directive = words.next()?;
+    if directive.ends_with(':') {
+        directive = words.next()?;
+    }
+
+    Some((directive, quote))
+}
+
+/// Returns completions for the escape sequences available in a string literal
+/// opened with `quote` in the arguments of `directive`
+///
+/// Returns `None` if escape sequences aren't processed in the literal, e.g. in
+/// NASM's single or double-quoted strings
+fn get_string_escape_comps(
+    directive: &str,
+    quote: char,
+    line: &str,
+    cursor_char: usize,
+    cursor_pos: Position,
+) -> Option<CompletionList> {
+    let directive = directive.to_ascii_lowercase();
+    if !STRING_DATA_DIRECTIVES.contains(&directive.as_str()) {
+        return None;
+    }
+    let escapes = match (directive.starts_with('.'), quote) {
+        (true, '"') => GAS_STRING_ESCAPES,
+        (false, '`') => NASM_STRING_ESCAPES,
+        _ => return None,
+    };
+
+    // replace a partially typed escape, backslash included
+    let before_cursor = line.get(..cursor_char).unwrap_or(line);
+    let typed = before_cursor.trim_end_matches(|c: char| c.is_ascii_alphanumeric());
+    let replace_len = if typed.ends_with('\\') {
+        before_cursor.len() - typed.len() + 1
+    } else {
+        0
+    };
+    let range = Range {
+        start: Position {
+            line: cursor_pos.line,
+            character: cursor_pos
+                .character
+                .saturating_sub(u32::try_from(replace_len).unwrap_or(0)),
+        },
+        end: cursor_pos,
+    };
+
+    Some(CompletionList {
+        is_incomplete: true,
+        items: escapes
+            .iter()
+            .map(|(escape, desc)| CompletionItem {
+                label: (*escape).to_string(),
+                kind: Some(CompletionItemKind::CONSTANT),
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: (*desc).to_string(),
+                })),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: (*escape).to_string(),
+                })),
+                ..Default::default()
+            })
+            .collect(),
+    })
+}
+
+macro_rules! cursor_matches {
+    ($cursor_line:expr,$cursor_char:expr,$query_start:expr,$query_end:expr) => {{
+        $query_start.row == $cursor_line
+            && $query_end.row == $cursor_line
+            && $query_start.column <= $cursor_char
+            && $query_end.column >= $cursor_char
+    }};
+}
+
+pub fn get_comp_resp(
+    curr_doc: &str,
+    tree_entry: &mut TreeEntry,
+    params: &CompletionParams,
+    config: &Config,
+    instr_comps: &[CompletionItem],
+    dir_comps: &[CompletionItem],
+    reg_comps: &[CompletionItem],
+) -> Option<CompletionList> {
+    let cursor_point = position_to_point(curr_doc, params.text_document_position.position);
+    let cursor_line = cursor_point.row;
+    // byte offset into the line, to match tree-sitter's columns
+    let cursor_char = cursor_point.column;
+    let mix_directives = config.opts.completion_mix_directives.unwrap_or(true);
+    // The suffixed variants only make sense in GAS, which may be disabled for this
+    // file by a modeline
+    let instr_comps: std::borrow::Cow<[CompletionItem]> =
+        if config.assemblers.gas.unwrap_or(false) || !instr_comps.iter().any(is_gas_suffix_comp) {
+            std::borrow::Cow::Borrowed(instr_comps)
+        } else {
+            instr_comps
+                .iter()
+                .filter(|item| !is_gas_suffix_comp(item))
+                .cloned()
+                .collect()
+        };
+    let instr_comps = instr_comps.as_ref();
+    // Registers like `rax` can't be used in 16 or 32-bit code. x86-64 code runs in
+    // 64-bit mode unless told otherwise
+    let default_mode = if config.instruction_sets.x86_64.unwrap_or(false) {
+        64
+    } else {
+        32
+    };
+    let reg_comps: std::borrow::Cow<[CompletionItem]> =
+        if get_x86_mode(curr_doc, cursor_line).unwrap_or(default_mode) == 64 {
+            std::borrow::Cow::Borrowed(reg_comps)
+        } else {
+            reg_comps
+                .iter()
+                .filter(|item| !is_64bit_only_comp(item))
+                .cloned()
+                .collect()
+        };
+    let reg_comps = reg_comps.as_ref();
+
+    // TODO: filter register completions by width allowed by corresponding instruction
+    tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
+
+    // Code completions are just noise in a comment
+    if tree_entry
+        .tree
+        .as_ref()
+        .is_some_and(|tree| is_in_comment(tree, cursor_point))
+    {
+        return Some(CompletionList {
+            is_incomplete: true,
+            items: get_comment_tag_comps(config),
+        });
+    }
+
+    // Only escape sequences make sense inside a string literal
+    if let Some((directive, quote)) =
+        get_string_literal_ctx(tree_entry.tree.as_ref(), curr_doc, cursor_line, cursor_char)
+    {
+        let line = curr_doc.lines().nth(cursor_line).unwrap_or_default();
+        return get_string_escape_comps(
+            directive,
+            quote,
+            line,
+            cursor_char,
+            params.text_document_position.position,
+        );
+    }
+
+    // Only mask registers make sense in an AVX-512 write mask, e.g. `{k1}`
+    if config.instruction_sets.x86.unwrap_or(false)
+        || config.instruction_sets.x86_64.unwrap_or(false)
+    {
+        let line = curr_doc.lines().nth(cursor_line).unwrap_or_default();
+        if let Some(before_cursor) = line
+            .get(..cursor_char)
+            .and_then(|before| before.rsplit_once('{'))
+            .map(|(_, after)| after.trim_start_matches('%'))
+        {
+            if before_cursor.chars().all(|c| c.is_ascii_alphanumeric()) {
+                let items: Vec<CompletionItem> = reg_comps
+                    .iter()
+                    .filter(|item| is_mask_register(&item.label))
+                    .cloned()
+                    .collect();
+                if !items.is_empty() {
+                    return Some(CompletionList {
+                        is_incomplete: true,
+                        items,
+                    });
+                }
+            }
+        }
+    }
+
+    // Inside an ARM register list, e.g. `push {r0, lr}`, only the registers not
+    // yet listed make sense
+    if config.instruction_sets.arm.unwrap_or(false) {
+        if let Some(items) = tree_entry
+            .tree
+            .as_ref()
+            .and_then(|tree| get_arm_reg_list_comps(tree, curr_doc, cursor_point, reg_comps))
+        {
+            return Some(CompletionList {
+                is_incomplete: true,
+                items,
+            });
+        }
+    }
 
-pub fn get_comp_resp(
-    curr_doc: &str,
-    tree_entry: &mut TreeEntry,
-    params: &CompletionParams,
-    config: &Config,
-    instr_comps: &[CompletionItem],
-    dir_comps: &[CompletionItem],
-    reg_comps: &[CompletionItem],
-) -> Option<CompletionList> {
-    let cursor_line = params.text_document_position.position.line as usize;
-    let cursor_char = params.text_document_position.position.character as usize;
+    // tree-sitter doesn't recognize m68k size suffixes, so `move.l d0, d1` isn't
+    // parsed as an instruction with operands
+    if config.instruction_sets.m68k.unwrap_or(false) {
+        let line = curr_doc.lines().nth(cursor_line).unwrap_or_default();
+        if let Some((mnemonic, _)) = line
+            .get(..cursor_char)
+            .and_then(|before| before.trim_start().split_once(char::is_whitespace))
+        {
+            if get_m68k_mnemonic(mnemonic)
+                .is_some_and(|base| instr_comps.iter().any(|item| item.label == base))
+            {
+                return Some(CompletionList {
+                    is_incomplete: true,
+                    items: filtered_comp_list(reg_comps),
+                });
+            }
+        }
+    }
 
     if let Some(ctx) = params.context.as_ref() {
         if ctx.trigger_kind == CompletionTriggerKind::TRIGGER_CHARACTER {
@@ -1038,7 +4931,17 @@ pub fn get_comp_resp(
                         items.append(&mut filtered_comp_list(reg_comps));
                     }
                     if config.assemblers.nasm.unwrap_or(false) {
-                        items.append(&mut filtered_comp_list_prefix(dir_comps, '%'));
+                        if config.opts.completion_both_directive_forms.unwrap_or(false) {
+                            let line = curr_doc.lines().nth(cursor_line).unwrap_or_default();
+                            items.append(&mut get_nasm_directive_comps(
+                                dir_comps,
+                                line,
+                                cursor_char,
+                                params.text_document_position.position,
+                            ));
+                        } else {
+                            items.append(&mut filtered_comp_list_prefix(dir_comps, '%'));
+                        }
                     }
 
                     if !items.is_empty() {
@@ -1050,6 +4953,18 @@ pub fn get_comp_resp(
                 }
                 // prepend all GAS, some MASM, some NASM directives with "."
                 Some(".") => {
+                    // `point.` is followed by one of the `point` structure's members
+                    if config.assemblers.nasm.unwrap_or(false) {
+                        let line = curr_doc.lines().nth(cursor_line).unwrap_or_default();
+                        if let Some(items) =
+                            get_nasm_struc_member_comps(curr_doc, line, cursor_char)
+                        {
+                            return Some(CompletionList {
+                                is_incomplete: true,
+                                items,
+                            });
+                        }
+                    }
                     if config.assemblers.gas.unwrap_or(false)
                         || config.assemblers.masm.unwrap_or(false)
                         || config.assemblers.nasm.unwrap_or(false)
@@ -1065,15 +4980,39 @@ pub fn get_comp_resp(
         }
     }
 
-    // TODO: filter register completions by width allowed by corresponding instruction
-    tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
+    // Inside a GAS macro body, `\<param>` refers to one of the macro's parameters
+    let macro_params = if config.assemblers.gas.unwrap_or(false) {
+        get_macro_params(curr_doc, cursor_line)
+    } else {
+        None
+    };
+    if let Some(ref macro_params) = macro_params {
+        let line = curr_doc.lines().nth(cursor_line).unwrap_or_default();
+        let before_cursor = line.get(..cursor_char).unwrap_or(line);
+        let word_start = before_cursor
+            .char_indices()
+            .rfind(|&(_, c)| !(c.is_alphanumeric() || c == '_' || c == '\\'))
+            .map_or(0, |(idx, c)| idx + c.len_utf8());
+        if before_cursor[word_start..].starts_with('\\') {
+            return Some(CompletionList {
+                is_incomplete: true,
+                items: macro_param_comps(macro_params),
+            });
+        }
+    }
+
+    // Labels are collected from the whole document, so jumps forward to a label
+    // defined later on can be completed too
+    let label_comps = tree_entry
+        .tree
+        .as_ref()
+        .map(|tree| get_label_comps(tree, curr_doc.as_bytes()))
+        .unwrap_or_default();
+
     if let Some(ref tree) = tree_entry.tree {
         static QUERY_DIRECTIVE: Lazy<tree_sitter::Query> = Lazy::new(|| {
-            tree_sitter::Query::new(
-                &tree_sitter_asm::language(),
-                "(meta kind: (meta_ident) @directive)",
-            )
-            .unwrap()
+            tree_sitter::Query::new(&asm_language(), "(meta kind: (meta_ident) @directive)")
+                .unwrap()
         });
         let mut line_cursor = tree_sitter::QueryCursor::new();
         line_cursor.set_point_range(std::ops::Range {
@@ -1105,32 +5044,9 @@ pub fn get_comp_resp(
             }
         }
 
-        // tree-sitter-asm currently parses label arguments to instructions as *registers*
-        // We'll collect all of labels in the document (that are being parsed as labels, at least)
-        // and suggest those along with the register completions
-        static QUERY_LABEL: Lazy<tree_sitter::Query> = Lazy::new(|| {
-            tree_sitter::Query::new(&tree_sitter_asm::language(), "(label (ident) @label)").unwrap()
-        });
-
-        // need a separate cursor to search the entire document
-        let mut doc_cursor = tree_sitter::QueryCursor::new();
-        let captures = doc_cursor.captures(&QUERY_LABEL, tree.root_node(), curr_doc);
-        let mut labels = HashSet::new();
-        for caps in captures.map(|c| c.0) {
-            for cap in caps.captures {
-                if cap.node.end_byte() >= curr_doc.len() {
-                    continue;
-                }
-                match cap.node.utf8_text(curr_doc) {
-                    Ok(text) => _ = labels.insert(text),
-                    Err(_) => continue,
-                }
-            }
-        }
-
         static QUERY_INSTR_ANY: Lazy<tree_sitter::Query> = Lazy::new(|| {
             tree_sitter::Query::new(
-                &tree_sitter_asm::language(),
+                &asm_language(),
                 "[
                     (instruction kind: (word) @instr_name)
                     (
@@ -1176,24 +5092,54 @@ pub fn get_comp_resp(
                     // an instruction is always capture #0 for this query, any capture
                     // number after must be a register or label
                     let is_instr = cap_num == 0;
-                    let mut items =
-                        filtered_comp_list(if is_instr { instr_comps } else { reg_comps });
+                    let mut items = match macro_params {
+                        // parameters take priority over registers inside a macro body
+                        Some(ref macro_params) if !is_instr => macro_param_comps(macro_params),
+                        _ => Vec::new(),
+                    };
+                    items.append(&mut filtered_comp_list(if is_instr {
+                        instr_comps
+                    } else {
+                        reg_comps
+                    }));
                     if is_instr {
                         // Sometimes tree-sitter-asm parses a directive as an instruction, so we'll
                         // suggest both in this case
-                        items.append(&mut filtered_comp_list(dir_comps));
+                        if mix_directives
+                            || cap
+                                .node
+                                .utf8_text(curr_doc)
+                                .is_ok_and(|word| word.starts_with(['.', '%']))
+                        {
+                            items.append(&mut filtered_comp_list(dir_comps));
+                        }
                     } else {
-                        items.append(
-                            &mut labels
-                                .iter()
-                                .map(|l| CompletionItem {
-                                    label: (*l).to_string(),
-                                    kind: Some(CompletionItemKind::VARIABLE),
-                                    ..Default::default()
-                                })
-                                .collect(),
-                        );
+                        if config.instruction_sets.arm.unwrap_or(false) {
+                            if let Ok(instr_name) = caps[0].node.utf8_text(curr_doc) {
+                                let mut sys_reg_comps =
+                                    get_arm_sys_reg_comps(instr_name, reg_comps);
+                                // the status registers are among the register completions
+                                // already if they match what's typed so far
+                                sys_reg_comps.retain(|comp| {
+                                    !items.iter().any(|item| item.label == comp.label)
+                                });
+                                items.append(&mut sys_reg_comps);
+                            }
+                        }
+                        // tree-sitter-asm currently parses label arguments to
+                        // instructions as *registers*, so suggest labels as well
+                        items.extend_from_slice(&label_comps);
                     }
+                    // Mnemonics are what's most likely wanted at the start of a
+                    // statement, and registers in an operand
+                    prioritize_comp_kind(
+                        &mut items,
+                        if is_instr {
+                            CompletionItemKind::FUNCTION
+                        } else {
+                            CompletionItemKind::VARIABLE
+                        },
+                    );
                     return Some(CompletionList {
                         is_incomplete: true,
                         items,
@@ -1203,7 +5149,271 @@ pub fn get_comp_resp(
         }
     }
 
-    None
+    get_lexical_comp_resp(
+        curr_doc,
+        cursor_line,
+        cursor_char,
+        instr_comps,
+        dir_comps,
+        reg_comps,
+        &label_comps,
+        mix_directives,
+    )
+}
+
+/// Returns a completion for each label defined in the document
+fn get_label_comps(tree: &Tree, curr_doc: &[u8]) -> Vec<CompletionItem> {
+    static QUERY_LABEL: Lazy<tree_sitter::Query> =
+        Lazy::new(|| tree_sitter::Query::new(&asm_language(), "(label (ident) @label)").unwrap());
+
+    let mut doc_cursor = tree_sitter::QueryCursor::new();
+    let captures = doc_cursor.captures(&QUERY_LABEL, tree.root_node(), curr_doc);
+    let mut labels = HashSet::new();
+    for caps in captures.map(|c| c.0) {
+        for cap in caps.captures {
+            match cap.node.utf8_text(curr_doc) {
+                Ok(text) => _ = labels.insert(text),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    labels
+        .iter()
+        .map(|l| CompletionItem {
+            label: (*l).to_string(),
+            kind: Some(CompletionItemKind::REFERENCE),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Falls back to completing from the raw text of the cursor's line, for when the
+/// tree-sitter parse of a (likely half-typed) document doesn't place the cursor
+/// in an instruction or one of its operands
+///
+/// An indented first word (or one following a label) is treated as an instruction
+/// or directive, and anything after an instruction as an operand
+fn get_lexical_comp_resp(
+    curr_doc: &str,
+    cursor_line: usize,
+    cursor_char: usize,
+    instr_comps: &[CompletionItem],
+    dir_comps: &[CompletionItem],
+    reg_comps: &[CompletionItem],
+    label_comps: &[CompletionItem],
+    mix_directives: bool,
+) -> Option<CompletionList> {
+    let line = curr_doc.lines().nth(cursor_line)?;
+    let before_cursor = line.get(..cursor_char).unwrap_or(line);
+    // `#` also starts GAS comments (and preprocessor lines), and only ever
+    // precedes an immediate otherwise
+    if before_cursor.contains([';', '#']) || before_cursor.contains("//") {
+        return None;
+    }
+
+    // skip over a leading label, but not a segment override like `es:[bx]`
+    let (after_label, stmt) = before_cursor
+        .split_once(':')
+        .filter(|(label, _)| {
+            let label = label.trim();
+            !label.is_empty() && !label.contains(char::is_whitespace)
+        })
+        .map_or((false, before_cursor), |(_, stmt)| (true, stmt));
+    let indented = after_label || stmt.starts_with(char::is_whitespace);
+    let stmt = stmt.trim_start();
+
+    let items = if stmt.contains(char::is_whitespace) {
+        // directive arguments are too varied to guess at
+        if stmt.starts_with('.') {
+            return None;
+        }
+        // operands can be registers or jump targets
+        let mut items = filtered_comp_list(reg_comps);
+        items.extend_from_slice(label_comps);
+        prioritize_comp_kind(&mut items, CompletionItemKind::VARIABLE);
+        items
+    } else if !indented {
+        // an unindented first word is most likely a label
+        return None;
+    } else if stmt.starts_with(['.', '%']) {
+        filtered_comp_list(dir_comps)
+    } else {
+        let mut items = filtered_comp_list(instr_comps);
+        if mix_directives {
+            items.append(&mut filtered_comp_list(dir_comps));
+        }
+        items
+    };
+
+    if items.is_empty() {
+        None
+    } else {
+        Some(CompletionList {
+            is_incomplete: true,
+            items,
+        })
+    }
+}
+
+/// Returns completions for the system registers accessed by the ARM
+/// instruction `instr_name`, i.e. the status registers for `mrs`/`msr` and
+/// coprocessor operands for `mcr`/`mrc`
+fn get_arm_sys_reg_comps(instr_name: &str, reg_comps: &[CompletionItem]) -> Vec<CompletionItem> {
+    match instr_name.to_ascii_lowercase().as_str() {
+        "mrs" | "msr" => reg_comps
+            .iter()
+            .filter(|comp| ["apsr", "cpsr", "spsr"].contains(&comp.label.as_str()))
+            .cloned()
+            .collect(),
+        "mcr" | "mcr2" | "mrc" | "mrc2" | "mcrr" | "mrrc" => ["p", "c"]
+            .iter()
+            .flat_map(|prefix| (0..=15).map(move |num| format!("{prefix}{num}")))
+            .map(|label| CompletionItem {
+                label,
+                kind: Some(CompletionItemKind::VARIABLE),
+                ..Default::default()
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns completions for the registers not yet named in the ARM register list
+/// `cursor` is in, e.g. `push {r0, |}`, along with a range (e.g. `r1-r12`) for each
+/// run of consecutive unlisted registers
+///
+/// tree-sitter-asm doesn't know about register lists, so the opening brace shows
+/// up as an error node within or just after the instruction on the cursor's line
+fn get_arm_reg_list_comps(
+    tree: &Tree,
+    curr_doc: &str,
+    cursor: tree_sitter::Point,
+    reg_comps: &[CompletionItem],
+) -> Option<Vec<CompletionItem>> {
+    let line = curr_doc.lines().nth(cursor.row)?;
+    let mut instr_name = None;
+    let mut list_start = None;
+    let mut node_cursor = tree.root_node().walk();
+    'walk: loop {
+        let node = node_cursor.node();
+        let on_line = node.start_position().row <= cursor.row
+            && node.end_position().row >= cursor.row
+            && node.start_position() < cursor;
+        if on_line && node.kind() == "instruction" && node.start_position().row == cursor.row {
+            instr_name = node
+                .child_by_field_name("kind")
+                .and_then(|kind| kind.utf8_text(curr_doc.as_bytes()).ok());
+        } else if on_line
+            && node.is_error()
+            && node.start_position().row == cursor.row
+            && node
+                .utf8_text(curr_doc.as_bytes())
+                .is_ok_and(|text| text.starts_with('{'))
+        {
+            list_start = Some(node.start_position().column + 1);
+        }
+        if on_line && node_cursor.goto_first_child() {
+            continue;
+        }
+        while !node_cursor.goto_next_sibling() {
+            if !node_cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+
+    let list_start = list_start?;
+    let list = &line[list_start..];
+    let list_end = list.find('}').unwrap_or(list.len());
+    // the list must still be open at the cursor
+    if list_start + list_end < cursor.column {
+        return None;
+    }
+
+    // `vpush {d8-d15}`
+    let is_float = instr_name?.to_ascii_lowercase().starts_with('v');
+    let in_class = |&(class, _): &(char, u32)| {
+        if is_float {
+            matches!(class, 's' | 'd')
+        } else {
+            class == 'r'
+        }
+    };
+    let mut listed = HashSet::new();
+    for entry in list[..list_end].split(',').map(str::trim) {
+        match entry.split_once('-') {
+            Some((first, last)) => {
+                if let (Some((class, first)), Some((last_class, last))) =
+                    (get_arm_reg_index(first), get_arm_reg_index(last))
+                {
+                    if class == last_class {
+                        listed.extend((first..=last).map(|idx| (class, idx)));
+                    }
+                }
+            }
+            None => listed.extend(get_arm_reg_index(entry)),
+        }
+    }
+
+    let mut items: Vec<CompletionItem> = reg_comps
+        .iter()
+        .filter(|comp| {
+            get_arm_reg_index(&comp.label)
+                .is_some_and(|reg| in_class(&reg) && !listed.contains(&reg))
+        })
+        .cloned()
+        .collect();
+
+    // pc, lr, and sp are left to be completed individually
+    let mut unlisted: Vec<(char, u32)> = reg_comps
+        .iter()
+        .filter_map(|comp| get_arm_reg_index(&comp.label))
+        .filter(|reg| in_class(reg) && !listed.contains(reg) && !(reg.0 == 'r' && reg.1 > 12))
+        .collect();
+    unlisted.sort_unstable();
+    unlisted.dedup();
+    let mut run_start = 0;
+    for idx in 1..=unlisted.len() {
+        let (class, num) = unlisted[idx - 1];
+        if unlisted
+            .get(idx)
+            .is_some_and(|&next| next == (class, num + 1))
+        {
+            continue;
+        }
+        let (_, first) = unlisted[run_start];
+        if first < num {
+            items.push(CompletionItem {
+                label: format!("{class}{first}-{class}{num}"),
+                kind: Some(CompletionItemKind::VARIABLE),
+                detail: Some(String::from("Register range")),
+                ..Default::default()
+            });
+        }
+        run_start = idx;
+    }
+
+    Some(items)
+}
+
+/// Returns the class and number of the ARM register `name`, e.g. `('d', 8)` for
+/// `d8`, with `ip`, `sp`, `lr`, and `pc` numbered as the core registers they alias
+fn get_arm_reg_index(name: &str) -> Option<(char, u32)> {
+    let name = name.trim().to_ascii_lowercase();
+    match name.as_str() {
+        "ip" => return Some(('r', 12)),
+        "sp" => return Some(('r', 13)),
+        "lr" => return Some(('r', 14)),
+        "pc" => return Some(('r', 15)),
+        _ => {}
+    }
+    let class = name
+        .chars()
+        .next()
+        .filter(|c| matches!(c, 'r' | 's' | 'd'))?;
+    let num = name[1..].parse().ok()?;
+    Some((class, num))
 }
 
 const fn lsp_pos_of_point(pos: tree_sitter::Point) -> lsp_types::Position {
@@ -1274,45 +5484,426 @@ fn explore_node(
     } else {
         let mut cursor = node.walk();
 
-        if cursor.goto_first_child() {
-            loop {
-                explore_node(curr_doc, cursor.node(), res, label_kind_id, ident_kind_id);
-                if !cursor.goto_next_sibling() {
-                    break;
-                }
-            }
+        if cursor.goto_first_child() {
+            loop {
+                explore_node(curr_doc, cursor.node(), res, label_kind_id, ident_kind_id);
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Lists every supported instruction set and assembler, along with whether each
+/// is enabled in `config`
+#[must_use]
+pub fn get_backends_resp(config: &Config) -> ListBackendsResult {
+    ListBackendsResult {
+        instruction_sets: Arch::iter()
+            .map(|arch| BackendInfo {
+                id: arch.config_key().to_string(),
+                name: arch.display_name().to_string(),
+                enabled: config.is_isa_enabled(arch),
+            })
+            .collect(),
+        assemblers: Assembler::iter()
+            .map(|assembler| BackendInfo {
+                id: assembler.config_key().to_string(),
+                name: assembler.display_name().to_string(),
+                enabled: config.is_assembler_enabled(assembler),
+            })
+            .collect(),
+    }
+}
+
+/// Lists the instructions of `params.arch` with a form that writes `params.register`,
+/// either implicitly or as a fixed operand like `al`
+///
+/// # Errors
+///
+/// Returns `Err` if the arch is unknown or isn't enabled in `config`
+pub fn get_register_writers_resp(
+    params: &InstructionsWritingRegisterParams,
+    config: &Config,
+    instruction_map: &NameToInstructionMap,
+) -> Result<Vec<RegisterWriter>> {
+    let Some(arch) = parse_arch_name(&params.arch) else {
+        return Err(anyhow!("Unknown instruction set `{}`", params.arch));
+    };
+    if !config.is_isa_enabled(arch) {
+        return Err(anyhow!("The {arch} instruction set isn't enabled"));
+    }
+    let register = params.register.trim_start_matches('%').to_ascii_lowercase();
+
+    let mut instrs: Vec<&Instruction> = instruction_map
+        .iter()
+        .filter(|((instr_arch, _), _)| *instr_arch == arch)
+        .map(|(_, &instr)| instr)
+        .collect();
+    instrs.sort_by(|a, b| a.name.cmp(&b.name));
+    instrs.dedup_by(|a, b| a.name == b.name);
+
+    Ok(instrs
+        .into_iter()
+        .filter_map(|instr| {
+            let mut implicit = Vec::new();
+            let mut explicit = Vec::new();
+            for form in &instr.forms {
+                let form_name = form
+                    .gas_name
+                    .as_deref()
+                    .or(form.go_name.as_deref())
+                    .unwrap_or(&instr.name);
+                if form
+                    .implicit_operands
+                    .iter()
+                    .any(|op| op.output.unwrap_or(false) && op.id.eq_ignore_ascii_case(&register))
+                {
+                    implicit.push(format!("`{form_name}`"));
+                }
+                if form
+                    .operands
+                    .iter()
+                    .any(|op| op.output.unwrap_or(false) && op.type_.as_ref() == register)
+                {
+                    explicit.push(format!("`{form_name}`"));
+                }
+            }
+            implicit.dedup();
+            explicit.dedup();
+            let note = match (implicit.is_empty(), explicit.is_empty()) {
+                (true, true) => return None,
+                (false, true) => format!("Implicitly written by {}", implicit.join(", ")),
+                (true, false) => format!("Written as an operand by {}", explicit.join(", ")),
+                (false, false) => format!(
+                    "Implicitly written by {}, and as an operand by {}",
+                    implicit.join(", "),
+                    explicit.join(", ")
+                ),
+            };
+            Some(RegisterWriter {
+                name: instr.name.clone(),
+                note,
+            })
+        })
+        .collect())
+}
+
+/// File extensions of the assembly sources run by `get_assemble_project_resp`
+const ASSEMBLY_EXTENSIONS: &[&str] = &["s", "S", "sx", "asm"];
+
+/// The most compile commands `get_assemble_project_resp` runs at once
+const MAX_ASSEMBLE_THREADS: usize = 8;
+
+/// Runs the compile command of every assembly source file in `compile_cmds`
+/// concurrently, collecting the resulting diagnostics by file
+///
+/// Returns an empty report if diagnostics are disabled in `config`
+///
+/// # Panics
+///
+/// Panics if running one of the compile commands panics
+#[must_use]
+pub fn get_assemble_project_resp(
+    config: &Config,
+    compile_cmds: &CompilationDatabase,
+) -> AssembleProjectResult {
+    if !config.opts.diagnostics.unwrap_or(true) {
+        return AssembleProjectResult::default();
+    }
+
+    let entries: Vec<(Uri, &CompileCommand)> = compile_cmds
+        .iter()
+        .filter_map(|entry| {
+            let SourceFile::File(ref file) = entry.file else {
+                return None;
+            };
+            if !file
+                .extension()
+                .is_some_and(|ext| ASSEMBLY_EXTENSIONS.iter().any(|asm_ext| ext == *asm_ext))
+            {
+                return None;
+            }
+            Some((path_to_uri(&entry.directory.join(file))?, entry))
+        })
+        .collect();
+
+    let next_entry = AtomicUsize::new(0);
+    let report = Mutex::new(AssembleProjectResult::default());
+    let num_threads = std::thread::available_parallelism()
+        .map_or(1, std::num::NonZeroUsize::get)
+        .min(MAX_ASSEMBLE_THREADS)
+        .min(entries.len());
+    std::thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| {
+                while let Some((uri, entry)) =
+                    entries.get(next_entry.fetch_add(1, Ordering::Relaxed))
+                {
+                    let mut diagnostics = Vec::new();
+                    apply_compile_cmd(config, &mut diagnostics, uri, entry);
+                    // a file can have multiple entries, e.g. one per build configuration
+                    let mut report = report.lock().unwrap();
+                    report
+                        .diagnostics
+                        .entry(uri.clone())
+                        .or_default()
+                        .append(&mut diagnostics);
+                }
+            });
+        }
+    });
+
+    let mut report = report.into_inner().unwrap();
+    for diagnostics in report.diagnostics.values_mut() {
+        dedup_diagnostics(diagnostics);
+    }
+
+    report
+}
+
+/// Get a tree of symbols describing the document's structure.
+pub fn get_document_symbols(
+    curr_doc: &str,
+    tree_entry: &mut TreeEntry,
+    _params: &DocumentSymbolParams,
+) -> Option<Vec<DocumentSymbol>> {
+    static LABEL_KIND_ID: Lazy<u16> = Lazy::new(|| asm_language().id_for_node_kind("label", true));
+    static IDENT_KIND_ID: Lazy<u16> = Lazy::new(|| asm_language().id_for_node_kind("ident", true));
+    tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
+
+    tree_entry.tree.as_ref().map(|tree| {
+        let mut res: Vec<DocumentSymbol> = vec![];
+        let mut cursor = tree.walk();
+        loop {
+            explore_node(
+                curr_doc,
+                cursor.node(),
+                &mut res,
+                &LABEL_KIND_ID,
+                &IDENT_KIND_ID,
+            );
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+        nest_repetition_blocks(curr_doc, tree, &mut res);
+        sort_symbols(&mut res);
+        res
+    })
+}
+
+/// Adds a symbol for each GAS `.rept`/`.irp`/`.irpc` ... `.endr` block, moving
+/// any symbols that lie within the block into its children so they're only
+/// listed once. Nested blocks close before the blocks enclosing them, so they
+/// end up as children of their enclosing block as well
+fn nest_repetition_blocks(curr_doc: &str, tree: &tree_sitter::Tree, res: &mut Vec<DocumentSymbol>) {
+    let mut open_blocks = Vec::new();
+    let mut cursor = tree.root_node().walk();
+    if !cursor.goto_first_child() {
+        return;
+    }
+    loop {
+        let node = cursor.node();
+        let directive = node
+            .child_by_field_name("kind")
+            .and_then(|kind| kind.utf8_text(curr_doc.as_bytes()).ok())
+            .map(str::to_ascii_lowercase);
+        match directive.as_deref() {
+            Some(".rept" | ".irp" | ".irpc") => open_blocks.push(node),
+            Some(".endr") => {
+                if let Some(start) = open_blocks.pop() {
+                    let range = lsp_types::Range::new(
+                        lsp_pos_of_point(start.start_position()),
+                        lsp_pos_of_point(node.end_position()),
+                    );
+                    // name the block after its opening line, minus any trailing comment
+                    let name = curr_doc
+                        .lines()
+                        .nth(start.start_position().row)
+                        .and_then(|line| line.split(['#', ';']).next())
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string();
+                    let (children, rest): (Vec<_>, Vec<_>) = res.drain(..).partition(|symbol| {
+                        range.start <= symbol.range.start && symbol.range.end <= range.end
+                    });
+                    *res = rest;
+
+                    #[allow(deprecated)]
+                    res.push(DocumentSymbol {
+                        name,
+                        detail: None,
+                        kind: SymbolKind::NAMESPACE,
+                        tags: None,
+                        deprecated: Some(false),
+                        range,
+                        selection_range: lsp_types::Range::new(
+                            lsp_pos_of_point(start.start_position()),
+                            lsp_pos_of_point(start.end_position()),
+                        ),
+                        children: if children.is_empty() {
+                            None
+                        } else {
+                            Some(children)
+                        },
+                    });
+                }
+            }
+            _ => {}
+        }
+        if !cursor.goto_next_sibling() {
+            break;
+        }
+    }
+}
+
+/// Returns the outline of `curr_doc` for the `asm-lsp/documentOutline` request
+#[must_use]
+pub fn get_document_outline(
+    curr_doc: &str,
+    tree_entry: &mut TreeEntry,
+    params: &DocumentOutlineParams,
+) -> DocumentOutlineResult {
+    let symbol_params = DocumentSymbolParams {
+        text_document: params.text_document.clone(),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+        partial_result_params: PartialResultParams::default(),
+    };
+    let mut outline = Vec::new();
+    if let Some(symbols) = get_document_symbols(curr_doc, tree_entry, &symbol_params) {
+        push_outline_symbols(symbols, None, params.flat, &mut outline);
+    }
+
+    DocumentOutlineResult {
+        uri: params.text_document.uri.clone(),
+        symbols: outline,
+    }
+}
+
+/// Converts `symbols` to `OutlineSymbol`s, appending them to `out`. If `flat` is
+/// set, children are appended after their parent rather than nested within it
+fn push_outline_symbols(
+    symbols: Vec<DocumentSymbol>,
+    container: Option<&str>,
+    flat: bool,
+    out: &mut Vec<OutlineSymbol>,
+) {
+    for symbol in symbols {
+        let mut entry = OutlineSymbol {
+            name: symbol.name,
+            kind: if symbol.kind == SymbolKind::NAMESPACE {
+                OutlineSymbolKind::Block
+            } else {
+                OutlineSymbolKind::Label
+            },
+            range: symbol.range,
+            selection_range: symbol.selection_range,
+            container: container.map(ToString::to_string),
+            children: Vec::new(),
+        };
+        let children = symbol.children.unwrap_or_default();
+        if flat {
+            let name = entry.name.clone();
+            out.push(entry);
+            push_outline_symbols(children, Some(&name), true, out);
+        } else {
+            push_outline_symbols(children, None, false, &mut entry.children);
+            out.push(entry);
+        }
+    }
+}
+
+/// Sorts `symbols` and all of their children by position, so the outline
+/// matches the order of the file regardless of the order they were discovered in
+fn sort_symbols(symbols: &mut [DocumentSymbol]) {
+    symbols.sort_by_key(|symbol| symbol.range.start);
+    for symbol in symbols {
+        if let Some(ref mut children) = symbol.children {
+            sort_symbols(children);
         }
     }
 }
 
-/// Get a tree of symbols describing the document's structure.
-pub fn get_document_symbols(
+/// Returns the legend for the tokens provided by `get_semantic_tokens`
+///
+/// Registers are the only tokens, reported as variables with a modifier for
+/// their class (see `RegisterType::semantic_token_modifier`)
+#[must_use]
+pub fn get_semantic_tokens_legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![SemanticTokenType::VARIABLE],
+        token_modifiers: RegisterType::iter()
+            .map(|reg_type| SemanticTokenModifier::new(reg_type.semantic_token_modifier()))
+            .collect(),
+    }
+}
+
+/// Get a semantic token for each known register in the document, so that editors
+/// can style general purpose, segment, floating point etc. registers distinctly
+pub fn get_semantic_tokens(
     curr_doc: &str,
     tree_entry: &mut TreeEntry,
-    _params: &DocumentSymbolParams,
-) -> Option<Vec<DocumentSymbol>> {
-    static LABEL_KIND_ID: Lazy<u16> =
-        Lazy::new(|| tree_sitter_asm::language().id_for_node_kind("label", true));
-    static IDENT_KIND_ID: Lazy<u16> =
-        Lazy::new(|| tree_sitter_asm::language().id_for_node_kind("ident", true));
-    tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
+    config: &Config,
+    register_map: &NameToRegisterMap,
+) -> Option<SemanticTokens> {
+    static QUERY_REG: Lazy<tree_sitter::Query> =
+        Lazy::new(|| tree_sitter::Query::new(&asm_language(), "(reg) @reg").unwrap());
 
-    tree_entry.tree.as_ref().map(|tree| {
-        let mut res: Vec<DocumentSymbol> = vec![];
-        let mut cursor = tree.walk();
-        loop {
-            explore_node(
-                curr_doc,
-                cursor.node(),
-                &mut res,
-                &LABEL_KIND_ID,
-                &IDENT_KIND_ID,
-            );
-            if !cursor.goto_next_sibling() {
-                break;
-            }
+    tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
+    let tree = tree_entry.tree.as_ref()?;
+    let lines: Vec<&str> = curr_doc.lines().collect();
+
+    let mut data = Vec::new();
+    let (mut prev_line, mut prev_start) = (0, 0);
+    let mut cursor = tree_sitter::QueryCursor::new();
+    let matches = cursor.matches(&QUERY_REG, tree.root_node(), curr_doc.as_bytes());
+    for cap in matches.flat_map(|match_| match_.captures) {
+        let start = cap.node.start_position();
+        // registers don't span lines
+        if cap.node.end_position().row != start.row {
+            continue;
         }
-        res
+        let Ok(text) = cap.node.utf8_text(curr_doc.as_bytes()) else {
+            continue;
+        };
+        // GAS prefixes registers with '%', which isn't part of their name
+        let name = text.trim_start_matches('%');
+        let byte_col = start.column + (text.len() - name.len());
+        let name = name.to_ascii_lowercase();
+        // labels used as operands are also parsed as registers
+        let Some(register) = Arch::iter()
+            .filter(|&arch| config.is_isa_enabled(arch))
+            .find_map(|arch| register_map.get(&(arch, name.as_str())))
+        else {
+            continue;
+        };
+
+        let line_prefix = lines
+            .get(start.row)
+            .and_then(|line| line.get(..byte_col))
+            .unwrap_or_default();
+        let line = u32::try_from(start.row).unwrap_or(u32::MAX);
+        let start_char = u32::try_from(line_prefix.encode_utf16().count()).unwrap_or(u32::MAX);
+        let delta_line = line - prev_line;
+        data.push(SemanticToken {
+            delta_line,
+            delta_start: if delta_line == 0 {
+                start_char - prev_start
+            } else {
+                start_char
+            },
+            length: u32::try_from(name.encode_utf16().count()).unwrap_or(u32::MAX),
+            token_type: 0,
+            token_modifiers_bitset: register.reg_type.map_or(0, |reg_type| 1 << reg_type as u32),
+        });
+        (prev_line, prev_start) = (line, start_char);
+    }
+
+    Some(SemanticTokens {
+        result_id: None,
+        data,
     })
 }
 
@@ -1321,31 +5912,44 @@ pub fn get_sig_help_resp(
     params: &SignatureHelpParams,
     tree_entry: &mut TreeEntry,
     instr_info: &NameToInstructionMap,
+    dir_info: &NameToDirectiveMap,
+    config: &Config,
 ) -> Option<SignatureHelp> {
-    let cursor_line = params.text_document_position_params.position.line as usize;
+    let cursor = position_to_point(curr_doc, params.text_document_position_params.position);
+    // tree-sitter-asm doesn't know about operands wrapping onto the next line, so
+    // find the line the instruction starts on ourselves
+    let stmt_start = get_logical_stmt_start(curr_doc, cursor.row);
 
     tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
     if let Some(ref tree) = tree_entry.tree {
+        let dir_sig_help = get_directive_sig_help(
+            tree,
+            curr_doc.as_bytes(),
+            params.text_document_position_params.position,
+            dir_info,
+        );
+        if dir_sig_help.is_some() {
+            return dir_sig_help;
+        }
+
         // Instruction with any (including zero) argument(s)
         static QUERY_INSTR_ANY_ARGS: Lazy<tree_sitter::Query> = Lazy::new(|| {
-            tree_sitter::Query::new(
-                &tree_sitter_asm::language(),
-                "(instruction kind: (word) @instr_name)",
-            )
-            .unwrap()
+            tree_sitter::Query::new(&asm_language(), "(instruction kind: (word) @instr_name)")
+                .unwrap()
         });
 
         let mut line_cursor = tree_sitter::QueryCursor::new();
         line_cursor.set_point_range(std::ops::Range {
             start: tree_sitter::Point {
-                row: cursor_line,
+                row: stmt_start,
                 column: 0,
             },
             end: tree_sitter::Point {
-                row: cursor_line,
+                row: stmt_start,
                 column: usize::MAX,
             },
         });
+        let doc_str = curr_doc;
         let curr_doc = curr_doc.as_bytes();
 
         let matches: Vec<tree_sitter::QueryMatch<'_, '_>> = line_cursor
@@ -1353,9 +5957,13 @@ pub fn get_sig_help_resp(
             .collect();
         if let Some(match_) = matches.first() {
             let caps = match_.captures;
-            if caps.len() == 1 && caps[0].node.end_byte() < curr_doc.len() {
+            if caps.len() == 1 {
                 if let Ok(instr_name) = caps[0].node.utf8_text(curr_doc) {
+                    let active_operand =
+                        get_active_operand(doc_str, caps[0].node.end_position(), cursor);
                     let mut value = String::new();
+                    // the most operands taken by any of the forms shown
+                    let mut operand_count = 0;
                     // Switch to a better structure
                     let mut has_x86 = false;
                     let mut has_x86_64 = false;
@@ -1364,48 +5972,46 @@ pub fn get_sig_help_resp(
                     let mut has_arm64 = false;
                     // ensure hovered instruction is always lowercase
                     let hovered_instr_name = instr_name.to_ascii_lowercase();
-                    let (x86_info, x86_64_info, z80_info, arm_info, arm64_info, riscv_info) =
+                    let prefer_go = config.prefers_go_names();
+                    // Go names like `MOVOU` are keyed as written
+                    let go_instr_name = instr_name.to_ascii_uppercase();
+                    let lookup_name = if prefer_go
+                        && SEARCH_ARCH_ORDER
+                            .iter()
+                            .any(|&arch| instr_info.contains_key(&(arch, go_instr_name.as_str())))
+                    {
+                        &go_instr_name
+                    } else {
+                        &hovered_instr_name
+                    };
+                    let (x86_info, x86_64_info, z80_info, arm_info, arm64_info, riscv_info, _, m68k_info) =
                     // TODO: switch to an appropriate DS like dyn list or static list
-                        search_for_hoverable_by_arch(&hovered_instr_name, instr_info);
+                        search_for_hoverable_by_arch(lookup_name, instr_info);
                     if let Some(sig) = x86_info {
                         for form in &sig.forms {
-                            if let Some(ref gas_name) = form.gas_name {
-                                if instr_name.eq_ignore_ascii_case(gas_name) {
-                                    if !has_x86 {
-                                        value += "**x86**\n";
-                                        has_x86 = true;
-                                    }
-                                    value += &format!("{form}\n");
-                                }
-                            } else if let Some(ref go_name) = form.go_name {
-                                if instr_name.eq_ignore_ascii_case(go_name) {
-                                    if !has_x86 {
-                                        value += "**x86**\n";
-                                        has_x86 = true;
-                                    }
-                                    value += &format!("{form}\n");
+                            if get_sig_form_name(form, prefer_go)
+                                .is_some_and(|name| instr_name.eq_ignore_ascii_case(name))
+                            {
+                                if !has_x86 {
+                                    value += "**x86**\n";
+                                    has_x86 = true;
                                 }
+                                operand_count = operand_count.max(form.operands.len());
+                                value += &format!("{}\n", get_sig_form(form, prefer_go));
                             }
                         }
                     }
                     if let Some(sig) = x86_64_info {
                         for form in &sig.forms {
-                            if let Some(ref gas_name) = form.gas_name {
-                                if instr_name.eq_ignore_ascii_case(gas_name) {
-                                    if !has_x86_64 {
-                                        value += "**x86_64**\n";
-                                        has_x86_64 = true;
-                                    }
-                                    value += &format!("{form}\n");
-                                }
-                            } else if let Some(ref go_name) = form.go_name {
-                                if instr_name.eq_ignore_ascii_case(go_name) {
-                                    if !has_x86_64 {
-                                        value += "**x86_64**\n";
-                                        has_x86_64 = true;
-                                    }
-                                    value += &format!("{form}\n");
+                            if get_sig_form_name(form, prefer_go)
+                                .is_some_and(|name| instr_name.eq_ignore_ascii_case(name))
+                            {
+                                if !has_x86_64 {
+                                    value += "**x86_64**\n";
+                                    has_x86_64 = true;
                                 }
+                                operand_count = operand_count.max(form.operands.len());
+                                value += &format!("{}\n", get_sig_form(form, prefer_go));
                             }
                         }
                     }
@@ -1417,6 +6023,10 @@ pub fn get_sig_help_resp(
                                         value += "**z80**\n";
                                         has_z80 = true;
                                     }
+                                    if let Some(ref z80_form) = form.z80_form {
+                                        operand_count =
+                                            operand_count.max(get_template_operand_count(z80_form));
+                                    }
                                     value += &format!("{form}\n");
                                 }
                             }
@@ -1428,6 +6038,7 @@ pub fn get_sig_help_resp(
                                 value += "**arm**\n";
                                 has_arm = true;
                             }
+                            operand_count = operand_count.max(get_template_operand_count(form));
                             value += &format!("{form}\n");
                         }
                     }
@@ -1437,6 +6048,7 @@ pub fn get_sig_help_resp(
                                 value += "**arm64**\n";
                                 has_arm64 = true;
                             }
+                            operand_count = operand_count.max(get_template_operand_count(form));
                             value += &format!("{form}\n");
                         }
                     }
@@ -1446,22 +6058,33 @@ pub fn get_sig_help_resp(
                                 value += "**riscv**\n";
                                 has_arm = true;
                             }
+                            operand_count = operand_count.max(get_template_operand_count(form));
+                            value += &format!("{form}\n");
+                        }
+                    }
+                    if let Some(sig) = m68k_info {
+                        if !sig.asm_templates.is_empty() {
+                            value += "**m68k**\n";
+                        }
+                        for form in &sig.asm_templates {
+                            operand_count = operand_count.max(get_template_operand_count(form));
                             value += &format!("{form}\n");
                         }
                     }
                     if !value.is_empty() {
+                        let (label, parameters) = get_instr_sig_label(instr_name, operand_count);
                         return Some(SignatureHelp {
                             signatures: vec![SignatureInformation {
-                                label: instr_name.to_string(),
+                                label,
                                 documentation: Some(Documentation::MarkupContent(MarkupContent {
                                     kind: MarkupKind::Markdown,
                                     value,
                                 })),
-                                parameters: None,
+                                parameters: Some(parameters),
                                 active_parameter: None,
                             }],
                             active_signature: None,
-                            active_parameter: None,
+                            active_parameter: active_operand,
                         });
                     }
                 }
@@ -1472,140 +6095,936 @@ pub fn get_sig_help_resp(
     None
 }
 
-pub fn get_goto_def_resp(
-    curr_doc: &FullTextDocument,
-    tree_entry: &mut TreeEntry,
+/// Returns signature help for the directive whose arguments contain the cursor,
+/// with the argument under the cursor active
+fn get_directive_sig_help(
+    tree: &tree_sitter::Tree,
+    curr_doc: &[u8],
+    position: Position,
+    dir_info: &NameToDirectiveMap,
+) -> Option<SignatureHelp> {
+    let doc_str = std::str::from_utf8(curr_doc).ok()?;
+    let cursor = position_to_point(doc_str, position);
+    let root = tree.root_node();
+    let mut walker = root.walk();
+    let directive = root
+        .children(&mut walker)
+        .filter(|node| node.kind() == "meta")
+        .find(|node| node.start_position().row == cursor.row)?
+        .child_by_field_name("kind")?;
+    if directive.end_position() > cursor {
+        return None;
+    }
+
+    // count the commas between the directive's name and the cursor, ignoring any
+    // in string arguments
+    let line = doc_str.lines().nth(cursor.row)?;
+    let args = line.get(directive.end_position().column..cursor.column)?;
+    let mut in_string = false;
+    let mut active_arg = 0;
+    for c in args.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            ',' if !in_string => active_arg += 1,
+            _ => {}
+        }
+    }
+
+    let name = directive.utf8_text(curr_doc).ok()?.to_ascii_lowercase();
+    let signatures: Vec<SignatureInformation> =
+        <[Option<&&Directive>; 4]>::from(search_for_hoverable_by_assembler(&name, dir_info))
+            .into_iter()
+            .flatten()
+            .flat_map(|dir| {
+                dir.get_signature_args()
+                    .into_iter()
+                    .map(move |sig| (dir, sig))
+            })
+            .map(|(dir, (label, args))| SignatureInformation {
+                label,
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: dir.description.clone(),
+                })),
+                // trailing variadic arguments share the last parameter
+                active_parameter: args
+                    .len()
+                    .checked_sub(1)
+                    .map(|last| active_arg.min(last) as u32),
+                parameters: Some(
+                    args.into_iter()
+                        .map(|offsets| ParameterInformation {
+                            label: ParameterLabel::LabelOffsets(offsets),
+                            documentation: None,
+                        })
+                        .collect(),
+                ),
+            })
+            .collect();
+
+    if signatures.is_empty() {
+        None
+    } else {
+        Some(SignatureHelp {
+            signatures,
+            active_signature: None,
+            active_parameter: None,
+        })
+    }
+}
+
+/// Returns the label of an instruction's signature help, its name followed by
+/// `operand_count` numbered operand placeholders, along with a parameter for each
+/// placeholder
+fn get_instr_sig_label(name: &str, operand_count: usize) -> (String, Vec<ParameterInformation>) {
+    let mut label = name.to_string();
+    let mut parameters = Vec::with_capacity(operand_count);
+    for idx in 1..=operand_count {
+        label += if idx == 1 { " " } else { ", " };
+        let start = label.encode_utf16().count() as u32;
+        let placeholder = format!("op{idx}");
+        label += &placeholder;
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, label.encode_utf16().count() as u32]),
+            documentation: None,
+        });
+    }
+
+    (label, parameters)
+}
+
+/// Returns the number of operands in an assembly template like
+/// `LDR <Wt>, [<Xn|SP>, #<simm>]!`, ignoring the commas within brackets and
+/// braces
+fn get_template_operand_count(template: &str) -> usize {
+    let Some((_, operands)) = template.trim().split_once(char::is_whitespace) else {
+        return 0;
+    };
+    let mut depth = 0usize;
+    let mut count = 1;
+    for c in operands.chars() {
+        match c {
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => count += 1,
+            _ => {}
+        }
+    }
+
+    count
+}
+
+/// Returns the name an x86 instruction form is matched by in signature help: its
+/// Go name if `prefer_go` is set, and its GAS name (or Go name, lacking one)
+/// otherwise
+fn get_sig_form_name(form: &InstructionForm, prefer_go: bool) -> Option<&String> {
+    if prefer_go {
+        form.go_name.as_ref()
+    } else {
+        form.gas_name.as_ref().or(form.go_name.as_ref())
+    }
+}
+
+/// Returns `form` as shown in signature help, without its GAS name if `prefer_go`
+/// is set
+fn get_sig_form(form: &InstructionForm, prefer_go: bool) -> std::borrow::Cow<'_, InstructionForm> {
+    if prefer_go {
+        std::borrow::Cow::Owned(InstructionForm {
+            gas_name: None,
+            ..form.clone()
+        })
+    } else {
+        std::borrow::Cow::Borrowed(form)
+    }
+}
+
+/// Returns the row on which the statement containing `row` starts, following
+/// lines continued with a trailing `\` or an operand list ending in `,`
+fn get_logical_stmt_start(curr_doc: &str, row: usize) -> usize {
+    let preceding_lines: Vec<&str> = curr_doc.lines().take(row).collect();
+    preceding_lines
+        .iter()
+        .rposition(|line| !line.trim_end().ends_with(['\\', ',']))
+        .map_or(0, |last_complete| last_complete + 1)
+}
+
+/// Returns the index of the operand `cursor` is in, counting the commas between
+/// the end of an instruction's mnemonic at `instr_end` and `cursor`, which may be
+/// on a later line. Commas within string literals are ignored
+fn get_active_operand(
+    curr_doc: &str,
+    instr_end: tree_sitter::Point,
+    cursor: tree_sitter::Point,
+) -> Option<u32> {
+    if cursor < instr_end {
+        return None;
+    }
+    let mut in_string = false;
+    let mut active_operand = 0;
+    for (row, line) in curr_doc
+        .lines()
+        .enumerate()
+        .take(cursor.row + 1)
+        .skip(instr_end.row)
+    {
+        let start = if row == instr_end.row {
+            instr_end.column
+        } else {
+            0
+        };
+        let end = if row == cursor.row {
+            cursor.column
+        } else {
+            line.len()
+        };
+        for c in line.get(start..end)?.chars() {
+            match c {
+                '"' => in_string = !in_string,
+                ',' if !in_string => active_operand += 1,
+                _ => {}
+            }
+        }
+    }
+
+    Some(active_operand)
+}
+
+/// Returns the edit re-indenting the line a newline was just typed onto
+///
+/// The line is indented by one indent unit after a label, or to the same column
+/// as the previous line when that line is part of an indented block of instructions
+#[must_use]
+pub fn get_on_type_formatting_resp(
+    curr_doc: &str,
+    params: &DocumentOnTypeFormattingParams,
+    config: &Config,
+) -> Option<Vec<TextEdit>> {
+    if params.ch != "\n" {
+        return None;
+    }
+    let row = params.text_document_position.position.line as usize;
+    let prev_line = curr_doc.lines().nth(row.checked_sub(1)?)?;
+    let curr_line = curr_doc.lines().nth(row).unwrap_or("");
+
+    let prev_stmt = prev_line
+        .split(['#', ';'])
+        .next()
+        .and_then(|stmt| stmt.split("//").next())
+        .unwrap_or("")
+        .trim();
+    let indent = if prev_stmt.strip_suffix(':').is_some_and(|label| {
+        !label.is_empty()
+            && label
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '$' | '@'))
+    }) {
+        config.get_indent_unit(&params.options)
+    } else if !prev_stmt.is_empty() && prev_line.starts_with(char::is_whitespace) {
+        prev_line[..prev_line.len() - prev_line.trim_start().len()].to_string()
+    } else {
+        return None;
+    };
+
+    let curr_indent = &curr_line[..curr_line.len() - curr_line.trim_start().len()];
+    if curr_indent == indent {
+        return None;
+    }
+
+    Some(vec![TextEdit {
+        range: Range {
+            start: Position {
+                line: row as u32,
+                character: 0,
+            },
+            end: Position {
+                line: row as u32,
+                character: curr_indent.encode_utf16().count() as u32,
+            },
+        },
+        new_text: indent,
+    }])
+}
+
+pub fn get_goto_def_resp(
+    curr_doc: &FullTextDocument,
+    tree_entry: &mut TreeEntry,
+    params: &GotoDefinitionParams,
+    config: &Config,
+    include_dirs: &HashMap<SourceFile, Vec<PathBuf>>,
+) -> Option<GotoDefinitionResponse> {
+    // `.include`/`.incbin` filenames go to the start of the file
+    if let Some(included) = get_included_file(get_line_from_pos_params(
+        curr_doc,
+        &params.text_document_position_params,
+    )) {
+        let locations: Vec<Location> = resolve_include(
+            &params.text_document_position_params.text_document.uri,
+            included.name,
+            include_dirs,
+            included.is_quoted,
+        )
+        .iter()
+        .filter_map(|path| path_to_uri(path))
+        .map(|uri| Location {
+            uri,
+            range: Range::default(),
+        })
+        .collect();
+        if !locations.is_empty() {
+            return Some(GotoDefinitionResponse::Array(locations));
+        }
+    }
+
+    let word = get_goto_def_word(curr_doc, &params.text_document_position_params, config);
+    if word.is_empty() {
+        return None;
+    }
+    if config.opts.disassembly_mode.unwrap_or(false) {
+        let line = get_line_from_pos_params(curr_doc, &params.text_document_position_params);
+        let col = utf16_col_to_byte_col(
+            line,
+            params.text_document_position_params.position.character,
+        );
+        if let Some(range) = get_disassembly_target(curr_doc.get_content(None), line, col, word) {
+            return Some(GotoDefinitionResponse::Scalar(Location {
+                uri: params
+                    .text_document_position_params
+                    .text_document
+                    .uri
+                    .clone(),
+                range,
+            }));
+        }
+    }
+    let symbols = get_symbol_index(curr_doc, tree_entry);
+    // a NASM local label refers to the one under the same non-local label
+    let parent =
+        (word.starts_with('.') && config.is_assembler_enabled(Assembler::Nasm)).then(|| {
+            get_nasm_parent_label(
+                &symbols.labels,
+                params.text_document_position_params.position.line,
+            )
+        });
+    symbols
+        .labels
+        .iter()
+        .find(|(name, range)| {
+            word.eq(name)
+                && parent.is_none_or(|parent| {
+                    get_nasm_parent_label(&symbols.labels, range.start.line) == parent
+                })
+        })
+        .map(|(_, range)| *range)
+        .or_else(|| {
+            // NASM structure types and their members aren't labels
+            if !config.assemblers.nasm.unwrap_or(false) {
+                return None;
+            }
+            get_nasm_strucs(curr_doc.get_content(None))
+                .iter()
+                .find_map(|struc| {
+                    if word == struc.name {
+                        Some(struc.range)
+                    } else {
+                        struc.get_member(word).map(|member| member.range)
+                    }
+                })
+        })
+        .map(|range| {
+            GotoDefinitionResponse::Scalar(Location {
+                uri: params
+                    .text_document_position_params
+                    .text_document
+                    .uri
+                    .clone(),
+                range,
+            })
+        })
+}
+
+/// Returns the range of the address or symbol name that `word` refers to in an
+/// `objdump -d` style disassembly listing, e.g. `1141` or `main` in the branch
+/// `jmp 1141 <main+0x8>`
+///
+/// Listing lines are either symbol headers like `0000000000001139 <main>:` or
+/// instructions like `    1139:\t55\tpush %rbp`. Addresses are only followed
+/// from an instruction's text (`line` from byte column `col`), not from its
+/// address or encoded bytes
+fn get_disassembly_target(curr_doc: &str, line: &str, col: Column, word: &str) -> Option<Range> {
+    let is_hex = |text: &str| !text.is_empty() && text.bytes().all(|c| c.is_ascii_hexdigit());
+    let is_instr_line = |line: &str| {
+        line.starts_with(char::is_whitespace)
+            && line
+                .trim_start()
+                .split_once(':')
+                .is_some_and(|(addr, rest)| is_hex(addr) && rest.starts_with('\t'))
+    };
+
+    let target = if is_instr_line(line) {
+        // the instruction comes after the address and bytes, each followed by a tab
+        let text_start = line.match_indices('\t').nth(1)?.0;
+        if col <= text_start {
+            return None;
+        }
+        u64::from_str_radix(word.trim_start_matches("0x"), 16).ok()
+    } else {
+        None
+    };
+
+    for (line_num, doc_line) in curr_doc.lines().enumerate() {
+        let trimmed = doc_line.trim_start();
+        let addr_start = doc_line.len() - trimmed.len();
+        let (addr, symbol) = if is_instr_line(doc_line) {
+            (trimmed.split_once(':')?.0, None)
+        } else if let Some((addr, rest)) = trimmed.split_once(" <") {
+            let Some(symbol) = rest.strip_suffix(">:") else {
+                continue;
+            };
+            (addr, Some(symbol))
+        } else {
+            continue;
+        };
+        if !is_hex(addr) {
+            continue;
+        }
+
+        let is_target = target
+            .is_some_and(|target| u64::from_str_radix(addr, 16).ok() == Some(target))
+            // e.g. `main` for `<main@plt>`
+            || symbol.is_some_and(|symbol| symbol.split('@').next() == Some(word));
+        if is_target {
+            let line_num = u32::try_from(line_num).ok()?;
+            let start = doc_line[..addr_start].encode_utf16().count() as u32;
+            return Some(Range {
+                start: Position {
+                    line: line_num,
+                    character: start,
+                },
+                end: Position {
+                    line: line_num,
+                    character: start + addr.len() as u32,
+                },
+            });
+        }
+    }
+
+    None
+}
+
+/// Returns the symbol index for `curr_doc`, rebuilding it from a fresh parse if
+/// there isn't one for the document's current version
+fn get_symbol_index<'a>(
+    curr_doc: &FullTextDocument,
+    tree_entry: &'a mut TreeEntry,
+) -> &'a SymbolIndex {
+    let version = curr_doc.version();
+    if tree_entry
+        .symbols
+        .as_ref()
+        .is_some_and(|symbols| symbols.version == version)
+    {
+        if let Some(ref symbols) = tree_entry.symbols {
+            return symbols;
+        }
+    }
+
+    let doc = curr_doc.get_content(None).as_bytes();
+    tree_entry.tree = tree_entry.parser.parse(doc, tree_entry.tree.as_ref());
+    let symbols = tree_entry.tree.as_ref().map_or_else(
+        || SymbolIndex {
+            version,
+            labels: Vec::new(),
+            idents: Vec::new(),
+        },
+        |tree| get_tree_symbols(doc, tree, version),
+    );
+
+    tree_entry.symbols.insert(symbols)
+}
+
+/// Collects the labels declared in and identifiers referenced by `doc`, as parsed
+/// into `tree`
+#[must_use]
+pub fn get_tree_symbols(doc: &[u8], tree: &Tree, version: i32) -> SymbolIndex {
+    static QUERY_IDENT: Lazy<tree_sitter::Query> = Lazy::new(|| {
+        // local labels, e.g. NASM's `.loop:`, are declared by a `meta_ident`
+        tree_sitter::Query::new(
+            &asm_language(),
+            "[(ident) @ident (label (meta_ident) @ident)]",
+        )
+        .unwrap()
+    });
+
+    let mut symbols = SymbolIndex {
+        version,
+        labels: Vec::new(),
+        idents: Vec::new(),
+    };
+    let mut cursor = tree_sitter::QueryCursor::new();
+    for match_ in cursor.matches(&QUERY_IDENT, tree.root_node(), doc) {
+        for cap in match_.captures {
+            let text = cap.node.utf8_text(doc).unwrap_or("");
+            // A label's own identifier is its declaration
+            let label = cap.node.parent().filter(|p| p.kind() == "label");
+            if let Some(label) = label {
+                symbols.labels.push((
+                    text.to_string(),
+                    Range {
+                        start: lsp_pos_of_point(label.start_position()),
+                        end: lsp_pos_of_point(label.end_position()),
+                    },
+                ));
+            }
+            // An identifier node can span several tokens, e.g. `$foo` or `foo+4`
+            for (offset, token) in get_ident_tokens(text) {
+                let mut start = cap.node.start_position();
+                start.column += offset;
+                let end = tree_sitter::Point {
+                    row: start.row,
+                    column: start.column + token.len(),
+                };
+                symbols.idents.push(IdentRef {
+                    name: token.to_string(),
+                    range: Range {
+                        start: lsp_pos_of_point(start),
+                        end: lsp_pos_of_point(end),
+                    },
+                    is_decl: label.is_some(),
+                });
+            }
+        }
+    }
+
+    symbols
+}
+
+/// Splits `text` into the identifier tokens recognized by `find_word_at_pos`,
+/// along with each token's byte offset into `text`
+fn get_ident_tokens(text: &str) -> Vec<(usize, &str)> {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let mut tokens = Vec::new();
+    let mut token_start = None;
+    for (idx, c) in text.char_indices() {
+        match (token_start, is_ident_char(c)) {
+            (None, true) => token_start = Some(idx),
+            (Some(start), false) => {
+                tokens.push((start, &text[start..idx]));
+                token_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = token_start {
+        tokens.push((start, &text[start..]));
+    }
+
+    tokens
+}
+
+pub fn get_ref_resp(
+    params: &ReferenceParams,
+    curr_doc: &FullTextDocument,
+    tree_entry: &mut TreeEntry,
+) -> Vec<Location> {
+    let (word, _) = get_word_from_pos_params(curr_doc, &params.text_document_position);
+    if word.is_empty() {
+        return Vec::new();
+    }
+    let uri = &params.text_document_position.text_document.uri;
+    let refs: HashSet<Location> = get_symbol_index(curr_doc, tree_entry)
+        .idents
+        .iter()
+        .filter(|ident| ident.name == word)
+        .filter(|ident| !ident.is_decl || params.context.include_declaration)
+        .map(|ident| Location {
+            uri: uri.clone(),
+            range: ident.range,
+        })
+        .collect();
+
+    refs.into_iter().collect()
+}
+
+/// Returns the word at the cursor whose definition is looked up
+fn get_goto_def_word<'a>(
+    curr_doc: &'a FullTextDocument,
+    pos_params: &TextDocumentPositionParams,
+    config: &Config,
+) -> &'a str {
+    // `ldr r0, =my_data` refers to `my_data`, even with the cursor on the `=`
+    if config.instruction_sets.arm.unwrap_or(false)
+        || config.instruction_sets.arm64.unwrap_or(false)
+    {
+        if let Some(sym) = get_literal_pool_symbol(
+            get_line_from_pos_params(curr_doc, pos_params),
+            pos_params.position.character,
+        ) {
+            return sym;
+        }
+    }
+
+    get_word_from_pos_params(curr_doc, pos_params).0
+}
+
+/// Looks up the declarations of the label at the cursor in the other files of
+/// `workspace_index`, for when it isn't declared in `curr_doc`
+#[must_use]
+pub fn get_workspace_goto_def_resp(
     params: &GotoDefinitionParams,
+    curr_doc: &FullTextDocument,
+    config: &Config,
+    workspace_index: &WorkspaceIndex,
 ) -> Option<GotoDefinitionResponse> {
-    let doc = curr_doc.get_content(None).as_bytes();
-    tree_entry.tree = tree_entry.parser.parse(doc, tree_entry.tree.as_ref());
+    let pos_params = &params.text_document_position_params;
+    let word = get_goto_def_word(curr_doc, pos_params, config);
+    if word.is_empty() {
+        return None;
+    }
+    let mut locations = workspace_index.get_definitions(word, &pos_params.text_document.uri);
+    match locations.len() {
+        0 => None,
+        1 => locations.pop().map(GotoDefinitionResponse::Scalar),
+        _ => Some(GotoDefinitionResponse::Array(locations)),
+    }
+}
 
-    if let Some(ref tree) = tree_entry.tree {
-        static QUERY_LABEL: Lazy<tree_sitter::Query> = Lazy::new(|| {
-            tree_sitter::Query::new(&tree_sitter_asm::language(), "(label) @label").unwrap()
-        });
+/// Returns the references to the word at the cursor in the other files of
+/// `workspace_index`
+#[must_use]
+pub fn get_workspace_ref_resp(
+    params: &ReferenceParams,
+    curr_doc: &FullTextDocument,
+    workspace_index: &WorkspaceIndex,
+) -> Vec<Location> {
+    let (word, _) = get_word_from_pos_params(curr_doc, &params.text_document_position);
+    if word.is_empty() {
+        return Vec::new();
+    }
+    workspace_index.get_references(
+        word,
+        params.context.include_declaration,
+        &params.text_document_position.text_document.uri,
+    )
+}
 
-        let is_not_ident_char = |c: char| !(c.is_alphanumeric() || c == '_');
-        let mut cursor = tree_sitter::QueryCursor::new();
-        let matches = cursor.matches(&QUERY_LABEL, tree.root_node(), doc);
+/// Returns the label identifier at the cursor, i.e. the one to be renamed, or
+/// `None` if the cursor isn't on a label's declaration or a reference to one
+///
+/// Numeric labels, e.g. GAS's `1:`, are never returned, since their references
+/// (`1b`, `1f`) are spelled differently and the same name can be declared many times
+fn get_rename_target<'a>(
+    pos_params: &TextDocumentPositionParams,
+    curr_doc: &FullTextDocument,
+    tree_entry: &'a mut TreeEntry,
+) -> Option<&'a IdentRef> {
+    let cursor = position_to_point(curr_doc.get_content(None), pos_params.position);
+    let symbols = get_symbol_index(curr_doc, tree_entry);
+    symbols.idents.iter().find(|ident| {
+        ident.range.start.line as usize == cursor.row
+            && (ident.range.start.character as usize..=ident.range.end.character as usize)
+                .contains(&cursor.column)
+            && !ident.name.starts_with(|c: char| c.is_ascii_digit())
+            && symbols.labels.iter().any(|(label, _)| *label == ident.name)
+    })
+}
 
-        let (word, _) = get_word_from_pos_params(curr_doc, &params.text_document_position_params);
+/// Returns the non-local label that a NASM local label (one starting with `.`)
+/// on `line` belongs to, i.e. the last label declared at or before `line` whose
+/// name doesn't start with `.`
+fn get_nasm_parent_label(labels: &[(String, Range)], line: u32) -> Option<&str> {
+    labels
+        .iter()
+        .take_while(|(_, range)| range.start.line <= line)
+        .filter(|(name, _)| !name.starts_with('.'))
+        .last()
+        .map(|(name, _)| name.as_str())
+}
 
-        for match_ in matches {
-            for cap in match_.captures {
-                if cap.node.end_byte() >= doc.len() {
-                    continue;
-                }
-                let text = cap
-                    .node
-                    .utf8_text(doc)
-                    .unwrap_or("")
-                    .trim()
-                    .trim_matches(is_not_ident_char);
-
-                if word.eq(text) {
-                    let start = cap.node.start_position();
-                    let end = cap.node.end_position();
-                    return Some(GotoDefinitionResponse::Scalar(Location {
-                        uri: params
-                            .text_document_position_params
-                            .text_document
-                            .uri
-                            .clone(),
-                        range: Range {
-                            start: lsp_pos_of_point(start),
-                            end: lsp_pos_of_point(end),
-                        },
-                    }));
-                }
+/// Returns the occurrences of the label `name` that's referenced on `line`, each
+/// with the name of its parent label if it's spelled qualified by it
+///
+/// With NASM enabled, a local label (one starting with `.`) belongs to the last
+/// non-local label before it, so locals of the same name under other labels are
+/// skipped, while references qualified by the parent, e.g. `main.loop`, are included
+fn get_label_occurrences<'a>(
+    symbols: &'a SymbolIndex,
+    name: &str,
+    line: u32,
+    config: &Config,
+) -> Vec<(&'a IdentRef, Option<&'a str>)> {
+    if !name.starts_with('.') || !config.is_assembler_enabled(Assembler::Nasm) {
+        return symbols
+            .idents
+            .iter()
+            .filter(|ident| ident.name == name)
+            .map(|ident| (ident, None))
+            .collect();
+    }
+
+    let parent = get_nasm_parent_label(&symbols.labels, line);
+    symbols
+        .idents
+        .iter()
+        .filter_map(|ident| {
+            if ident.name == name {
+                (get_nasm_parent_label(&symbols.labels, ident.range.start.line) == parent)
+                    .then_some((ident, None))
+            } else {
+                let parent = parent?;
+                (ident.name.strip_prefix(parent) == Some(name)).then_some((ident, Some(parent)))
             }
-        }
+        })
+        .collect()
+}
+
+/// Returns the range of the label at the cursor if it can be renamed
+#[must_use]
+pub fn get_prepare_rename_resp(
+    pos_params: &TextDocumentPositionParams,
+    curr_doc: &FullTextDocument,
+    tree_entry: &mut TreeEntry,
+) -> Option<Range> {
+    get_rename_target(pos_params, curr_doc, tree_entry).map(|ident| ident.range)
+}
+
+/// Returns the edits renaming the label at the cursor, along with every reference
+/// to it in the document, to `params.new_name`
+///
+/// # Errors
+///
+/// Returns `Err` if the cursor isn't on a label, or `params.new_name` isn't a valid
+/// label name or would shadow an instruction, register, or directive of `config`'s
+/// enabled instruction sets and assemblers, or would turn a NASM local label into
+/// a non-local one
+pub fn get_rename_resp(
+    params: &RenameParams,
+    curr_doc: &FullTextDocument,
+    tree_entry: &mut TreeEntry,
+    config: &Config,
+    names_to_info: &NameToInfoMaps,
+) -> Result<WorkspaceEdit> {
+    let new_name = params.new_name.as_str();
+    if new_name.is_empty()
+        || new_name.starts_with(|c: char| c.is_ascii_digit())
+        || !new_name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '$'))
+    {
+        return Err(anyhow!("\"{new_name}\" isn't a valid label name"));
+    }
+    if let Some(kind) = get_builtin_kind(new_name, config, names_to_info) {
+        return Err(anyhow!(
+            "\"{new_name}\" is the name of a built-in {kind}, renaming to it would make the code ambiguous"
+        ));
     }
 
-    None
+    let Some(target) = get_rename_target(&params.text_document_position, curr_doc, tree_entry)
+    else {
+        return Err(anyhow!("Only labels can be renamed"));
+    };
+    let (old_name, line) = (target.name.clone(), target.range.start.line);
+    if old_name.starts_with('.')
+        && !new_name.starts_with('.')
+        && config.is_assembler_enabled(Assembler::Nasm)
+    {
+        return Err(anyhow!(
+            "\"{old_name}\" is a local label, its new name must also start with '.'"
+        ));
+    }
+    let symbols = get_symbol_index(curr_doc, tree_entry);
+    let edits = get_label_occurrences(symbols, &old_name, line, config)
+        .into_iter()
+        .map(|(ident, parent)| TextEdit {
+            range: ident.range,
+            new_text: parent.map_or_else(|| new_name.to_string(), |p| format!("{p}{new_name}")),
+        })
+        .collect();
+
+    Ok(WorkspaceEdit {
+        changes: Some(HashMap::from([(
+            params.text_document_position.text_document.uri.clone(),
+            edits,
+        )])),
+        ..Default::default()
+    })
 }
 
-pub fn get_ref_resp(
-    params: &ReferenceParams,
+/// Returns the ranges of the label at the cursor and every reference to it, so the
+/// client can edit them all as one
+///
+/// Returns `None` if the cursor isn't on a label, the label shares its name with
+/// a built-in instruction, register, or directive, or it's a NASM local label
+/// that's also referenced qualified by its parent label
+#[must_use]
+pub fn get_linked_editing_range_resp(
+    pos_params: &TextDocumentPositionParams,
     curr_doc: &FullTextDocument,
     tree_entry: &mut TreeEntry,
-) -> Vec<Location> {
-    let mut refs: HashSet<Location> = HashSet::new();
-    let doc = curr_doc.get_content(None).as_bytes();
-    tree_entry.tree = tree_entry.parser.parse(doc, tree_entry.tree.as_ref());
+    config: &Config,
+    names_to_info: &NameToInfoMaps,
+) -> Option<LinkedEditingRanges> {
+    let target = get_rename_target(pos_params, curr_doc, tree_entry)?;
+    let (name, line) = (target.name.clone(), target.range.start.line);
+    if get_builtin_kind(&name, config, names_to_info).is_some() {
+        return None;
+    }
+    let symbols = get_symbol_index(curr_doc, tree_entry);
+    let occurrences = get_label_occurrences(symbols, &name, line, config);
+    // a reference qualified by its parent label is spelled differently, so it
+    // can't be edited along with the rest
+    if occurrences.iter().any(|(_, parent)| parent.is_some()) {
+        return None;
+    }
+    let mut ranges: Vec<Range> = occurrences.iter().map(|(ident, _)| ident.range).collect();
+    ranges.sort_unstable_by_key(|range| (range.start.line, range.start.character));
+    ranges.dedup();
+
+    Some(LinkedEditingRanges {
+        ranges,
+        // what `get_rename_resp` accepts as a label name, in the client's regex syntax
+        word_pattern: Some(String::from(r"[A-Za-z_.$][\w.$]*")),
+    })
+}
 
-    if let Some(ref tree) = tree_entry.tree {
-        static QUERY_LABEL: Lazy<tree_sitter::Query> = Lazy::new(|| {
-            tree_sitter::Query::new(
-                &tree_sitter_asm::language(),
-                "(label (ident (reg (word)))) @label",
-            )
-            .unwrap()
-        });
+/// Returns the kind of built-in token `name` is for `config`'s enabled instruction
+/// sets and assemblers, i.e. "instruction", "register", or "directive", if any
+fn get_builtin_kind(
+    name: &str,
+    config: &Config,
+    names_to_info: &NameToInfoMaps,
+) -> Option<&'static str> {
+    let lower = name.to_ascii_lowercase();
+    let enabled_arches = || Arch::iter().filter(|&arch| config.is_isa_enabled(arch));
+    if enabled_arches().any(|arch| {
+        names_to_info
+            .instructions
+            .contains_key(&(arch, lower.as_str()))
+    }) {
+        return Some("instruction");
+    }
+    if enabled_arches().any(|arch| {
+        names_to_info
+            .registers
+            .contains_key(&(arch, lower.as_str()))
+    }) {
+        return Some("register");
+    }
+    if Assembler::iter()
+        .filter(|&assembler| config.is_assembler_enabled(assembler))
+        .any(|assembler| {
+            names_to_info.directives.contains_key(&(assembler, name))
+                || names_to_info
+                    .directives
+                    .contains_key(&(assembler, lower.as_str()))
+        })
+    {
+        return Some("directive");
+    }
 
-        static QUERY_WORD: Lazy<tree_sitter::Query> = Lazy::new(|| {
-            tree_sitter::Query::new(&tree_sitter_asm::language(), "(ident) @ident").unwrap()
-        });
+    None
+}
 
-        let is_not_ident_char = |c: char| !(c.is_alphanumeric() || c == '_');
-        let (word, _) = get_word_from_pos_params(curr_doc, &params.text_document_position);
-        let uri = &params.text_document_position.text_document.uri;
-
-        let mut cursor = tree_sitter::QueryCursor::new();
-        if params.context.include_declaration {
-            let label_matches = cursor.matches(&QUERY_LABEL, tree.root_node(), doc);
-            for match_ in label_matches {
-                for cap in match_.captures {
-                    // HACK: Temporary solution for what I believe is a bug in tree-sitter core
-                    if cap.node.end_byte() >= doc.len() {
-                        continue;
-                    }
-                    let text = cap
-                        .node
-                        .utf8_text(doc)
-                        .unwrap_or("")
-                        .trim()
-                        .trim_matches(is_not_ident_char);
-
-                    if word.eq(text) {
-                        let start = lsp_pos_of_point(cap.node.start_position());
-                        let end = lsp_pos_of_point(cap.node.end_position());
-                        refs.insert(Location {
-                            uri: uri.clone(),
-                            range: Range { start, end },
-                        });
-                    }
-                }
-            }
-        }
+/// Returns a markdown list explaining each instruction in `range` of `curr_doc`,
+/// in order, with its source text and the summary of the first enabled
+/// architecture's matching instruction
+///
+/// Returns `None` if there are no instructions within `range`
+#[must_use]
+pub fn get_explain_range_resp(
+    curr_doc: &str,
+    tree_entry: &mut TreeEntry,
+    range: Range,
+    config: &Config,
+    instruction_map: &NameToInstructionMap,
+) -> Option<MarkupContent> {
+    static QUERY_INSTR: Lazy<tree_sitter::Query> = Lazy::new(|| {
+        tree_sitter::Query::new(
+            &asm_language(),
+            "(instruction kind: (word) @instr_name) @instr",
+        )
+        .unwrap()
+    });
 
-        let word_matches = cursor.matches(&QUERY_WORD, tree.root_node(), doc);
-        for match_ in word_matches {
-            for cap in match_.captures {
-                // HACK: Temporary solution for what I believe is a bug in tree-sitter core
-                if cap.node.end_byte() >= doc.len() {
-                    continue;
-                }
-                let text = cap
-                    .node
-                    .utf8_text(doc)
-                    .unwrap_or("")
-                    .trim()
-                    .trim_matches(is_not_ident_char);
-
-                if word.eq(text) {
-                    let start = lsp_pos_of_point(cap.node.start_position());
-                    let end = lsp_pos_of_point(cap.node.end_position());
-                    refs.insert(Location {
-                        uri: uri.clone(),
-                        range: Range { start, end },
-                    });
-                }
-            }
-        }
+    tree_entry.tree = tree_entry.parser.parse(curr_doc, tree_entry.tree.as_ref());
+    let tree = tree_entry.tree.as_ref()?;
+    let curr_doc_bytes = curr_doc.as_bytes();
+
+    let mut cursor = tree_sitter::QueryCursor::new();
+    cursor.set_point_range(std::ops::Range {
+        start: position_to_point(curr_doc, range.start),
+        end: position_to_point(curr_doc, range.end),
+    });
+    let mut lines = Vec::new();
+    for match_ in cursor.matches(&QUERY_INSTR, tree.root_node(), curr_doc_bytes) {
+        let [instr_cap, name_cap] = match_.captures else {
+            continue;
+        };
+        let (Ok(instr), Ok(mnemonic)) = (
+            instr_cap.node.utf8_text(curr_doc_bytes),
+            name_cap.node.utf8_text(curr_doc_bytes),
+        ) else {
+            continue;
+        };
+        let instr = instr.lines().next().unwrap_or_default().trim();
+
+        let gas_suffix = get_gas_size_suffix(mnemonic, config, instruction_map);
+        let name = gas_suffix
+            .map_or(mnemonic, |(base, _)| base)
+            .to_ascii_lowercase();
+        let mut infos: Vec<(Option<&&Instruction>, Arch)> = <[Option<&&Instruction>; 8]>::from(
+            search_for_hoverable_by_arch(&name, instruction_map),
+        )
+        .into_iter()
+        .zip(SEARCH_ARCH_ORDER)
+        .collect();
+        sort_by_arch_priority(&mut infos, config);
+        let summary = infos
+            .into_iter()
+            .find_map(|(info, arch)| info.filter(|_| config.is_isa_enabled(arch)))
+            .map(|info| match gas_suffix {
+                Some((_, bits)) => format!("{} ({bits}-bit operands)", info.summary),
+                None => info.summary.clone(),
+            });
+
+        lines.push(format!(
+            "{}. `{instr}`: {}",
+            lines.len() + 1,
+            summary.as_deref().unwrap_or("*Unknown instruction*")
+        ));
     }
 
-    refs.into_iter().collect()
+    if lines.is_empty() {
+        return None;
+    }
+
+    Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: lines.join("\n"),
+    })
+}
+
+/// The order of the architectures in the results of `search_for_hoverable_by_arch`
+const SEARCH_ARCH_ORDER: [Arch; 8] = [
+    Arch::X86,
+    Arch::X86_64,
+    Arch::Z80,
+    Arch::ARM,
+    Arch::ARM64,
+    Arch::RISCV,
+    Arch::Mos6502,
+    Arch::M68k,
+];
+
+/// Reorders `results` so the arches listed by the `arch_priority` option come first,
+/// in the order listed, followed by the rest in their existing order
+fn sort_by_arch_priority<T>(results: &mut [(T, Arch)], config: &Config) {
+    let priority = config.get_arch_priority();
+    results.sort_by_key(|(_, arch)| {
+        priority
+            .iter()
+            .position(|prio| prio == arch)
+            .unwrap_or(priority.len())
+    });
 }
 
 // Note: Some issues here regarding entangled lifetimes
@@ -1624,6 +7043,8 @@ fn search_for_hoverable_by_arch<'a, T: Hoverable>(
     Option<&'a T>,
     Option<&'a T>,
     Option<&'a T>,
+    Option<&'a T>,
+    Option<&'a T>,
 ) {
     let x86_resp = map.get(&(Arch::X86, word));
     let x86_64_resp = map.get(&(Arch::X86_64, word));
@@ -1631,6 +7052,8 @@ fn search_for_hoverable_by_arch<'a, T: Hoverable>(
     let arm_resp = map.get(&(Arch::ARM, word));
     let arm64_resp = map.get(&(Arch::ARM64, word));
     let riscv_resp = map.get(&(Arch::RISCV, word));
+    let mos6502_resp = map.get(&(Arch::Mos6502, word));
+    let m68k_resp = map.get(&(Arch::M68k, word));
     (
         x86_resp,
         x86_64_resp,
@@ -1638,6 +7061,8 @@ fn search_for_hoverable_by_arch<'a, T: Hoverable>(
         arm_resp,
         arm64_resp,
         riscv_resp,
+        mos6502_resp,
+        m68k_resp,
     )
 }
 
@@ -1653,16 +7078,167 @@ fn search_for_hoverable_by_assembler<'a, T: Hoverable>(
     (gas_resp, go_resp, masm_resp, nasm_resp)
 }
 
+/// Maximum number of lines at the start of a file searched for an `asm-lsp:` modeline
+pub const MODELINE_MAX_LINES: usize = 5;
+
+/// Parses a modeline comment in the first `MODELINE_MAX_LINES` lines of `curr_doc`,
+/// returning `config` with the instruction set and/or assembler it names, e.g.
+/// `# asm-lsp: arch=riscv, assembler=gas`
+///
+/// Each given setting replaces the corresponding table of `config` entirely, so
+/// only the named arch or assembler is enabled. A `diagnostics=off` setting mutes
+/// diagnostics for the file, and a bare `disable` turns off all features for it.
+/// Returns `None` if there's no valid modeline
+#[must_use]
+pub fn get_modeline_config(curr_doc: &str, config: &Config) -> Option<Config> {
+    const COMMENT_CHARS: [char; 6] = ['#', ';', '/', '*', '@', '!'];
+
+    let settings = curr_doc.lines().take(MODELINE_MAX_LINES).find_map(|line| {
+        let comment = line.trim_start();
+        // require an actual comment, not just the text somewhere in the line
+        if !comment.starts_with(COMMENT_CHARS) {
+            return None;
+        }
+        let settings = comment
+            .trim_start_matches(COMMENT_CHARS)
+            .trim_start()
+            .strip_prefix("asm-lsp:")?;
+        Some(settings.trim_end().trim_end_matches("*/"))
+    })?;
+
+    let mut file_config = config.clone();
+    file_config.arch = None;
+    file_config.assembler = None;
+    let mut has_settings = false;
+    for setting in settings.split([',', ' ', '\t']).filter(|s| !s.is_empty()) {
+        match setting.split_once('=') {
+            None if setting == "disable" => {
+                file_config.disabled = true;
+                has_settings = true;
+            }
+            Some(("diagnostics", value)) => match value {
+                "on" | "true" => {
+                    file_config.opts.diagnostics = Some(true);
+                    has_settings = true;
+                }
+                "off" | "false" => {
+                    file_config.opts.diagnostics = Some(false);
+                    has_settings = true;
+                }
+                _ => warn!("Ignoring invalid modeline diagnostics value \"{value}\""),
+            },
+            Some(("arch", arch)) => {
+                file_config.instruction_sets = InstructionSets::unset();
+                file_config.assemblers.z80 = None;
+                file_config.arch = Some(arch.to_string());
+            }
+            Some(("assembler", assembler)) => {
+                file_config.assemblers = Assemblers {
+                    z80: file_config.assemblers.z80,
+                    ..Assemblers::unset()
+                };
+                file_config.assembler = Some(assembler.to_string());
+            }
+            _ => warn!("Ignoring unknown modeline setting \"{setting}\""),
+        }
+    }
+    if !has_settings && file_config.arch.is_none() && file_config.assembler.is_none() {
+        return None;
+    }
+    if let Err(e) = file_config.expand_shorthand() {
+        warn!("Ignoring invalid modeline -- Error: {e}");
+        return None;
+    }
+
+    Some(file_config)
+}
+
+/// Returns the names of the instruction sets and assemblers enabled in a file's
+/// modeline config `file_config` but not in the project `config`
+///
+/// Documentation is only loaded for the project config, so there's none for these
+#[must_use]
+pub fn get_modeline_unloaded_docs(file_config: &Config, config: &Config) -> Vec<String> {
+    let arches = Arch::iter()
+        .filter(|&arch| file_config.is_isa_enabled(arch) && !config.is_isa_enabled(arch))
+        .map(|arch| arch.to_string());
+    let assemblers = Assembler::iter()
+        .filter(|&asm| file_config.is_assembler_enabled(asm) && !config.is_assembler_enabled(asm))
+        .map(|asm| asm.to_string());
+    arches.chain(assemblers).collect()
+}
+
 /// Searches for global config in ~/.config/asm-lsp, then the project's directory
 /// Project specific configs will override global configs
+///
+/// An `explicit_config`, read from the path given with `--config` or
+/// `ASM_LSP_CONFIG`, overrides both, and neither is searched for
+///
+/// If the config doesn't specify any assemblers, also returns per-file overrides
+/// for the assemblers implied by `compile_cmds` (see `get_compile_cmd_overrides`).
+/// These assemblers are enabled in the returned config too, so that their
+/// documentation gets loaded
 #[must_use]
-pub fn get_config(params: &InitializeParams) -> Config {
-    let mut config = match (get_global_config(), get_project_config(params)) {
-        (_, Some(proj_cfg)) => proj_cfg,
-        (Some(global_cfg), None) => global_cfg,
-        (None, None) => Config::default(),
+pub fn get_config(
+    params: &InitializeParams,
+    compile_cmds: &CompilationDatabase,
+    explicit_config: Option<Config>,
+) -> (Config, ConfigOverrides) {
+    let project_root = get_project_root(params);
+    let (mut config, is_default) = explicit_config.map_or_else(
+        || {
+            let project_config = project_root.as_deref().and_then(get_project_config);
+            match (get_global_config(), project_config) {
+                (_, Some(proj_cfg)) => (proj_cfg, false),
+                (Some(global_cfg), None) => (global_cfg, false),
+                (None, None) => (Config::default(), true),
+            }
+        },
+        |config| (config, false),
+    );
+
+    config.project_root = project_root;
+    set_default_diagnostics_opts(&mut config);
+
+    // The default config's assemblers aren't a user choice, so they shouldn't
+    // stop us from detecting a file's assembler
+    let compile_cmd_overrides = if is_default {
+        let mut base_config = config.clone();
+        base_config.assemblers = Assemblers {
+            z80: base_config.assemblers.z80,
+            ..Assemblers::unset()
+        };
+        get_compile_cmd_overrides(&base_config, compile_cmds)
+    } else {
+        get_compile_cmd_overrides(&config, compile_cmds)
     };
+    for file_config in compile_cmd_overrides.values() {
+        for assembler in Assembler::iter() {
+            if file_config.is_assembler_enabled(assembler) {
+                config.set_assembler_enabled(assembler, true);
+            }
+        }
+    }
+
+    (config, compile_cmd_overrides)
+}
+
+/// Enables x86-64 and GAS if `config` is empty, which is most likely a mistake,
+/// rather than silently providing nothing. Returns `true` if it did
+///
+/// This can be turned off with the `empty_config_fallback` option
+pub fn apply_empty_config_fallback(config: &mut Config) -> bool {
+    if !config.is_empty() || !config.opts.empty_config_fallback.unwrap_or(true) {
+        return false;
+    }
+    warn!("No instruction sets or assemblers enabled, falling back to x86_64 and GAS");
+    config.instruction_sets.x86_64 = Some(true);
+    config.assemblers.gas = Some(true);
+    true
+}
 
+/// Enables diagnostics and default diagnostics unless the config says otherwise
+const fn set_default_diagnostics_opts(config: &mut Config) {
     // Want diagnostics enabled by default
     if config.opts.diagnostics.is_none() {
         config.opts.diagnostics = Some(true);
@@ -1672,8 +7248,61 @@ pub fn get_config(params: &InitializeParams) -> Config {
     if config.opts.default_diagnostics.is_none() {
         config.opts.default_diagnostics = Some(true);
     }
+}
+
+/// Walks up from `dir` to the nearest directory containing a `.asm-lsp.toml` or
+/// `compile_commands.json`
+#[must_use]
+pub fn find_project_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|ancestor| {
+            ancestor.join(".asm-lsp.toml").is_file()
+                || ancestor.join("compile_commands.json").is_file()
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Returns the config for a file opened without a project root from the client
+///
+/// This happens when editing a single file, in which case the nearest parent
+/// directory of the file with a `.asm-lsp.toml` or `compile_commands.json` is
+/// used as the root. The `.asm-lsp.toml` there replaces `config` if present, unless
+/// `config` was given explicitly with `--config`. Documentation is only
+/// loaded at startup, so any instruction sets or assemblers it enables that
+/// `config` doesn't won't have any
+///
+/// Returns `None` if `config` already has a project root, or no such directory
+/// is found
+#[must_use]
+pub fn get_root_fallback_config(uri: &Uri, config: &Config) -> Option<Config> {
+    if config.project_root.is_some() {
+        return None;
+    }
+    let path = uri_to_path(uri)?.canonicalize().ok()?;
+    let root = find_project_root(path.parent()?)?;
+    info!(
+        "Detected project root {} from {}",
+        root.display(),
+        path.display()
+    );
+
+    // an explicitly given config applies to every file
+    let project_config = if config.config_path.is_some() {
+        None
+    } else {
+        get_project_config(&root)
+    };
+    let mut file_config = project_config.map_or_else(
+        || config.clone(),
+        |mut proj_cfg| {
+            proj_cfg.client = config.client;
+            set_default_diagnostics_opts(&mut proj_cfg);
+            proj_cfg
+        },
+    );
+    file_config.project_root = Some(root);
 
-    config
+    Some(file_config)
 }
 
 /// Checks ~/.config/asm-lsp for a config file, creating directories along the way as necessary
@@ -1691,20 +7320,8 @@ fn get_global_config() -> Option<Config> {
         info!("Creating directories along {} as necessary...", cfg_path_s);
         match create_dir_all(&cfg_path) {
             Ok(()) => {
-                cfg_path.push(".asm-lsp.toml");
-                if let Ok(config) = std::fs::read_to_string(&cfg_path) {
-                    let cfg_path_s = cfg_path.display();
-                    match toml::from_str::<Config>(&config) {
-                        Ok(config) => {
-                            info!("Parsing global asm-lsp config from file -> {cfg_path_s}\n");
-                            return Some(config);
-                        }
-                        Err(e) => {
-                            error!(
-                                "Failed to parse global config file {cfg_path_s} - Error: {e}\n"
-                            );
-                        }
-                    }
+                if let Some(config) = read_global_config(cfg_path) {
+                    return Some(config);
                 }
             }
             Err(e) => {
@@ -1716,6 +7333,116 @@ fn get_global_config() -> Option<Config> {
     None
 }
 
+/// Reads the global config in `dir`, made up of `.asm-lsp.toml` and any `*.toml`
+/// fragments in `dir/conf.d/`
+///
+/// The fragments are merged on top of `.asm-lsp.toml` in alphabetical order. See
+/// `merge_toml_tables` for how their fields are combined
+#[must_use]
+pub fn read_global_config(dir: &Path) -> Option<Config> {
+    let mut cfg_paths = vec![dir.join(".asm-lsp.toml")];
+    if let Ok(entries) = std::fs::read_dir(dir.join("conf.d")) {
+        let mut fragments: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        fragments.sort();
+        cfg_paths.append(&mut fragments);
+    }
+
+    let mut merged: Option<toml::Table> = None;
+    for cfg_path in &cfg_paths {
+        let Ok(contents) = std::fs::read_to_string(cfg_path) else {
+            continue;
+        };
+        let cfg_path_s = cfg_path.display();
+        match toml::from_str::<toml::Table>(&contents) {
+            Ok(table) => {
+                info!("Parsing global asm-lsp config from file -> {cfg_path_s}\n");
+                match merged {
+                    Some(ref mut merged) => merge_toml_tables(merged, table),
+                    None => merged = Some(table),
+                }
+            }
+            Err(e) => {
+                error!("Failed to parse global config file {cfg_path_s} - Error: {e}\n");
+            }
+        }
+    }
+
+    match parse_config(toml::Value::Table(merged?)) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            error!(
+                "Failed to parse global config in {} - Error: {e}\n",
+                dir.display()
+            );
+            None
+        }
+    }
+}
+
+/// Applies the settings sent by the client in a `workspace/didChangeConfiguration`
+/// notification on top of `config`
+///
+/// `settings` follows the layout of `.asm-lsp.toml`, either directly or under an
+/// `asm-lsp` key. Any value it sets replaces the one in `config`
+///
+/// # Errors
+///
+/// Returns `Err` if `settings` isn't a valid config
+pub fn apply_config_settings(config: &Config, settings: &serde_json::Value) -> Result<Config> {
+    let settings = settings.get("asm-lsp").unwrap_or(settings);
+    let serde_json::Value::Object(settings) = settings else {
+        return Err(anyhow!("Expected a table of settings, got `{settings}`"));
+    };
+
+    let mut merged = serde_json::to_value(config)?;
+    merge_json_objects(&mut merged, settings.clone());
+    let mut new_config = parse_config(merged)?;
+    new_config.project_root.clone_from(&config.project_root);
+    new_config.config_path.clone_from(&config.config_path);
+
+    Ok(new_config)
+}
+
+/// Merges `overlay` into the JSON object `base`, key by key. Any value other
+/// than an object in `overlay` replaces the one in `base`
+fn merge_json_objects(
+    base: &mut serde_json::Value,
+    overlay: serde_json::Map<String, serde_json::Value>,
+) {
+    let serde_json::Value::Object(base) = base else {
+        *base = serde_json::Value::Object(overlay);
+        return;
+    };
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(base_value @ serde_json::Value::Object(_)), serde_json::Value::Object(value)) => {
+                merge_json_objects(base_value, value);
+            }
+            (_, value) => _ = base.insert(key, value),
+        }
+    }
+}
+
+/// Merges the config fragment `overlay` into `base`. Tables are merged key by
+/// key, arrays are concatenated, and any other value in `overlay` replaces the
+/// one in `base`
+fn merge_toml_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_toml_tables(base_table, table);
+            }
+            (Some(toml::Value::Array(base_array)), toml::Value::Array(mut array)) => {
+                base_array.append(&mut array);
+            }
+            (_, value) => _ = base.insert(key, value),
+        }
+    }
+}
+
 fn alt_mac_config_dir() -> Option<PathBuf> {
     home::home_dir().map(|mut path| {
         path.push(".config");
@@ -1730,14 +7457,14 @@ fn alt_mac_config_dir() -> Option<PathBuf> {
 //    root_uri field
 // 3. If both workspace folders and root_uri didn't provide a path, check the (deprecated)
 //    root_path field
-fn get_project_root(params: &InitializeParams) -> Option<PathBuf> {
+#[must_use]
+pub fn get_project_root(params: &InitializeParams) -> Option<PathBuf> {
     // first check workspace folders
     if let Some(folders) = &params.workspace_folders {
         // if there's multiple, just visit in order until we find a valid folder
         for folder in folders {
-            #[allow(irrefutable_let_patterns)] // TODO: Remove once CI is bumped past 1.82
-            let Ok(parsed) = PathBuf::from_str(folder.uri.path().as_str()) else {
-                unreachable!()
+            let Some(parsed) = uri_to_path(&folder.uri) else {
+                continue;
             };
             if let Ok(parsed_path) = parsed.canonicalize() {
                 info!("Detected project root: {}", parsed_path.display());
@@ -1749,11 +7476,7 @@ fn get_project_root(params: &InitializeParams) -> Option<PathBuf> {
     // if workspace folders weren't set or came up empty, we check the root_uri
     #[allow(deprecated)]
     if let Some(root_uri) = &params.root_uri {
-        #[allow(irrefutable_let_patterns)] // TODO: Remove once CI is bumped past 1.82
-        let Ok(parsed) = PathBuf::from_str(root_uri.path().as_str()) else {
-            unreachable!()
-        };
-        if let Ok(parsed_path) = parsed.canonicalize() {
+        if let Some(parsed_path) = uri_to_path(root_uri).and_then(|path| path.canonicalize().ok()) {
             info!("Detected project root: {}", parsed_path.display());
             return Some(parsed_path);
         }
@@ -1775,26 +7498,64 @@ fn get_project_root(params: &InitializeParams) -> Option<PathBuf> {
     None
 }
 
+/// Deserializes a config, e.g. from the contents of a `.asm-lsp.toml`, expanding
+/// its `arch`/`assembler` shorthand and validating its options
+///
+/// # Errors
+///
+/// Returns `Err` if `deserializer` doesn't hold a valid config
+fn parse_config<'de, D>(deserializer: D) -> Result<Config>
+where
+    D: serde::Deserializer<'de>,
+    D::Error: Send + Sync + 'static,
+{
+    let mut config = Config::deserialize(deserializer)?;
+    config.expand_shorthand()?;
+    config.validate_severity_map()?;
+    config.validate_arch_priority()?;
+    config.validate_assemblers_by_arch()?;
+
+    Ok(config)
+}
+
+/// Reads and validates the config file at `path`, as given with `--config` or
+/// `ASM_LSP_CONFIG`
+///
+/// # Errors
+///
+/// Returns `Err` if the file can't be read, or isn't a valid config
+pub fn read_config_file(path: &Path) -> Result<Config> {
+    let path_s = path.display();
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read config file {path_s} - Error: {e}"))?;
+    let mut config = parse_config(toml::Deserializer::new(&contents))
+        .map_err(|e| anyhow!("Failed to parse config file {path_s} - Error: {e}"))?;
+    info!("Parsing asm-lsp config from file -> {path_s}");
+    config.config_path = Some(path.to_path_buf());
+
+    Ok(config)
+}
+
 /// checks for a config specific to the project's root directory
-fn get_project_config(params: &InitializeParams) -> Option<Config> {
-    if let Some(mut path) = get_project_root(params) {
-        path.push(".asm-lsp.toml");
-        match std::fs::read_to_string(&path) {
-            Ok(config) => {
-                let path_s = path.display();
-                match toml::from_str::<Config>(&config) {
-                    Ok(config) => {
-                        info!("Parsing asm-lsp project config from file -> {path_s}");
-                        return Some(config);
-                    }
-                    Err(e) => {
-                        error!("Failed to parse project config file {path_s} - Error: {e}");
-                    } // if there's an error we fall through to check for a global config in the caller
+fn get_project_config(root: &Path) -> Option<Config> {
+    let path = root.join(".asm-lsp.toml");
+    match std::fs::read_to_string(&path) {
+        Ok(config) => {
+            let path_s = path.display();
+            match parse_config(toml::Deserializer::new(&config)) {
+                Ok(config) => {
+                    info!("Parsing asm-lsp project config from file -> {path_s}");
+                    return Some(config);
                 }
+                Err(e) => {
+                    error!("Failed to parse project config file {path_s} - Error: {e}");
+                } // if there's an error we fall through to check for a global config in the caller
             }
-            Err(e) => {
-                error!("Failed to read config file {} - Error: {e}", path.display());
-            }
+        }
+        // a root can be marked by its `compile_commands.json` alone
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            error!("Failed to read config file {} - Error: {e}", path.display());
         }
     }
 
@@ -1830,5 +7591,13 @@ pub fn instr_filter_targets(instr: &Instruction, config: &Config) -> Instruction
         .collect();
 
     instr.forms = forms;
+    // examples written in a disabled assembler's syntax would just be noise
+    instr.examples.retain(|example| {
+        example.assemblers.is_empty()
+            || example
+                .assemblers
+                .iter()
+                .any(|&assembler| config.is_assembler_enabled(assembler))
+    });
     instr
 }
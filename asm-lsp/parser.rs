@@ -8,9 +8,9 @@ use std::path::PathBuf;
 use std::str::{FromStr, Lines};
 
 use crate::types::{
-    Arch, Assembler, Directive, Instruction, InstructionForm, MMXMode, NameToDirectiveMap,
-    NameToInstructionMap, NameToRegisterMap, Operand, OperandType, Register, RegisterBitInfo,
-    RegisterType, RegisterWidth, XMMMode, Z80Timing, Z80TimingInfo, ISA,
+    Arch, Assembler, Directive, ImplicitOperand, Instruction, InstructionExample, InstructionForm,
+    MMXMode, NameToDirectiveMap, NameToInstructionMap, NameToRegisterMap, Operand, OperandType,
+    Register, RegisterBitInfo, RegisterType, RegisterWidth, XMMMode, Z80Timing, Z80TimingInfo, ISA,
 };
 use crate::InstructionAlias;
 
@@ -18,7 +18,7 @@ use anyhow::{anyhow, Result};
 use log::{debug, error, info, warn};
 use quick_xml::escape::unescape;
 use quick_xml::events::attributes::Attribute;
-use quick_xml::events::Event;
+use quick_xml::events::{BytesStart, Event};
 use quick_xml::name::QName;
 use quick_xml::Reader;
 use regex::Regex;
@@ -112,8 +112,15 @@ pub fn populate_riscv_registers(rst_contents: &str) -> Vec<Register> {
                 };
                 let description = format!("{}{}", entries[2].trim_ascii(), saved_info);
                 let reg_name = entries[0].trim_ascii().to_lowercase();
+                // e.g. `s0/fp`
+                let abi_names = entries[1]
+                    .trim_ascii()
+                    .split('/')
+                    .map(str::to_lowercase)
+                    .collect();
                 let curr_register = Register {
                     name: reg_name,
+                    alt_names: abi_names,
                     description: Some(description),
                     reg_type: curr_reg_type,
                     arch: Some(Arch::RISCV),
@@ -662,6 +669,9 @@ pub fn populate_instructions(xml_contents: &str) -> Result<Vec<Instruction>> {
     // ref to the instruction that's currently under construction
     let mut curr_instruction = Instruction::default();
     let mut curr_instruction_form = InstructionForm::default();
+    let mut curr_is_pseudo = false;
+    // parts of the x86 encoding that's currently under construction
+    let mut curr_encoding: Option<Vec<String>> = None;
     let mut arch: Option<Arch> = None;
 
     debug!("Parsing instruction XML contents...");
@@ -684,6 +694,7 @@ pub fn populate_instructions(xml_contents: &str) -> Result<Vec<Instruction>> {
                         // start of a new instruction
                         curr_instruction = Instruction::default();
                         curr_instruction.arch = arch;
+                        curr_is_pseudo = false;
 
                         // iterate over the attributes
                         for attr in e.attributes() {
@@ -696,6 +707,20 @@ pub fn populate_instructions(xml_contents: &str) -> Result<Vec<Instruction>> {
                                 "summary" => {
                                     ustr::get_str(&value).clone_into(&mut curr_instruction.summary);
                                 }
+                                "url" => {
+                                    curr_instruction.url = Some(ustr::get_str(&value).to_owned());
+                                }
+                                "pseudo" => {
+                                    curr_is_pseudo = ustr::get_str(&value) == "true";
+                                }
+                                "deprecated" => {
+                                    curr_instruction.deprecated =
+                                        Some(ustr::get_str(&value).to_owned());
+                                }
+                                "invalid-64bit" => {
+                                    curr_instruction.invalid_64bit =
+                                        ustr::get_str(&value) == "true";
+                                }
                                 _ => {}
                             }
                         }
@@ -784,6 +809,9 @@ pub fn populate_instructions(xml_contents: &str) -> Result<Vec<Instruction>> {
                     }
                     // TODO
                     QName(b"Encoding") => {
+                        if let Some(Arch::X86 | Arch::X86_64) = arch {
+                            curr_encoding = Some(Vec::new());
+                        }
                         for attr in e.attributes() {
                             let Attribute { key, value } = attr.unwrap();
                             if key.into_inner() == b"byte" {
@@ -800,6 +828,11 @@ pub fn populate_instructions(xml_contents: &str) -> Result<Vec<Instruction>> {
                 }
             }
             Ok(Event::Empty(ref e)) => {
+                if let Some(ref mut parts) = curr_encoding {
+                    if let Some(part) = get_x86_encoding_part(e) {
+                        parts.push(part);
+                    }
+                }
                 match e.name() {
                     QName(b"ISA") => {
                         for attr in e.attributes() {
@@ -864,6 +897,107 @@ pub fn populate_instructions(xml_contents: &str) -> Result<Vec<Instruction>> {
                             extended_size,
                         });
                     }
+                    QName(b"ImplicitOperand") => {
+                        let mut id = None;
+                        let mut input = None;
+                        let mut output = None;
+
+                        for attr in e.attributes() {
+                            let Attribute { key, value } = attr.unwrap();
+                            match key.into_inner() {
+                                b"id" => id = Some(ustr::get_str(&value).to_string()),
+                                b"input" => input = Some(value.as_ref() == b"true"),
+                                b"output" => output = Some(value.as_ref() == b"true"),
+                                _ => {} // unknown event
+                            }
+                        }
+
+                        if let Some(id) = id {
+                            curr_instruction_form
+                                .implicit_operands
+                                .push(ImplicitOperand { id, input, output });
+                        }
+                    }
+                    // 6502 addressing modes, listed as templates since the forms only differ
+                    // in their operand syntax
+                    QName(b"AddressingMode") => {
+                        let mut attrs = HashMap::new();
+                        for attr in e.attributes() {
+                            let Attribute { key, value } = attr.unwrap();
+                            attrs.insert(
+                                ustr::get_str(key.into_inner()).to_owned(),
+                                ustr::get_str(&value).to_owned(),
+                            );
+                        }
+                        let get = |name: &str| attrs.get(name).map_or("", String::as_str);
+                        let (form, mode, opcode, bytes, cycles) = (
+                            get("form"),
+                            get("mode"),
+                            get("opcode"),
+                            get("bytes"),
+                            get("cycles"),
+                        );
+                        curr_instruction.asm_templates.push(format!(
+                            "{form} ; {mode}, opcode {opcode}, {bytes} bytes, {cycles} cycles"
+                        ));
+                    }
+                    // 68k operand syntaxes, along with the size suffixes each accepts
+                    QName(b"Syntax") => {
+                        let (mut form, mut sizes) = (String::new(), String::new());
+                        for attr in e.attributes() {
+                            let Attribute { key, value } = attr.unwrap();
+                            match key.into_inner() {
+                                b"form" => ustr::get_str(&value).clone_into(&mut form),
+                                b"sizes" => ustr::get_str(&value).clone_into(&mut sizes),
+                                _ => {}
+                            }
+                        }
+                        if sizes.is_empty() {
+                            curr_instruction.asm_templates.push(form);
+                        } else {
+                            let sizes: Vec<String> = sizes
+                                .split_whitespace()
+                                .map(|size| format!(".{size}"))
+                                .collect();
+                            curr_instruction
+                                .asm_templates
+                                .push(format!("{form} ; sizes: {}", sizes.join(" ")));
+                        }
+                    }
+                    // Assembler pseudo-instructions, along with the real instructions they
+                    // expand to
+                    QName(b"Expansion") => {
+                        let (mut form, mut expansion) = (String::new(), String::new());
+                        for attr in e.attributes() {
+                            let Attribute { key, value } = attr.unwrap();
+                            match key.into_inner() {
+                                b"form" => ustr::get_str(&value).clone_into(&mut form),
+                                b"value" => ustr::get_str(&value).clone_into(&mut expansion),
+                                _ => {}
+                            }
+                        }
+                        curr_instruction
+                            .asm_templates
+                            .push(format!("{form} → {expansion}"));
+                    }
+                    // Usage examples, optionally limited to a space separated list of
+                    // assemblers whose syntax they're written in
+                    QName(b"Example") => {
+                        let mut example = InstructionExample::default();
+                        for attr in e.attributes() {
+                            let Attribute { key, value } = attr.unwrap();
+                            match key.into_inner() {
+                                b"assembler" => {
+                                    for name in ustr::get_str(&value).split_whitespace() {
+                                        example.assemblers.push(Assembler::from_str(name)?);
+                                    }
+                                }
+                                b"value" => ustr::get_str(&value).clone_into(&mut example.code),
+                                _ => {}
+                            }
+                        }
+                        curr_instruction.examples.push(example);
+                    }
                     QName(b"TimingZ80") => {
                         for attr in e.attributes() {
                             let Attribute { key, value } = attr.unwrap();
@@ -954,12 +1088,21 @@ pub fn populate_instructions(xml_contents: &str) -> Result<Vec<Instruction>> {
                 match e.name() {
                     QName(b"Instruction") => {
                         // finish instruction
+                        if curr_is_pseudo {
+                            curr_instruction.summary =
+                                format!("*Pseudo-instruction*. {}", curr_instruction.summary);
+                        }
                         instructions_map
                             .insert(curr_instruction.name.clone(), curr_instruction.clone());
                     }
                     QName(b"InstructionForm") => {
                         curr_instruction.push_form(curr_instruction_form.clone());
                     }
+                    QName(b"Encoding") => {
+                        if let Some(parts) = curr_encoding.take() {
+                            curr_instruction_form.encodings.push(parts.join(" "));
+                        }
+                    }
                     _ => {} // unknown event
                 }
             }
@@ -998,6 +1141,103 @@ pub fn populate_instructions(xml_contents: &str) -> Result<Vec<Instruction>> {
     Ok(instructions_map.into_values().collect())
 }
 
+/// Renders an element of an x86 `<Encoding>` in Intel's opcode notation, e.g.
+/// `<Immediate size="1" .../>` as `ib`. Elements only describing operand
+/// placement (like optional REX prefixes) render as `None`
+fn get_x86_encoding_part(e: &BytesStart) -> Option<String> {
+    let attrs: HashMap<String, String> = e
+        .attributes()
+        .filter_map(Result::ok)
+        .map(|Attribute { key, value }| {
+            (
+                ustr::get_str(key.into_inner()).to_owned(),
+                ustr::get_str(&value).to_owned(),
+            )
+        })
+        .collect();
+    let attr = |name: &str| attrs.get(name).map(String::as_str);
+    // `#n` values are filled in from the nth operand
+    let fixed = |name: &str| attr(name).filter(|val| !val.starts_with('#'));
+    let pp = |val: Option<&str>| match val {
+        Some("01") => "66",
+        Some("10") => "F3",
+        Some("11") => "F2",
+        _ => "NP",
+    };
+    let map = |val: Option<&str>| match val.and_then(|val| u8::from_str_radix(val, 2).ok()) {
+        Some(1) => "0F".to_string(),
+        Some(2) => "0F38".to_string(),
+        Some(3) => "0F3A".to_string(),
+        Some(5) => "MAP5".to_string(),
+        Some(6) => "MAP6".to_string(),
+        Some(m) => format!("M{m:02X}"),
+        None => "M?".to_string(),
+    };
+    let w = |val: Option<&str>| val.map_or_else(|| "WIG".to_string(), |w| format!("W{w}"));
+    let size_suffix = |size: Option<&str>| match size {
+        Some("1") => "b",
+        Some("2") => "w",
+        Some("4") => "d",
+        _ => "o",
+    };
+
+    match e.name() {
+        QName(b"Prefix") => attr("byte").map(str::to_owned),
+        QName(b"REX") => match (attr("mandatory"), attr("W")) {
+            (_, Some("1")) => Some("REX.W".to_string()),
+            (Some("true"), _) => Some("REX".to_string()),
+            _ => None,
+        },
+        QName(b"VEX") => {
+            let len = match fixed("L") {
+                Some("0") => "128",
+                Some("1") => "256",
+                _ => "LIG",
+            };
+            Some(format!(
+                "{}.{len}.{}.{}.{}",
+                attr("type").unwrap_or("VEX"),
+                pp(attr("pp")),
+                map(attr("m-mmmm")),
+                w(attr("W"))
+            ))
+        }
+        QName(b"EVEX") => {
+            let len = match fixed("LL") {
+                Some("00") => "128",
+                Some("01") => "256",
+                Some("10") => "512",
+                _ => "LLIG",
+            };
+            Some(format!(
+                "EVEX.{len}.{}.{}.{}",
+                pp(attr("pp")),
+                map(attr("mmm")),
+                w(attr("W"))
+            ))
+        }
+        QName(b"Opcode") => {
+            let byte = attr("byte")?;
+            Some(if attr("addend").is_some() {
+                format!("{byte}+r")
+            } else {
+                byte.to_owned()
+            })
+        }
+        QName(b"ModRM") => {
+            Some(fixed("reg").map_or_else(|| "/r".to_string(), |reg| format!("/{reg}")))
+        }
+        QName(b"Immediate") => Some(format!("i{}", size_suffix(attr("size")))),
+        QName(b"CodeOffset") => Some(format!("c{}", size_suffix(attr("size")))),
+        QName(b"DataOffset") => Some(format!(
+            "moffs{}",
+            attr("size").map_or(0, |size| size.parse::<u32>().unwrap_or(0) * 8)
+        )),
+        QName(b"RegisterByte") => Some("/is4".to_string()),
+        _ => None,
+    }
+}
+
 pub fn populate_name_to_instruction_map<'instruction>(
     arch: Arch,
     instructions: &'instruction Vec<Instruction>,
@@ -1086,6 +1326,9 @@ pub fn populate_registers(xml_contents: &str) -> Result<Vec<Register>> {
                                         RegisterWidth::from_str(ustr::get_str(&value))
                                             .map_or(None, |width| Some(width));
                                 }
+                                b"only-64bit" => {
+                                    curr_register.only_64bit = ustr::get_str(&value) == "true";
+                                }
                                 _ => {}
                             }
                         }
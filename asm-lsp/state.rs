@@ -0,0 +1,280 @@
+use anyhow::Result;
+use log::info;
+use lsp_types::{CompletionItem, CompletionItemKind};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    add_condition_code_completes, get_completes, get_gas_suffix_completes, instr_filter_targets,
+    populate_name_to_directive_map, populate_name_to_instruction_map,
+    populate_name_to_register_map, tag_64bit_only_completes, tag_deprecated_completes, Arch,
+    Assembler, Config, Directive, Instruction, NameToInfoMaps, Register,
+};
+
+/// The instruction, register, and directive documentation for the instruction sets
+/// and assemblers enabled in a `Config`
+///
+/// This owns the deserialized docs, and a `ServerState` borrows from it. The two are
+/// kept apart because the lookup maps hold references into these vectors.
+#[derive(Debug, Default)]
+pub struct LoadedDocs {
+    pub instructions: Vec<(Arch, Vec<Instruction>)>,
+    pub registers: Vec<(Arch, Vec<Register>)>,
+    pub directives: Vec<(Assembler, Vec<Directive>)>,
+}
+
+/// The lookup maps and completion items the request handlers share
+#[derive(Debug, Default)]
+pub struct ServerState<'a> {
+    pub names_to_info: NameToInfoMaps<'a>,
+    pub instr_completion_items: Vec<CompletionItem>,
+    pub reg_completion_items: Vec<CompletionItem>,
+    pub directive_completion_items: Vec<CompletionItem>,
+}
+
+/// Deserializes `bytes` if `enabled`, logging how long it took
+fn load_serialized<T: DeserializeOwned>(
+    enabled: Option<bool>,
+    bytes: &[u8],
+    description: &str,
+) -> Result<Vec<T>> {
+    if !enabled.unwrap_or(false) {
+        return Ok(Vec::new());
+    }
+    let start = std::time::Instant::now();
+    let items = bincode::deserialize(bytes)?;
+    info!("{description} loaded in {}ms", start.elapsed().as_millis());
+    Ok(items)
+}
+
+impl LoadedDocs {
+    /// Loads the documentation for every instruction set and assembler enabled in
+    /// `config`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if any of the serialized docs fail to deserialize
+    pub fn load(config: &Config) -> Result<Self> {
+        let sets = &config.instruction_sets;
+        // filter out assemblers by user config
+        let filter_targets = |instrs: Vec<Instruction>| -> Vec<Instruction> {
+            instrs
+                .into_iter()
+                .map(|instruction| instr_filter_targets(&instruction, config))
+                .filter(|instruction| !instruction.forms.is_empty())
+                .collect()
+        };
+
+        let x86_instructions = filter_targets(load_serialized(
+            sets.x86,
+            include_bytes!("serialized/opcodes/x86"),
+            "x86 instruction set",
+        )?);
+        let x86_64_instructions = filter_targets(load_serialized(
+            sets.x86_64,
+            include_bytes!("serialized/opcodes/x86_64"),
+            "x86-64 instruction set",
+        )?);
+        let z80_instructions = filter_targets(load_serialized(
+            sets.z80,
+            include_bytes!("serialized/opcodes/z80"),
+            "z80 instruction set",
+        )?);
+        // NOTE: No need to filter the remaining instructions by assembler like we do
+        // for x86/x86_64, as their docs don't contain any assembler-specific
+        // information (yet)
+        let mut arm_instructions = load_serialized(
+            sets.arm,
+            include_bytes!("serialized/opcodes/arm"),
+            "arm instruction set",
+        )?;
+        // push/pop and friends are A32/T32 assembler pseudo-instructions
+        arm_instructions.extend(load_serialized(
+            sets.arm,
+            include_bytes!("serialized/opcodes/arm_pseudo"),
+            "arm pseudo-instruction set",
+        )?);
+        // TODO: change to arm64 after arm32 has been added
+        let arm64_instructions = load_serialized(
+            sets.arm64,
+            include_bytes!("serialized/opcodes/arm"),
+            "arm64 instruction set",
+        )?;
+        let mut riscv_instructions = load_serialized(
+            sets.riscv,
+            include_bytes!("serialized/opcodes/riscv"),
+            "riscv instruction set",
+        )?;
+        riscv_instructions.extend(load_serialized(
+            sets.riscv,
+            include_bytes!("serialized/opcodes/riscv_pseudo"),
+            "riscv pseudo-instruction set",
+        )?);
+        let mos6502_instructions = load_serialized(
+            sets.mos6502,
+            include_bytes!("serialized/opcodes/6502"),
+            "6502 instruction set",
+        )?;
+        let m68k_instructions = load_serialized(
+            sets.m68k,
+            include_bytes!("serialized/opcodes/m68k"),
+            "m68k instruction set",
+        )?;
+
+        let registers = vec![
+            (
+                Arch::X86,
+                load_serialized(
+                    sets.x86,
+                    include_bytes!("serialized/registers/x86"),
+                    "x86 register set",
+                )?,
+            ),
+            (
+                Arch::X86_64,
+                load_serialized(
+                    sets.x86_64,
+                    include_bytes!("serialized/registers/x86_64"),
+                    "x86-64 register set",
+                )?,
+            ),
+            (
+                Arch::Z80,
+                load_serialized(
+                    sets.z80,
+                    include_bytes!("serialized/registers/z80"),
+                    "z80 register set",
+                )?,
+            ),
+            (
+                Arch::ARM,
+                load_serialized(
+                    sets.arm,
+                    include_bytes!("serialized/registers/arm"),
+                    "arm register set",
+                )?,
+            ),
+            (
+                Arch::ARM64,
+                load_serialized(
+                    sets.arm64,
+                    include_bytes!("serialized/registers/arm64"),
+                    "arm64 register set",
+                )?,
+            ),
+            (
+                Arch::RISCV,
+                load_serialized(
+                    sets.riscv,
+                    include_bytes!("serialized/registers/riscv"),
+                    "riscv register set",
+                )?,
+            ),
+            (
+                Arch::Mos6502,
+                load_serialized(
+                    sets.mos6502,
+                    include_bytes!("serialized/registers/6502"),
+                    "6502 register set",
+                )?,
+            ),
+            (
+                Arch::M68k,
+                load_serialized(
+                    sets.m68k,
+                    include_bytes!("serialized/registers/m68k"),
+                    "m68k register set",
+                )?,
+            ),
+        ];
+
+        let directives = vec![
+            (
+                Assembler::Gas,
+                load_serialized(
+                    config.assemblers.gas,
+                    include_bytes!("serialized/directives/gas"),
+                    "Gas directive set",
+                )?,
+            ),
+            (
+                Assembler::Masm,
+                load_serialized(
+                    config.assemblers.masm,
+                    include_bytes!("serialized/directives/masm"),
+                    "MASM directive set",
+                )?,
+            ),
+            (
+                Assembler::Nasm,
+                load_serialized(
+                    config.assemblers.nasm,
+                    include_bytes!("serialized/directives/nasm"),
+                    "Nasm directive set",
+                )?,
+            ),
+        ];
+
+        Ok(Self {
+            instructions: vec![
+                (Arch::X86, x86_instructions),
+                (Arch::X86_64, x86_64_instructions),
+                (Arch::Z80, z80_instructions),
+                (Arch::ARM, arm_instructions),
+                (Arch::ARM64, arm64_instructions),
+                (Arch::RISCV, riscv_instructions),
+                (Arch::Mos6502, mos6502_instructions),
+                (Arch::M68k, m68k_instructions),
+            ],
+            registers,
+            directives,
+        })
+    }
+}
+
+impl<'a> ServerState<'a> {
+    /// Builds the lookup maps and completion items for `docs`
+    #[must_use]
+    pub fn new(docs: &'a LoadedDocs, config: &Config) -> Self {
+        let mut names_to_info = NameToInfoMaps::default();
+        for (arch, instrs) in &docs.instructions {
+            populate_name_to_instruction_map(*arch, instrs, &mut names_to_info.instructions);
+        }
+        for (arch, regs) in &docs.registers {
+            populate_name_to_register_map(*arch, regs, &mut names_to_info.registers);
+        }
+        for (assembler, dirs) in &docs.directives {
+            populate_name_to_directive_map(*assembler, dirs, &mut names_to_info.directives);
+        }
+
+        // The suffixed variants go first so they win over any plain entries with the
+        // same label when the list is deduplicated
+        let mut instr_completion_items =
+            get_gas_suffix_completes(&names_to_info.instructions, config);
+        instr_completion_items.extend(get_completes(
+            &names_to_info.instructions,
+            Some(CompletionItemKind::FUNCTION),
+        ));
+        add_condition_code_completes(
+            &mut instr_completion_items,
+            &names_to_info.instructions,
+            config,
+        );
+        tag_deprecated_completes(
+            &mut instr_completion_items,
+            &names_to_info.instructions,
+            config,
+        );
+        let mut reg_completion_items =
+            get_completes(&names_to_info.registers, Some(CompletionItemKind::VARIABLE));
+        tag_64bit_only_completes(&mut reg_completion_items, &names_to_info.registers);
+        let directive_completion_items =
+            get_completes(&names_to_info.directives, Some(CompletionItemKind::KEYWORD));
+
+        Self {
+            names_to_info,
+            instr_completion_items,
+            reg_completion_items,
+            directive_completion_items,
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::Arch;
+
+/// Linux syscall numbers for 32-bit x86 (`int 0x80`)
+const X86_SYSCALLS: &[(u32, &str)] = &[
+    (1, "exit"),
+    (2, "fork"),
+    (3, "read"),
+    (4, "write"),
+    (5, "open"),
+    (6, "close"),
+    (7, "waitpid"),
+    (10, "unlink"),
+    (11, "execve"),
+    (12, "chdir"),
+    (13, "time"),
+    (19, "lseek"),
+    (20, "getpid"),
+    (33, "access"),
+    (37, "kill"),
+    (38, "rename"),
+    (39, "mkdir"),
+    (40, "rmdir"),
+    (41, "dup"),
+    (42, "pipe"),
+    (45, "brk"),
+    (54, "ioctl"),
+    (55, "fcntl"),
+    (63, "dup2"),
+    (64, "getppid"),
+    (91, "munmap"),
+    (102, "socketcall"),
+    (122, "uname"),
+    (125, "mprotect"),
+    (158, "sched_yield"),
+    (162, "nanosleep"),
+    (183, "getcwd"),
+    (190, "vfork"),
+    (192, "mmap2"),
+    (224, "gettid"),
+    (240, "futex"),
+    (252, "exit_group"),
+    (265, "clock_gettime"),
+    (295, "openat"),
+    (296, "mkdirat"),
+    (301, "unlinkat"),
+    (330, "dup3"),
+    (331, "pipe2"),
+    (355, "getrandom"),
+];
+
+/// Linux syscall numbers for x86-64 (`syscall`)
+const X86_64_SYSCALLS: &[(u32, &str)] = &[
+    (0, "read"),
+    (1, "write"),
+    (2, "open"),
+    (3, "close"),
+    (4, "stat"),
+    (5, "fstat"),
+    (8, "lseek"),
+    (9, "mmap"),
+    (10, "mprotect"),
+    (11, "munmap"),
+    (12, "brk"),
+    (16, "ioctl"),
+    (21, "access"),
+    (22, "pipe"),
+    (24, "sched_yield"),
+    (32, "dup"),
+    (33, "dup2"),
+    (35, "nanosleep"),
+    (39, "getpid"),
+    (41, "socket"),
+    (42, "connect"),
+    (43, "accept"),
+    (49, "bind"),
+    (50, "listen"),
+    (57, "fork"),
+    (58, "vfork"),
+    (59, "execve"),
+    (60, "exit"),
+    (61, "wait4"),
+    (62, "kill"),
+    (63, "uname"),
+    (72, "fcntl"),
+    (79, "getcwd"),
+    (80, "chdir"),
+    (82, "rename"),
+    (83, "mkdir"),
+    (84, "rmdir"),
+    (87, "unlink"),
+    (102, "getuid"),
+    (104, "getgid"),
+    (110, "getppid"),
+    (158, "arch_prctl"),
+    (186, "gettid"),
+    (201, "time"),
+    (202, "futex"),
+    (228, "clock_gettime"),
+    (231, "exit_group"),
+    (257, "openat"),
+    (258, "mkdirat"),
+    (263, "unlinkat"),
+    (292, "dup3"),
+    (293, "pipe2"),
+    (318, "getrandom"),
+];
+
+/// Linux syscall numbers for 32-bit ARM, EABI (`svc 0` with the number in `r7`)
+const ARM_SYSCALLS: &[(u32, &str)] = &[
+    (1, "exit"),
+    (2, "fork"),
+    (3, "read"),
+    (4, "write"),
+    (5, "open"),
+    (6, "close"),
+    (10, "unlink"),
+    (11, "execve"),
+    (12, "chdir"),
+    (19, "lseek"),
+    (20, "getpid"),
+    (33, "access"),
+    (37, "kill"),
+    (38, "rename"),
+    (39, "mkdir"),
+    (40, "rmdir"),
+    (41, "dup"),
+    (42, "pipe"),
+    (45, "brk"),
+    (54, "ioctl"),
+    (55, "fcntl"),
+    (63, "dup2"),
+    (64, "getppid"),
+    (91, "munmap"),
+    (122, "uname"),
+    (125, "mprotect"),
+    (158, "sched_yield"),
+    (162, "nanosleep"),
+    (183, "getcwd"),
+    (190, "vfork"),
+    (192, "mmap2"),
+    (224, "gettid"),
+    (240, "futex"),
+    (248, "exit_group"),
+    (263, "clock_gettime"),
+    (281, "socket"),
+    (282, "bind"),
+    (283, "connect"),
+    (284, "listen"),
+    (285, "accept"),
+    (322, "openat"),
+    (323, "mkdirat"),
+    (328, "unlinkat"),
+    (358, "dup3"),
+    (359, "pipe2"),
+    (384, "getrandom"),
+];
+
+/// Linux syscall numbers from the generic table shared by ARM64 (`svc #0` with
+/// the number in `x8`) and RISC-V (`ecall` with the number in `a7`)
+const GENERIC_SYSCALLS: &[(u32, &str)] = &[
+    (17, "getcwd"),
+    (23, "dup"),
+    (24, "dup3"),
+    (25, "fcntl"),
+    (29, "ioctl"),
+    (34, "mkdirat"),
+    (35, "unlinkat"),
+    (49, "chdir"),
+    (56, "openat"),
+    (57, "close"),
+    (59, "pipe2"),
+    (62, "lseek"),
+    (63, "read"),
+    (64, "write"),
+    (93, "exit"),
+    (94, "exit_group"),
+    (98, "futex"),
+    (101, "nanosleep"),
+    (113, "clock_gettime"),
+    (124, "sched_yield"),
+    (129, "kill"),
+    (160, "uname"),
+    (172, "getpid"),
+    (173, "getppid"),
+    (174, "getuid"),
+    (176, "getgid"),
+    (178, "gettid"),
+    (198, "socket"),
+    (200, "bind"),
+    (201, "listen"),
+    (202, "accept"),
+    (203, "connect"),
+    (214, "brk"),
+    (215, "munmap"),
+    (221, "execve"),
+    (222, "mmap"),
+    (226, "mprotect"),
+    (260, "wait4"),
+    (278, "getrandom"),
+];
+
+/// Kernel-side parameter lists of the syscalls in the tables above
+const SYSCALL_PARAMS: &[(&str, &str)] = &[
+    ("access", "const char *filename, int mode"),
+    ("accept", "int fd, struct sockaddr *upeer_sockaddr, int *upeer_addrlen"),
+    ("arch_prctl", "int option, unsigned long arg2"),
+    ("bind", "int fd, struct sockaddr *umyaddr, int addrlen"),
+    ("brk", "unsigned long brk"),
+    ("chdir", "const char *filename"),
+    ("clock_gettime", "clockid_t which_clock, struct __kernel_timespec *tp"),
+    ("close", "unsigned int fd"),
+    ("connect", "int fd, struct sockaddr *uservaddr, int addrlen"),
+    ("dup", "unsigned int fildes"),
+    ("dup2", "unsigned int oldfd, unsigned int newfd"),
+    ("dup3", "unsigned int oldfd, unsigned int newfd, int flags"),
+    (
+        "execve",
+        "const char *filename, const char *const *argv, const char *const *envp",
+    ),
+    ("exit", "int error_code"),
+    ("exit_group", "int error_code"),
+    ("fcntl", "unsigned int fd, unsigned int cmd, unsigned long arg"),
+    ("fork", "void"),
+    ("fstat", "unsigned int fd, struct stat *statbuf"),
+    (
+        "futex",
+        "u32 *uaddr, int op, u32 val, const struct __kernel_timespec *utime, u32 *uaddr2, u32 val3",
+    ),
+    ("getcwd", "char *buf, unsigned long size"),
+    ("getgid", "void"),
+    ("getpid", "void"),
+    ("getppid", "void"),
+    ("getrandom", "char *buf, size_t count, unsigned int flags"),
+    ("gettid", "void"),
+    ("getuid", "void"),
+    ("ioctl", "unsigned int fd, unsigned int cmd, unsigned long arg"),
+    ("kill", "pid_t pid, int sig"),
+    ("listen", "int fd, int backlog"),
+    ("lseek", "unsigned int fd, off_t offset, unsigned int whence"),
+    ("mkdir", "const char *pathname, umode_t mode"),
+    ("mkdirat", "int dfd, const char *pathname, umode_t mode"),
+    (
+        "mmap",
+        "unsigned long addr, unsigned long len, unsigned long prot, unsigned long flags, unsigned long fd, unsigned long off",
+    ),
+    (
+        "mmap2",
+        "unsigned long addr, unsigned long len, unsigned long prot, unsigned long flags, unsigned long fd, unsigned long pgoff",
+    ),
+    ("mprotect", "unsigned long start, size_t len, unsigned long prot"),
+    ("munmap", "unsigned long addr, size_t len"),
+    (
+        "nanosleep",
+        "struct __kernel_timespec *rqtp, struct __kernel_timespec *rmtp",
+    ),
+    ("open", "const char *filename, int flags, umode_t mode"),
+    ("openat", "int dfd, const char *filename, int flags, umode_t mode"),
+    ("pipe", "int *fildes"),
+    ("pipe2", "int *fildes, int flags"),
+    ("read", "unsigned int fd, char *buf, size_t count"),
+    ("rename", "const char *oldname, const char *newname"),
+    ("rmdir", "const char *pathname"),
+    ("sched_yield", "void"),
+    ("socket", "int family, int type, int protocol"),
+    ("socketcall", "int call, unsigned long *args"),
+    ("stat", "const char *filename, struct stat *statbuf"),
+    ("time", "time_t *tloc"),
+    ("uname", "struct new_utsname *name"),
+    ("unlink", "const char *pathname"),
+    ("unlinkat", "int dfd, const char *pathname, int flag"),
+    ("vfork", "void"),
+    ("wait4", "pid_t upid, int *stat_addr, int options, struct rusage *ru"),
+    ("waitpid", "pid_t pid, int *stat_addr, int options"),
+    ("write", "unsigned int fd, const char *buf, size_t count"),
+];
+
+/// Returns the name and kernel-side parameter list of the Linux syscall numbered
+/// `number` on `arch`, if it's one we know of
+#[must_use]
+pub fn get_syscall(arch: Arch, number: u32) -> Option<(&'static str, &'static str)> {
+    let table = match arch {
+        Arch::X86 => X86_SYSCALLS,
+        Arch::X86_64 => X86_64_SYSCALLS,
+        Arch::ARM => ARM_SYSCALLS,
+        Arch::ARM64 | Arch::RISCV => GENERIC_SYSCALLS,
+        Arch::Z80 | Arch::Mos6502 | Arch::M68k => return None,
+    };
+    let name = table
+        .iter()
+        .find_map(|&(num, name)| (num == number).then_some(name))?;
+    let params = SYSCALL_PARAMS
+        .iter()
+        .find_map(|&(syscall, params)| (syscall == name).then_some(params))?;
+
+    Some((name, params))
+}
@@ -1,32 +1,67 @@
 #[cfg(test)]
 mod tests {
     use core::panic;
-    use std::{collections::HashMap, path::PathBuf, str::FromStr};
+    use std::{
+        collections::HashMap,
+        path::PathBuf,
+        str::FromStr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
 
-    use anyhow::Result;
+    use compile_commands::{CompileArgs, CompileCommand, SourceFile};
+    use lsp_server::{Connection, Message, RequestId};
     use lsp_textdocument::{FullTextDocument, TextDocuments};
     use lsp_types::{
-        CompletionContext, CompletionItem, CompletionItemKind, CompletionParams,
-        CompletionTriggerKind, DidOpenTextDocumentParams, HoverContents, HoverParams,
-        MarkupContent, MarkupKind, PartialResultParams, Position, TextDocumentIdentifier,
-        TextDocumentItem, TextDocumentPositionParams, Uri, WorkDoneProgressParams,
+        CompletionContext, CompletionItem, CompletionItemKind, CompletionItemTag, CompletionList,
+        CompletionParams, CompletionTextEdit, CompletionTriggerKind, Diagnostic,
+        DiagnosticSeverity, DidChangeConfigurationParams, DidChangeTextDocumentParams,
+        DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentOnTypeFormattingParams,
+        DocumentSymbol, DocumentSymbolParams, Documentation, FormattingOptions,
+        GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+        InitializeParams, MarkupContent, MarkupKind, ParameterLabel, PartialResultParams, Position,
+        Range, ReferenceContext, ReferenceParams, RenameParams, SignatureHelpParams,
+        TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
+        TextDocumentPositionParams, TextEdit, Uri, VersionedTextDocumentIdentifier,
+        WorkDoneProgressParams, WorkspaceFolder,
     };
     use tree_sitter::Parser;
 
     use crate::{
-        get_comp_resp, get_completes, get_hover_resp, get_word_from_pos_params,
+        add_debug_hover_info, add_diagnostics_context, apply_compile_cmd, apply_config_settings,
+        apply_empty_config_fallback, apply_linters, asm_language, dedup_diagnostics,
+        find_word_at_pos, get_arm_state_diagnostics, get_assemble_project_resp, get_backends_resp,
+        get_comp_resp, get_compile_cmd_assembler, get_compile_cmd_overrides, get_config,
+        get_config_include_dirs, get_default_compile_cmd, get_defines, get_diagnostics,
+        get_document_outline, get_document_symbols, get_explain_range_resp, get_gas_size_suffix,
+        get_gas_suffix_completes, get_goto_def_resp, get_hover_resp, get_include_dirs,
+        get_linked_editing_range_resp, get_listing_encoding, get_modeline_config, get_nasm_strucs,
+        get_on_type_formatting_resp, get_prepare_rename_resp, get_ref_resp,
+        get_register_writers_resp, get_rename_resp, get_root_fallback_config, get_semantic_tokens,
+        get_semantic_tokens_legend, get_sig_help_resp, get_syntax_diagnostics,
+        get_word_from_pos_params, get_workspace_goto_def_resp,
+        handle::{
+            handle_did_change_configuration_notification,
+            handle_did_change_text_document_notification,
+            handle_did_close_text_document_notification,
+            handle_did_open_text_document_notification, handle_document_outline_request,
+        },
         instr_filter_targets,
-        parser::{get_cache_dir, populate_arm_instructions, populate_masm_nasm_directives},
-        populate_gas_directives, populate_instructions, populate_name_to_directive_map,
-        populate_name_to_instruction_map, populate_name_to_register_map, populate_registers, Arch,
-        Assembler, Assemblers, Config, ConfigOptions, Directive, Instruction, InstructionSets,
-        NameToDirectiveMap, NameToInstructionMap, NameToRegisterMap, Register, TreeEntry,
-        TreeStore,
+        parser::{populate_arm_instructions, populate_masm_nasm_directives},
+        path_to_uri, populate_gas_directives, populate_instructions, populate_registers,
+        read_config_file, read_global_config, send_empty_resp, tag_deprecated_completes,
+        uri_to_path, Arch, Assembler, Assemblers, BackendInfo, ClosedTrees, CompletionData, Config,
+        ConfigOptions, ConfigOverrides, DemangleStyle, Directive, DocumentOutlineParams,
+        DocumentOutlineResult, Instruction, InstructionExample, InstructionForm, InstructionSets,
+        InstructionsWritingRegisterParams, LoadedDocs, LspClient, NameToDirectiveMap,
+        NameToInstructionMap, NameToRegisterMap, OutlineSymbol, OutlineSymbolKind, Register,
+        RegisterType, ServerState, TreeEntry, TreeStore, WorkspaceIndex, ISA,
     };
 
     fn empty_test_config() -> Config {
         Config {
             version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers {
                 gas: Some(false),
                 go: Some(false),
@@ -41,19 +76,56 @@ mod tests {
                 arm: Some(false),
                 arm64: Some(false),
                 riscv: Some(false),
+                mos6502: Some(false),
+                m68k: Some(false),
             },
             opts: ConfigOptions {
                 compiler: None,
                 diagnostics: None,
                 default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
             },
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
         }
     }
 
     fn z80_test_config() -> Config {
         Config {
             version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers {
                 gas: Some(false),
                 go: Some(false),
@@ -68,19 +140,56 @@ mod tests {
                 arm: Some(false),
                 arm64: Some(false),
                 riscv: Some(false),
+                mos6502: Some(false),
+                m68k: Some(false),
             },
             opts: ConfigOptions {
                 compiler: None,
                 diagnostics: None,
                 default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
             },
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
         }
     }
 
     fn arm_test_config() -> Config {
         Config {
             version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers {
                 gas: Some(false),
                 go: Some(false),
@@ -95,19 +204,56 @@ mod tests {
                 arm: Some(true),
                 arm64: Some(false),
                 riscv: Some(false),
+                mos6502: Some(false),
+                m68k: Some(false),
             },
             opts: ConfigOptions {
                 compiler: None,
                 diagnostics: None,
                 default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
             },
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
         }
     }
 
     fn riscv_test_config() -> Config {
         Config {
             version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers {
                 gas: Some(false),
                 go: Some(false),
@@ -122,19 +268,184 @@ mod tests {
                 arm: Some(false),
                 arm64: Some(false),
                 riscv: Some(true),
+                mos6502: Some(false),
+                m68k: Some(false),
+            },
+            opts: ConfigOptions {
+                compiler: None,
+                diagnostics: None,
+                default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
+            },
+            client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
+        }
+    }
+
+    fn mos6502_test_config() -> Config {
+        Config {
+            version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
+            assemblers: Assemblers {
+                gas: Some(false),
+                go: Some(false),
+                masm: Some(false),
+                nasm: Some(false),
+                z80: Some(false),
+            },
+            instruction_sets: InstructionSets {
+                x86: Some(false),
+                x86_64: Some(false),
+                z80: Some(false),
+                arm: Some(false),
+                arm64: Some(false),
+                riscv: Some(false),
+                mos6502: Some(true),
+                m68k: Some(false),
+            },
+            opts: ConfigOptions {
+                compiler: None,
+                diagnostics: None,
+                default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
+            },
+            client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
+        }
+    }
+
+    fn m68k_test_config() -> Config {
+        Config {
+            version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
+            assemblers: Assemblers {
+                gas: Some(false),
+                go: Some(false),
+                masm: Some(false),
+                nasm: Some(false),
+                z80: Some(false),
+            },
+            instruction_sets: InstructionSets {
+                x86: Some(false),
+                x86_64: Some(false),
+                z80: Some(false),
+                arm: Some(false),
+                arm64: Some(false),
+                riscv: Some(false),
+                mos6502: Some(false),
+                m68k: Some(true),
             },
             opts: ConfigOptions {
                 compiler: None,
                 diagnostics: None,
                 default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
             },
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
         }
     }
 
     fn x86_x86_64_test_config() -> Config {
         Config {
             version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers {
                 gas: Some(true),
                 go: Some(true),
@@ -149,19 +460,56 @@ mod tests {
                 arm: Some(false),
                 arm64: Some(false),
                 riscv: Some(false),
+                mos6502: Some(false),
+                m68k: Some(false),
             },
             opts: ConfigOptions {
                 compiler: None,
                 diagnostics: None,
                 default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
             },
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
         }
     }
 
     fn gas_test_config() -> Config {
         Config {
             version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers {
                 gas: Some(true),
                 go: Some(false),
@@ -176,19 +524,56 @@ mod tests {
                 arm: Some(false),
                 arm64: Some(false),
                 riscv: Some(false),
+                mos6502: Some(false),
+                m68k: Some(false),
             },
             opts: ConfigOptions {
                 compiler: None,
                 diagnostics: None,
                 default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
             },
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
         }
     }
 
     fn masm_test_config() -> Config {
         Config {
             version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers {
                 gas: Some(false),
                 go: Some(false),
@@ -203,19 +588,56 @@ mod tests {
                 arm: Some(false),
                 arm64: Some(false),
                 riscv: Some(false),
+                mos6502: Some(false),
+                m68k: Some(false),
             },
             opts: ConfigOptions {
                 compiler: None,
                 diagnostics: None,
                 default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
             },
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
         }
     }
 
     fn nasm_test_config() -> Config {
         Config {
             version: "0.1".to_string(),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers {
                 gas: Some(false),
                 go: Some(false),
@@ -230,339 +652,69 @@ mod tests {
                 arm: Some(false),
                 arm64: Some(false),
                 riscv: Some(false),
+                mos6502: Some(false),
+                m68k: Some(false),
             },
             opts: ConfigOptions {
                 compiler: None,
                 diagnostics: None,
                 default_diagnostics: None,
+                diagnostics_full_line: None,
+                diagnostics_context: Some(false),
+                syntax_diagnostics: Some(false),
+                severity_map: None,
+                show_encoding: None,
+                show_opcodes: None,
+                log_level: None,
+                keep_closed_trees: None,
+                workspace_index: None,
+                syscall_hover: None,
+                aliases: None,
+                linters: None,
+                include_dirs: None,
+                diagnostics_command: None,
+                assemblers_by_arch: None,
+                debug_hover: None,
+                hover_single_arch: None,
+                arch_priority: None,
+                prefer_go_names: None,
+                indent_unit: None,
+                telemetry: None,
+                comment_tags: None,
+                min_hover_word_len: None,
+                demangle_options: None,
+                empty_config_fallback: None,
+                completion_mix_directives: None,
+                completion_both_directive_forms: None,
+                directive_prefix_fallback: None,
+                disassembly_mode: None,
+                suppress_empty_responses: None,
             },
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
         }
     }
 
-    #[derive(Debug)]
-    struct GlobalInfo {
-        x86_instructions: Vec<Instruction>,
-        x86_64_instructions: Vec<Instruction>,
-        x86_registers: Vec<Register>,
-        x86_64_registers: Vec<Register>,
-        arm_instructions: Vec<Instruction>,
-        arm_registers: Vec<Register>,
-        arm64_instructions: Vec<Instruction>,
-        arm64_registers: Vec<Register>,
-        riscv_instructions: Vec<Instruction>,
-        riscv_registers: Vec<Register>,
-        z80_instructions: Vec<Instruction>,
-        z80_registers: Vec<Register>,
-        gas_directives: Vec<Directive>,
-        masm_directives: Vec<Directive>,
-        nasm_directives: Vec<Directive>,
-    }
-
-    #[derive(Debug)]
-    struct GlobalVars<'a> {
-        names_to_instructions: NameToInstructionMap<'a>,
-        names_to_registers: NameToRegisterMap<'a>,
-        names_to_directives: NameToDirectiveMap<'a>,
-        instr_completion_items: Vec<CompletionItem>,
-        reg_completion_items: Vec<CompletionItem>,
-        directive_completion_items: Vec<CompletionItem>,
-    }
-
-    impl GlobalInfo {
-        const fn new() -> Self {
-            Self {
-                x86_instructions: Vec::new(),
-                x86_64_instructions: Vec::new(),
-                x86_registers: Vec::new(),
-                x86_64_registers: Vec::new(),
-                arm_instructions: Vec::new(),
-                arm_registers: Vec::new(),
-                arm64_instructions: Vec::new(),
-                arm64_registers: Vec::new(),
-                riscv_instructions: Vec::new(),
-                riscv_registers: Vec::new(),
-                z80_instructions: Vec::new(),
-                z80_registers: Vec::new(),
-                gas_directives: Vec::new(),
-                masm_directives: Vec::new(),
-                nasm_directives: Vec::new(),
-            }
-        }
-    }
-
-    impl GlobalVars<'_> {
-        fn new() -> Self {
-            Self {
-                names_to_instructions: NameToInstructionMap::new(),
-                names_to_registers: NameToRegisterMap::new(),
-                names_to_directives: NameToDirectiveMap::new(),
-                instr_completion_items: Vec::new(),
-                reg_completion_items: Vec::new(),
-                directive_completion_items: Vec::new(),
-            }
-        }
-    }
-
-    fn init_global_info(config: &Config) -> Result<GlobalInfo> {
-        let mut info = GlobalInfo::new();
-
-        info.x86_instructions = if config.instruction_sets.x86.unwrap_or(false) {
-            let x86_instrs = include_bytes!("serialized/opcodes/x86");
-            bincode::deserialize::<Vec<Instruction>>(x86_instrs)?
-                .into_iter()
-                .map(|instruction| {
-                    // filter out assemblers by user config
-                    instr_filter_targets(&instruction, config)
-                })
-                .filter(|instruction| !instruction.forms.is_empty())
-                .collect()
-        } else {
-            Vec::new()
-        };
-
-        info.x86_64_instructions = if config.instruction_sets.x86_64.unwrap_or(false) {
-            let x86_64_instrs = include_bytes!("serialized/opcodes/x86_64");
-            bincode::deserialize::<Vec<Instruction>>(x86_64_instrs)?
-                .into_iter()
-                .map(|instruction| {
-                    // filter out assemblers by user config
-                    instr_filter_targets(&instruction, config)
-                })
-                .filter(|instruction| !instruction.forms.is_empty())
-                .collect()
-        } else {
-            Vec::new()
-        };
-
-        info.z80_instructions = if config.instruction_sets.z80.unwrap_or(false) {
-            let z80_instrs = include_bytes!("serialized/opcodes/z80");
-            bincode::deserialize::<Vec<Instruction>>(z80_instrs)?
-                .into_iter()
-                .map(|instruction| {
-                    // filter out assemblers by user config
-                    instr_filter_targets(&instruction, config)
-                })
-                .filter(|instruction| !instruction.forms.is_empty())
-                .collect()
-        } else {
-            Vec::new()
-        };
-
-        info.arm_instructions = if config.instruction_sets.arm.unwrap_or(false) {
-            let arm_instrs = include_bytes!("serialized/opcodes/arm");
-            bincode::deserialize::<Vec<Instruction>>(arm_instrs)?
-        } else {
-            Vec::new()
-        };
-
-        info.arm64_instructions = if config.instruction_sets.arm64.unwrap_or(false) {
-            let arm64_instrs = include_bytes!("serialized/opcodes/arm64");
-            bincode::deserialize::<Vec<Instruction>>(arm64_instrs)?
-        } else {
-            Vec::new()
-        };
-
-        info.riscv_instructions = if config.instruction_sets.riscv.unwrap_or(false) {
-            let riscv_instrs = include_bytes!("serialized/opcodes/riscv");
-            bincode::deserialize::<Vec<Instruction>>(riscv_instrs)?
-        } else {
-            Vec::new()
-        };
-
-        info.x86_registers = if config.instruction_sets.x86.unwrap_or(false) {
-            let regs_x86 = include_bytes!("serialized/registers/x86");
-            bincode::deserialize(regs_x86)?
-        } else {
-            Vec::new()
-        };
-
-        info.x86_64_registers = if config.instruction_sets.x86_64.unwrap_or(false) {
-            let regs_x86_64 = include_bytes!("serialized/registers/x86_64");
-            bincode::deserialize(regs_x86_64)?
-        } else {
-            Vec::new()
-        };
-
-        info.z80_registers = if config.instruction_sets.z80.unwrap_or(false) {
-            let regs_z80 = include_bytes!("serialized/registers/z80");
-            bincode::deserialize(regs_z80)?
-        } else {
-            Vec::new()
-        };
-
-        info.arm_registers = if config.instruction_sets.arm.unwrap_or(false) {
-            let regs_arm = include_bytes!("serialized/registers/arm");
-            bincode::deserialize(regs_arm)?
-        } else {
-            Vec::new()
-        };
-
-        info.arm64_registers = if config.instruction_sets.arm64.unwrap_or(false) {
-            let regs_arm64 = include_bytes!("serialized/registers/arm64");
-            bincode::deserialize(regs_arm64)?
-        } else {
-            Vec::new()
-        };
-
-        info.riscv_registers = if config.instruction_sets.riscv.unwrap_or(false) {
-            let regs_riscv = include_bytes!("serialized/registers/riscv");
-            bincode::deserialize(regs_riscv)?
-        } else {
-            Vec::new()
-        };
-
-        info.gas_directives = if config.assemblers.gas.unwrap_or(false) {
-            let gas_dirs = include_bytes!("serialized/directives/gas");
-            bincode::deserialize(gas_dirs)?
-        } else {
-            Vec::new()
-        };
-
-        info.masm_directives = if config.assemblers.masm.unwrap_or(false) {
-            let masm_dirs = include_bytes!("serialized/directives/masm");
-            bincode::deserialize(masm_dirs)?
-        } else {
-            Vec::new()
-        };
-
-        info.nasm_directives = if config.assemblers.nasm.unwrap_or(false) {
-            let nasm_dirs = include_bytes!("serialized/directives/nasm");
-            bincode::deserialize(nasm_dirs)?
-        } else {
-            Vec::new()
-        };
-
-        Ok(info)
-    }
-
-    fn init_test_store(info: &GlobalInfo) -> GlobalVars<'_> {
-        let mut store = GlobalVars::new();
-
-        let mut x86_cache_path = get_cache_dir().unwrap();
-        x86_cache_path.push("x86_instr_docs.html");
-        if x86_cache_path.is_file() {
-            std::fs::remove_file(&x86_cache_path).unwrap();
-        }
-
-        populate_name_to_instruction_map(
-            Arch::X86,
-            &info.x86_instructions,
-            &mut store.names_to_instructions,
-        );
-
-        populate_name_to_instruction_map(
-            Arch::X86_64,
-            &info.x86_64_instructions,
-            &mut store.names_to_instructions,
-        );
-
-        populate_name_to_instruction_map(
-            Arch::ARM,
-            &info.arm_instructions,
-            &mut store.names_to_instructions,
-        );
-
-        populate_name_to_instruction_map(
-            Arch::ARM64,
-            &info.arm64_instructions,
-            &mut store.names_to_instructions,
-        );
-
-        populate_name_to_instruction_map(
-            Arch::RISCV,
-            &info.riscv_instructions,
-            &mut store.names_to_instructions,
-        );
-
-        populate_name_to_instruction_map(
-            Arch::Z80,
-            &info.z80_instructions,
-            &mut store.names_to_instructions,
-        );
-
-        populate_name_to_register_map(
-            Arch::X86,
-            &info.x86_registers,
-            &mut store.names_to_registers,
-        );
-
-        populate_name_to_register_map(
-            Arch::X86_64,
-            &info.x86_64_registers,
-            &mut store.names_to_registers,
-        );
-
-        populate_name_to_register_map(
-            Arch::ARM,
-            &info.arm_registers,
-            &mut store.names_to_registers,
-        );
-
-        populate_name_to_register_map(
-            Arch::ARM64,
-            &info.arm64_registers,
-            &mut store.names_to_registers,
-        );
-
-        populate_name_to_register_map(
-            Arch::RISCV,
-            &info.riscv_registers,
-            &mut store.names_to_registers,
-        );
-
-        populate_name_to_register_map(
-            Arch::Z80,
-            &info.z80_registers,
-            &mut store.names_to_registers,
-        );
-
-        populate_name_to_directive_map(
-            Assembler::Gas,
-            &info.gas_directives,
-            &mut store.names_to_directives,
-        );
-
-        populate_name_to_directive_map(
-            Assembler::Masm,
-            &info.masm_directives,
-            &mut store.names_to_directives,
-        );
-
-        populate_name_to_directive_map(
-            Assembler::Nasm,
-            &info.nasm_directives,
-            &mut store.names_to_directives,
-        );
-
-        store.instr_completion_items = get_completes(
-            &store.names_to_instructions,
-            Some(CompletionItemKind::OPERATOR),
-        );
-
-        store.reg_completion_items = get_completes(
-            &store.names_to_registers,
-            Some(CompletionItemKind::VARIABLE),
-        );
-
-        store.directive_completion_items = get_completes(
-            &store.names_to_directives,
-            Some(CompletionItemKind::OPERATOR),
-        );
-
-        store
+    fn get_test_hover(source: &str, config: &Config) -> Option<Hover> {
+        get_test_hover_with_defines(source, config, &HashMap::new())
     }
 
-    fn test_hover(source: &str, expected: &str, config: &Config) {
-        let info = init_global_info(config).expect("Failed to load info");
-        let globals = init_test_store(&info);
+    fn get_test_hover_with_defines(
+        source: &str,
+        config: &Config,
+        defines: &HashMap<SourceFile, HashMap<String, String>>,
+    ) -> Option<Hover> {
+        let docs = LoadedDocs::load(config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, config);
 
         let mut position: Option<Position> = None;
         for (line_num, line) in source.lines().enumerate() {
             if let Some((idx, _)) = line.match_indices("<cursor>").next() {
                 position = Some(Position {
                     line: line_num as u32,
-                    character: idx as u32,
+                    character: line[..idx].encode_utf16().count() as u32,
                 });
                 break;
             }
@@ -596,10 +748,14 @@ mod tests {
         };
 
         let mut parser = Parser::new();
-        parser.set_language(&tree_sitter_asm::language()).unwrap();
+        parser.set_language(&asm_language()).unwrap();
         let tree = parser.parse(&source_code, None);
         let mut tree_store = TreeStore::new();
-        let tree_entry = TreeEntry { tree, parser };
+        let tree_entry = TreeEntry {
+            tree,
+            parser,
+            symbols: None,
+        };
         tree_store.insert(uri, tree_entry);
 
         let hover_params = HoverParams {
@@ -616,19 +772,29 @@ mod tests {
             |doc| get_word_from_pos_params(doc, &pos_params),
         );
 
-        let resp = get_hover_resp(
+        let mut resp = get_hover_resp(
             &hover_params,
             config,
             word,
             cursor_offset,
             &text_store,
             &mut tree_store,
-            &globals.names_to_instructions,
-            &globals.names_to_registers,
-            &globals.names_to_directives,
+            &state.names_to_info.instructions,
+            &state.names_to_info.registers,
+            &state.names_to_info.directives,
             &HashMap::new(),
-        )
-        .unwrap();
+            defines,
+            &Vec::new(),
+        );
+        if config.opts.debug_hover.unwrap_or(false) {
+            resp = add_debug_hover_info(resp, &hover_params, &text_store, &mut tree_store);
+        }
+
+        resp
+    }
+
+    fn test_hover(source: &str, expected: &str, config: &Config) {
+        let resp = get_test_hover(source, config).unwrap();
 
         if let HoverContents::Markup(MarkupContent {
             kind: MarkupKind::Markdown,
@@ -642,29 +808,46 @@ mod tests {
         }
     }
 
-    fn test_autocomplete(
+    /// Creates an empty directory for a test's files under the system temp dir,
+    /// unique to this process and `name` so that parallel test runs don't collide
+    fn unique_temp_dir(name: &str) -> PathBuf {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "asm-lsp-{name}-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn get_test_comps(
         source: &str,
         config: &Config,
-        expected_kind: CompletionItemKind,
         trigger_kind: CompletionTriggerKind,
         trigger_character: Option<String>,
-    ) {
-        let info = init_global_info(config).expect("Failed to load info");
-        let globals = init_test_store(&info);
+    ) -> Option<CompletionList> {
+        let docs = LoadedDocs::load(config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, config);
 
         let source_code = source.replace("<cursor>", "");
 
         let mut parser = Parser::new();
-        parser.set_language(&tree_sitter_asm::language()).unwrap();
+        parser.set_language(&asm_language()).unwrap();
         let tree = parser.parse(&source_code, None);
-        let mut tree_entry = TreeEntry { tree, parser };
+        let mut tree_entry = TreeEntry {
+            tree,
+            parser,
+            symbols: None,
+        };
 
         let mut position: Option<Position> = None;
         for (line_num, line) in source.lines().enumerate() {
             if let Some((idx, _)) = line.match_indices("<cursor>").next() {
                 position = Some(Position {
                     line: line_num as u32,
-                    character: idx as u32,
+                    character: line[..idx].encode_utf16().count() as u32,
                 });
                 break;
             }
@@ -693,16 +876,25 @@ mod tests {
             context: Some(comp_ctx),
         };
 
-        let resp = get_comp_resp(
+        get_comp_resp(
             &source_code,
             &mut tree_entry,
             &params,
             config,
-            &globals.instr_completion_items,
-            &globals.directive_completion_items,
-            &globals.reg_completion_items,
+            &state.instr_completion_items,
+            &state.directive_completion_items,
+            &state.reg_completion_items,
         )
-        .unwrap();
+    }
+
+    fn test_autocomplete(
+        source: &str,
+        config: &Config,
+        expected_kind: CompletionItemKind,
+        trigger_kind: CompletionTriggerKind,
+        trigger_character: Option<String>,
+    ) -> CompletionList {
+        let resp = get_test_comps(source, config, trigger_kind, trigger_character).unwrap();
 
         // - We currently have a very course-grained approach to completions,
         // - We just send all of the appropriate items (e.g. all instrucitons, all
@@ -711,13 +903,30 @@ mod tests {
         // - Given this, we won't check for equality for all of the expected items,
         // but instead just that
         //      1) There are some items
-        //      2) Said items are of the right type
-        // NOTE: Both instructions and directives use the OPERATOR complection type,
-        // so another means of verification should be added here
+        //      2) Said items are of the right type, or one of the kinds mixed in
+        //      alongside them
+        //      3) At least one of them is of the expected type
+        let allowed_kinds = match expected_kind {
+            // directives are suggested where an instruction is expected
+            CompletionItemKind::FUNCTION => {
+                vec![CompletionItemKind::FUNCTION, CompletionItemKind::KEYWORD]
+            }
+            // labels are suggested alongside registers
+            CompletionItemKind::VARIABLE => {
+                vec![CompletionItemKind::VARIABLE, CompletionItemKind::REFERENCE]
+            }
+            _ => vec![expected_kind],
+        };
         assert!(!resp.items.is_empty());
         for comp in &resp.items {
-            assert!(comp.kind == Some(expected_kind));
+            assert!(comp.kind.is_some_and(|kind| allowed_kinds.contains(&kind)));
         }
+        assert!(resp
+            .items
+            .iter()
+            .any(|comp| comp.kind == Some(expected_kind)));
+
+        resp
     }
 
     fn test_register_autocomplete(
@@ -742,7 +951,7 @@ mod tests {
         trigger_kind: CompletionTriggerKind,
         trigger_character: Option<String>,
     ) {
-        let expected_kind = CompletionItemKind::OPERATOR;
+        let expected_kind = CompletionItemKind::FUNCTION;
         test_autocomplete(
             source,
             config,
@@ -758,7 +967,7 @@ mod tests {
         trigger_kind: CompletionTriggerKind,
         trigger_character: Option<String>,
     ) {
-        let expected_kind = CompletionItemKind::OPERATOR;
+        let expected_kind = CompletionItemKind::KEYWORD;
         test_autocomplete(
             source,
             config,
@@ -773,7 +982,7 @@ mod tests {
         trigger_kind: CompletionTriggerKind,
         trigger_character: Option<String>,
     ) {
-        let expected_kind = CompletionItemKind::VARIABLE;
+        let expected_kind = CompletionItemKind::REFERENCE;
         test_autocomplete(
             source,
             &empty_test_config(),
@@ -783,6 +992,97 @@ mod tests {
         );
     }
 
+    /// Returns the (line, character) start positions of all references to the
+    /// word under `<cursor>` in `source`. If `edits` are given, they're applied
+    /// to the document via the didChange handler before the request is made
+    fn test_references(
+        source: &str,
+        edits: Vec<TextDocumentContentChangeEvent>,
+    ) -> Vec<(u32, u32)> {
+        let uri = Uri::from_str("file://").unwrap();
+        let source_code = source.replace("<cursor>", "");
+        let mut text_store = TextDocuments::new();
+        let did_open_params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "asm".to_string(),
+                version: 0,
+                text: source_code.clone(),
+            },
+        };
+        let params = serde_json::to_value(did_open_params).unwrap();
+        text_store.listen("textDocument/didOpen", &params);
+
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let tree = parser.parse(&source_code, None);
+        let mut tree_store = TreeStore::new();
+        tree_store.insert(
+            uri.clone(),
+            TreeEntry {
+                tree,
+                parser,
+                symbols: None,
+            },
+        );
+
+        let mut position: Option<Position> = None;
+        if !edits.is_empty() {
+            let change_params = DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 1,
+                },
+                content_changes: edits,
+            };
+            handle_did_change_text_document_notification(
+                &change_params,
+                &Config::default(),
+                &mut text_store,
+                &mut tree_store,
+                &mut ConfigOverrides::new(),
+                &ConfigOverrides::new(),
+                &ConfigOverrides::new(),
+            )
+            .unwrap();
+        }
+        for (line_num, line) in source.lines().enumerate() {
+            if let Some((idx, _)) = line.match_indices("<cursor>").next() {
+                position = Some(Position {
+                    line: line_num as u32,
+                    character: line[..idx].encode_utf16().count() as u32,
+                });
+                break;
+            }
+        }
+
+        let params = ReferenceParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: position.expect("No <cursor> marker found"),
+            },
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: PartialResultParams {
+                partial_result_token: None,
+            },
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+        };
+        let mut refs: Vec<(u32, u32)> = get_ref_resp(
+            &params,
+            text_store.get_document(&uri).unwrap(),
+            tree_store.get_mut(&uri).unwrap(),
+        )
+        .iter()
+        .map(|loc| (loc.range.start.line, loc.range.start.character))
+        .collect();
+        refs.sort_unstable();
+        refs
+    }
+
     /**************************************************************************
      * RISCV Tests
      *************************************************************************/
@@ -796,6 +1096,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn handle_autocomplete_riscv_it_provides_reg_comps_by_abi_name() {
+        let resp = test_autocomplete(
+            "addi a0, a<cursor>, 1",
+            &riscv_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        for reg in ["x10", "a0", "x8", "s0", "fp", "fa0"] {
+            assert!(labels.contains(&reg), "Missing completion for {reg}");
+        }
+    }
+
+    #[test]
+    fn handle_autocomplete_riscv_it_sorts_regs_before_labels_in_operands() {
+        let resp = test_autocomplete(
+            "loop:\n    addi a0, a<cursor>, 1",
+            &riscv_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let sort_text = |label: &str| {
+            resp.items
+                .iter()
+                .find(|item| item.label == label)
+                .and_then(|item| item.sort_text.clone())
+                .unwrap_or_else(|| panic!("Missing completion for {label}"))
+        };
+        assert!(sort_text("a0") < sort_text("loop"));
+    }
+
     #[test]
     fn handle_autocomplete_riscv_it_provides_instr_comps_one_character_start() {
         test_instruction_autocomplete(
@@ -827,22 +1161,134 @@ Adds the sign-extended 12-bit immediate to register rs1. Arithmetic overflow is
             "X0 [riscv]
 Hard-wired zero
 
+Alternate names: zero
 Type: General Purpose Register",
             &riscv_test_config(),
         );
     }
 
-    /**************************************************************************
-     * ARM Tests
-     *************************************************************************/
     #[test]
-    fn handle_autocomplete_arm_it_provides_reg_comps_in_existing_reg_arg() {
-        test_register_autocomplete(
-            "    mov  r<cursor>, #4",
-            &arm_test_config(),
-            CompletionTriggerKind::INVOKED,
+    fn handle_hover_riscv_it_provides_reg_info_by_abi_name() {
+        test_hover(
+            "addi a<cursor>0, x0, 1",
+            "X10 [riscv]
+Function arguments/return values
+Caller saved
+
+Alternate names: a0
+Type: General Purpose Register",
+            &riscv_test_config(),
+        );
+        test_hover(
+            "addi f<cursor>p, x0, 1",
+            "X8 [riscv]
+Saved register/frame pointer
+Callee saved
+
+Alternate names: s0, fp
+Type: General Purpose Register",
+            &riscv_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_riscv_it_provides_pseudo_instr_info() {
+        test_hover(
+            "<cursor>mv a0, a1",
+            "mv [riscv]
+*Pseudo-instruction*. Copy register.
+
+## Templates
+
+ + `mv rd, rs → addi rd, rs, 0`
+## Examples
+
+ + `mv a0, s1`",
+            &riscv_test_config(),
+        );
+    }
+
+    /**************************************************************************
+     * ARM Tests
+     *************************************************************************/
+    #[test]
+    fn handle_autocomplete_arm_it_provides_reg_comps_in_existing_reg_arg() {
+        test_register_autocomplete(
+            "    mov  r<cursor>, #4",
+            &arm_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+
+    #[test]
+    fn handle_autocomplete_arm_it_provides_reg_list_comps() {
+        let resp = test_autocomplete(
+            "    push {r0, <cursor>}",
+            &arm_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        assert!(!labels.contains(&"r0"));
+        assert!(labels.contains(&"r1"));
+        assert!(labels.contains(&"lr"));
+        assert!(labels.contains(&"r1-r12"));
+        // only core registers can be pushed
+        assert!(!labels.contains(&"d0"));
+        assert!(!labels.contains(&"cpsr"));
+
+        // ranges and aliases count as listed, including after the cursor
+        let resp = test_autocomplete(
+            "    pop {r4-r7, <cursor>, r14}",
+            &arm_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        assert!(!labels.contains(&"r5"));
+        assert!(!labels.contains(&"lr"));
+        assert!(labels.contains(&"pc"));
+        assert!(labels.contains(&"r0-r3"));
+        assert!(labels.contains(&"r8-r12"));
+
+        let resp = test_autocomplete(
+            "    push {<cursor>}",
+            &arm_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        assert!(resp.items.iter().any(|item| item.label == "r0-r12"));
+
+        let resp = test_autocomplete(
+            "    vpush {d8-d15, <cursor>}",
+            &arm_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
             None,
         );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        assert!(labels.contains(&"d0"));
+        assert!(!labels.contains(&"d8"));
+        assert!(!labels.contains(&"r0"));
+    }
+
+    #[test]
+    fn handle_hover_arm_it_provides_pseudo_instr_info() {
+        test_hover(
+            "<cursor>push {r4, lr}",
+            "push [arm]
+*Pseudo-instruction*. Push registers onto the full descending stack (A32/T32).
+
+## Templates
+
+ + `push {reglist} → stmdb sp!, {reglist}`
+ + `push {rt} → str rt, [sp, #-4]!`",
+            &arm_test_config(),
+        );
     }
 
     #[test]
@@ -855,6 +1301,178 @@ Type: General Purpose Register",
         );
     }
 
+    #[test]
+    fn handle_autocomplete_arm_it_provides_coproc_comps_in_mcr_args() {
+        test_register_autocomplete(
+            "    mcr p<cursor>, 0, r0, c1, c0, 0",
+            &arm_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+
+    #[test]
+    fn handle_hover_arm_it_provides_sys_reg_info() {
+        test_hover(
+            "    mcr p15, 0, r0, c2, c0, 0 @ write tt<cursor>br0",
+            "TTBR0 [arm]
+Translation Table Base Register 0. CP15: p15, 0, Rt, c2, c0, 0
+
+Type: Control Register
+Width: 32 bits",
+            &arm_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_arm_it_provides_coproc_reg_info() {
+        test_hover(
+            "    mcr p15, 0, r0, c<cursor>1, c0, 0",
+            "C1 [arm]
+Coprocessor register 1, selects a register of the target coprocessor together with the opcode operands of `mcr`/`mrc`",
+            &arm_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_arm_it_prefers_labels_over_coproc_regs() {
+        let hover = get_test_hover("c1:\n    .word 0\n    b c<cursor>1", &arm_test_config())
+            .expect("No hover for the label");
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markup hover contents");
+        };
+        assert!(!content.value.contains("Coprocessor register"));
+    }
+
+    #[test]
+    fn handle_autocomplete_arm_it_provides_status_regs_once() {
+        let comps = test_autocomplete(
+            "    msr c<cursor>psr, r0",
+            &arm_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let cpsr_count = comps
+            .items
+            .iter()
+            .filter(|item| item.label == "cpsr")
+            .count();
+        assert_eq!(cpsr_count, 1);
+    }
+
+    #[test]
+    fn handle_hover_arm_it_provides_thumb_only_instr_info() {
+        test_hover(
+            "    .thumb
+    t<cursor>bb [r0, r1]",
+            "TBB [arm]
+Table Branch Byte
+
+Instruction set state: Thumb
+Only available in Thumb state",
+            &arm_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_arm_it_provides_instr_set_state() {
+        test_hover(
+            "    .code 16
+    .arm
+<cursor>push {r4, lr}",
+            "push [arm]
+*Pseudo-instruction*. Push registers onto the full descending stack (A32/T32).
+
+## Templates
+
+ + `push {reglist} → stmdb sp!, {reglist}`
+ + `push {rt} → str rt, [sp, #-4]!`
+
+Instruction set state: ARM",
+            &arm_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_arm_it_explains_shift_operators() {
+        test_hover(
+            "	add r0, r1, r2, l<cursor>sl #2",
+            "LSL (shift operator)
+Logical shift left
+
+`r2` is shifted left by `#2`, shifting in zeros, before the instruction uses it",
+            &arm_test_config(),
+        );
+        test_hover(
+            "	ldr r0, [r1, r2, a<cursor>sr r3]",
+            "ASR (shift operator)
+Arithmetic shift right
+
+`r2` is shifted right by the value in `r3`, shifting in copies of the sign bit, before the instruction uses it",
+            &arm_test_config(),
+        );
+
+        // in mnemonic position it's still the instruction
+        let hover = get_test_hover("	l<cursor>sl r0, r1, #2", &arm_test_config()).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markup hover contents");
+        };
+        assert!(!content.value.contains("shift operator"));
+    }
+
+    #[test]
+    fn get_arm_state_diagnostics_it_warns_on_state_mismatches() {
+        let source = "    cbz r0, 1f @ no state directive yet
+    .arm
+    rscne r0, r1, r2
+    tbb [r0, r1]
+.thumb_func
+loop: cbnz r0, loop // 🚀
+    .thumb
+    cbz r0, 1f
+    swp r0, r1, [r2]
+";
+        let diagnostics = get_arm_state_diagnostics(source);
+        let found: Vec<(u32, u32, u32, &str)> = diagnostics
+            .iter()
+            .map(|diag| {
+                (
+                    diag.range.start.line,
+                    diag.range.start.character,
+                    diag.range.end.character,
+                    diag.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    3,
+                    4,
+                    7,
+                    "`tbb` is only available in Thumb state, but the current state is ARM"
+                ),
+                (
+                    5,
+                    6,
+                    10,
+                    "`cbnz` is only available in Thumb state, but the current state is ARM"
+                ),
+                (
+                    8,
+                    4,
+                    7,
+                    "`swp` is only available in ARM state, but the current state is Thumb"
+                ),
+            ]
+        );
+        assert!(diagnostics
+            .iter()
+            .all(|diag| diag.severity == Some(DiagnosticSeverity::WARNING)));
+    }
+
     /**************************************************************************
      * Misc Tests
      *************************************************************************/
@@ -874,6 +1492,378 @@ bar:
         );
     }
 
+    #[test]
+    fn handle_autocomplete_it_provides_forward_label_comps_before_an_operand_is_typed() {
+        let resp = test_autocomplete(
+            r"
+_start:
+        jmp <cursor>
+        nop
+done:
+        ret
+            ",
+            &x86_x86_64_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        assert!(resp
+            .items
+            .iter()
+            .any(|item| item.label == "done" && item.kind == Some(CompletionItemKind::REFERENCE)));
+    }
+
+    #[test]
+    fn handle_goto_def_it_tracks_edits_to_cached_labels() {
+        let uri = Uri::from_str("file://").unwrap();
+        let source_code = "    jmp .L1\n.L1:\n";
+        let mut text_store = TextDocuments::new();
+        let did_open_params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "asm".to_string(),
+                version: 0,
+                text: source_code.to_string(),
+            },
+        };
+        let params = serde_json::to_value(did_open_params).unwrap();
+        text_store.listen("textDocument/didOpen", &params);
+
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let tree = parser.parse(source_code, None);
+        let mut tree_store = TreeStore::new();
+        tree_store.insert(
+            uri.clone(),
+            TreeEntry {
+                tree,
+                parser,
+                symbols: None,
+            },
+        );
+
+        let goto_def_line = |text_store: &TextDocuments, tree_store: &mut TreeStore, line: u32| {
+            let def_params = GotoDefinitionParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position { line, character: 9 },
+                },
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: None,
+                },
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+            };
+            match get_goto_def_resp(
+                text_store.get_document(&uri).unwrap(),
+                tree_store.get_mut(&uri).unwrap(),
+                &def_params,
+                &Config::default(),
+                &HashMap::new(),
+            ) {
+                Some(GotoDefinitionResponse::Scalar(location)) => location.range.start.line,
+                resp => panic!("Invalid goto definition response: {resp:?}"),
+            }
+        };
+        assert_eq!(goto_def_line(&text_store, &mut tree_store, 0), 1);
+
+        // push everything down a line, keeping the same document version
+        let change_params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: 0,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::default()),
+                range_length: None,
+                text: "\n".to_string(),
+            }],
+        };
+        handle_did_change_text_document_notification(
+            &change_params,
+            &Config::default(),
+            &mut text_store,
+            &mut tree_store,
+            &mut ConfigOverrides::new(),
+            &ConfigOverrides::new(),
+            &ConfigOverrides::new(),
+        )
+        .unwrap();
+        assert_eq!(goto_def_line(&text_store, &mut tree_store, 1), 2);
+    }
+
+    #[test]
+    fn get_goto_def_resp_it_follows_disassembly_branch_targets() {
+        let source = "0000000000001139 <main>:\n    1139:\t55                   \tpush   %rbp\n    113a:\teb 01                \tjmp    113d <main+0x4>\n    113c:\t90                   \tnop\n    113d:\te8 f7 ff ff ff       \tcall   1139 <main>\n";
+        let doc = FullTextDocument::new("asm".to_string(), 0, source.to_string());
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        let mut config = gas_test_config();
+        config.opts.disassembly_mode = Some(true);
+
+        let mut goto_def = |line, character, config: &Config| {
+            let def_params = GotoDefinitionParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: Uri::from_str("file:///tmp/main.dis").unwrap(),
+                    },
+                    position: Position { line, character },
+                },
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: None,
+                },
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+            };
+            match get_goto_def_resp(&doc, &mut tree_entry, &def_params, config, &HashMap::new()) {
+                Some(GotoDefinitionResponse::Scalar(location)) => {
+                    Some((location.range.start.line, location.range.start.character))
+                }
+                _ => None,
+            }
+        };
+
+        // `jmp 113d`
+        assert_eq!(goto_def(2, 40, &config), Some((4, 4)));
+        // `call 1139` goes to the symbol header
+        assert_eq!(goto_def(4, 40, &config), Some((0, 0)));
+        // `main` in `<main+0x4>`
+        assert_eq!(goto_def(2, 46, &config), Some((0, 0)));
+        // encoded bytes aren't addresses
+        assert_eq!(goto_def(2, 11, &config), None);
+        assert_eq!(goto_def(2, 40, &gas_test_config()), None);
+    }
+
+    #[test]
+    fn get_goto_def_resp_arm_it_follows_literal_pool_operands() {
+        let source = "\tldr r0, =my_data\n\tbx lr\nmy_data:\n\t.word 42\n";
+        let doc = FullTextDocument::new("asm".to_string(), 0, source.to_string());
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+
+        let mut goto_def = |character, config: &Config| {
+            let def_params = GotoDefinitionParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: Uri::from_str("file:///tmp/main.s").unwrap(),
+                    },
+                    position: Position { line: 0, character },
+                },
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: None,
+                },
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+            };
+            match get_goto_def_resp(&doc, &mut tree_entry, &def_params, config, &HashMap::new()) {
+                Some(GotoDefinitionResponse::Scalar(location)) => Some(location.range.start.line),
+                _ => None,
+            }
+        };
+
+        // on the symbol, and on the `=`
+        assert_eq!(goto_def(12, &arm_test_config()), Some(2));
+        assert_eq!(goto_def(9, &arm_test_config()), Some(2));
+        assert_eq!(goto_def(9, &empty_test_config()), None);
+    }
+
+    #[test]
+    fn handle_hover_arm_it_explains_literal_pool_operands() {
+        let hover = get_test_hover(
+            "\tldr r0, =my_<cursor>data\n\tbx lr\nmy_data:\n\t.word 42",
+            &arm_test_config(),
+        )
+        .unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markup hover contents");
+        };
+        assert!(content.value.starts_with(
+            "`.word 42`\n\n`=my_data` loads the address of `my_data` through the literal pool"
+        ));
+        // constants aren't symbols
+        assert!(get_test_hover("\tldr r0, =0x12<cursor>34", &arm_test_config()).is_none());
+    }
+
+    #[test]
+    fn handle_hover_x86_64_it_explains_lea_rip_relative_operands() {
+        let hover = |source: &str| {
+            let hover = get_test_hover(source, &x86_x86_64_test_config())?;
+            let HoverContents::Markup(content) = hover.contents else {
+                panic!("Expected markup hover contents");
+            };
+            Some(content.value)
+        };
+        let note = "RIP-relative address: `msg + 4` is assembled as an offset from `rip`";
+
+        let intel = hover("\tlea rax, [rip + m<cursor>sg + 4]\n\tret\nmsg:\n\t.quad 42").unwrap();
+        assert!(intel.starts_with(note));
+        assert!(intel.ends_with("\n\nLabel `msg` is defined on line 3: `.quad 42`"));
+        let att = hover("\tleaq msg + <cursor>4(%rip), %rax\nmsg:").unwrap();
+        assert!(att.starts_with(note));
+        assert!(att.ends_with("\n\nLabel `msg` is defined on line 2"));
+        let external = hover("\tlea rdi, [rel print<cursor>f]").unwrap();
+        assert!(external.ends_with("`printf` isn't defined in this file, the linker resolves it"));
+
+        // the destination and other addressing modes get the usual hover
+        assert!(!hover("\tlea r<cursor>ax, [rip + msg]")
+            .is_some_and(|value| value.starts_with("RIP-relative")));
+        assert!(!hover("\tlea rax, [r<cursor>ip + msg]")
+            .is_some_and(|value| value.starts_with("RIP-relative")));
+        assert!(!hover("\tlea rax, [rb<cursor>x + 8]")
+            .is_some_and(|value| value.starts_with("RIP-relative")));
+        // byte 4 of the operand isn't a char boundary
+        assert!(!hover("\tlea rax, [aéé + m<cursor>sg]")
+            .is_some_and(|value| value.starts_with("RIP-relative")));
+    }
+
+    #[test]
+    fn handle_hover_gas_it_counts_numeric_label_refs() {
+        let source = "
+copy:
+        jmp 1f
+        jmp 1f # not 1b
+<first>1:
+        dec ecx
+        jnz 1b
+        jmp 2f
+2:
+        jmp 1b
+<second>1:
+        jnz 1b
+next:
+        jmp 1b
+";
+        let hover = |cursor: &str, other: &str| {
+            let source = source.replace(cursor, "<cursor>").replace(other, "");
+            let hover = get_test_hover(&source, &x86_x86_64_test_config()).unwrap();
+            let HoverContents::Markup(content) = hover.contents else {
+                panic!("Expected markup hover contents");
+            };
+            content.value
+        };
+        assert_eq!(
+            hover("<first>", "<second>"),
+            "Numeric label `1` in `copy`\n\n + `1f` references: 2\n + `1b` references: 2"
+        );
+        assert_eq!(
+            hover("<second>", "<first>"),
+            "Numeric label `1` in `copy`\n\n + `1f` references: 0\n + `1b` references: 1"
+        );
+    }
+
+    // Comments
+    #[test]
+    fn handle_autocomplete_it_only_provides_tags_in_comments() {
+        let resp = test_autocomplete(
+            "    mov eax, ebx # clobbers e<cursor>",
+            &x86_x86_64_test_config(),
+            CompletionItemKind::TEXT,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["TODO", "FIXME", "NOTE", "HACK"]);
+
+        let mut config = x86_x86_64_test_config();
+        config.opts.comment_tags = Some(vec![String::from("XXX")]);
+        let resp = test_autocomplete(
+            "    /* see %<cursor> */",
+            &config,
+            CompletionItemKind::TEXT,
+            CompletionTriggerKind::TRIGGER_CHARACTER,
+            Some(String::from("%")),
+        );
+        assert_eq!(resp.items.len(), 1);
+        assert_eq!(resp.items[0].label, "XXX");
+    }
+
+    // Constant expressions
+    #[test]
+    fn handle_hover_it_evaluates_constant_exprs() {
+        test_hover(
+            ".equ SIZE, 16\n.set BYTES, SIZE << 2\nCOUNT = 0x10\n\t.quad (BYTES <cursor>- 8) * COUNT, 1",
+            "`(BYTES - 8) * COUNT = 896` (`0x380`)",
+            &gas_test_config(),
+        );
+        test_hover(
+            "\tmovl $4 <cursor>* 8, %eax",
+            "`4 * 8 = 32` (`0x20`)",
+            &gas_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_it_evaluates_exprs_with_command_line_symbols() {
+        let defines = HashMap::from([(
+            SourceFile::All,
+            HashMap::from([(String::from("SIZE"), String::from("16"))]),
+        )]);
+        let Some(Hover {
+            contents: HoverContents::Markup(content),
+            ..
+        }) = get_test_hover_with_defines("\t.quad SIZE <cursor>* 2", &gas_test_config(), &defines)
+        else {
+            panic!("No hover for `SIZE * 2`");
+        };
+        assert_eq!(content.value, "`SIZE * 2 = 32` (`0x20`)");
+    }
+
+    #[test]
+    fn handle_hover_it_ignores_exprs_with_unresolved_symbols() {
+        let config = gas_test_config();
+        // labels aren't constants
+        assert!(get_test_hover("start:\n\t.quad end <cursor>- start\nend:", &config).is_none());
+        assert!(get_test_hover(".set A, B\n.set B, A\n\t.long A <cursor>+ 1", &config).is_none());
+    }
+
+    // Macros
+    #[test]
+    fn handle_autocomplete_gas_it_provides_macro_param_comps() {
+        let resp = test_autocomplete(
+            r"
+.macro sum from=0, to:req
+    .long \from
+    add \t<cursor>
+.endm
+            ",
+            &gas_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["\\from", "\\to"]);
+    }
+
+    #[test]
+    fn handle_autocomplete_gas_it_prioritizes_macro_params_over_regs() {
+        let resp = test_autocomplete(
+            r"
+.macro zero reg
+    xor r<cursor>, \reg
+.endm
+            ",
+            &gas_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        assert_eq!(resp.items[0].label, "\\reg");
+    }
+
     #[test]
     fn handle_hover_gas_it_provides_label_data_1() {
         test_hover(
@@ -905,30 +1895,2779 @@ bar:
         );
     }
 
-    // Demangling
     #[test]
-    fn handle_hover_it_demangles_cpp_1() {
-        test_hover("	call	<cursor>_ZStlsISt11char_traitsIcEERSt13basic_ostreamIcT_ES5_PKc@PLT",
-            "std::basic_ostream<char, std::char_traits<char> >& std::operator<< <std::char_traits<char> >(std::basic_ostream<char, std::char_traits<char> >&, char const*)",
-            &empty_test_config(),
-            );
+    fn handle_references_it_finds_label_on_last_line() {
+        let refs = test_references("    jmp f<cursor>oo\nfoo:", Vec::new());
+        assert_eq!(refs, vec![(0, 8), (1, 0)]);
     }
 
     #[test]
-    fn handle_hover_it_demangles_cpp_2() {
-        test_hover(
-            "	leaq	_ZSt4c<cursor>out(%rip), %rdi",
-            "std::cout",
-            &empty_test_config(),
+    fn handle_references_it_does_not_match_token_prefixes() {
+        let refs = test_references(
+            "    jmp f<cursor>oo\n    jmp foobar\nfoobar:\nfoo:",
+            Vec::new(),
+        );
+        assert_eq!(refs, vec![(0, 8), (3, 0)]);
+    }
+
+    #[test]
+    fn handle_references_it_matches_tokens_within_operands() {
+        let refs = test_references(
+            "foo:\n    movq $f<cursor>oo, %rax\n    lea foo+4(%rip), %rbx",
+            Vec::new(),
+        );
+        assert_eq!(refs, vec![(0, 0), (1, 10), (2, 8)]);
+    }
+
+    #[test]
+    fn handle_references_it_finds_label_on_last_line_after_edit() {
+        // delete the second line, then append a new reference at the very end
+        let refs = test_references(
+            "    jmp f<cursor>oo\n    nop\nfoo:",
+            vec![
+                TextDocumentContentChangeEvent {
+                    range: Some(Range {
+                        start: Position {
+                            line: 1,
+                            character: 0,
+                        },
+                        end: Position {
+                            line: 2,
+                            character: 0,
+                        },
+                    }),
+                    range_length: None,
+                    text: String::new(),
+                },
+                TextDocumentContentChangeEvent {
+                    range: Some(Range {
+                        start: Position {
+                            line: 1,
+                            character: 4,
+                        },
+                        end: Position {
+                            line: 1,
+                            character: 4,
+                        },
+                    }),
+                    range_length: None,
+                    text: "\n    jmp foo".to_string(),
+                },
+            ],
+        );
+        assert_eq!(refs, vec![(0, 8), (1, 0), (2, 8)]);
+    }
+
+    #[test]
+    fn get_rename_resp_it_renames_labels_but_not_to_builtin_names() {
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let names_to_info = state.names_to_info;
+        let uri = Uri::from_str("file://").unwrap();
+        let doc = FullTextDocument::new(
+            String::from("asm"),
+            0,
+            String::from("    jmp foo\nfoo:\n    movq $foo, %rax"),
+        );
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        let pos_params = |line, character| TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position { line, character },
+        };
+        let rename_params = |new_name: &str| RenameParams {
+            text_document_position: pos_params(0, 9),
+            new_name: new_name.to_string(),
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+        };
+
+        assert_eq!(
+            get_prepare_rename_resp(&pos_params(1, 1), &doc, &mut tree_entry),
+            Some(Range {
+                start: Position {
+                    line: 1,
+                    character: 0
+                },
+                end: Position {
+                    line: 1,
+                    character: 3
+                },
+            })
+        );
+        // only labels can be renamed
+        assert!(get_prepare_rename_resp(&pos_params(0, 5), &doc, &mut tree_entry).is_none());
+
+        let edit = get_rename_resp(
+            &rename_params("bar"),
+            &doc,
+            &mut tree_entry,
+            &config,
+            &names_to_info,
+        )
+        .unwrap();
+        let mut starts: Vec<(u32, u32)> = edit.changes.unwrap()[&uri]
+            .iter()
+            .inspect(|edit| assert_eq!(edit.new_text, "bar"))
+            .map(|edit| (edit.range.start.line, edit.range.start.character))
+            .collect();
+        starts.sort_unstable();
+        assert_eq!(starts, vec![(0, 8), (1, 0), (2, 10)]);
+
+        for (new_name, kind) in [
+            ("mov", "instruction"),
+            ("RAX", "register"),
+            (".text", "directive"),
+        ] {
+            let err = get_rename_resp(
+                &rename_params(new_name),
+                &doc,
+                &mut tree_entry,
+                &config,
+                &names_to_info,
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains(kind), "{err}");
+        }
+        assert!(get_rename_resp(
+            &rename_params("1st"),
+            &doc,
+            &mut tree_entry,
+            &config,
+            &names_to_info
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn get_rename_resp_it_scopes_nasm_local_labels_to_their_parent() {
+        let config = nasm_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let names_to_info = state.names_to_info;
+        let uri = Uri::from_str("file://").unwrap();
+        let doc = FullTextDocument::new(
+            String::from("asm"),
+            0,
+            String::from(
+                "foo:\n.loop:\n    jmp .loop\nbar:\n.loop:\n    jmp .loop\n    jmp foo.loop",
+            ),
+        );
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        let rename_params = |line, character, new_name: &str| RenameParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position { line, character },
+            },
+            new_name: new_name.to_string(),
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+        };
+
+        let edit = get_rename_resp(
+            &rename_params(2, 10, ".next"),
+            &doc,
+            &mut tree_entry,
+            &config,
+            &names_to_info,
+        )
+        .unwrap();
+        let mut edits: Vec<(u32, u32, String)> = edit.changes.unwrap()[&uri]
+            .iter()
+            .map(|edit| {
+                (
+                    edit.range.start.line,
+                    edit.range.start.character,
+                    edit.new_text.clone(),
+                )
+            })
+            .collect();
+        edits.sort_unstable();
+        assert_eq!(
+            edits,
+            vec![
+                (1, 0, String::from(".next")),
+                (2, 8, String::from(".next")),
+                (6, 8, String::from("foo.next")),
+            ]
+        );
+
+        // a local label can't become a non-local one
+        assert!(get_rename_resp(
+            &rename_params(2, 10, "next"),
+            &doc,
+            &mut tree_entry,
+            &config,
+            &names_to_info
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn get_prepare_rename_resp_it_rejects_numeric_labels() {
+        let uri = Uri::from_str("file://").unwrap();
+        let doc = FullTextDocument::new(
+            String::from("asm"),
+            0,
+            String::from("1:\n    jmp 1b\n1:\n    jmp 1b"),
+        );
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        for line in [0, 2] {
+            let pos_params = TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position { line, character: 0 },
+            };
+            assert!(get_prepare_rename_resp(&pos_params, &doc, &mut tree_entry).is_none());
+        }
+    }
+
+    #[test]
+    fn get_linked_editing_range_resp_it_links_label_occurrences() {
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let names_to_info = state.names_to_info;
+        let uri = Uri::from_str("file://").unwrap();
+        let doc = FullTextDocument::new(
+            String::from("asm"),
+            0,
+            String::from("    jmp foo\nfoo:\n    movq $foo, %rax\npush:\n    jmp push"),
+        );
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        let mut linked_ranges = |line, character| {
+            let pos_params = TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position { line, character },
+            };
+            get_linked_editing_range_resp(
+                &pos_params,
+                &doc,
+                &mut tree_entry,
+                &config,
+                &names_to_info,
+            )
+            .map(|resp| {
+                resp.ranges
+                    .iter()
+                    .map(|range| (range.start.line, range.start.character, range.end.character))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let expected = Some(vec![(0, 8, 11), (1, 0, 3), (2, 10, 13)]);
+        assert_eq!(linked_ranges(0, 9), expected);
+        assert_eq!(linked_ranges(1, 1), expected);
+        // instructions aren't linked, nor are labels named after one
+        assert_eq!(linked_ranges(0, 5), None);
+        assert_eq!(linked_ranges(4, 9), None);
+    }
+
+    #[test]
+    fn get_linked_editing_range_resp_it_scopes_nasm_local_labels() {
+        let config = nasm_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let names_to_info = state.names_to_info;
+        let uri = Uri::from_str("file://").unwrap();
+        let doc = FullTextDocument::new(
+            String::from("asm"),
+            0,
+            String::from(
+                "foo:\n.loop:\n    jmp .loop\nbar:\n.loop:\n    jmp .loop\n    jmp bar.loop\n1:\n    jmp 1b",
+            ),
+        );
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        let mut linked_ranges = |line, character| {
+            let pos_params = TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position { line, character },
+            };
+            get_linked_editing_range_resp(
+                &pos_params,
+                &doc,
+                &mut tree_entry,
+                &config,
+                &names_to_info,
+            )
+            .map(|resp| {
+                resp.ranges
+                    .iter()
+                    .map(|range| (range.start.line, range.start.character))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(linked_ranges(2, 10), Some(vec![(1, 0), (2, 8)]));
+        // `bar.loop` is spelled differently, so `bar`'s local isn't linked
+        assert_eq!(linked_ranges(5, 10), None);
+        // nor are numeric labels
+        assert_eq!(linked_ranges(7, 0), None);
+    }
+
+    // Structures
+    const NASM_STRUC_SOURCE: &str = "struc point, 4
+    .x:     resd 1
+    .y      resw 2 * 3
+            alignb 8
+    .label: resb NAME_LEN
+    .z:     resq 1
+endstruc
+    mov eax, [ebx + point.y]";
+
+    #[test]
+    fn get_nasm_strucs_it_tracks_member_offsets() {
+        let strucs = get_nasm_strucs(NASM_STRUC_SOURCE);
+        assert_eq!(strucs.len(), 1);
+        assert_eq!(strucs[0].name, "point");
+        let members: Vec<(&str, Option<u64>, Option<u64>)> = strucs[0]
+            .members
+            .iter()
+            .map(|member| (member.name.as_str(), member.offset, member.size))
+            .collect();
+        assert_eq!(
+            members,
+            vec![
+                (".x", Some(4), Some(4)),
+                (".y", Some(8), Some(12)),
+                (".label", Some(24), None),
+                (".z", None, Some(8)),
+            ]
+        );
+        assert_eq!(strucs[0].size, None);
+    }
+
+    #[test]
+    fn get_nasm_strucs_it_uses_utf16_columns() {
+        let strucs = get_nasm_strucs("struc größe\n    .ä: resd 1\n    .b: resd 1\nendstruc");
+        let range = |range: Range| (range.start.character, range.end.character);
+        assert_eq!(range(strucs[0].range), (6, 11));
+        let members: Vec<(u32, u32)> = strucs[0]
+            .members
+            .iter()
+            .map(|member| range(member.range))
+            .collect();
+        assert_eq!(members, vec![(4, 6), (4, 6)]);
+    }
+
+    #[test]
+    fn handle_hover_nasm_it_provides_struc_member_offsets() {
+        let source = NASM_STRUC_SOURCE.replace("point.y", "point.<cursor>y");
+        test_hover(
+            &source,
+            "`point.y`: member of struc `point`\n\nOffset: 8 (0x8)\n\nSize: 12 (0xc)",
+            &nasm_test_config(),
+        );
+        // only NASM has `struc`
+        assert!(get_test_hover(&source, &gas_test_config()).is_none());
+    }
+
+    #[test]
+    fn handle_hover_nasm_it_lists_struc_members() {
+        test_hover(
+            "struc <cursor>pair\n    .a: resb 1\n    .b: resd 1\nendstruc",
+            "struc `pair`\n\nSize: 5 (0x5)\n\n| Member | Offset | Size |\n|---|---|---|\n| `pair.a` | 0 (0x0) | 1 (0x1) |\n| `pair.b` | 1 (0x1) | 4 (0x4) |",
+            &nasm_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_autocomplete_nasm_it_provides_struc_member_comps() {
+        let source = NASM_STRUC_SOURCE.replace("point.y]", "point.<cursor>]");
+        let resp = test_autocomplete(
+            &source,
+            &nasm_test_config(),
+            CompletionItemKind::FIELD,
+            CompletionTriggerKind::TRIGGER_CHARACTER,
+            Some(".".to_string()),
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["x", "y", "label", "z"]);
+    }
+
+    #[test]
+    fn get_goto_def_resp_nasm_it_follows_struc_members() {
+        let doc = FullTextDocument::new("asm".to_string(), 0, NASM_STRUC_SOURCE.to_string());
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+
+        let mut goto_def = |character, config: &Config| {
+            let def_params = GotoDefinitionParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: Uri::from_str("file:///tmp/main.asm").unwrap(),
+                    },
+                    position: Position { line: 7, character },
+                },
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: None,
+                },
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+            };
+            match get_goto_def_resp(&doc, &mut tree_entry, &def_params, config, &HashMap::new()) {
+                Some(GotoDefinitionResponse::Scalar(location)) => {
+                    Some((location.range.start.line, location.range.start.character))
+                }
+                _ => None,
+            }
+        };
+
+        assert_eq!(goto_def(27, &nasm_test_config()), Some((2, 4)));
+        assert_eq!(goto_def(27, &gas_test_config()), None);
+    }
+
+    // Aliases
+    #[test]
+    fn handle_hover_it_provides_alias_docs() {
+        let mut config = gas_test_config();
+        config.opts.aliases = Some(HashMap::from([(
+            "ret_far".to_string(),
+            "Far return through the trampoline table".to_string(),
+        )]));
+        test_hover(
+            "    ret_<cursor>far",
+            "Far return through the trampoline table",
+            &config,
+        );
+    }
+
+    // Empty words
+    #[test]
+    fn handle_hover_it_ignores_whitespace_between_tokens() {
+        let config = x86_x86_64_test_config();
+        assert!(get_test_hover("	mov <cursor> %eax, %ebx", &config).is_none());
+        assert!(get_test_hover("	mov %eax,<cursor> %ebx", &config).is_none());
+        assert!(get_test_hover("<cursor>", &config).is_none());
+    }
+
+    #[test]
+    fn handle_references_it_ignores_whitespace_between_tokens() {
+        let refs = test_references("    jmp <cursor> foo\nfoo:", Vec::new());
+        assert!(refs.is_empty());
+    }
+
+    #[test]
+    fn handle_hover_it_ignores_comments_and_strings() {
+        let config = x86_x86_64_test_config();
+        assert!(get_test_hover("# clobbers e<cursor>ax", &config).is_none());
+        assert!(get_test_hover("/* clobbers e<cursor>ax */", &config).is_none());
+        assert!(get_test_hover("    .ascii \"e<cursor>ax\"", &config).is_none());
+    }
+
+    #[test]
+    fn handle_hover_it_skips_short_words() {
+        let mut config = x86_x86_64_test_config();
+        assert!(get_test_hover("    push %<cursor>ax", &config).is_some());
+        config.opts.min_hover_word_len = Some(3);
+        assert!(get_test_hover("    push %<cursor>ax", &config).is_none());
+        assert!(get_test_hover("    push %e<cursor>ax", &config).is_some());
+    }
+
+    // Debug hover
+    #[test]
+    fn handle_hover_it_appends_debug_info() {
+        let mut config = empty_test_config();
+        config.opts.debug_hover = Some(true);
+        config.opts.aliases = Some(HashMap::from([(
+            "ret_far".to_string(),
+            "Far return through the trampoline table".to_string(),
+        )]));
+        test_hover(
+            "    ret_<cursor>far",
+            "Far return through the trampoline table
+
+---
+
+*tree-sitter*: `word` (parent `instruction`), bytes 4..11",
+            &config,
+        );
+    }
+
+    #[test]
+    fn handle_hover_it_falls_back_to_debug_info() {
+        let mut config = empty_test_config();
+        config.opts.debug_hover = Some(true);
+        test_hover(
+            "    jmp fo<cursor>o",
+            "*tree-sitter*: `word` (parent `reg`), bytes 8..11",
+            &config,
+        );
+    }
+
+    // Document symbols
+    #[test]
+    fn handle_document_symbols_it_sorts_by_position() {
+        let source = r#"
+_start:
+    call helper
+    jmp done
+msg:
+    .string "hello"
+helper:
+    ret
+len:
+    .long 5
+done:
+    nop
+"#;
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let tree = parser.parse(source, None);
+        let mut tree_entry = TreeEntry {
+            tree,
+            parser,
+            symbols: None,
+        };
+        let params = DocumentSymbolParams {
+            text_document: TextDocumentIdentifier {
+                uri: Uri::from_str("file://").unwrap(),
+            },
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: PartialResultParams {
+                partial_result_token: None,
+            },
+        };
+
+        let symbols = get_document_symbols(source, &mut tree_entry, &params).unwrap();
+        fn flatten(symbols: &[DocumentSymbol], out: &mut Vec<(String, Position)>) {
+            for symbol in symbols {
+                out.push((symbol.name.clone(), symbol.range.start));
+                if let Some(ref children) = symbol.children {
+                    flatten(children, out);
+                }
+            }
+        }
+        let mut flattened = Vec::new();
+        flatten(&symbols, &mut flattened);
+
+        let names: Vec<&str> = flattened.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["_start", "msg", "helper", "len", "done"]);
+        assert!(flattened.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+
+    #[test]
+    fn handle_document_symbols_it_nests_repetition_blocks() {
+        let source = r"start:
+    .rept 3
+body:
+    nop
+    .endr
+    .irp reg, rax, rbx # save registers
+inner:
+    push \reg
+    .irpc c, 012
+    .endr
+    .endr
+end:
+    ret
+";
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let tree = parser.parse(source, None);
+        let mut tree_entry = TreeEntry {
+            tree,
+            parser,
+            symbols: None,
+        };
+        let params = DocumentSymbolParams {
+            text_document: TextDocumentIdentifier {
+                uri: Uri::from_str("file://").unwrap(),
+            },
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: PartialResultParams {
+                partial_result_token: None,
+            },
+        };
+
+        let symbols = get_document_symbols(source, &mut tree_entry, &params).unwrap();
+        fn describe(symbols: &[DocumentSymbol]) -> Vec<String> {
+            symbols
+                .iter()
+                .map(|symbol| {
+                    symbol.children.as_ref().map_or_else(
+                        || symbol.name.clone(),
+                        |children| format!("{} [{}]", symbol.name, describe(children).join(", ")),
+                    )
+                })
+                .collect()
+        }
+        assert_eq!(
+            describe(&symbols),
+            vec![
+                "start",
+                ".rept 3 [body]",
+                ".irp reg, rax, rbx [inner, .irpc c, 012]",
+                "end",
+            ]
+        );
+        assert_eq!(symbols[2].range.start.line, 5);
+        assert_eq!(symbols[2].range.end.line, 10);
+    }
+
+    #[test]
+    fn get_document_outline_it_lists_nested_and_flat_symbols() {
+        let source = "start:\n    .rept 3\nbody:\n    nop\n    .endr\nend:\n    ret\n";
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        let mut params = DocumentOutlineParams {
+            text_document: TextDocumentIdentifier {
+                uri: Uri::from_str("file:///tmp/outline.s").unwrap(),
+            },
+            flat: false,
+        };
+
+        let nested = get_document_outline(source, &mut tree_entry, &params);
+        let describe = |symbols: &[OutlineSymbol]| -> Vec<(String, OutlineSymbolKind, usize)> {
+            symbols
+                .iter()
+                .map(|symbol| (symbol.name.clone(), symbol.kind, symbol.children.len()))
+                .collect()
+        };
+        assert_eq!(
+            describe(&nested.symbols),
+            vec![
+                ("start".to_string(), OutlineSymbolKind::Label, 0),
+                (".rept 3".to_string(), OutlineSymbolKind::Block, 1),
+                ("end".to_string(), OutlineSymbolKind::Label, 0),
+            ]
+        );
+        assert_eq!(nested.symbols[1].children[0].name, "body");
+
+        params.flat = true;
+        let flat = get_document_outline(source, &mut tree_entry, &params);
+        let containers: Vec<(&str, Option<&str>)> = flat
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.container.as_deref()))
+            .collect();
+        assert_eq!(
+            containers,
+            vec![
+                ("start", None),
+                (".rept 3", None),
+                ("body", Some(".rept 3")),
+                ("end", None),
+            ]
+        );
+        assert!(flat.symbols.iter().all(|symbol| symbol.children.is_empty()));
+
+        // the schema is plain JSON, with nothing left empty
+        let json = serde_json::to_value(&flat.symbols[2]).unwrap();
+        assert_eq!(json["kind"], "label");
+        assert_eq!(json["container"], ".rept 3");
+        assert_eq!(json["selectionRange"]["start"]["line"], 2);
+        assert!(json.get("children").is_none());
+    }
+
+    #[test]
+    fn handle_document_outline_it_reads_unopened_documents() {
+        let root = unique_temp_dir("outline-test");
+        let outline_of = |name: &str, text: &str| {
+            let path = root.join(name);
+            std::fs::write(&path, text).unwrap();
+            let params = DocumentOutlineParams {
+                text_document: TextDocumentIdentifier {
+                    uri: path_to_uri(&path).unwrap(),
+                },
+                flat: false,
+            };
+
+            let (server, client) = Connection::memory();
+            handle_document_outline_request(
+                &server,
+                RequestId::from(1),
+                &params,
+                &TextDocuments::new(),
+                &mut TreeStore::new(),
+            )
+            .unwrap();
+            let Ok(Message::Response(resp)) = client.receiver.try_recv() else {
+                panic!("Expected a response");
+            };
+            let outline: DocumentOutlineResult =
+                serde_json::from_value(resp.result.unwrap()).unwrap();
+            assert_eq!(outline.uri, params.text_document.uri);
+            outline
+        };
+
+        let outline = outline_of("main.s", "main:\n    ret\n");
+        assert_eq!(outline.symbols.len(), 1);
+        assert_eq!(outline.symbols[0].name, "main");
+        // an empty document still gets an outline
+        assert!(outline_of("empty.s", "").symbols.is_empty());
+    }
+
+    // Semantic tokens
+    #[test]
+    fn get_semantic_tokens_it_marks_register_classes() {
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let source = "mov %rax, %cs\n    jmp loop\nloop:\n    push rbp";
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+
+        let tokens = get_semantic_tokens(
+            source,
+            &mut tree_entry,
+            &config,
+            &state.names_to_info.registers,
+        )
+        .unwrap();
+        let legend = get_semantic_tokens_legend();
+        let modifier_of = |reg_type: RegisterType| {
+            let idx = legend
+                .token_modifiers
+                .iter()
+                .position(|modifier| modifier.as_str() == reg_type.semantic_token_modifier())
+                .unwrap();
+            1 << idx
+        };
+        // `loop` is parsed as a register, but isn't one
+        let tokens: Vec<(u32, u32, u32, u32)> = tokens
+            .data
+            .iter()
+            .map(|token| {
+                (
+                    token.delta_line,
+                    token.delta_start,
+                    token.length,
+                    token.token_modifiers_bitset,
+                )
+            })
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, 5, 3, modifier_of(RegisterType::GeneralPurpose)),
+                (0, 6, 2, modifier_of(RegisterType::Segment)),
+                (3, 9, 3, modifier_of(RegisterType::GeneralPurpose)),
+            ]
+        );
+    }
+
+    // On type formatting
+    fn on_type_formatting_params(line: u32, insert_spaces: bool) -> DocumentOnTypeFormattingParams {
+        DocumentOnTypeFormattingParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Uri::from_str("file://").unwrap(),
+                },
+                position: Position { line, character: 0 },
+            },
+            ch: String::from("\n"),
+            options: FormattingOptions {
+                tab_size: 4,
+                insert_spaces,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn get_on_type_formatting_resp_it_indents_after_labels() {
+        let source = "_start: # entry\n\n";
+        let edits = get_on_type_formatting_resp(
+            source,
+            &on_type_formatting_params(1, true),
+            &gas_test_config(),
+        );
+        assert_eq!(
+            edits,
+            Some(vec![TextEdit {
+                range: Range {
+                    start: Position {
+                        line: 1,
+                        character: 0
+                    },
+                    end: Position {
+                        line: 1,
+                        character: 0
+                    },
+                },
+                new_text: String::from("    "),
+            }])
+        );
+
+        let edits = get_on_type_formatting_resp(
+            source,
+            &on_type_formatting_params(1, false),
+            &gas_test_config(),
+        )
+        .unwrap();
+        assert_eq!(edits[0].new_text, "\t");
+
+        let mut config = gas_test_config();
+        config.opts.indent_unit = Some(String::from("  "));
+        let edits =
+            get_on_type_formatting_resp(source, &on_type_formatting_params(1, true), &config)
+                .unwrap();
+        assert_eq!(edits[0].new_text, "  ");
+    }
+
+    #[test]
+    fn get_on_type_formatting_resp_it_continues_instruction_blocks() {
+        let config = gas_test_config();
+        let source = "_start:\n\tmov $1, %eax\n    \n";
+        let edits =
+            get_on_type_formatting_resp(source, &on_type_formatting_params(2, true), &config);
+        assert_eq!(
+            edits,
+            Some(vec![TextEdit {
+                range: Range {
+                    start: Position {
+                        line: 2,
+                        character: 0
+                    },
+                    end: Position {
+                        line: 2,
+                        character: 4
+                    },
+                },
+                new_text: String::from("\t"),
+            }])
+        );
+
+        // Already indented, and nothing to follow after top level lines
+        let source = "_start:\n    \n";
+        assert_eq!(
+            get_on_type_formatting_resp(source, &on_type_formatting_params(1, true), &config),
+            None
+        );
+        let source = ".globl _start\n\n";
+        assert_eq!(
+            get_on_type_formatting_resp(source, &on_type_formatting_params(1, true), &config),
+            None
+        );
+    }
+
+    // Explain range
+    #[test]
+    fn get_explain_range_resp_it_summarizes_selected_instrs() {
+        let mut config = x86_x86_64_test_config();
+        config.assemblers.go = Some(false);
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let source = "start:\n    pushq %rbp\n    movl $1, %eax\n    frob %eax\n    ret\n";
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        let range = |start_line, end_line| Range {
+            start: Position {
+                line: start_line,
+                character: 0,
+            },
+            end: Position {
+                line: end_line,
+                character: 0,
+            },
+        };
+
+        let explanation = get_explain_range_resp(
+            source,
+            &mut tree_entry,
+            range(1, 4),
+            &config,
+            &state.names_to_info.instructions,
+        )
+        .unwrap();
+        assert_eq!(
+            explanation.value,
+            "1. `pushq %rbp`: Push Value Onto the Stack (64-bit operands)
+2. `movl $1, %eax`: Move (32-bit operands)
+3. `frob %eax`: *Unknown instruction*"
+        );
+
+        // no instructions on a label's line
+        assert!(get_explain_range_resp(
+            source,
+            &mut tree_entry,
+            range(0, 1),
+            &config,
+            &state.names_to_info.instructions,
+        )
+        .is_none());
+    }
+
+    // Instructions writing a register
+    #[test]
+    fn get_register_writers_resp_it_lists_implicit_and_operand_writes() {
+        let mut config = x86_x86_64_test_config();
+        config.assemblers.go = Some(false);
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let writers = |arch: &str, register: &str| {
+            get_register_writers_resp(
+                &InstructionsWritingRegisterParams {
+                    arch: arch.to_string(),
+                    register: register.to_string(),
+                },
+                &config,
+                &state.names_to_info.instructions,
+            )
+        };
+
+        let rdx = writers("x86_64", "%rdx").unwrap();
+        let mul = rdx.iter().find(|writer| writer.name == "mul").unwrap();
+        assert_eq!(mul.note, "Implicitly written by `mulq`");
+        let al = writers("x86-64", "al").unwrap();
+        let adc = al.iter().find(|writer| writer.name == "adc").unwrap();
+        assert_eq!(adc.note, "Written as an operand by `adcb`");
+        assert!(writers("x86_64", "r9").unwrap().is_empty());
+
+        assert!(writers("pdp11", "r0").is_err());
+        assert!(writers("arm", "r0").is_err());
+    }
+
+    // Demangling
+    #[test]
+    fn handle_hover_it_demangles_cpp_1() {
+        test_hover("	call	<cursor>_ZStlsISt11char_traitsIcEERSt13basic_ostreamIcT_ES5_PKc@PLT",
+            "std::basic_ostream<char, std::char_traits<char> >& std::operator<< <std::char_traits<char> >(std::basic_ostream<char, std::char_traits<char> >&, char const*)",
+            &empty_test_config(),
+            );
+    }
+
+    #[test]
+    fn handle_hover_it_demangles_cpp_2() {
+        test_hover(
+            "	leaq	_ZSt4c<cursor>out(%rip), %rdi",
+            "std::cout",
+            &empty_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_it_demangles_cpp_3() {
+        test_hover("	movq	_ZSt4endlIcSt<cursor>11char_traitsIcEERSt13basic_ostreamIT_T0_ES6_@GOTPCREL(%rip), %rax",
+        "std::basic_ostream<char, std::char_traits<char> >& std::endl<char, std::char_traits<char> >(std::basic_ostream<char, std::char_traits<char> >&)",
+            &empty_test_config(),
+            );
+    }
+
+    #[test]
+    fn handle_hover_it_demangles_cpp_name_only() {
+        let mut config = empty_test_config();
+        config.opts.demangle_options = Some(DemangleStyle::NameOnly);
+        test_hover("	movq	_ZSt4endlIcSt<cursor>11char_traitsIcEERSt13basic_ostreamIT_T0_ES6_@GOTPCREL(%rip), %rax",
+            "std::endl<char, std::char_traits<char> >",
+            &config,
+        );
+    }
+
+    // Server state
+    #[test]
+    fn server_state_it_only_loads_enabled_docs() {
+        let config = arm_test_config();
+        let docs = LoadedDocs::load(&config).unwrap();
+        let state = ServerState::new(&docs, &config);
+
+        assert!(state
+            .names_to_info
+            .instructions
+            .keys()
+            .all(|(arch, _)| *arch == Arch::ARM));
+        // the A32/T32 pseudo-instructions are loaded alongside the rest
+        assert!(state
+            .names_to_info
+            .instructions
+            .contains_key(&(Arch::ARM, "push")));
+        assert!(state
+            .instr_completion_items
+            .iter()
+            .any(|item| item.label == "push"));
+        assert!(!state.names_to_info.registers.is_empty());
+        assert!(state.reg_completion_items.len() <= state.names_to_info.registers.len());
+        // no assemblers are enabled
+        assert!(state.names_to_info.directives.is_empty());
+        assert!(state.directive_completion_items.is_empty());
+    }
+
+    // Config
+    #[test]
+    fn apply_empty_config_fallback_it_enables_x86_64_and_gas() {
+        let mut config = empty_test_config();
+        assert!(apply_empty_config_fallback(&mut config));
+        assert_eq!(config.instruction_sets.x86_64, Some(true));
+        assert_eq!(config.assemblers.gas, Some(true));
+        // nothing to do once something's enabled
+        assert!(!apply_empty_config_fallback(&mut config));
+
+        let mut config = empty_test_config();
+        config.opts.empty_config_fallback = Some(false);
+        assert!(!apply_empty_config_fallback(&mut config));
+        assert!(config.is_empty());
+    }
+
+    #[test]
+    fn apply_config_settings_it_merges_client_settings() {
+        let config = x86_x86_64_test_config();
+        let settings = serde_json::json!({
+            "asm-lsp": {
+                "assemblers": { "nasm": true },
+                "opts": { "diagnostics": false, "aliases": { "nop2": "Two byte nop" } }
+            }
+        });
+        let new_config = apply_config_settings(&config, &settings).unwrap();
+        assert_eq!(new_config.opts.diagnostics, Some(false));
+        assert_eq!(
+            new_config.opts.hover_single_arch,
+            config.opts.hover_single_arch
+        );
+        assert!(new_config.opts.aliases.unwrap().contains_key("nop2"));
+        assert_eq!(new_config.assemblers.nasm, Some(true));
+        assert_eq!(new_config.instruction_sets, config.instruction_sets);
+
+        let settings = serde_json::json!({ "opts": { "diagnostics": "yes" } });
+        assert!(apply_config_settings(&config, &settings).is_err());
+        assert!(apply_config_settings(&config, &serde_json::Value::Null).is_err());
+    }
+
+    #[test]
+    fn handle_did_change_configuration_it_keeps_detected_assemblers() {
+        let mut config = x86_x86_64_test_config();
+        let uri = Uri::from_str("file:///tmp/main.asm").unwrap();
+        let mut nasm_config = config.clone();
+        nasm_config.assemblers = Assemblers {
+            nasm: Some(true),
+            ..Assemblers::unset()
+        };
+        let mut compile_cmd_overrides = ConfigOverrides::from([(uri.clone(), nasm_config)]);
+
+        let params = DidChangeConfigurationParams {
+            settings: serde_json::json!({
+                "assemblers": { "gas": true },
+                "opts": { "diagnostics": false }
+            }),
+        };
+        handle_did_change_configuration_notification(
+            &params,
+            &mut config,
+            &TextDocuments::new(),
+            &mut ConfigOverrides::new(),
+            &mut compile_cmd_overrides,
+            &mut ConfigOverrides::new(),
+        );
+        let file_config = &compile_cmd_overrides[&uri];
+        assert_eq!(file_config.opts.diagnostics, Some(false));
+        assert!(file_config.is_assembler_enabled(Assembler::Nasm));
+        assert!(!file_config.is_assembler_enabled(Assembler::Gas));
+
+        // the include search paths are gathered when the documentation is loaded
+        let params = DidChangeConfigurationParams {
+            settings: serde_json::json!({ "opts": { "include_dirs": ["inc"] } }),
+        };
+        assert!(handle_did_change_configuration_notification(
+            &params,
+            &mut config,
+            &TextDocuments::new(),
+            &mut ConfigOverrides::new(),
+            &mut compile_cmd_overrides,
+            &mut ConfigOverrides::new(),
+        ));
+    }
+
+    #[test]
+    fn config_it_expands_arch_and_assembler_shorthand() {
+        let mut config: Config = toml::from_str(
+            r#"
+version = "0.1"
+arch = "x86_64"
+assembler = "gas"
+
+[instruction_sets]
+x86 = true
+
+[opts]
+"#,
+        )
+        .unwrap();
+        config.expand_shorthand().unwrap();
+
+        // explicit entries take precedence
+        assert_eq!(config.instruction_sets.x86, Some(true));
+        assert_eq!(config.instruction_sets.x86_64, Some(true));
+        assert_eq!(config.instruction_sets.arm, Some(false));
+        assert_eq!(config.instruction_sets.z80, Some(false));
+        assert_eq!(config.instruction_sets.mos6502, Some(false));
+        assert_eq!(config.instruction_sets.m68k, Some(false));
+        assert_eq!(config.assemblers.gas, Some(true));
+        assert_eq!(config.assemblers.go, Some(false));
+        assert_eq!(config.assemblers.nasm, Some(false));
+    }
+
+    #[test]
+    fn send_empty_resp_it_respects_suppress_empty_responses() {
+        let sends_resp = |config: &Config| {
+            let (server, client) = Connection::memory();
+            send_empty_resp(&server, RequestId::from(1), config).unwrap();
+            client.receiver.try_recv().is_ok()
+        };
+        let mut config = Config::default();
+        assert!(sends_resp(&config));
+        config.client = Some(LspClient::Helix);
+        assert!(!sends_resp(&config));
+
+        // the opt overrides client detection either way
+        config.opts.suppress_empty_responses = Some(false);
+        assert!(sends_resp(&config));
+        config.client = None;
+        config.opts.suppress_empty_responses = Some(true);
+        assert!(!sends_resp(&config));
+    }
+
+    #[test]
+    fn read_global_config_it_merges_conf_d_fragments() {
+        let root = unique_temp_dir("conf-d-test");
+        std::fs::create_dir_all(root.join("conf.d")).unwrap();
+        std::fs::write(
+            root.join(".asm-lsp.toml"),
+            "version = \"0.1\"\n\n[opts]\ndiagnostics = false\nlinters = [\"base-lint\"]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("conf.d/20-opts.toml"),
+            "[opts]\ndiagnostics = true\nlinters = [\"team-lint\"]\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("conf.d/10-arch.toml"), "arch = \"riscv\"\n").unwrap();
+        std::fs::write(root.join("conf.d/README"), "not a config fragment").unwrap();
+
+        let config = read_global_config(&root).unwrap();
+        assert_eq!(config.instruction_sets.riscv, Some(true));
+        assert_eq!(config.instruction_sets.x86_64, Some(false));
+        assert_eq!(config.opts.diagnostics, Some(true));
+        assert_eq!(
+            config.opts.linters,
+            Some(vec!["base-lint".to_string(), "team-lint".to_string()])
+        );
+    }
+
+    #[test]
+    fn config_it_rejects_unknown_arch_shorthand() {
+        let mut config: Config = toml::from_str(
+            r#"
+version = "0.1"
+arch = "mips"
+
+[opts]
+"#,
+        )
+        .unwrap();
+        let err = config.expand_shorthand().unwrap_err();
+        assert!(err.to_string().contains("Unknown arch \"mips\""));
+    }
+
+    #[test]
+    fn config_it_parses_arch_priority() {
+        let mut config: Config = toml::from_str(
+            r#"
+version = "0.1"
+
+[opts]
+arch_priority = ["x86-64", "ARM"]
+"#,
+        )
+        .unwrap();
+        config.validate_arch_priority().unwrap();
+        assert_eq!(config.get_arch_priority(), vec![Arch::X86_64, Arch::ARM]);
+
+        config.opts.arch_priority = Some(vec!["x86_64".to_string(), "mips".to_string()]);
+        let err = config.validate_arch_priority().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unknown arch_priority arch \"mips\""));
+    }
+
+    #[test]
+    fn config_it_detects_empty_configs() {
+        let mut config: Config = toml::from_str(
+            r#"
+version = "0.1"
+
+[instruction_sets]
+x86_64 = false
+
+[assemblers]
+gas = false
+
+[opts]
+"#,
+        )
+        .unwrap();
+        config.expand_shorthand().unwrap();
+        assert!(config.is_empty());
+
+        config.assemblers.nasm = Some(true);
+        assert!(!config.is_empty());
+        assert!(!Config::default().is_empty());
+    }
+
+    #[test]
+    fn get_backends_resp_it_lists_all_backends() {
+        let mut config: Config = toml::from_str(
+            r#"
+version = "0.1"
+arch = "riscv"
+assembler = "gas"
+
+[opts]
+"#,
+        )
+        .unwrap();
+        config.expand_shorthand().unwrap();
+        let resp = get_backends_resp(&config);
+
+        let enabled = |backends: &[BackendInfo]| -> Vec<String> {
+            backends
+                .iter()
+                .filter(|backend| backend.enabled)
+                .map(|backend| backend.id.clone())
+                .collect()
+        };
+        assert_eq!(resp.instruction_sets.len(), 8);
+        assert_eq!(resp.assemblers.len(), 4);
+        assert_eq!(enabled(&resp.instruction_sets), vec!["riscv"]);
+        assert_eq!(enabled(&resp.assemblers), vec!["gas"]);
+
+        // the response schema is part of the public interface
+        let json = serde_json::to_value(&resp).unwrap();
+        assert_eq!(
+            json["instructionSets"][1],
+            serde_json::json!({ "id": "x86_64", "name": "x86-64", "enabled": false })
+        );
+        assert_eq!(
+            json["assemblers"][0],
+            serde_json::json!({ "id": "gas", "name": "GNU Assembler (GAS)", "enabled": true })
+        );
+    }
+
+    #[test]
+    fn get_modeline_config_it_overrides_arch_and_assembler() {
+        let config = x86_x86_64_test_config();
+        let file_config = get_modeline_config(
+            "\n# asm-lsp: arch=riscv, assembler=gas\n    addi a0, a0, 1\n",
+            &config,
+        )
+        .unwrap();
+        assert!(file_config.is_isa_enabled(Arch::RISCV));
+        assert!(!file_config.is_isa_enabled(Arch::X86));
+        assert!(!file_config.is_isa_enabled(Arch::X86_64));
+        assert!(file_config.is_assembler_enabled(Assembler::Gas));
+        assert!(!file_config.is_assembler_enabled(Assembler::Go));
+
+        // only the given settings are overridden
+        let file_config = get_modeline_config("/* asm-lsp: arch=x86_64 */", &config).unwrap();
+        assert!(file_config.is_isa_enabled(Arch::X86_64));
+        assert!(!file_config.is_isa_enabled(Arch::X86));
+        assert_eq!(file_config.assemblers.gas, config.assemblers.gas);
+        assert_eq!(file_config.assemblers.go, config.assemblers.go);
+
+        let uri = Uri::from_str("file:///tmp/boot.s").unwrap();
+        let overrides = ConfigOverrides::from([(uri.clone(), file_config)]);
+        assert!(!config
+            .get_config(&uri, &overrides)
+            .is_isa_enabled(Arch::X86));
+        let other_uri = Uri::from_str("file:///tmp/main.s").unwrap();
+        assert!(config
+            .get_config(&other_uri, &overrides)
+            .is_isa_enabled(Arch::X86));
+    }
+
+    #[test]
+    fn get_modeline_config_it_disables_features() {
+        let config = x86_x86_64_test_config();
+        let file_config = get_modeline_config("# asm-lsp: diagnostics=off\n", &config).unwrap();
+        assert_eq!(file_config.opts.diagnostics, Some(false));
+        assert!(!file_config.disabled);
+        // the instruction sets and assemblers are left alone
+        assert!(file_config.is_isa_enabled(Arch::X86));
+        assert!(file_config.is_assembler_enabled(Assembler::Go));
+
+        let file_config = get_modeline_config("; asm-lsp: disable\n", &config).unwrap();
+        assert!(file_config.disabled);
+
+        let file_config =
+            get_modeline_config("// asm-lsp: arch=x86_64 diagnostics=off\n", &config).unwrap();
+        assert!(!file_config.is_isa_enabled(Arch::X86));
+        assert_eq!(file_config.opts.diagnostics, Some(false));
+
+        assert!(get_modeline_config("# asm-lsp: diagnostics=maybe", &config).is_none());
+    }
+
+    #[test]
+    fn get_modeline_config_it_rejects_invalid_modelines() {
+        let config = x86_x86_64_test_config();
+        // past the first few lines
+        let late = format!("{}; asm-lsp: arch=riscv\n", "nop\n".repeat(5));
+        assert!(get_modeline_config(&late, &config).is_none());
+        // not in a comment
+        assert!(get_modeline_config("label: .ascii \"asm-lsp: arch=riscv\"", &config).is_none());
+        assert!(get_modeline_config("# asm-lsp: arch=mips", &config).is_none());
+        assert!(get_modeline_config("# asm-lsp: tabwidth=8", &config).is_none());
+    }
+
+    // Closed trees
+    #[test]
+    fn handle_did_close_it_keeps_trees_for_unchanged_reopens() {
+        let mut config = x86_x86_64_test_config();
+        config.opts.keep_closed_trees = Some(1);
+        let mut text_store = TextDocuments::new();
+        let mut tree_store = TreeStore::new();
+        let mut closed_trees = ClosedTrees::default();
+        let mut config_overrides = ConfigOverrides::new();
+        let mut root_configs = ConfigOverrides::new();
+
+        let mut open = |path: &str, text: &str| {
+            let params = DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: Uri::from_str(path).unwrap(),
+                    language_id: "asm".to_string(),
+                    version: 0,
+                    text: text.to_string(),
+                },
+            };
+            handle_did_open_text_document_notification(
+                &params,
+                &config,
+                &mut text_store,
+                &mut tree_store,
+                &mut closed_trees,
+                &mut config_overrides,
+                &ConfigOverrides::new(),
+                &mut root_configs,
+            );
+            let params = DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Uri::from_str(path).unwrap(),
+                },
+            };
+            handle_did_close_text_document_notification(
+                &params,
+                &config,
+                &mut text_store,
+                &mut tree_store,
+                &mut closed_trees,
+                &mut config_overrides,
+                &mut root_configs,
+            );
+            assert!(tree_store.is_empty());
+            closed_trees.len()
+        };
+
+        assert_eq!(open("file:///tmp/a.s", "    nop\n"), 1);
+        // reopened unchanged, the tree is taken and kept again on close
+        assert_eq!(open("file:///tmp/a.s", "    nop\n"), 1);
+        // only the most recently closed tree fits
+        assert_eq!(open("file:///tmp/b.s", "    ret\n"), 1);
+
+        // changed on disk since it was closed
+        let mut closed_trees = ClosedTrees::default();
+        let uri = Uri::from_str("file:///tmp/a.s").unwrap();
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let entry = TreeEntry {
+            tree: parser.parse("    nop\n", None),
+            parser,
+            symbols: None,
+        };
+        closed_trees.insert(uri.clone(), "    nop\n".to_string(), entry, 1);
+        assert!(closed_trees.take(&uri, "    nop\n    ret\n").is_none());
+        assert!(closed_trees.is_empty());
+    }
+
+    // Workspace index
+    #[test]
+    fn workspace_index_it_resolves_labels_across_files() {
+        let root = unique_temp_dir("workspace-index-test");
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        let main_source = "_start:\n    call helper\n    jmp _start\n    ldr r0, =helper\n";
+        std::fs::write(root.join("main.s"), main_source).unwrap();
+        std::fs::write(root.join("lib/helper.inc"), "helper:\n    ret\n").unwrap();
+        // neither hidden or build directories nor other files are indexed
+        std::fs::write(root.join(".git/hidden.s"), "hidden:\n").unwrap();
+        std::fs::write(root.join("build/generated.s"), "generated:\n").unwrap();
+        std::fs::write(root.join("notes.txt"), "helper:\n").unwrap();
+        let root = root.canonicalize().unwrap();
+        let main_uri = path_to_uri(&root.join("main.s")).unwrap();
+        let helper_uri = path_to_uri(&root.join("lib/helper.inc")).unwrap();
+
+        let mut index = WorkspaceIndex::build(&root);
+        assert_eq!(index.len(), 2);
+        assert!(index.get_definitions("hidden", &main_uri).is_empty());
+        assert!(index.get_definitions("generated", &main_uri).is_empty());
+
+        let doc = FullTextDocument::new("asm".to_string(), 0, main_source.to_string());
+        let def_params = |line, character| GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: main_uri.clone(),
+                },
+                position: Position { line, character },
+            },
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: PartialResultParams {
+                partial_result_token: None,
+            },
+        };
+        // including ARM's `ldr r0, =helper`, with the cursor on the `=`
+        for (def_params, config) in [
+            (def_params(1, 11), x86_x86_64_test_config()),
+            (def_params(3, 12), arm_test_config()),
+        ] {
+            let Some(GotoDefinitionResponse::Scalar(location)) =
+                get_workspace_goto_def_resp(&def_params, &doc, &config, &index)
+            else {
+                panic!("Expected a single definition");
+            };
+            assert_eq!(location.uri, helper_uri);
+            assert_eq!(location.range.start.line, 0);
+        }
+
+        // the current document's own references aren't repeated
+        let refs = index.get_references("helper", true, &main_uri);
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].uri, helper_uri);
+        let refs = index.get_references("helper", true, &helper_uri);
+        assert_eq!(refs.len(), 2);
+        assert!(refs.iter().all(|location| location.uri == main_uri));
+
+        let names: Vec<String> = index
+            .get_symbols("")
+            .into_iter()
+            .map(|sym| sym.name)
+            .collect();
+        assert_eq!(names, ["_start", "helper"]);
+
+        // saving a file replaces only that file's entries
+        index.update_file(&helper_uri, "helper2:\n    ret\n");
+        assert!(index.get_definitions("helper", &main_uri).is_empty());
+        let names: Vec<String> = index
+            .get_symbols("HELP")
+            .into_iter()
+            .map(|sym| sym.name)
+            .collect();
+        assert_eq!(names, ["helper2"]);
+        assert_eq!(index.get_definitions("_start", &helper_uri).len(), 1);
+    }
+
+    // URIs
+    #[test]
+    fn uri_to_path_it_resolves_file_uris() {
+        let uri = Uri::from_str("file:///tmp/some%20dir/test.s").unwrap();
+        if cfg!(windows) {
+            return;
+        }
+        assert_eq!(
+            uri_to_path(&uri),
+            Some(PathBuf::from("/tmp/some dir/test.s"))
+        );
+    }
+
+    #[test]
+    fn uri_to_path_it_rejects_non_file_uris() {
+        let untitled = Uri::from_str("untitled:Untitled-1").unwrap();
+        assert_eq!(uri_to_path(&untitled), None);
+        let remote = Uri::from_str("vscode-remote://ssh-remote+host/home/user/test.s").unwrap();
+        assert_eq!(uri_to_path(&remote), None);
+    }
+
+    // Diagnostics
+    #[test]
+    fn get_syntax_diagnostics_it_reports_broken_lines() {
+        let source = "	mov %eax,, %ebx
+	movl (%eax, %ebx
+	mov eax, [ebx
+	mov ) eax
+";
+        let found: Vec<(u32, u32, u32, String)> = get_syntax_diagnostics(source)
+            .into_iter()
+            .map(|diag| {
+                (
+                    diag.range.start.line,
+                    diag.range.start.character,
+                    diag.range.end.character,
+                    diag.message,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, 9, 10, "Syntax error: Unexpected `,`".to_string()),
+                (1, 6, 11, "Syntax error: Missing closing `)`".to_string()),
+                (2, 10, 14, "Syntax error: Missing closing `]`".to_string()),
+                (3, 5, 6, "Syntax error: Unexpected `)`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn get_syntax_diagnostics_it_ignores_valid_syntax() {
+        let source = "	push {r4, lr}
+	mov r0, r1 @ comment, with a comma
+	vaddps zmm1{k1}{z}, zmm2, zmm3
+%macro foo 1
+	dq $ - start
+	MOVQ x+0(FP), AX
+	ex af, af'
+	lda #$10
+	mov eax, [rbx + rcx*4 + 8]
+	ld a, (ix+5)
+msg: db \"hello\", 10, 0
+label: .byte 1, 2, 3
+	stp x29, x30, [sp, #-16]!
+	call *8(%rax,%rbx,4)
+	movl (%rax,%rbx), %eax
+	leal (,%rbx,4), %eax
+";
+        assert_eq!(get_syntax_diagnostics(source), vec![]);
+    }
+
+    #[test]
+    fn dedup_diagnostics_it_sorts_and_removes_duplicates() {
+        let diag = |line: u32, character: u32, msg: &str| {
+            let pos = Position { line, character };
+            Diagnostic::new_simple(
+                Range {
+                    start: pos,
+                    end: pos,
+                },
+                msg.to_string(),
+            )
+        };
+        let mut diagnostics = vec![
+            diag(4, 0, "Error: junk at end of line"),
+            diag(1, 2, "Error: no such instruction"),
+            diag(4, 0, "Error: junk at end of line"),
+            diag(1, 2, "Error: no such instruction"),
+            diag(1, 0, "Error: no such instruction"),
+        ];
+        dedup_diagnostics(&mut diagnostics);
+        assert_eq!(
+            diagnostics,
+            vec![
+                diag(1, 0, "Error: no such instruction"),
+                diag(1, 2, "Error: no such instruction"),
+                diag(4, 0, "Error: junk at end of line"),
+            ]
+        );
+    }
+
+    #[test]
+    fn add_diagnostics_context_it_appends_the_source_line() {
+        let output =
+            "/tmp/test.s:2:5: Error: no such instruction: `foo'\n/tmp/test.s:3: Error: bad\n";
+        let source = "mov eax, 1\n    foo bar\n\n";
+        let mut diagnostics = Vec::new();
+        get_diagnostics(&mut diagnostics, output, Some(source), &gas_test_config());
+        add_diagnostics_context(&mut diagnostics, source);
+        let messages: Vec<&str> = diagnostics
+            .iter()
+            .map(|diag| diag.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Error: no such instruction: `foo'\n2 | foo bar",
+                // nothing to show for a blank line
+                "Error: bad",
+            ]
+        );
+    }
+
+    #[test]
+    fn get_diagnostics_it_spans_the_line_for_line_only_diagnostics() {
+        let output = "/tmp/test.s:2: Error: no such instruction: `foo'\n";
+        let source = "mov eax, 1\nfoo bar\n";
+        let mut diagnostics = Vec::new();
+        get_diagnostics(&mut diagnostics, output, Some(source), &gas_test_config());
+        get_diagnostics(&mut diagnostics, output, None, &gas_test_config());
+        let ranges: Vec<Range> = diagnostics.iter().map(|diag| diag.range).collect();
+        assert_eq!(
+            ranges,
+            vec![
+                Range {
+                    start: Position {
+                        line: 1,
+                        character: 0
+                    },
+                    end: Position {
+                        line: 1,
+                        character: 7
+                    },
+                },
+                Range {
+                    start: Position {
+                        line: 1,
+                        character: 0
+                    },
+                    end: Position {
+                        line: 1,
+                        character: 0
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn get_diagnostics_it_applies_the_severity_map() {
+        let output = "/tmp/test.s:1: Error: bad\n/tmp/test.s:2:3: Warning: odd\n/tmp/test.s:3: note: fyi\n/tmp/test.s:4: something\n";
+        let severities = |config: &Config| {
+            let mut diagnostics = Vec::new();
+            get_diagnostics(&mut diagnostics, output, None, config);
+            diagnostics
+                .iter()
+                .map(|diag| diag.severity)
+                .collect::<Vec<_>>()
+        };
+
+        let mut config = gas_test_config();
+        assert_eq!(
+            severities(&config),
+            vec![
+                Some(DiagnosticSeverity::ERROR),
+                Some(DiagnosticSeverity::WARNING),
+                Some(DiagnosticSeverity::INFORMATION),
+                None,
+            ]
+        );
+
+        config.opts.severity_map = Some(HashMap::from([
+            ("warning".to_string(), "hint".to_string()),
+            ("note".to_string(), "none".to_string()),
+        ]));
+        config.validate_severity_map().unwrap();
+        assert_eq!(
+            severities(&config),
+            vec![
+                Some(DiagnosticSeverity::ERROR),
+                Some(DiagnosticSeverity::HINT),
+                None,
+            ]
+        );
+
+        config.opts.severity_map = Some(HashMap::from([(
+            "warning".to_string(),
+            "quiet".to_string(),
+        )]));
+        let err = config.validate_severity_map().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unknown severity_map level \"quiet\""));
+    }
+
+    #[test]
+    fn apply_linters_it_merges_linter_output() {
+        if cfg!(windows) {
+            return;
+        }
+        let root = unique_temp_dir("linters-test");
+        let source = root.join("main.s");
+        std::fs::write(&source, "mov eax, 1\n\tbad_instr\n").unwrap();
+        let uri = path_to_uri(&source).unwrap();
+
+        let mut config = gas_test_config();
+        config.opts.linters = Some(vec![
+            "asm-lsp-nonexistent-linter".to_string(),
+            // prints `<path>:2:\tbad_instr`
+            "grep -Hn bad_instr".to_string(),
+        ]);
+        let mut diagnostics = Vec::new();
+        apply_linters(&config, &mut diagnostics, &uri);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 1);
+        assert_eq!(diagnostics[0].message, "bad_instr");
+        assert_eq!(diagnostics[0].source.as_deref(), Some("grep"));
+    }
+
+    // Instruction encodings
+    #[test]
+    fn get_listing_encoding_it_collects_continued_bytes() {
+        let listing = "GAS LISTING /tmp/test.s \t\t\tpage 1\n\n\n   1              \t.intel_syntax noprefix\n   2 0000 48B88877 \tmovabs rax, 0x1122334455667788\n   2      66554433 \n   2      2211\n";
+        assert_eq!(
+            get_listing_encoding(listing, 2).as_deref(),
+            Some("48 B8 88 77 66 55 44 33 22 11")
+        );
+        assert_eq!(get_listing_encoding(listing, 1), None);
+    }
+
+    fn get_test_encoding(source: &str, config: &Config) -> Option<String> {
+        let Some(Hover {
+            contents: HoverContents::Markup(content),
+            ..
+        }) = get_test_hover(source, config)
+        else {
+            panic!("No hover for {source}");
+        };
+        let (_, encoding) = content.value.split_once("Encoding: `")?;
+        Some(encoding.split('`').next().unwrap().to_string())
+    }
+
+    #[test]
+    fn handle_hover_it_shows_instruction_encodings() {
+        if !cfg!(target_arch = "x86_64")
+            || std::process::Command::new("gcc")
+                .arg("--version")
+                .output()
+                .is_err()
+        {
+            return;
+        }
+        let mut config = x86_x86_64_test_config();
+        config.opts.show_encoding = Some(true);
+        assert_eq!(
+            get_test_encoding("    mo<cursor>v $1, %eax", &config).as_deref(),
+            Some("B8 01 00 00 00")
+        );
+
+        // NASM sources are in Intel syntax without saying so
+        config.assemblers.gas = Some(false);
+        config.assemblers.nasm = Some(true);
+        assert_eq!(
+            get_test_encoding("    mo<cursor>v eax, 1", &config).as_deref(),
+            Some("B8 01 00 00 00")
+        );
+
+        // `40` is `inc eax` in 32-bit mode, but a REX prefix in 64-bit mode
+        assert_eq!(
+            get_test_encoding("    in<cursor>c eax", &config).as_deref(),
+            Some("FF C0")
+        );
+        config.instruction_sets.x86_64 = Some(false);
+        assert_eq!(
+            get_test_encoding("    in<cursor>c eax", &config).as_deref(),
+            Some("40")
+        );
+
+        // nasm can't produce a listing, so there's nothing to show
+        config.opts.diagnostics_command = Some("nasm -f elf32".to_string());
+        assert_eq!(get_test_encoding("    in<cursor>c eax", &config), None);
+    }
+
+    #[test]
+    fn handle_hover_it_shows_opcode_encodings() {
+        let mut config = x86_x86_64_test_config();
+        config.opts.show_opcodes = Some(true);
+        let Some(Hover {
+            contents: HoverContents::Markup(content),
+            ..
+        }) = get_test_hover("    ad<cursor>d rax, 1", &config)
+        else {
+            panic!("No hover for `add`");
+        };
+        assert!(content.value.contains("**Encodings**"));
+        assert!(content.value.contains("| `add r8, imm8` | `80 /0 ib` |"));
+        assert!(content
+            .value
+            .contains("| `add r64, imm32` | `REX.W 81 /0 id` |"));
+
+        config.opts.show_opcodes = Some(false);
+        let Some(Hover {
+            contents: HoverContents::Markup(content),
+            ..
+        }) = get_test_hover("    ad<cursor>d rax, 1", &config)
+        else {
+            panic!("No hover for `add`");
+        };
+        assert!(!content.value.contains("**Encodings**"));
+    }
+
+    // Syscalls
+    #[test]
+    fn handle_hover_it_shows_syscalls_by_number() {
+        let hover_value = |source: &str, mut config: Config| {
+            config.opts.syscall_hover = Some(true);
+            match get_test_hover(source, &config) {
+                Some(Hover {
+                    contents: HoverContents::Markup(content),
+                    ..
+                }) => Some(content.value),
+                _ => None,
+            }
+        };
+        let value = hover_value(
+            "    mov eax, 6<cursor>0\n    xor edi, edi\n    syscall",
+            x86_x86_64_test_config(),
+        )
+        .unwrap();
+        assert!(value.starts_with("**exit** (x86-64 Linux syscall 60)"));
+        assert!(value.contains("exit(int error_code)"));
+
+        // AT&T syntax, with the 32-bit ABI
+        let value = hover_value(
+            "    movl $0x<cursor>4, %eax\n    int $0x80",
+            x86_x86_64_test_config(),
+        )
+        .unwrap();
+        assert!(value.starts_with("**write** (x86 Linux syscall 4)"));
+
+        let value = hover_value("    li a7, 9<cursor>3\n    ecall", riscv_test_config()).unwrap();
+        assert!(value.starts_with("**exit** (RISC-V Linux syscall 93)"));
+
+        // not followed by a syscall, or loaded into the wrong register
+        assert!(
+            hover_value("    mov eax, 6<cursor>0\n    ret", x86_x86_64_test_config()).is_none()
+        );
+        assert!(hover_value(
+            "    mov ebx, 6<cursor>0\n    syscall",
+            x86_x86_64_test_config()
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn handle_hover_it_explains_interrupt_operands() {
+        let hover_value = |source: &str, config: &Config| match get_test_hover(source, config) {
+            Some(Hover {
+                contents: HoverContents::Markup(content),
+                ..
+            }) => Some(content.value),
+            _ => None,
+        };
+        let mut config = x86_x86_64_test_config();
+        let value = hover_value("    mov eax, 4\n    int 0x<cursor>80", &config).unwrap();
+        assert!(value.starts_with("**int 0x80**\n\nLegacy Linux syscall interrupt"));
+        assert!(!value.contains("Syscall:"));
+        let value = hover_value("    int $0x<cursor>21", &config).unwrap();
+        assert!(value.contains("MS-DOS services"));
+
+        config.opts.syscall_hover = Some(true);
+        let value = hover_value("    movl $4, %eax\n    int $0x<cursor>80", &config).unwrap();
+        assert!(value.ends_with("Syscall: **write** (x86 Linux syscall 4)"));
+
+        let mut config = arm_test_config();
+        config.opts.syscall_hover = Some(true);
+        let value = hover_value("    mov r7, #1\n    svc #<cursor>0", &config).unwrap();
+        assert!(value.starts_with("**svc 0**\n\nSupervisor call"));
+        assert!(value.ends_with("Syscall: **exit** (ARM Linux syscall 1)"));
+
+        // not an interrupt's operand
+        assert!(hover_value("    mov r0, #<cursor>0", &config).is_none());
+    }
+
+    // Instruction examples
+    #[test]
+    fn instr_filter_targets_it_keeps_examples_for_enabled_assemblers() {
+        let example = |assemblers: Vec<Assembler>, code: &str| InstructionExample {
+            assemblers,
+            code: code.to_string(),
+        };
+        let instr = Instruction {
+            name: "push".to_string(),
+            examples: vec![
+                example(vec![Assembler::Gas], "pushq %rbp"),
+                example(vec![Assembler::Nasm, Assembler::Masm], "push rbp"),
+                example(Vec::new(), "push 1"),
+            ],
+            ..Default::default()
+        };
+        let hover = instr.to_string();
+        assert!(hover.contains("## Examples"));
+        assert!(hover.contains("\n + *GAS*: `pushq %rbp`\n + *NASM/MASM*: `push rbp`\n + `push 1`"));
+
+        let mut config = nasm_test_config();
+        let codes = |config: &Config| -> Vec<String> {
+            instr_filter_targets(&instr, config)
+                .examples
+                .into_iter()
+                .map(|example| example.code)
+                .collect()
+        };
+        assert_eq!(codes(&config), vec!["push rbp", "push 1"]);
+        config.assemblers.nasm = Some(false);
+        assert_eq!(codes(&config), vec!["push 1"]);
+    }
+
+    // Required extensions
+    #[test]
+    fn get_required_isas_it_skips_baseline_instructions() {
+        let form = |isa| InstructionForm {
+            isa,
+            ..Default::default()
+        };
+        let mut instr = Instruction {
+            name: "pext".to_string(),
+            forms: vec![form(Some(ISA::BMI2)), form(Some(ISA::BMI2))],
+            ..Default::default()
+        };
+        assert_eq!(instr.get_required_isas(), Some(vec![ISA::BMI2]));
+        assert!(instr.to_string().contains("\nRequires: BMI2\n"));
+
+        instr.forms.push(form(None));
+        assert_eq!(instr.get_required_isas(), None);
+        assert!(!instr.to_string().contains("Requires:"));
+    }
+
+    // Deprecated instructions
+    #[test]
+    fn tag_deprecated_completes_it_tags_obsolete_and_32_bit_only_instructions() {
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let is_tagged = |config: &Config, label: &str| {
+            // the state's items are already tagged for `config`
+            let mut items = state.instr_completion_items.clone();
+            for item in &mut items {
+                item.tags = None;
+            }
+            tag_deprecated_completes(&mut items, &state.names_to_info.instructions, config);
+            items
+                .iter()
+                .find(|item| item.label == label)
+                .expect("Missing completion item")
+                .tags
+                .as_ref()
+                .is_some_and(|tags| tags.contains(&CompletionItemTag::DEPRECATED))
+        };
+
+        assert!(is_tagged(&config, "aaa"));
+        assert!(is_tagged(&config, "pfadd"));
+        assert!(!is_tagged(&config, "add"));
+
+        // 32-bit only instructions are fine without x86-64
+        let mut x86_config = x86_x86_64_test_config();
+        x86_config.instruction_sets.x86_64 = Some(false);
+        assert!(!is_tagged(&x86_config, "aaa"));
+        assert!(is_tagged(&x86_config, "pfadd"));
+    }
+
+    #[test]
+    fn handle_hover_x86_x86_64_it_notes_deprecation() {
+        let hover = get_test_hover("	<cursor>aaa", &x86_x86_64_test_config()).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markup hover contents");
+        };
+        assert!(content
+            .value
+            .starts_with("aaa [x86]\nASCII Adjust After Addition\n**Invalid in 64-bit mode**"));
+
+        let hover = get_test_hover("	<cursor>pfadd %mm1, %mm0", &x86_x86_64_test_config()).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markup hover contents");
+        };
+        assert!(content
+            .value
+            .contains("**Deprecated**: 3DNow! isn't supported"));
+    }
+
+    // Compile commands
+    #[test]
+    fn apply_compile_cmd_it_runs_in_the_command_directory() {
+        if std::process::Command::new("gcc")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let root = unique_temp_dir("compile-dir-test");
+        std::fs::create_dir_all(root.join("inc")).unwrap();
+        std::fs::create_dir_all(root.join("elsewhere")).unwrap();
+        std::fs::write(root.join("inc/defs.inc"), ".set FOO, 1\n").unwrap();
+        let source = root.join("main.s");
+        std::fs::write(&source, ".include \"defs.inc\"\n.long FOO\n").unwrap();
+        let uri = path_to_uri(&source).unwrap();
+
+        let diagnostics_from = |directory: PathBuf| {
+            let cmd = CompileCommand {
+                file: SourceFile::File(source.clone()),
+                directory,
+                arguments: Some(CompileArgs::Flags(vec![
+                    "-c".to_string(),
+                    "-Iinc".to_string(),
+                    "-o".to_string(),
+                    root.join("main.o").display().to_string(),
+                ])),
+                command: None,
+                output: None,
+            };
+            let mut diagnostics = Vec::new();
+            apply_compile_cmd(&gas_test_config(), &mut diagnostics, &uri, &cmd);
+            diagnostics
+        };
+
+        // `-Iinc` only resolves relative to the project's root
+        assert!(diagnostics_from(root.clone()).is_empty());
+        assert!(!diagnostics_from(root.join("elsewhere")).is_empty());
+    }
+
+    #[test]
+    fn apply_compile_cmd_it_falls_back_to_the_first_compiler_that_launches() {
+        if std::process::Command::new("gcc")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let root = unique_temp_dir("compile-fallback-test");
+        let source = root.join("bad.s");
+        std::fs::write(&source, "not_an_instruction\n").unwrap();
+        let uri = path_to_uri(&source).unwrap();
+
+        let diagnostics_from = |program: &str| {
+            let cmd = CompileCommand {
+                file: SourceFile::File(source.clone()),
+                directory: root.clone(),
+                arguments: Some(CompileArgs::Arguments(vec![
+                    program.to_string(),
+                    "-c".to_string(),
+                    "bad.s".to_string(),
+                    "-o".to_string(),
+                    "/dev/null".to_string(),
+                ])),
+                command: None,
+                output: None,
+            };
+            let mut diagnostics = Vec::new();
+            apply_compile_cmd(&gas_test_config(), &mut diagnostics, &uri, &cmd);
+            diagnostics
+        };
+
+        // only one of the fallback compilers is run
+        let expected = diagnostics_from("gcc");
+        assert!(!expected.is_empty());
+        assert_eq!(diagnostics_from("asm-lsp-missing-gcc"), expected);
+        // and only in place of another gcc or clang driver
+        assert!(diagnostics_from("asm-lsp-missing-assembler").is_empty());
+    }
+
+    #[test]
+    fn get_assemble_project_resp_it_reports_each_source_file() {
+        if std::process::Command::new("gcc")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let root = unique_temp_dir("assemble-project-test");
+        std::fs::write(root.join("good.s"), "nop\n").unwrap();
+        std::fs::write(root.join("bad.s"), "not_an_instruction\n").unwrap();
+        std::fs::write(root.join("main.c"), "int main;\n").unwrap();
+        let cmds: Vec<CompileCommand> = ["good.s", "bad.s", "main.c"]
+            .iter()
+            .map(|file| CompileCommand {
+                file: SourceFile::File(PathBuf::from(file)),
+                directory: root.clone(),
+                arguments: Some(CompileArgs::Arguments(vec![
+                    "gcc".to_string(),
+                    "-c".to_string(),
+                    (*file).to_string(),
+                    "-o".to_string(),
+                    "/dev/null".to_string(),
+                ])),
+                command: None,
+                output: None,
+            })
+            .collect();
+
+        let report = get_assemble_project_resp(&gas_test_config(), &cmds);
+        let diagnostics_of =
+            |file: &str| &report.diagnostics[&path_to_uri(&root.join(file)).unwrap()];
+        // only assembly sources are run
+        assert_eq!(report.diagnostics.len(), 2);
+        assert!(diagnostics_of("good.s").is_empty());
+        assert!(!diagnostics_of("bad.s").is_empty());
+
+        let mut config = gas_test_config();
+        config.opts.diagnostics = Some(false);
+        assert!(get_assemble_project_resp(&config, &cmds)
+            .diagnostics
+            .is_empty());
+    }
+
+    #[test]
+    fn get_default_compile_cmd_it_fills_in_diagnostics_command() {
+        let uri = Uri::from_str("file:///tmp/main.s").unwrap();
+        let mut config = gas_test_config();
+        config.opts.diagnostics_command =
+            Some("riscv64-unknown-elf-as {file} -o /dev/null".to_string());
+        assert_eq!(
+            get_default_compile_cmd(&uri, &config).arguments,
+            Some(CompileArgs::Arguments(vec![
+                "riscv64-unknown-elf-as".to_string(),
+                "/tmp/main.s".to_string(),
+                "-o".to_string(),
+                "/dev/null".to_string(),
+            ]))
+        );
+
+        // without a placeholder, the path goes at the end
+        config.opts.diagnostics_command = Some("riscv64-unknown-elf-as -march=rv64gc".to_string());
+        assert_eq!(
+            get_default_compile_cmd(&uri, &config).arguments,
+            Some(CompileArgs::Arguments(vec![
+                "riscv64-unknown-elf-as".to_string(),
+                "-march=rv64gc".to_string(),
+                "/tmp/main.s".to_string(),
+            ]))
+        );
+    }
+
+    #[test]
+    fn get_default_compile_cmd_it_picks_the_assembler_for_the_arch() {
+        let uri = Uri::from_str("file:///tmp/main.s").unwrap();
+        let mut config = riscv_test_config();
+        config.opts.assemblers_by_arch = Some(HashMap::from([
+            ("riscv64".to_string(), "riscv64-linux-gnu-as".to_string()),
+            ("arm".to_string(), "arm-none-eabi-as".to_string()),
+        ]));
+        assert_eq!(
+            get_default_compile_cmd(&uri, &config).arguments,
+            Some(CompileArgs::Arguments(vec![
+                "riscv64-linux-gnu-as".to_string(),
+                "/tmp/main.s".to_string(),
+            ]))
+        );
+
+        // with several enabled arches mapped, `arch_priority` decides
+        config.instruction_sets.arm = Some(true);
+        config.opts.arch_priority = Some(vec!["arm".to_string()]);
+        assert_eq!(
+            get_default_compile_cmd(&uri, &config).arguments,
+            Some(CompileArgs::Arguments(vec![
+                "arm-none-eabi-as".to_string(),
+                "/tmp/main.s".to_string(),
+            ]))
+        );
+
+        // the width can't tell apart keys for the same arch
+        config.opts.assemblers_by_arch = Some(HashMap::from([
+            ("riscv32".to_string(), "riscv32-unknown-elf-as".to_string()),
+            ("riscv64".to_string(), "riscv64-linux-gnu-as".to_string()),
+        ]));
+        let err = config.validate_assemblers_by_arch().unwrap_err();
+        assert!(err.to_string().contains("riscv32"), "{err}");
+        config.opts.assemblers_by_arch = Some(HashMap::from([(
+            "sparc".to_string(),
+            "sparc-as".to_string(),
+        )]));
+        assert!(config.validate_assemblers_by_arch().is_err());
+
+        // no mapping for the file's arch
+        let mut config = x86_x86_64_test_config();
+        config.opts.assemblers_by_arch = Some(HashMap::from([(
+            "riscv64".to_string(),
+            "riscv64-linux-gnu-as".to_string(),
+        )]));
+        assert_eq!(
+            get_default_compile_cmd(&uri, &config).arguments,
+            Some(CompileArgs::Flags(vec!["/tmp/main.s".to_string()]))
+        );
+    }
+
+    #[test]
+    fn get_compile_cmd_assembler_it_maps_common_programs() {
+        let assembler_of = |cmd: &str| {
+            get_compile_cmd_assembler(&CompileCommand {
+                file: SourceFile::All,
+                directory: std::env::temp_dir(),
+                arguments: None,
+                command: Some(cmd.to_string()),
+                output: None,
+            })
+        };
+        assert_eq!(
+            assembler_of("nasm -f elf64 main.asm"),
+            Some(Assembler::Nasm)
+        );
+        assert_eq!(
+            assembler_of("/usr/bin/yasm main.asm"),
+            Some(Assembler::Nasm)
+        );
+        assert_eq!(assembler_of("ML64.EXE /c main.asm"), Some(Assembler::Masm));
+        assert_eq!(
+            assembler_of("arm-none-eabi-gcc -c main.s"),
+            Some(Assembler::Gas)
+        );
+        assert_eq!(assembler_of("clang-17 -c main.S"), Some(Assembler::Gas));
+        assert_eq!(
+            assembler_of("riscv64-unknown-elf-as main.s"),
+            Some(Assembler::Gas)
+        );
+        assert_eq!(assembler_of("go tool asm main.s"), Some(Assembler::Go));
+        assert_eq!(assembler_of("go build ./..."), None);
+        assert_eq!(assembler_of("make main.o"), None);
+    }
+
+    #[test]
+    fn get_compile_cmd_overrides_it_respects_configured_assemblers() {
+        let source = std::env::temp_dir().join("main.asm");
+        let cmds = vec![CompileCommand {
+            file: SourceFile::File(PathBuf::from("main.asm")),
+            directory: std::env::temp_dir(),
+            arguments: Some(CompileArgs::Arguments(vec![
+                "nasm".to_string(),
+                "main.asm".to_string(),
+            ])),
+            command: None,
+            output: None,
+        }];
+
+        let mut config = x86_x86_64_test_config();
+        config.assemblers = Assemblers::unset();
+        let overrides = get_compile_cmd_overrides(&config, &cmds);
+        let file_config = overrides.get(&path_to_uri(&source).unwrap()).unwrap();
+        assert!(file_config.is_assembler_enabled(Assembler::Nasm));
+        assert!(!file_config.is_assembler_enabled(Assembler::Gas));
+        assert_eq!(file_config.instruction_sets.x86_64, Some(true));
+
+        // an explicitly configured assembler always wins
+        assert!(get_compile_cmd_overrides(&nasm_test_config(), &cmds).is_empty());
+        config.assembler = Some("gas".to_string());
+        assert!(get_compile_cmd_overrides(&config, &cmds).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_did_open_it_matches_compile_cmd_overrides_through_symlinks() {
+        let root = unique_temp_dir("symlink-test");
+        let real_dir = root.join("real");
+        let link_dir = root.join("link");
+        std::fs::create_dir_all(&real_dir).unwrap();
+        std::fs::write(real_dir.join("main.asm"), "mov rax, 1\n").unwrap();
+        std::os::unix::fs::symlink(&real_dir, &link_dir).unwrap();
+
+        let cmds = vec![CompileCommand {
+            file: SourceFile::File(PathBuf::from("main.asm")),
+            directory: link_dir.clone(),
+            arguments: Some(CompileArgs::Arguments(vec![
+                "nasm".to_string(),
+                "main.asm".to_string(),
+            ])),
+            command: None,
+            output: None,
+        }];
+        let mut config = x86_x86_64_test_config();
+        config.assemblers = Assemblers::unset();
+        let compile_cmd_overrides = get_compile_cmd_overrides(&config, &cmds);
+
+        // the file is found whether the client opens it through the symlink or not
+        for dir in [&real_dir, &link_dir] {
+            let uri = path_to_uri(&dir.join("main.asm")).unwrap();
+            let mut text_store = TextDocuments::new();
+            let mut tree_store = TreeStore::new();
+            let mut config_overrides = ConfigOverrides::new();
+            let params = DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "asm".to_string(),
+                    version: 0,
+                    text: "mov rax, 1\n".to_string(),
+                },
+            };
+            handle_did_open_text_document_notification(
+                &params,
+                &config,
+                &mut text_store,
+                &mut tree_store,
+                &mut ClosedTrees::default(),
+                &mut config_overrides,
+                &compile_cmd_overrides,
+                &mut ConfigOverrides::new(),
+            );
+            let file_config = config.get_config(&uri, &config_overrides);
+            assert!(file_config.is_assembler_enabled(Assembler::Nasm));
+        }
+    }
+
+    #[test]
+    fn get_root_fallback_config_it_uses_the_nearest_project_config() {
+        let root = unique_temp_dir("root-fallback-test");
+        let src_dir = root.join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(
+            root.join(".asm-lsp.toml"),
+            "version = \"0.1\"\narch = \"riscv\"\n\n[opts]\ndiagnostics_context = true\n",
+        )
+        .unwrap();
+        std::fs::write(src_dir.join("main.s"), "nop\n").unwrap();
+        let uri = path_to_uri(&src_dir.join("main.s")).unwrap();
+
+        let mut config = x86_x86_64_test_config();
+        config.project_root = None;
+        let file_config = get_root_fallback_config(&uri, &config).unwrap();
+        assert_eq!(file_config.project_root, Some(root.canonicalize().unwrap()));
+        assert!(file_config.is_isa_enabled(Arch::RISCV));
+        assert_eq!(file_config.opts.diagnostics_context, Some(true));
+        assert_eq!(file_config.opts.diagnostics, Some(true));
+
+        // a root reported by the client takes precedence
+        config.project_root = Some(root);
+        assert!(get_root_fallback_config(&uri, &config).is_none());
+    }
+
+    #[test]
+    fn handle_did_change_it_keeps_the_root_fallback_config_from_did_open() {
+        let root = unique_temp_dir("root-fallback-change-test");
+        std::fs::write(
+            root.join(".asm-lsp.toml"),
+            "version = \"0.1\"\narch = \"riscv\"\n\n[opts]\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("main.s"), "nop\n").unwrap();
+        let uri = path_to_uri(&root.join("main.s")).unwrap();
+
+        let mut config = x86_x86_64_test_config();
+        config.project_root = None;
+        let mut text_store = TextDocuments::new();
+        let mut tree_store = TreeStore::new();
+        let mut config_overrides = ConfigOverrides::new();
+        let mut root_configs = ConfigOverrides::new();
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "asm".to_string(),
+                version: 0,
+                text: "nop\n".to_string(),
+            },
+        };
+        handle_did_open_text_document_notification(
+            &params,
+            &config,
+            &mut text_store,
+            &mut tree_store,
+            &mut ClosedTrees::default(),
+            &mut config_overrides,
+            &ConfigOverrides::new(),
+            &mut root_configs,
+        );
+        assert!(config
+            .get_config(&uri, &config_overrides)
+            .is_isa_enabled(Arch::RISCV));
+
+        // the root isn't looked for again on each change
+        std::fs::remove_file(root.join(".asm-lsp.toml")).unwrap();
+        let change_params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: 1,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "nop\nnop\n".to_string(),
+            }],
+        };
+        handle_did_change_text_document_notification(
+            &change_params,
+            &config,
+            &mut text_store,
+            &mut tree_store,
+            &mut config_overrides,
+            &ConfigOverrides::new(),
+            &root_configs,
+        )
+        .unwrap();
+        assert!(config
+            .get_config(&uri, &config_overrides)
+            .is_isa_enabled(Arch::RISCV));
+    }
+
+    #[test]
+    fn read_config_file_it_replaces_the_config_search() {
+        let root = unique_temp_dir("explicit-config-test");
+        let src_dir = root.join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(
+            root.join(".asm-lsp.toml"),
+            "version = \"0.1\"\narch = \"arm\"\n",
+        )
+        .unwrap();
+        std::fs::write(src_dir.join("main.s"), "nop\n").unwrap();
+        let path = root.join("ci.toml");
+        std::fs::write(
+            &path,
+            "version = \"0.1\"\narch = \"riscv\"\nassembler = \"gas\"\n\n[opts]\n",
+        )
+        .unwrap();
+
+        let explicit_config = read_config_file(&path).unwrap();
+        assert_eq!(explicit_config.config_path.as_deref(), Some(path.as_path()));
+        let params = InitializeParams {
+            workspace_folders: Some(vec![WorkspaceFolder {
+                uri: path_to_uri(&root).unwrap(),
+                name: String::from("root"),
+            }]),
+            ..Default::default()
+        };
+        let (config, _) = get_config(&params, &Vec::new(), Some(explicit_config));
+        assert!(config.is_isa_enabled(Arch::RISCV));
+        assert!(!config.is_isa_enabled(Arch::ARM));
+        assert_eq!(config.project_root, Some(root.canonicalize().unwrap()));
+
+        // nor is the project config picked up for files opened without a root
+        let mut rootless_config = config;
+        rootless_config.project_root = None;
+        let uri = path_to_uri(&src_dir.join("main.s")).unwrap();
+        let file_config = get_root_fallback_config(&uri, &rootless_config).unwrap();
+        assert!(file_config.is_isa_enabled(Arch::RISCV));
+        assert!(!file_config.is_isa_enabled(Arch::ARM));
+
+        let err = read_config_file(&root.join("missing.toml")).unwrap_err();
+        assert!(err.to_string().contains("missing.toml"), "{err}");
+        std::fs::write(&path, "version = \"0.1\"\narch = \"pdp11\"\n\n[opts]\n").unwrap();
+        let err = read_config_file(&path).unwrap_err();
+        assert!(err.to_string().contains("ci.toml"), "{err}");
+    }
+
+    #[test]
+    fn get_defines_it_collects_command_line_symbols() {
+        let cmds = vec![CompileCommand {
+            file: SourceFile::All,
+            directory: std::env::temp_dir(),
+            arguments: Some(CompileArgs::Flags(vec![
+                "-DDEBUG".to_string(),
+                "-D".to_string(),
+                "STACK_SIZE=4096".to_string(),
+                "--defsym".to_string(),
+                "BASE=0x1000".to_string(),
+                "-Wa,--defsym,USE_SSE=1,-I,inc".to_string(),
+                "-Iinclude".to_string(),
+            ])),
+            command: None,
+            output: None,
+        }];
+
+        let defines = get_defines(&cmds);
+        let all = defines.get(&SourceFile::All).unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all.get("DEBUG").map(String::as_str), Some("1"));
+        assert_eq!(all.get("STACK_SIZE").map(String::as_str), Some("4096"));
+        assert_eq!(all.get("BASE").map(String::as_str), Some("0x1000"));
+        assert_eq!(all.get("USE_SSE").map(String::as_str), Some("1"));
+    }
+
+    // Include directories
+    #[test]
+    fn get_include_dirs_it_handles_system_and_sysroot_flags() {
+        let root = unique_temp_dir("include-dirs-test");
+        let sysroot = root.join("sysroot");
+        std::fs::create_dir_all(sysroot.join("usr/include")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let cmds = vec![CompileCommand {
+            file: SourceFile::All,
+            directory: root.clone(),
+            arguments: Some(CompileArgs::Flags(vec![
+                "-isystem".to_string(),
+                "/opt/toolchain/include".to_string(),
+                "-iquote/opt/project/include".to_string(),
+                format!("--sysroot={}", root.join("sysroot").display()),
+            ])),
+            command: None,
+            output: None,
+        }];
+
+        let include_dirs = get_include_dirs(&cmds);
+        let dirs = include_dirs.get(&SourceFile::All).unwrap();
+        for expected in [
+            PathBuf::from("/opt/toolchain/include"),
+            PathBuf::from("/opt/project/include"),
+            root.join("sysroot/usr/include"),
+        ] {
+            assert!(dirs.contains(&expected), "Missing {}", expected.display());
+        }
+        // only existing directories under the sysroot should be added
+        assert!(!dirs.contains(&root.join("sysroot/usr/local/include")));
+    }
+
+    #[test]
+    fn get_include_dirs_it_probes_with_dialect_flags() {
+        if cfg!(windows) {
+            return;
+        }
+        let root = unique_temp_dir("dialect-include-dirs-test");
+        let root = root.canonicalize().unwrap();
+        let source = root.join("boot.S");
+        std::fs::write(&source, "").unwrap();
+        let cmds = vec![
+            CompileCommand {
+                file: SourceFile::All,
+                directory: root.clone(),
+                arguments: Some(CompileArgs::Flags(vec![
+                    "-x".to_string(),
+                    "assembler-with-cpp".to_string(),
+                ])),
+                command: None,
+                output: None,
+            },
+            CompileCommand {
+                file: SourceFile::File(source.clone()),
+                directory: root,
+                arguments: None,
+                command: Some("g++ -xc++ -std=gnu++17 -c boot.S".to_string()),
+                output: None,
+            },
+        ];
+
+        let include_dirs = get_include_dirs(&cmds);
+        let is_cpp_dir = |dir: &PathBuf| dir.components().any(|comp| comp.as_os_str() == "c++");
+        let global_dirs = include_dirs.get(&SourceFile::All).unwrap();
+        // probing as assembly shouldn't pick up the C++ headers
+        assert!(!global_dirs.iter().any(is_cpp_dir));
+        let file_dirs = include_dirs
+            .get(&SourceFile::File(source))
+            .cloned()
+            .unwrap_or_default();
+        assert!(file_dirs.iter().all(|dir| !global_dirs.contains(dir)));
+        if !global_dirs.is_empty() && PathBuf::from("/usr/include/c++").is_dir() {
+            assert!(file_dirs.iter().any(is_cpp_dir));
+        }
+    }
+
+    #[test]
+    fn get_config_include_dirs_it_resolves_against_the_project_root() {
+        let root = unique_temp_dir("config-include-dirs-test");
+        std::fs::create_dir_all(root.join("inc")).unwrap();
+        std::fs::create_dir_all(root.join("abs")).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let mut config = gas_test_config();
+        config.project_root = Some(root.clone());
+        config.opts.include_dirs = Some(vec![
+            PathBuf::from("inc"),
+            root.join("abs"),
+            PathBuf::from("missing"),
+        ]);
+
+        // missing directories are dropped
+        assert_eq!(
+            get_config_include_dirs(&config),
+            vec![root.join("inc"), root.join("abs")]
+        );
+    }
+
+    #[test]
+    fn incbin_it_resolves_binary_includes() {
+        if cfg!(windows) {
+            return;
+        }
+        let root = unique_temp_dir("incbin-test");
+        std::fs::create_dir_all(root.join("blobs")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let blob = root.join("blobs/font-8x8.bin");
+        std::fs::write(&blob, [0u8; 16]).unwrap();
+        let source = "font:\n\t.incbin \"font-8x8.bin\"\n";
+        let uri = path_to_uri(&root.join("main.s")).unwrap();
+        let include_dirs = HashMap::from([(SourceFile::All, vec![root.join("blobs")])]);
+
+        let mut text_store = TextDocuments::new();
+        let did_open_params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "asm".to_string(),
+                version: 0,
+                text: source.to_string(),
+            },
+        };
+        text_store.listen(
+            "textDocument/didOpen",
+            &serde_json::to_value(did_open_params).unwrap(),
+        );
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let tree = parser.parse(source, None);
+        let mut tree_store = TreeStore::new();
+        tree_store.insert(
+            uri.clone(),
+            TreeEntry {
+                tree,
+                parser,
+                symbols: None,
+            },
+        );
+
+        // cursor on the `8x8` in the filename
+        let pos_params = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position {
+                line: 1,
+                character: 16,
+            },
+        };
+        let hover_params = HoverParams {
+            text_document_position_params: pos_params.clone(),
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+        };
+        let doc = text_store.get_document(&uri).unwrap();
+        let (word, cursor_offset) = get_word_from_pos_params(doc, &pos_params);
+        let hover = get_hover_resp(
+            &hover_params,
+            &gas_test_config(),
+            word,
+            cursor_offset,
+            &text_store,
+            &mut tree_store,
+            &NameToInstructionMap::new(),
+            &NameToRegisterMap::new(),
+            &NameToDirectiveMap::new(),
+            &include_dirs,
+            &HashMap::new(),
+            &Vec::new(),
+        )
+        .unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Invalid hover response contents: {:?}", hover.contents);
+        };
+        assert_eq!(
+            content.value,
+            format!("file://{} (16 bytes)\n", blob.display())
         );
+
+        let def_params = GotoDefinitionParams {
+            text_document_position_params: pos_params,
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: PartialResultParams {
+                partial_result_token: None,
+            },
+        };
+        let Some(GotoDefinitionResponse::Array(locations)) = get_goto_def_resp(
+            doc,
+            tree_store.get_mut(&uri).unwrap(),
+            &def_params,
+            &gas_test_config(),
+            &include_dirs,
+        ) else {
+            panic!("Expected a definition location");
+        };
+        assert_eq!(locations.len(), 1);
+        assert_eq!(uri_to_path(&locations[0].uri), Some(blob));
     }
 
     #[test]
-    fn handle_hover_it_demangles_cpp_3() {
-        test_hover("	movq	_ZSt4endlIcSt<cursor>11char_traitsIcEERSt13basic_ostreamIT_T0_ES6_@GOTPCREL(%rip), %rax",
-        "std::basic_ostream<char, std::char_traits<char> >& std::endl<char, std::char_traits<char> >(std::basic_ostream<char, std::char_traits<char> >&)",
-            &empty_test_config(),
-            );
+    fn get_goto_def_resp_it_resolves_sibling_includes() {
+        if cfg!(windows) {
+            return;
+        }
+        let root = unique_temp_dir("sibling-include-test");
+        std::fs::create_dir_all(root.join("include")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let sibling = root.join("defs.inc");
+        std::fs::write(&sibling, ".equ FOO, 1\n").unwrap();
+        std::fs::write(root.join("include/defs.inc"), ".equ FOO, 2\n").unwrap();
+        let uri = path_to_uri(&root.join("main.S")).unwrap();
+        let include_dirs = HashMap::from([(SourceFile::All, vec![root.join("include")])]);
+
+        let goto_def = |source: &str| {
+            let doc = FullTextDocument::new("asm".to_string(), 0, source.to_string());
+            let mut parser = Parser::new();
+            parser.set_language(&asm_language()).unwrap();
+            let mut tree_entry = TreeEntry {
+                tree: None,
+                parser,
+                symbols: None,
+            };
+            let def_params = GotoDefinitionParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position {
+                        line: 0,
+                        character: 12,
+                    },
+                },
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: None,
+                },
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+            };
+            match get_goto_def_resp(
+                &doc,
+                &mut tree_entry,
+                &def_params,
+                &gas_test_config(),
+                &include_dirs,
+            ) {
+                Some(GotoDefinitionResponse::Array(locations)) => locations
+                    .iter()
+                    .filter_map(|location| uri_to_path(&location.uri))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+
+        // quoted names are looked for next to the including file first
+        assert_eq!(
+            goto_def(".include \"defs.inc\"\n"),
+            vec![sibling.clone(), root.join("include/defs.inc")]
+        );
+        assert_eq!(
+            goto_def("#include \"defs.inc\"\n"),
+            vec![sibling, root.join("include/defs.inc")]
+        );
+        assert_eq!(
+            goto_def("#include <defs.inc>\n"),
+            vec![root.join("include/defs.inc")]
+        );
     }
 
     /**************************************************************************
@@ -963,6 +4702,49 @@ bar:
         );
     }
     #[test]
+    fn handle_autocomplete_x86_x86_64_it_filters_reg_comps_by_mode() {
+        let labels = |source: &str| {
+            let resp = test_autocomplete(
+                source,
+                &x86_x86_64_test_config(),
+                CompletionItemKind::VARIABLE,
+                CompletionTriggerKind::INVOKED,
+                None,
+            );
+            resp.items
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<String>>()
+        };
+        let comps = labels("    pushq %<cursor>");
+        assert!(comps.contains(&"rax".to_string()));
+        assert!(comps.contains(&"r8d".to_string()));
+
+        for source in [
+            ".code32\n    pushl %<cursor>",
+            "bits 16\n    push <cursor>",
+            "[BITS 32]\n    push <cursor>",
+        ] {
+            let comps = labels(source);
+            assert!(comps.contains(&"eax".to_string()));
+            assert!(!comps.contains(&"rax".to_string()));
+            assert!(!comps.contains(&"r8d".to_string()));
+            assert!(!comps.contains(&"xmm8".to_string()));
+        }
+
+        let comps = labels(".code32\n.code64\n    pushq %<cursor>");
+        assert!(comps.contains(&"rax".to_string()));
+    }
+    #[test]
+    fn handle_autocomplete_x86_x86_64_it_provides_reg_comps_after_multibyte_label() {
+        test_register_autocomplete(
+            "été_😀:	movq	%rs<cursor>, %rbp",
+            &x86_x86_64_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+    #[test]
     fn handle_autocomplete_x86_x86_64_it_provides_reg_comps_in_existing_reg_arg_2() {
         test_register_autocomplete(
             "	movq	%rs<cursor>, %rbp",
@@ -999,12 +4781,57 @@ bar:
         );
     }
 
+    // the tree-sitter parse of these lines doesn't contain the cursor
+    #[test]
+    fn handle_autocomplete_x86_x86_64_it_provides_instr_comps_on_empty_line() {
+        test_instruction_autocomplete(
+            "	mov	eax, 1\n	<cursor>",
+            &x86_x86_64_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+    #[test]
+    fn handle_autocomplete_x86_x86_64_it_provides_reg_comps_before_first_arg() {
+        test_register_autocomplete(
+            "	mov	<cursor>",
+            &x86_x86_64_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+    #[test]
+    fn handle_autocomplete_x86_x86_64_it_provides_reg_comps_in_broken_arg() {
+        test_register_autocomplete(
+            "	mov	eax, (<cursor>",
+            &x86_x86_64_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+    #[test]
+    fn handle_autocomplete_x86_x86_64_it_ignores_gas_comments() {
+        let comps = get_test_comps(
+            "	mov	eax, ( # <cursor>",
+            &x86_x86_64_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        )
+        .unwrap();
+        // only the comment tags are offered
+        assert!(comps
+            .items
+            .iter()
+            .all(|comp| comp.kind == Some(CompletionItemKind::TEXT)));
+    }
+
     #[test]
     fn handle_hover_x86_x86_64_it_provides_instr_info_no_args() {
         test_hover(
             "<cursor>MOVLPS",
             "movlps [x86]
 Move Low Packed Single-Precision Floating-Point Values
+Requires: SSE
 
 ## Forms
 
@@ -1019,6 +4846,7 @@ Move Low Packed Single-Precision Floating-Point Values
 
 movlps [x86-64]
 Move Low Packed Single-Precision Floating-Point Values
+Requires: SSE
 
 ## Forms
 
@@ -1061,6 +4889,9 @@ Push Value Onto the Stack
 - *GAS*: pushl | *GO*: PUSHL
 
   + [m32]    input = true   output = false
+## Examples
+
+ + *GAS*: `pushl %ebp`
 
 push [x86-64]
 Push Value Onto the Stack
@@ -1084,17 +4915,148 @@ Push Value Onto the Stack
   + [m16]    input = true   output = false
 - *GAS*: pushq | *GO*: PUSHQ
 
-  + [m64]    input = true   output = false",
+  + [m64]    input = true   output = false
+## Examples
+
+ + *GAS*: `pushq %rbp`
+
+Size suffix: `q` (64-bit operands)",
             &x86_x86_64_test_config(),
         ); // More info: https://www.felixcloutier.com/x86/push
     }
 
+    #[test]
+    fn get_gas_size_suffix_it_only_strips_size_suffixes() {
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let instrs = &state.names_to_info.instructions;
+
+        assert_eq!(
+            get_gas_size_suffix("movl", &config, instrs),
+            Some(("mov", "32"))
+        );
+        assert_eq!(
+            get_gas_size_suffix("ADDB", &config, instrs),
+            Some(("ADD", "8"))
+        );
+        // suffix is part of the real name
+        assert_eq!(get_gas_size_suffix("movq", &config, instrs), None);
+        assert_eq!(get_gas_size_suffix("movsb", &config, instrs), None);
+        assert_eq!(get_gas_size_suffix("shl", &config, instrs), None);
+        // x87 suffixes aren't operand sizes
+        assert_eq!(get_gas_size_suffix("fldl", &config, instrs), None);
+    }
+
+    #[test]
+    fn get_gas_suffix_completes_it_provides_sized_variants() {
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let instrs = &state.names_to_info.instructions;
+
+        let items = get_gas_suffix_completes(instrs, &config);
+        let detail = |label: &str| {
+            items
+                .iter()
+                .find(|item| item.label == label)
+                .and_then(|item| item.detail.clone())
+        };
+        assert_eq!(detail("movb").as_deref(), Some("mov with 8-bit operands"));
+        assert_eq!(detail("movl").as_deref(), Some("mov with 32-bit operands"));
+        assert_eq!(detail("addq").as_deref(), Some("add with 64-bit operands"));
+        // `movq` is its own instruction
+        assert_eq!(detail("movq"), None);
+
+        assert!(get_gas_suffix_completes(instrs, &nasm_test_config()).is_empty());
+    }
+
+    #[test]
+    fn tag_64bit_only_completes_it_keeps_the_arch_data() {
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let data = |label: &str| {
+            let item = state
+                .reg_completion_items
+                .iter()
+                .find(|item| item.label == label)
+                .unwrap();
+            serde_json::from_value::<CompletionData>(item.data.clone().unwrap()).unwrap()
+        };
+
+        let r8 = data("r8");
+        assert!(r8.only_64bit);
+        assert!(r8.arch.is_some());
+        let eax = data("eax");
+        assert!(!eax.only_64bit);
+        assert!(eax.arch.is_some());
+    }
+
+    #[test]
+    fn add_condition_code_completes_it_provides_conditional_variants() {
+        let description = |items: &[CompletionItem], label: &str| {
+            let item = items.iter().find(|item| item.label == label)?;
+            item.label_details.as_ref()?.description.clone()
+        };
+
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).unwrap();
+        let state = ServerState::new(&docs, &config);
+        let items = &state.instr_completion_items;
+        assert_eq!(
+            description(items, "jne").as_deref(),
+            Some("Not equal (ZF=0)")
+        );
+        assert_eq!(
+            description(items, "cmovge").as_deref(),
+            Some("Signed greater or equal (SF=OF)")
+        );
+        assert_eq!(description(items, "setc").as_deref(), Some("Carry (CF=1)"));
+        assert_eq!(description(items, "jmp"), None);
+
+        // z80's `jp` is an unconditional jump, not x86's "jump if parity"
+        let mut config = z80_test_config();
+        config.instruction_sets.x86_64 = Some(true);
+        config.assemblers.gas = Some(true);
+        let docs = LoadedDocs::load(&config).unwrap();
+        let state = ServerState::new(&docs, &config);
+        let items = &state.instr_completion_items;
+        let jp_descriptions: Vec<Option<String>> = items
+            .iter()
+            .filter(|item| item.label == "jp")
+            .map(|item| item.label_details.as_ref()?.description.clone())
+            .collect();
+        assert!(jp_descriptions.contains(&None));
+        assert!(jp_descriptions.contains(&Some(String::from("Parity (PF=1)"))));
+
+        let config = arm_test_config();
+        let docs = LoadedDocs::load(&config).unwrap();
+        let state = ServerState::new(&docs, &config);
+        let items = &state.instr_completion_items;
+        assert_eq!(
+            description(items, "moveq").as_deref(),
+            Some("Equal (Z set)")
+        );
+        assert_eq!(description(items, "bleq").as_deref(), Some("Equal (Z set)"));
+        assert_eq!(
+            description(items, "blt").as_deref(),
+            Some("Signed less (N != V)")
+        );
+        // only mnemonics known to take a condition are expanded
+        assert!(!items.iter().any(|item| item.label == "movkeq"));
+        // SVE's `cmpeq` is documented on its own
+        assert_eq!(items.iter().filter(|item| item.label == "cmpeq").count(), 1);
+        assert_eq!(description(items, "cmpeq"), None);
+    }
+
     #[test]
     fn handle_hover_x86_x86_64_it_provides_instr_info_two_reg_args() {
         test_hover(
             "	m<cursor>ovq	%rsp, %rbp",
             "movq [x86]
 Move Quadword
+Requires: MMX or SSE2
 
 ## Forms
 
@@ -1125,6 +5087,7 @@ Move Quadword
 
 movq [x86-64]
 Move Quadword
+Requires: MMX or SSE2
 
 ## Forms
 
@@ -1181,16 +5144,182 @@ Stack Base Pointer
 
 Type: General Purpose Register
 Width: 64 bits
+Only valid in 64-bit mode
+
+RBP [x86-64]
+Base Pointer (meant for stack frames)
+
+Type: General Purpose Register
+Width: 64 bits
+Only valid in 64-bit mode",
+            &x86_x86_64_test_config(),
+        );
+    }
+    #[test]
+    fn handle_hover_x86_x86_64_it_provides_single_arch_reg_info() {
+        let mut config = x86_x86_64_test_config();
+        config.opts.hover_single_arch = Some(true);
+        test_hover(
+            "	pushq	%r<cursor>bp",
+            "RBP [x86]
+Stack Base Pointer
+
+Type: General Purpose Register
+Width: 64 bits
+Only valid in 64-bit mode",
+            &config,
+        );
+    }
+    #[test]
+    fn handle_hover_x86_x86_64_it_provides_prioritized_arch_reg_info() {
+        let mut config = x86_x86_64_test_config();
+        config.opts.hover_single_arch = Some(true);
+        config.opts.arch_priority = Some(vec!["x86_64".to_string()]);
+        test_hover(
+            "	pushq	%r<cursor>bp",
+            "RBP [x86-64]
+Base Pointer (meant for stack frames)
+
+Type: General Purpose Register
+Width: 64 bits
+Only valid in 64-bit mode",
+            &config,
+        );
+    }
+    #[test]
+    fn handle_hover_x86_x86_64_it_provides_reg_info_after_multibyte_comment() {
+        // the emoji is two UTF-16 code units and four bytes wide
+        test_hover(
+            "	/* 🚀 stack frame */	pushq	%r<cursor>bp",
+            "RBP [x86]
+Stack Base Pointer
+
+Type: General Purpose Register
+Width: 64 bits
+Only valid in 64-bit mode
 
 RBP [x86-64]
 Base Pointer (meant for stack frames)
 
 Type: General Purpose Register
+Width: 64 bits
+Only valid in 64-bit mode",
+            &x86_x86_64_test_config(),
+        );
+    }
+    #[test]
+    fn handle_hover_x86_x86_64_it_provides_mask_reg_info() {
+        test_hover(
+            "	kmovw	%k<cursor>1, %eax",
+            "K1 [x86-64]
+An AVX-512 opmask register. Holds a per-element mask for masked vector operations, and can be used as a write mask with the {k1} operand decorator.
+
+Type: Mask Register
 Width: 64 bits",
             &x86_x86_64_test_config(),
         );
     }
     #[test]
+    fn handle_hover_x86_x86_64_it_notes_partial_register_writes() {
+        let hover_text = |source: &str, config: &Config| {
+            let hover = get_test_hover(source, config).unwrap();
+            let HoverContents::Markup(content) = hover.contents else {
+                panic!("Expected markup hover contents");
+            };
+            content.value
+        };
+        let config = x86_x86_64_test_config();
+        assert!(hover_text("	movl	$1, %e<cursor>ax", &config).ends_with(
+            "Partial register: `eax` is bits 0-31 of `rax`. Writing `eax` zeroes bits 32-63 of `rax`"
+        ));
+        assert!(hover_text("	movb	$1, %a<cursor>l", &config)
+            .contains("`al` is bits 0-7 of `rax`. Writing `al` merges the result into `rax`"));
+        assert!(hover_text("	movb	$1, %a<cursor>h", &config)
+            .ends_with("`ah` can't be used in an instruction with a REX prefix"));
+        assert!(hover_text("	movw	$1, %r9<cursor>w", &config).contains("bits 0-15 of `r9`"));
+        assert!(!hover_text("	movq	$1, %r<cursor>ax", &config).contains("Partial register"));
+
+        // without x86-64, the 32-bit registers are the full ones
+        let mut config = x86_x86_64_test_config();
+        config.instruction_sets.x86_64 = Some(false);
+        assert!(hover_text("	movw	$1, %a<cursor>x", &config)
+            .contains("`ax` is bits 0-15 of `eax`. Writing `ax` merges the result into `eax`"));
+        assert!(!hover_text("	movl	$1, %e<cursor>ax", &config).contains("Partial register"));
+
+        // nor are other arches' registers of the same name
+        config.instruction_sets.arm = Some(true);
+        config.opts.hover_single_arch = Some(true);
+        config.opts.arch_priority = Some(vec![String::from("arm")]);
+        let arm_sp = hover_text("	mov	r0, s<cursor>p", &config);
+        assert!(arm_sp.starts_with("SP [arm]"), "{arm_sp}");
+        assert!(!arm_sp.contains("Partial register"));
+    }
+    #[test]
+    fn handle_hover_x86_x86_64_it_explains_evex_decorators() {
+        let config = x86_x86_64_test_config();
+        let hover_text = |source: &str| {
+            let hover = get_test_hover(source, &config).unwrap();
+            let HoverContents::Markup(content) = hover.contents else {
+                panic!("Expected markup hover contents");
+            };
+            content.value
+        };
+        assert!(hover_text("	vaddps	%zmm1, %zmm2, %zmm3{%k<cursor>1}{z}")
+            .starts_with("**Write mask** `{%k1}`"));
+        assert!(hover_text("	vaddps	%zmm1, %zmm2, %zmm3{%k1}{<cursor>z}")
+            .starts_with("**Zero-masking** `{z}`"));
+        assert!(hover_text("	vaddps	zmm3, zmm2, zmm1, {rn<cursor>-sae}")
+            .contains("Rounds to nearest (even)"));
+        assert!(
+            hover_text("	vaddps	(%rax){1to<cursor>16}, %zmm2, %zmm3").contains("all 16 elements")
+        );
+    }
+    #[test]
+    fn handle_autocomplete_x86_x86_64_it_provides_mask_regs_in_write_masks() {
+        let comps = test_autocomplete(
+            "	vaddps	%zmm1, %zmm2, %zmm3{%<cursor>",
+            &x86_x86_64_test_config(),
+            CompletionItemKind::VARIABLE,
+            CompletionTriggerKind::TRIGGER_CHARACTER,
+            Some("%".to_string()),
+        );
+        assert!(comps.items.iter().all(|item| item.label.starts_with('k')));
+        assert!(comps.items.iter().any(|item| item.label == "k1"));
+    }
+    #[test]
+    fn find_word_at_pos_it_handles_tabs_and_multibyte_chars() {
+        let line = "/* é 😀 */\tmovq\t%rax, %rbx";
+        // LSP columns count UTF-16 code units, so the emoji counts twice
+        let col = line[..=line.find("rbx").unwrap()].encode_utf16().count();
+        let ((start, end), cursor_offset) = find_word_at_pos(line, col);
+        assert_eq!(&line[start..end], "rbx");
+        assert_eq!(cursor_offset, 1);
+
+        let col = line[..line.find("movq").unwrap()].encode_utf16().count();
+        let ((start, end), cursor_offset) = find_word_at_pos(line, col);
+        assert_eq!(&line[start..end], "movq");
+        assert_eq!(cursor_offset, 0);
+
+        // positions past the end of the line are clamped
+        let ((start, end), _) = find_word_at_pos(line, 100);
+        assert_eq!(&line[start..end], "rbx");
+    }
+    #[test]
+    fn handle_hover_x86_x86_64_it_provides_reg_info_at_each_char() {
+        let config = x86_x86_64_test_config();
+        let expected =
+            get_test_hover("\tmovq\t%r<cursor>ax, %rbx", &config).expect("No hover for rax");
+        // on the `%`, within the name, and just past its end
+        for col in 0..="%rax".len() {
+            let source = format!("\tmovq\t{}<cursor>{}, %rbx", &"%rax"[..col], &"%rax"[col..]);
+            assert_eq!(
+                get_test_hover(&source, &config),
+                Some(expected.clone()),
+                "Wrong hover for {source:?}"
+            );
+        }
+    }
+    #[test]
     fn handle_hover_x86_x86_64_it_provides_reg_info_offset() {
         test_hover(
             "	movl	%edi, -20(%r<cursor>bp)",
@@ -1199,12 +5328,14 @@ Stack Base Pointer
 
 Type: General Purpose Register
 Width: 64 bits
+Only valid in 64-bit mode
 
 RBP [x86-64]
 Base Pointer (meant for stack frames)
 
 Type: General Purpose Register
-Width: 64 bits",
+Width: 64 bits
+Only valid in 64-bit mode",
             &x86_x86_64_test_config(),
         );
     }
@@ -1217,12 +5348,14 @@ Instruction Pointer
 
 Type: Pointer Register
 Width: 64 bits
+Only valid in 64-bit mode
 
 RIP [x86-64]
 Instruction Pointer. Can only be used in RIP-relative addressing.
 
 Type: Pointer Register
-Width: 64 bits",
+Width: 64 bits
+Only valid in 64-bit mode",
             &x86_x86_64_test_config(),
         );
     }
@@ -1230,6 +5363,30 @@ Width: 64 bits",
     /**************************************************************************
      * GAS Tests
      *************************************************************************/
+    #[test]
+    fn handle_autocomplete_gas_it_sorts_instrs_before_directives() {
+        let mut config = gas_test_config();
+        config.instruction_sets.x86_64 = Some(true);
+        let resp = test_autocomplete(
+            "	mo<cursor>",
+            &config,
+            CompletionItemKind::FUNCTION,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let sort_texts_of = |kind: CompletionItemKind| -> Vec<String> {
+            resp.items
+                .iter()
+                .filter(|item| item.kind == Some(kind))
+                .filter_map(|item| item.sort_text.clone())
+                .collect()
+        };
+        let instrs = sort_texts_of(CompletionItemKind::FUNCTION);
+        let directives = sort_texts_of(CompletionItemKind::KEYWORD);
+        assert!(!instrs.is_empty() && !directives.is_empty());
+        assert!(instrs.iter().max() < directives.iter().min());
+    }
+
     #[test]
     fn handle_autocomplete_gas_it_provides_directive_completes_1() {
         test_directive_autocomplete(
@@ -1258,6 +5415,296 @@ Width: 64 bits",
         );
     }
 
+    #[test]
+    fn handle_autocomplete_gas_it_provides_cfi_directive_completes() {
+        let resp = test_autocomplete(
+            "	.cfi_<cursor>",
+            &gas_test_config(),
+            CompletionItemKind::KEYWORD,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        for cfi in [
+            ".cfi_startproc",
+            ".cfi_def_cfa_offset",
+            ".cfi_negate_ra_state",
+        ] {
+            assert!(labels.contains(&cfi), "Missing completion for {cfi}");
+        }
+    }
+
+    #[test]
+    fn handle_autocomplete_gas_it_respects_completion_mix_directives() {
+        let mut config = x86_x86_64_test_config();
+        config.assemblers.gas = Some(true);
+        let has_directives = |config: &Config, source: &str| {
+            test_autocomplete(
+                source,
+                config,
+                CompletionItemKind::FUNCTION,
+                CompletionTriggerKind::INVOKED,
+                None,
+            )
+            .items
+            .iter()
+            .any(|item| item.label.starts_with('.'))
+        };
+
+        assert!(has_directives(&config, "	mov eax, 1\n	m<cursor>"));
+        assert!(has_directives(&config, "	m<cursor>"));
+
+        config.opts.completion_mix_directives = Some(false);
+        assert!(!has_directives(&config, "	mov eax, 1\n	m<cursor>"));
+        assert!(!has_directives(&config, "	m<cursor>"));
+        // directives are still offered, on their own, once the word looks like one
+        test_autocomplete(
+            "	.cfi_<cursor>",
+            &config,
+            CompletionItemKind::KEYWORD,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+
+    #[test]
+    fn handle_autocomplete_gas_it_provides_string_escape_comps() {
+        let resp = test_autocomplete(
+            "	mov %eax, %ebx\n	.asciz \"done\\<cursor>\"",
+            &gas_test_config(),
+            CompletionItemKind::CONSTANT,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        for escape in ["\\n", "\\t", "\\0", "\\x", "\\\""] {
+            assert!(labels.contains(&escape), "Missing completion for {escape}");
+        }
+        // the typed backslash is replaced
+        let Some(CompletionTextEdit::Edit(ref edit)) = resp.items[0].text_edit else {
+            panic!("Missing text edit for {}", resp.items[0].label);
+        };
+        assert_eq!(edit.range.start, Position::new(1, 13));
+        assert_eq!(edit.range.end, Position::new(1, 14));
+
+        // no directives leak through when the trigger character is in a string
+        test_autocomplete(
+            "	.ascii \"see fig.<cursor>",
+            &gas_test_config(),
+            CompletionItemKind::CONSTANT,
+            CompletionTriggerKind::TRIGGER_CHARACTER,
+            Some(".".to_string()),
+        );
+    }
+
+    #[test]
+    fn handle_hover_gas_it_provides_cfi_directive_info() {
+        test_hover("	.cfi_start<cursor>proc", ".cfi_startproc [gas]
+`.cfi_startproc` is used at the beginning of each function that should have an entry in `.eh_frame`. It initializes some internal data structures. Unless `simple` is given, the architecture's initial CFA and register rules are emitted. Don't forget to close the function by `.cfi_endproc`.
+
+- .cfi_startproc [simple]
+
+More info: https://sourceware.org/binutils/docs-2.41/as/CFI-directives.html",
+            &gas_test_config(),
+            );
+    }
+
+    #[test]
+    fn handle_sig_help_gas_it_provides_directive_args() {
+        let config = gas_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+
+        let source = "	.align 16, 0x90";
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let tree = parser.parse(source, None);
+        let mut tree_entry = TreeEntry {
+            tree,
+            parser,
+            symbols: None,
+        };
+        let params = SignatureHelpParams {
+            context: None,
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Uri::from_str("file://").unwrap(),
+                },
+                position: Position {
+                    line: 0,
+                    character: source.len() as u32,
+                },
+            },
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+        };
+
+        let sig_help = get_sig_help_resp(
+            source,
+            &params,
+            &mut tree_entry,
+            &state.names_to_info.instructions,
+            &state.names_to_info.directives,
+            &config,
+        )
+        .unwrap();
+        let sig = &sig_help.signatures[0];
+        assert_eq!(sig.label, ".align [abs-expr[, abs-expr[, abs-expr]]]");
+        assert_eq!(sig.active_parameter, Some(1));
+        let params = sig.parameters.as_ref().unwrap();
+        assert_eq!(params.len(), 3);
+        let ParameterLabel::LabelOffsets([start, end]) = params[1].label else {
+            panic!("Expected label offsets, found {:?}", params[1].label);
+        };
+        assert_eq!(&sig.label[start as usize..end as usize], "abs-expr");
+    }
+
+    #[test]
+    fn handle_hover_x86_x86_64_it_prefers_go_names() {
+        let mut config = x86_x86_64_test_config();
+        config.opts.prefer_go_names = Some(true);
+        let hover = get_test_hover("\tCVTSD2<cursor>SQ X0, AX", &config).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markup hover contents");
+        };
+        assert!(!content.value.contains("*GAS*"));
+        let first_form = content.value.find("*GO*: ").unwrap();
+        assert!(content.value[first_form..].starts_with("*GO*: CVTSD2SQ"));
+
+        // GAS names are shown as usual without the option
+        config.opts.prefer_go_names = Some(false);
+        let hover = get_test_hover("\tcvtsd2<cursor>si %xmm0, %rax", &config).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markup hover contents");
+        };
+        assert!(content.value.contains("*GAS*: cvtsd2si"));
+    }
+
+    #[test]
+    fn handle_sig_help_x86_x86_64_it_prefers_go_names() {
+        let mut config = x86_x86_64_test_config();
+        config.opts.prefer_go_names = Some(true);
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+
+        let source = "\tCVTSD2SQ X0, AX";
+        let mut parser = Parser::new();
+        parser.set_language(&asm_language()).unwrap();
+        let mut tree_entry = TreeEntry {
+            tree: None,
+            parser,
+            symbols: None,
+        };
+        let params = SignatureHelpParams {
+            context: None,
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Uri::from_str("file://").unwrap(),
+                },
+                position: Position {
+                    line: 0,
+                    character: 12,
+                },
+            },
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: None,
+            },
+        };
+
+        let sig_help = get_sig_help_resp(
+            source,
+            &params,
+            &mut tree_entry,
+            &state.names_to_info.instructions,
+            &state.names_to_info.directives,
+            &config,
+        )
+        .unwrap();
+        let Some(Documentation::MarkupContent(ref doc)) = sig_help.signatures[0].documentation
+        else {
+            panic!("Expected markup documentation");
+        };
+        assert!(doc.value.contains("*GO*: CVTSD2SQ"));
+        assert!(!doc.value.contains("*GAS*"));
+    }
+
+    #[test]
+    fn handle_sig_help_x86_x86_64_it_follows_wrapped_operands() {
+        let config = x86_x86_64_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+
+        let sig_help_at = |source: &str, line: u32, character: u32| {
+            let mut parser = Parser::new();
+            parser.set_language(&asm_language()).unwrap();
+            let mut tree_entry = TreeEntry {
+                tree: None,
+                parser,
+                symbols: None,
+            };
+            let params = SignatureHelpParams {
+                context: None,
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: Uri::from_str("file://").unwrap(),
+                    },
+                    position: Position { line, character },
+                },
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: None,
+                },
+            };
+            get_sig_help_resp(
+                source,
+                &params,
+                &mut tree_entry,
+                &state.names_to_info.instructions,
+                &state.names_to_info.directives,
+                &config,
+            )
+            .map(|sig_help| {
+                let sig = &sig_help.signatures[0];
+                // the active operand has a parameter to highlight
+                let active = sig_help.active_parameter.unwrap() as usize;
+                let Some(ParameterLabel::LabelOffsets([start, end])) = sig
+                    .parameters
+                    .as_ref()
+                    .and_then(|params| params.get(active))
+                    .map(|param| param.label.clone())
+                else {
+                    panic!("No parameter for operand {active}");
+                };
+                assert_eq!(
+                    &sig.label[start as usize..end as usize],
+                    format!("op{}", active + 1)
+                );
+                (sig.label.clone(), sig_help.active_parameter)
+            })
+        };
+
+        let source = "\tvpaddd %zmm2,\n\t\t%zmm1, %zmm0\n\tvpaddd %zmm3, %zmm4";
+        assert_eq!(
+            sig_help_at(source, 0, 10),
+            Some(("vpaddd op1, op2, op3".to_string(), Some(0)))
+        );
+        assert_eq!(
+            sig_help_at(source, 1, 9),
+            Some(("vpaddd op1, op2, op3".to_string(), Some(2)))
+        );
+        // the next statement isn't part of the operand list
+        assert_eq!(
+            sig_help_at(source, 2, 15),
+            Some(("vpaddd op1, op2, op3".to_string(), Some(1)))
+        );
+
+        let source = "\tvpaddd %zmm2, %zmm1, \\\n\t\t%zmm0";
+        assert_eq!(
+            sig_help_at(source, 1, 4),
+            Some(("vpaddd op1, op2, op3".to_string(), Some(2)))
+        );
+    }
+
     #[test]
     fn handle_hover_gas_it_provides_directive_info_1() {
         test_hover(r#"	.f<cursor>ile	"a.cpp"#, ".file [gas]
@@ -1324,6 +5771,28 @@ More info: https://sourceware.org/binutils/docs-2.41/as/Global.html",
         );
     }
 
+    #[test]
+    fn handle_hover_masm_it_shows_canonical_instr_name() {
+        let mut config = masm_test_config();
+        config.instruction_sets.x86_64 = Some(true);
+        // the x86-64 docs only have GAS and Go forms
+        config.assemblers.gas = Some(true);
+        let hover_value = |source: &str| {
+            let hover = get_test_hover(source, &config).unwrap();
+            let HoverContents::Markup(content) = hover.contents else {
+                panic!("Expected markup hover contents");
+            };
+            content.value
+        };
+        let canonical = hover_value("    mo<cursor>v rax, rbx");
+        let upper = hover_value("    MO<cursor>V rax, rbx");
+        assert!(upper.starts_with("mov [x86-64]"));
+        assert_eq!(
+            upper,
+            format!("{canonical}\n\nMatched `MOV` case-insensitively")
+        );
+    }
+
     #[test]
     fn handle_hover_masm_it_provides_directive_info_1() {
         test_hover(
@@ -1386,6 +5855,42 @@ MASM64: Generates a UWOP_ALLOC_SMALL or a UWOP_ALLOC_LARGE with the specified si
         );
     }
 
+    #[test]
+    fn handle_autocomplete_nasm_it_provides_both_directive_forms() {
+        let mut config = nasm_test_config();
+        config.opts.completion_both_directive_forms = Some(true);
+        let resp = test_autocomplete(
+            "%<cursor>",
+            &config,
+            CompletionItemKind::KEYWORD,
+            CompletionTriggerKind::TRIGGER_CHARACTER,
+            Some("%".to_string()),
+        );
+        let labels: Vec<String> = resp
+            .items
+            .iter()
+            .map(|item| item.label.to_ascii_lowercase())
+            .collect();
+        assert!(labels.contains(&"%include".to_string()));
+        assert!(labels.contains(&"bits".to_string()));
+        assert!(!labels.contains(&"include".to_string()));
+    }
+
+    #[test]
+    fn handle_autocomplete_nasm_it_provides_string_escape_comps() {
+        let resp = test_autocomplete(
+            "msg: db `hello\\<cursor>`, 0",
+            &nasm_test_config(),
+            CompletionItemKind::CONSTANT,
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+        let labels: Vec<&str> = resp.items.iter().map(|item| item.label.as_str()).collect();
+        for escape in ["\\n", "\\e", "\\u", "\\`"] {
+            assert!(labels.contains(&escape), "Missing completion for {escape}");
+        }
+    }
+
     #[test]
     fn handle_hover_nasm_it_provides_directive_info_1() {
         test_hover(
@@ -1415,6 +5920,60 @@ switch to AT&amp;T syntax",
         );
     }
 
+    #[test]
+    fn handle_hover_nasm_it_provides_dotted_directive_info() {
+        test_hover(
+            ".INTEL_<cursor>SYNTAX",
+            ".intel_syntax [nasm]
+switch to INTEL syntax",
+            &nasm_test_config(),
+        );
+        // the '.' can be left out
+        test_hover(
+            "att_<cursor>syntax",
+            ".att_syntax [nasm]
+switch to AT&amp;T syntax",
+            &nasm_test_config(),
+        );
+
+        let mut config = nasm_test_config();
+        config.opts.directive_prefix_fallback = Some(false);
+        assert!(get_test_hover("att_<cursor>syntax", &config).is_none());
+        // a '%' in the source is still honored
+        test_hover(
+            "%def<cursor>ine",
+            "%define [nasm]
+Define Single-line macros that is resolved at the time the embedded macro is expanded.",
+            &config,
+        );
+    }
+
+    #[test]
+    fn handle_hover_nasm_it_resolves_every_dotted_directive() {
+        let config = nasm_test_config();
+        let docs = LoadedDocs::load(&config).expect("Failed to load docs");
+        let state = ServerState::new(&docs, &config);
+        let mut dotted: Vec<&str> = state
+            .names_to_info
+            .directives
+            .keys()
+            .filter(|(assembler, name)| *assembler == Assembler::Nasm && name.starts_with('.'))
+            .map(|(_, name)| *name)
+            .collect();
+        dotted.sort_unstable();
+        assert_eq!(dotted, vec![".att_syntax", ".intel_syntax"]);
+
+        for name in dotted {
+            for source in [format!("{name}<cursor>"), format!("{}<cursor>", &name[1..])] {
+                let hover = get_test_hover(&source, &config).unwrap();
+                let HoverContents::Markup(content) = hover.contents else {
+                    panic!("Expected markup hover contents");
+                };
+                assert!(content.value.starts_with(&format!("{name} [nasm]")));
+            }
+        }
+    }
+
     /**************************************************************************
      * z80 Tests
      *************************************************************************/
@@ -2008,6 +6567,146 @@ Width: 8 bits",
         );
     }
 
+    /**************************************************************************
+     * 6502 Tests
+     *************************************************************************/
+    #[test]
+    fn handle_autocomplete_6502_it_provides_instr_comps_one_character_start() {
+        test_instruction_autocomplete(
+            "l<cursor>",
+            &mos6502_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+
+    #[test]
+    fn handle_autocomplete_6502_it_provides_reg_comps_in_indirect_indexed_arg() {
+        test_register_autocomplete(
+            "        lda ($20),Y<cursor>",
+            &mos6502_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+
+    #[test]
+    fn handle_autocomplete_6502_it_provides_reg_comps_in_indexed_arg() {
+        test_register_autocomplete(
+            "        sta $0200,X<cursor>",
+            &mos6502_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+
+    #[test]
+    fn handle_hover_6502_it_provides_instr_info_immediate_arg() {
+        test_hover(
+            "        ld<cursor>a #$10",
+            "lda [6502]
+Load accumulator with memory. Flags: N Z
+
+## Templates
+
+ + `LDA #$nn ; Immediate, opcode A9, 2 bytes, 2 cycles`
+ + `LDA $nn ; Zero Page, opcode A5, 2 bytes, 3 cycles`
+ + `LDA $nn,X ; Zero Page,X, opcode B5, 2 bytes, 4 cycles`
+ + `LDA $nnnn ; Absolute, opcode AD, 3 bytes, 4 cycles`
+ + `LDA $nnnn,X ; Absolute,X, opcode BD, 3 bytes, 4+ cycles`
+ + `LDA $nnnn,Y ; Absolute,Y, opcode B9, 3 bytes, 4+ cycles`
+ + `LDA ($nn,X) ; (Indirect,X), opcode A1, 2 bytes, 6 cycles`
+ + `LDA ($nn),Y ; (Indirect),Y, opcode B1, 2 bytes, 5+ cycles`
+
+More info: https://www.masswerk.at/6502/6502_instruction_set.html#LDA",
+            &mos6502_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_6502_it_provides_reg_info_indirect_indexed_arg() {
+        test_hover(
+            "        lda ($20),<cursor>Y",
+            "Y [6502]
+Index register Y. Used as an offset in indexed and (Indirect),Y addressing modes.
+
+Type: General Purpose Register
+Width: 8 bits",
+            &mos6502_test_config(),
+        );
+    }
+
+    /**************************************************************************
+     * M68k Tests
+     *************************************************************************/
+    #[test]
+    fn handle_autocomplete_m68k_it_provides_instr_comps_one_character_start() {
+        test_instruction_autocomplete(
+            "m<cursor>",
+            &m68k_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+
+    #[test]
+    fn handle_autocomplete_m68k_it_provides_reg_comps_in_arg() {
+        test_register_autocomplete(
+            "        move.l d0, d<cursor>",
+            &m68k_test_config(),
+            CompletionTriggerKind::INVOKED,
+            None,
+        );
+    }
+
+    #[test]
+    fn handle_hover_m68k_it_provides_instr_info() {
+        test_hover(
+            "        db<cursor>ra d0, loop",
+            "dbra [m68k]
+Decrement and branch. Decrements the low word of Dn and branches to the label unless it becomes -1, making Dn + 1 iterations of a loop.
+
+## Templates
+
+ + `DBRA Dn,label ; sizes: .w`",
+            &m68k_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_m68k_it_provides_instr_info_size_suffix() {
+        test_hover(
+            "        mo<cursor>ve.l d0, d1",
+            "move [m68k]
+Move data from the source to the destination. Flags: N Z V(0) C(0)
+
+## Templates
+
+ + `MOVE ea,ea ; sizes: .b .w .l`
+ + `MOVE ea,CCR ; sizes: .w`
+ + `MOVE SR,ea ; sizes: .w`
+ + `MOVE ea,SR ; sizes: .w`
+ + `MOVE USP,An ; sizes: .l`
+ + `MOVE An,USP ; sizes: .l`
+
+Size suffix: `.l` (long, 32 bits)",
+            &m68k_test_config(),
+        );
+    }
+
+    #[test]
+    fn handle_hover_m68k_it_provides_reg_info() {
+        test_hover(
+            "        move.l d<cursor>0, d1",
+            "D0 [m68k]
+Data register 0. Byte, word, and long operations use its low 8, 16, or all 32 bits.
+
+Type: General Purpose Register
+Width: 32 bits",
+            &m68k_test_config(),
+        );
+    }
+
     /**************************************************************************
      * Serialization Tests
      *************************************************************************/
@@ -2175,6 +6874,60 @@ Width: 8 bits",
         }
     }
     #[test]
+    fn serialized_mos6502_registers_are_up_to_date() {
+        let mut cmp_map = HashMap::new();
+        let mos6502_regs_ser = include_bytes!("serialized/registers/6502");
+        let ser_vec = bincode::deserialize::<Vec<Register>>(mos6502_regs_ser).unwrap();
+
+        let mos6502_regs_raw = include_str!("../docs_store/registers/raw/6502.xml");
+        let raw_vec = populate_registers(mos6502_regs_raw).unwrap();
+
+        for reg in ser_vec {
+            *cmp_map.entry(reg.clone()).or_insert(0) += 1;
+        }
+        for reg in raw_vec {
+            let entry = cmp_map.get_mut(&reg).unwrap();
+            assert!(
+                *entry != 0,
+                "Expected at least one more instruction entry for {reg:?}, but the count is 0"
+            );
+            *entry -= 1;
+        }
+        for (reg, count) in &cmp_map {
+            assert!(
+                *count == 0,
+                "Expected count to be 0, found {count} for {reg:?}"
+            );
+        }
+    }
+    #[test]
+    fn serialized_m68k_registers_are_up_to_date() {
+        let mut cmp_map = HashMap::new();
+        let m68k_regs_ser = include_bytes!("serialized/registers/m68k");
+        let ser_vec = bincode::deserialize::<Vec<Register>>(m68k_regs_ser).unwrap();
+
+        let m68k_regs_raw = include_str!("../docs_store/registers/raw/m68k.xml");
+        let raw_vec = populate_registers(m68k_regs_raw).unwrap();
+
+        for reg in ser_vec {
+            *cmp_map.entry(reg.clone()).or_insert(0) += 1;
+        }
+        for reg in raw_vec {
+            let entry = cmp_map.get_mut(&reg).unwrap();
+            assert!(
+                *entry != 0,
+                "Expected at least one more instruction entry for {reg:?}, but the count is 0"
+            );
+            *entry -= 1;
+        }
+        for (reg, count) in &cmp_map {
+            assert!(
+                *count == 0,
+                "Expected count to be 0, found {count} for {reg:?}"
+            );
+        }
+    }
+    #[test]
     fn serialized_x86_instructions_are_up_to_date() {
         let mut cmp_map = HashMap::new();
         let x86_instrs_ser = include_bytes!("serialized/opcodes/x86");
@@ -2304,6 +7057,114 @@ Width: 8 bits",
         }
     }
     #[test]
+    fn serialized_mos6502_instructions_are_up_to_date() {
+        let mut cmp_map = HashMap::new();
+        let mos6502_instrs_ser = include_bytes!("serialized/opcodes/6502");
+        let ser_vec = bincode::deserialize::<Vec<Instruction>>(mos6502_instrs_ser).unwrap();
+
+        let mos6502_instrs_raw = include_str!("../docs_store/opcodes/raw/6502.xml");
+        let raw_vec = populate_instructions(mos6502_instrs_raw).unwrap();
+
+        for instr in ser_vec {
+            *cmp_map.entry(instr.clone()).or_insert(0) += 1;
+        }
+        for instr in raw_vec {
+            let entry = cmp_map.get_mut(&instr).unwrap();
+            assert!(
+                *entry != 0,
+                "Expected at least one more instruction entry for {instr:?}, but the count is 0"
+            );
+            *entry -= 1;
+        }
+        for (instr, count) in &cmp_map {
+            assert!(
+                *count == 0,
+                "Expected count to be 0, found {count} for {instr:?}"
+            );
+        }
+    }
+    #[test]
+    fn serialized_m68k_instructions_are_up_to_date() {
+        let mut cmp_map = HashMap::new();
+        let m68k_instrs_ser = include_bytes!("serialized/opcodes/m68k");
+        let ser_vec = bincode::deserialize::<Vec<Instruction>>(m68k_instrs_ser).unwrap();
+
+        let m68k_instrs_raw = include_str!("../docs_store/opcodes/raw/m68k.xml");
+        let raw_vec = populate_instructions(m68k_instrs_raw).unwrap();
+
+        for instr in ser_vec {
+            *cmp_map.entry(instr.clone()).or_insert(0) += 1;
+        }
+        for instr in raw_vec {
+            let entry = cmp_map.get_mut(&instr).unwrap();
+            assert!(
+                *entry != 0,
+                "Expected at least one more instruction entry for {instr:?}, but the count is 0"
+            );
+            *entry -= 1;
+        }
+        for (instr, count) in &cmp_map {
+            assert!(
+                *count == 0,
+                "Expected count to be 0, found {count} for {instr:?}"
+            );
+        }
+    }
+    #[test]
+    fn serialized_riscv_pseudo_instructions_are_up_to_date() {
+        let mut cmp_map = HashMap::new();
+        let riscv_pseudo_instrs_ser = include_bytes!("serialized/opcodes/riscv_pseudo");
+        let ser_vec = bincode::deserialize::<Vec<Instruction>>(riscv_pseudo_instrs_ser).unwrap();
+
+        let riscv_pseudo_instrs_raw = include_str!("../docs_store/opcodes/raw/riscv_pseudo.xml");
+        let raw_vec = populate_instructions(riscv_pseudo_instrs_raw).unwrap();
+
+        for instr in ser_vec {
+            *cmp_map.entry(instr.clone()).or_insert(0) += 1;
+        }
+        for instr in raw_vec {
+            let entry = cmp_map.get_mut(&instr).unwrap();
+            assert!(
+                *entry != 0,
+                "Expected at least one more instruction entry for {instr:?}, but the count is 0"
+            );
+            *entry -= 1;
+        }
+        for (instr, count) in &cmp_map {
+            assert!(
+                *count == 0,
+                "Expected count to be 0, found {count} for {instr:?}"
+            );
+        }
+    }
+    #[test]
+    fn serialized_arm_pseudo_instructions_are_up_to_date() {
+        let mut cmp_map = HashMap::new();
+        let arm_pseudo_instrs_ser = include_bytes!("serialized/opcodes/arm_pseudo");
+        let ser_vec = bincode::deserialize::<Vec<Instruction>>(arm_pseudo_instrs_ser).unwrap();
+
+        let arm_pseudo_instrs_raw = include_str!("../docs_store/opcodes/raw/arm_pseudo.xml");
+        let raw_vec = populate_instructions(arm_pseudo_instrs_raw).unwrap();
+
+        for instr in ser_vec {
+            *cmp_map.entry(instr.clone()).or_insert(0) += 1;
+        }
+        for instr in raw_vec {
+            let entry = cmp_map.get_mut(&instr).unwrap();
+            assert!(
+                *entry != 0,
+                "Expected at least one more instruction entry for {instr:?}, but the count is 0"
+            );
+            *entry -= 1;
+        }
+        for (instr, count) in &cmp_map {
+            assert!(
+                *count == 0,
+                "Expected count to be 0, found {count} for {instr:?}"
+            );
+        }
+    }
+    #[test]
     fn serialized_gas_directives_are_up_to_date() {
         let mut cmp_map = HashMap::new();
         let gas_dirs_ser = include_bytes!("serialized/directives/gas");
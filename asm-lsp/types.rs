@@ -1,12 +1,18 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, VecDeque},
     fmt::Display,
+    path::PathBuf,
     str::FromStr,
 };
 
-use lsp_types::Uri;
+use anyhow::{anyhow, Result};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, FormattingOptions, MarkupContent, Range,
+    TextDocumentIdentifier, Uri,
+};
 use serde::{Deserialize, Serialize};
-use strum_macros::{AsRefStr, Display, EnumString};
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, Display, EnumIter, EnumString};
 use tree_sitter::{Parser, Tree};
 
 // Instruction ------------------------------------------------------------------------------------
@@ -17,13 +23,41 @@ pub struct Instruction {
     pub forms: Vec<InstructionForm>,
     pub asm_templates: Vec<String>,
     pub aliases: Vec<InstructionAlias>,
+    pub examples: Vec<InstructionExample>,
     pub url: Option<String>,
     pub arch: Option<Arch>,
+    /// Why the instruction shouldn't be used anymore, if it's obsolete
+    pub deprecated: Option<String>,
+    /// Whether the instruction can't be encoded in 64-bit mode
+    pub invalid_64bit: bool,
 }
 
 impl Hoverable for &Instruction {}
 impl Completable for &Instruction {}
 
+impl Instruction {
+    /// Returns the instruction set extensions needed to use this instruction,
+    /// deduplicated in form order
+    ///
+    /// Returns `None` if the instruction has no forms, or if any of its forms
+    /// is available without an extension
+    #[must_use]
+    pub fn get_required_isas(&self) -> Option<Vec<ISA>> {
+        if self.forms.is_empty() {
+            return None;
+        }
+        let mut isas = Vec::new();
+        for form in &self.forms {
+            let isa = form.isa?;
+            if !isas.contains(&isa) {
+                isas.push(isa);
+            }
+        }
+
+        Some(isas)
+    }
+}
+
 impl Default for Instruction {
     fn default() -> Self {
         let name = String::new();
@@ -31,8 +65,11 @@ impl Default for Instruction {
         let forms = vec![];
         let asm_templates = vec![];
         let aliases = vec![];
+        let examples = vec![];
         let url = None;
         let arch = None;
+        let deprecated = None;
+        let invalid_64bit = false;
 
         Self {
             name,
@@ -40,8 +77,11 @@ impl Default for Instruction {
             forms,
             asm_templates,
             aliases,
+            examples,
             url,
             arch,
+            deprecated,
+            invalid_64bit,
         }
     }
 }
@@ -57,7 +97,26 @@ impl std::fmt::Display for Instruction {
         }
 
         //let mut v: Vec<&str> = vec![&header, &self.summary, "\n", "## Forms", "\n"];
-        let mut v: Vec<&str> = vec![&header, &self.summary, "\n"];
+        let mut v: Vec<&str> = vec![&header, &self.summary];
+
+        // obsolete or mode-restricted instructions
+        let deprecated: String;
+        if let Some(reason) = &self.deprecated {
+            deprecated = format!("**Deprecated**: {reason}");
+            v.push(&deprecated);
+        }
+        if self.invalid_64bit {
+            v.push("**Invalid in 64-bit mode**");
+        }
+
+        // required extension(s)
+        let requires: String;
+        if let Some(isas) = self.get_required_isas() {
+            let names: Vec<&str> = isas.iter().map(AsRef::as_ref).collect();
+            requires = format!("Requires: {}", names.join(" or "));
+            v.push(&requires);
+        }
+        v.push("\n");
 
         if !self.forms.is_empty() {
             v.append(&mut vec!["## Forms", "\n"]);
@@ -94,6 +153,17 @@ impl std::fmt::Display for Instruction {
             v.push(item.as_str());
         }
 
+        if !self.examples.is_empty() {
+            v.append(&mut vec!["## Examples", "\n"]);
+        }
+
+        // instruction examples
+        let instruction_example_strs: Vec<String> =
+            self.examples.iter().map(|f| format!("{f}")).collect();
+        for item in &instruction_example_strs {
+            v.push(item.as_str());
+        }
+
         // url
         let more_info: String;
         if let Some(url) = &self.url {
@@ -157,6 +227,11 @@ pub struct InstructionForm {
     pub nacl_version: Option<u8>,
     pub nacl_zero_extends_outputs: Option<bool>,
     pub operands: Vec<Operand>,
+    /// Registers read or written without being given as an operand, e.g. `rdx:rax`
+    /// for `mulq`
+    pub implicit_operands: Vec<ImplicitOperand>,
+    /// Each way of encoding the form, in Intel's opcode notation (e.g. `REX.W 81 /0 id`)
+    pub encodings: Vec<String>,
     // --- Z80-Specific Information ---
     pub z80_name: Option<String>,
     pub z80_form: Option<String>,
@@ -246,6 +321,31 @@ impl std::fmt::Display for InstructionForm {
     }
 }
 
+// InstructionExample ------------------------------------------------------------------------------
+/// A short usage example of an instruction
+#[derive(Default, Eq, PartialEq, Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct InstructionExample {
+    /// The assemblers whose syntax the example is written in, e.g. AT&T syntax
+    /// for GAS vs Intel syntax for NASM/MASM. Empty if it's the same for all of them
+    pub assemblers: Vec<Assembler>,
+    pub code: String,
+}
+
+impl std::fmt::Display for InstructionExample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.assemblers.is_empty() {
+            write!(f, " + `{}`", self.code)
+        } else {
+            let names: Vec<String> = self
+                .assemblers
+                .iter()
+                .map(|assembler| assembler.config_key().to_ascii_uppercase())
+                .collect();
+            write!(f, " + *{}*: `{}`", names.join("/"), self.code)
+        }
+    }
+}
+
 // InstructionAlias --------------------------------------------------------------------------------
 #[derive(Default, Eq, PartialEq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub struct InstructionAlias {
@@ -504,18 +604,54 @@ impl<'own> Directive {
 
         names
     }
+
+    /// Get each signature with its markdown emphasis stripped, along with the
+    /// `[start, end)` UTF-16 offsets of its arguments within it
+    ///
+    /// e.g. `.align *[abs-expr[, abs-expr[, abs-expr]]]*` has three `abs-expr`
+    /// arguments
+    #[must_use]
+    pub fn get_signature_args(&self) -> Vec<(String, Vec<[u32; 2]>)> {
+        let is_arg_delim = |c: char| c.is_whitespace() || matches!(c, '[' | ']' | '.');
+        self.signatures
+            .iter()
+            .map(|sig| {
+                let label = sig.replace('*', "");
+                let utf16_offset = |idx: usize| label[..idx].encode_utf16().count() as u32;
+                let mut args = Vec::new();
+                // the directive's name (and any size suffix) comes first
+                if let Some(args_start) = label.find(char::is_whitespace) {
+                    let mut offset = args_start;
+                    for arg in label[args_start..].split(',') {
+                        let name = arg.trim_matches(is_arg_delim);
+                        // skip `...` continuations
+                        if let Some(name_start) = arg.find(name).filter(|_| !name.is_empty()) {
+                            let start = offset + name_start;
+                            args.push([utf16_offset(start), utf16_offset(start + name.len())]);
+                        }
+                        offset += arg.len() + 1;
+                    }
+                }
+                (label, args)
+            })
+            .collect()
+    }
 }
 
 // Register ---------------------------------------------------------------------------------------
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Register {
     pub name: String,
+    /// Other names the register can be referred to by, e.g. RISCV ABI names
+    pub alt_names: Vec<String>,
     pub description: Option<String>,
     pub reg_type: Option<RegisterType>,
     pub width: Option<RegisterWidth>,
     pub flag_info: Vec<RegisterBitInfo>,
     pub arch: Option<Arch>,
     pub url: Option<String>,
+    /// Whether the register can only be used in 64-bit mode, e.g. `r8` on x86-64
+    pub only_64bit: bool,
 }
 
 impl Hoverable for &Register {}
@@ -524,21 +660,25 @@ impl Completable for &Register {}
 impl Default for Register {
     fn default() -> Self {
         let name = String::new();
+        let alt_names = vec![];
         let description = None;
         let reg_type = None;
         let width = None;
         let flag_info = vec![];
         let arch = None;
         let url = None;
+        let only_64bit = false;
 
         Self {
             name,
+            alt_names,
             description,
             reg_type,
             width,
             flag_info,
             arch,
             url,
+            only_64bit,
         }
     }
 }
@@ -559,6 +699,12 @@ impl std::fmt::Display for Register {
             vec![header, String::from("\n")]
         };
 
+        // Alternate names
+        if !self.alt_names.is_empty() {
+            let alt_names = format!("Alternate names: {}", self.alt_names.join(", "));
+            v.push(alt_names);
+        }
+
         // Register Type
         if let Some(reg_type_) = &self.reg_type {
             let reg_type = format!("Type: {reg_type_}");
@@ -571,6 +717,10 @@ impl std::fmt::Display for Register {
             v.push(reg_width);
         }
 
+        if self.only_64bit {
+            v.push(String::from("Only valid in 64-bit mode"));
+        }
+
         // Bit-mask flag meanings if applicable
         if !self.flag_info.is_empty() {
             let flag_heading = String::from("\n## Flags:");
@@ -607,7 +757,8 @@ impl<'own> Register {
     /// get the names of all the associated registers
     #[must_use]
     pub fn get_associated_names(&'own self) -> Vec<&'own str> {
-        let names = vec![self.name.as_ref()];
+        let mut names = vec![self.name.as_ref()];
+        names.extend(self.alt_names.iter().map(String::as_str));
 
         names
     }
@@ -631,7 +782,22 @@ pub type NameToDirectiveMap<'directive> =
 
 pub trait Hoverable: Display + Clone + Copy {}
 pub trait Completable: Display {}
-pub trait ArchOrAssembler {}
+pub trait ArchOrAssembler: AsRef<str> {}
+
+/// The details asm-lsp attaches to its completion items as their `data`, for
+/// telling them apart when filtering
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletionData {
+    /// The architecture or assembler the item is documented for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// Set on the GAS size suffixed variants of x86 instructions, e.g. `movl`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub gas_size_suffix: bool,
+    /// Set on the registers that can only be used in 64-bit mode
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub only_64bit: bool,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, EnumString, AsRefStr, Serialize, Deserialize)]
 pub enum XMMMode {
@@ -646,7 +812,18 @@ pub enum MMXMode {
 }
 
 #[derive(
-    Debug, Default, Hash, PartialEq, Eq, Clone, Copy, EnumString, AsRefStr, Serialize, Deserialize,
+    Debug,
+    Default,
+    Hash,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    EnumString,
+    EnumIter,
+    AsRefStr,
+    Serialize,
+    Deserialize,
 )]
 pub enum Arch {
     #[default]
@@ -662,10 +839,59 @@ pub enum Arch {
     RISCV,
     #[strum(serialize = "z80")]
     Z80,
+    #[strum(to_string = "6502", serialize = "mos6502")]
+    Mos6502,
+    #[strum(serialize = "m68k")]
+    M68k,
 }
 
 impl ArchOrAssembler for Arch {}
 
+/// Parses an arch's name as given in the config, accepting both `x86_64` and the
+/// `x86-64` used elsewhere
+pub(crate) fn parse_arch_name(name: &str) -> Option<Arch> {
+    Arch::from_str(&name.to_ascii_lowercase().replace('_', "-")).ok()
+}
+
+/// Parses a key of the `assemblers_by_arch` option, which may also carry the
+/// target's width like the names of cross toolchains do, e.g. `riscv64`
+fn parse_assembler_arch_key(key: &str) -> Option<Arch> {
+    parse_arch_name(key)
+        .or_else(|| parse_arch_name(key.strip_suffix("64").or_else(|| key.strip_suffix("32"))?))
+}
+
+impl Arch {
+    /// Returns the key used for this arch in the `instruction_sets` config table
+    #[must_use]
+    pub const fn config_key(self) -> &'static str {
+        match self {
+            Self::X86 => "x86",
+            Self::X86_64 => "x86_64",
+            Self::ARM => "arm",
+            Self::ARM64 => "arm64",
+            Self::RISCV => "riscv",
+            Self::Z80 => "z80",
+            Self::Mos6502 => "mos6502",
+            Self::M68k => "m68k",
+        }
+    }
+
+    /// Returns a human-readable name for this arch
+    #[must_use]
+    pub const fn display_name(self) -> &'static str {
+        match self {
+            Self::X86 => "x86",
+            Self::X86_64 => "x86-64",
+            Self::ARM => "ARM",
+            Self::ARM64 => "ARM64",
+            Self::RISCV => "RISC-V",
+            Self::Z80 => "Z80",
+            Self::Mos6502 => "MOS 6502",
+            Self::M68k => "Motorola 68000",
+        }
+    }
+}
+
 impl std::fmt::Display for Arch {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -675,13 +901,26 @@ impl std::fmt::Display for Arch {
             Self::ARM64 => write!(f, "arm64")?,
             Self::Z80 => write!(f, "z80")?,
             Self::RISCV => write!(f, "riscv")?,
+            Self::Mos6502 => write!(f, "6502")?,
+            Self::M68k => write!(f, "m68k")?,
         }
         Ok(())
     }
 }
 
 #[derive(
-    Debug, Display, Hash, PartialEq, Eq, Clone, Copy, EnumString, AsRefStr, Serialize, Deserialize,
+    Debug,
+    Display,
+    Hash,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    EnumString,
+    EnumIter,
+    AsRefStr,
+    Serialize,
+    Deserialize,
 )]
 pub enum Assembler {
     #[strum(serialize = "gas")]
@@ -696,8 +935,53 @@ pub enum Assembler {
 
 impl ArchOrAssembler for Assembler {}
 
+impl Assembler {
+    /// Returns the key used for this assembler in the `assemblers` config table
+    #[must_use]
+    pub const fn config_key(self) -> &'static str {
+        match self {
+            Self::Gas => "gas",
+            Self::Go => "go",
+            Self::Masm => "masm",
+            Self::Nasm => "nasm",
+        }
+    }
+
+    /// Returns a human-readable name for this assembler
+    #[must_use]
+    pub const fn display_name(self) -> &'static str {
+        match self {
+            Self::Gas => "GNU Assembler (GAS)",
+            Self::Go => "Go Assembler",
+            Self::Masm => "Microsoft Macro Assembler (MASM)",
+            Self::Nasm => "Netwide Assembler (NASM)",
+        }
+    }
+}
+
+/// The instruction set state of 32-bit ARM code, as set by the `.arm`/`.thumb`
+/// (or `.code 32`/`.code 16`) directives
+#[derive(Debug, PartialEq, Eq, Clone, Copy, AsRefStr, Display)]
+pub enum ArmState {
+    #[strum(serialize = "ARM")]
+    Arm,
+    #[strum(serialize = "Thumb")]
+    Thumb,
+}
+
 #[derive(
-    Debug, Hash, PartialEq, Eq, Clone, Copy, EnumString, AsRefStr, Display, Serialize, Deserialize,
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    EnumString,
+    EnumIter,
+    AsRefStr,
+    Display,
+    Serialize,
+    Deserialize,
 )]
 pub enum RegisterType {
     #[strum(serialize = "General Purpose Register")]
@@ -724,6 +1008,31 @@ pub enum RegisterType {
     ProtectedMode,
     #[strum(serialize = "Floating Point Register")]
     FloatingPoint,
+    #[strum(serialize = "Mask Register")]
+    Mask,
+}
+
+impl RegisterType {
+    /// The semantic token modifier used to mark registers of this type, so that
+    /// editor themes can style each class of register differently
+    #[must_use]
+    pub const fn semantic_token_modifier(self) -> &'static str {
+        match self {
+            Self::GeneralPurpose => "generalPurpose",
+            Self::SpecialPurpose => "specialPurpose",
+            Self::Pointer => "pointer",
+            Self::Segment => "segment",
+            Self::Flag => "flag",
+            Self::Control => "control",
+            Self::ExtendedControl => "extendedControl",
+            Self::MSR => "machineState",
+            Self::Debug => "debug",
+            Self::Test => "test",
+            Self::ProtectedMode => "protectedMode",
+            Self::FloatingPoint => "floatingPoint",
+            Self::Mask => "mask",
+        }
+    }
 }
 
 #[derive(
@@ -782,7 +1091,7 @@ impl std::fmt::Display for RegisterBitInfo {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Assemblers {
     pub gas: Option<bool>,
     pub go: Option<bool>,
@@ -791,6 +1100,20 @@ pub struct Assemblers {
     pub z80: Option<bool>,
 }
 
+impl Assemblers {
+    /// All assemblers left unspecified, used when the `assemblers` table is
+    /// omitted from a config file
+    pub(crate) const fn unset() -> Self {
+        Self {
+            gas: None,
+            go: None,
+            masm: None,
+            nasm: None,
+            z80: None,
+        }
+    }
+}
+
 impl Default for Assemblers {
     fn default() -> Self {
         Self {
@@ -804,7 +1127,7 @@ impl Default for Assemblers {
 }
 
 #[allow(non_snake_case)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct InstructionSets {
     pub x86: Option<bool>,
     pub x86_64: Option<bool>,
@@ -812,6 +1135,25 @@ pub struct InstructionSets {
     pub arm: Option<bool>,
     pub arm64: Option<bool>,
     pub riscv: Option<bool>,
+    pub mos6502: Option<bool>,
+    pub m68k: Option<bool>,
+}
+
+impl InstructionSets {
+    /// All instruction sets left unspecified, used when the `instruction_sets`
+    /// table is omitted from a config file
+    pub(crate) const fn unset() -> Self {
+        Self {
+            x86: None,
+            x86_64: None,
+            z80: None,
+            arm: None,
+            arm64: None,
+            riscv: None,
+            mos6502: None,
+            m68k: None,
+        }
+    }
 }
 
 impl Default for InstructionSets {
@@ -823,6 +1165,8 @@ impl Default for InstructionSets {
             arm: Some(false),
             arm64: Some(false),
             riscv: Some(false),
+            mos6502: Some(false),
+            m68k: Some(false),
         }
     }
 }
@@ -832,6 +1176,72 @@ pub struct ConfigOptions {
     pub compiler: Option<String>,
     pub diagnostics: Option<bool>,
     pub default_diagnostics: Option<bool>,
+    /// Command run for diagnostics on files without a compile command, with
+    /// `{file}` standing in for the source file's path
+    pub diagnostics_command: Option<String>,
+    /// Assembler run for diagnostics on files without a compile command, by the
+    /// arch it targets, e.g. `riscv64 = "riscv64-linux-gnu-as"`
+    pub assemblers_by_arch: Option<HashMap<String, String>>,
+    pub diagnostics_full_line: Option<bool>,
+    pub diagnostics_context: Option<bool>,
+    /// Report clear syntax errors found while parsing the file, without needing
+    /// an assembler
+    pub syntax_diagnostics: Option<bool>,
+    /// Maps the severity keyword of an assembler's message (`error`, `warning`, or
+    /// `note`) to the `DiagnosticLevel` it's reported with
+    pub severity_map: Option<HashMap<String, String>>,
+    pub show_encoding: Option<bool>,
+    /// List the opcode encoding of each of an x86 instruction's forms on hover
+    pub show_opcodes: Option<bool>,
+    /// `flexi_logger` spec for the server's log output, e.g. `debug`
+    pub log_level: Option<String>,
+    /// How many recently closed documents' trees to keep, so they needn't be
+    /// parsed again if reopened unchanged
+    pub keep_closed_trees: Option<usize>,
+    /// Index the labels of every assembly file under the project root in the
+    /// background, for goto definition, references, and workspace symbols across
+    /// files
+    pub workspace_index: Option<bool>,
+    /// Show the Linux syscall a number loaded before a syscall instruction refers to
+    pub syscall_hover: Option<bool>,
+    pub aliases: Option<HashMap<String, String>>,
+    pub linters: Option<Vec<String>>,
+    /// Extra directories searched for included files, absolute or relative to
+    /// the project root
+    pub include_dirs: Option<Vec<PathBuf>>,
+    pub debug_hover: Option<bool>,
+    pub hover_single_arch: Option<bool>,
+    /// Arches whose documentation comes first when a word is known to several,
+    /// e.g. `["x86_64", "x86"]`. Unlisted arches follow in the default order
+    pub arch_priority: Option<Vec<String>>,
+    /// When the Go assembler is enabled, show only instruction forms Go has a name
+    /// for, by their Go names, in hover and signature help
+    pub prefer_go_names: Option<bool>,
+    /// String inserted to indent instructions when formatting, e.g. `"\t"` or `"    "`.
+    /// Follows the client's formatting options if unset
+    pub indent_unit: Option<String>,
+    /// Send a `telemetry/event` notification with each request's method and how
+    /// long it took to service
+    pub telemetry: Option<bool>,
+    /// Tags offered as completions inside comments. Defaults to `TODO`, `FIXME`,
+    /// `NOTE`, and `HACK`
+    pub comment_tags: Option<Vec<String>>,
+    /// Words shorter than this many characters get no hover
+    pub min_hover_word_len: Option<usize>,
+    pub demangle_options: Option<DemangleStyle>,
+    pub empty_config_fallback: Option<bool>,
+    pub completion_mix_directives: Option<bool>,
+    /// Also suggest NASM directives without a `%` prefix when completing after one
+    pub completion_both_directive_forms: Option<bool>,
+    /// When hovering a NASM word that isn't a directive as written, also try it
+    /// with the `%` and `.` prefixes NASM directives may carry
+    pub directive_prefix_fallback: Option<bool>,
+    /// Treat documents as `objdump -d` style disassembly listings, so branch target
+    /// addresses can be followed with goto definition
+    pub disassembly_mode: Option<bool>,
+    /// Send nothing instead of empty responses. Detected from the client's name
+    /// if unset
+    pub suppress_empty_responses: Option<bool>,
 }
 
 impl Default for ConfigOptions {
@@ -840,6 +1250,36 @@ impl Default for ConfigOptions {
             compiler: None,
             diagnostics: Some(true),
             default_diagnostics: Some(true),
+            diagnostics_command: None,
+            assemblers_by_arch: None,
+            diagnostics_full_line: Some(true),
+            diagnostics_context: Some(false),
+            syntax_diagnostics: Some(false),
+            severity_map: None,
+            show_encoding: Some(false),
+            show_opcodes: Some(false),
+            log_level: Some(String::from("info")),
+            keep_closed_trees: Some(0),
+            workspace_index: Some(false),
+            syscall_hover: Some(false),
+            aliases: None,
+            linters: None,
+            include_dirs: None,
+            debug_hover: Some(false),
+            hover_single_arch: Some(false),
+            arch_priority: None,
+            prefer_go_names: Some(false),
+            indent_unit: None,
+            telemetry: Some(false),
+            comment_tags: None,
+            min_hover_word_len: Some(1),
+            demangle_options: Some(DemangleStyle::Complete),
+            empty_config_fallback: Some(true),
+            completion_mix_directives: Some(true),
+            completion_both_directive_forms: Some(false),
+            directive_prefix_fallback: Some(true),
+            disassembly_mode: Some(false),
+            suppress_empty_responses: None,
         }
     }
 }
@@ -847,29 +1287,527 @@ impl Default for ConfigOptions {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub version: String,
+    /// Shorthand to enable a single instruction set, e.g. `arch = "x86_64"`
+    pub arch: Option<String>,
+    /// Shorthand to enable a single assembler, e.g. `assembler = "gas"`
+    pub assembler: Option<String>,
+    #[serde(default = "Assemblers::unset")]
     pub assemblers: Assemblers,
+    #[serde(default = "InstructionSets::unset")]
     pub instruction_sets: InstructionSets,
     pub opts: ConfigOptions,
     pub client: Option<LspClient>,
+    /// The root directory of the current project, detected at startup
+    #[serde(skip)]
+    pub project_root: Option<PathBuf>,
+    /// The file given with `--config` or `ASM_LSP_CONFIG`, if any. Such a config is
+    /// used as is, without searching for global or project configs
+    #[serde(skip)]
+    pub config_path: Option<PathBuf>,
+    /// Set by an `asm-lsp: disable` modeline to turn off all features for a file
+    #[serde(skip)]
+    pub disabled: bool,
+}
+
+impl Config {
+    /// Expands the `arch` and `assembler` shorthand fields into the corresponding
+    /// `instruction_sets` and `assemblers` entries. The shorthand only fills in
+    /// entries that weren't explicitly set, so those take precedence
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `arch` or `assembler` isn't a recognized name
+    pub fn expand_shorthand(&mut self) -> Result<()> {
+        if let Some(ref name) = self.arch {
+            let Some(arch) = parse_arch_name(name) else {
+                return Err(anyhow!(
+                    "Unknown arch \"{name}\", expected one of: x86, x86_64, arm, arm64, riscv, z80, 6502, m68k"
+                ));
+            };
+            let sets = &mut self.instruction_sets;
+            sets.x86.get_or_insert(arch == Arch::X86);
+            sets.x86_64.get_or_insert(arch == Arch::X86_64);
+            sets.z80.get_or_insert(arch == Arch::Z80);
+            sets.arm.get_or_insert(arch == Arch::ARM);
+            sets.arm64.get_or_insert(arch == Arch::ARM64);
+            sets.riscv.get_or_insert(arch == Arch::RISCV);
+            sets.mos6502.get_or_insert(arch == Arch::Mos6502);
+            sets.m68k.get_or_insert(arch == Arch::M68k);
+            self.assemblers.z80.get_or_insert(arch == Arch::Z80);
+        }
+
+        if let Some(ref name) = self.assembler {
+            let Ok(assembler) = Assembler::from_str(&name.to_ascii_lowercase()) else {
+                return Err(anyhow!(
+                    "Unknown assembler \"{name}\", expected one of: gas, go, masm, nasm"
+                ));
+            };
+            let assemblers = &mut self.assemblers;
+            assemblers.gas.get_or_insert(assembler == Assembler::Gas);
+            assemblers.go.get_or_insert(assembler == Assembler::Go);
+            assemblers.masm.get_or_insert(assembler == Assembler::Masm);
+            assemblers.nasm.get_or_insert(assembler == Assembler::Nasm);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the `severity_map` option only maps known severity keywords to
+    /// known levels
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if a keyword or level isn't recognized
+    pub fn validate_severity_map(&self) -> Result<()> {
+        let Some(ref severity_map) = self.opts.severity_map else {
+            return Ok(());
+        };
+        for (keyword, level) in severity_map {
+            if !DIAGNOSTIC_KEYWORDS.contains(&keyword.as_str()) {
+                return Err(anyhow!(
+                    "Unknown severity_map keyword \"{keyword}\", expected one of: error, warning, note"
+                ));
+            }
+            if DiagnosticLevel::from_str(level).is_err() {
+                return Err(anyhow!(
+                    "Unknown severity_map level \"{level}\" for \"{keyword}\", expected one of: error, warning, information, hint, none"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the `arch_priority` option only lists known arches
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if an arch isn't recognized
+    pub fn validate_arch_priority(&self) -> Result<()> {
+        let Some(ref arch_priority) = self.opts.arch_priority else {
+            return Ok(());
+        };
+        for name in arch_priority {
+            if parse_arch_name(name).is_none() {
+                return Err(anyhow!(
+                    "Unknown arch_priority arch \"{name}\", expected one of: x86, x86_64, arm, arm64, riscv, z80, 6502, m68k"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the `assemblers_by_arch` option only maps known arches, each by
+    /// one key at most
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if an arch isn't recognized, or two keys name the same arch,
+    /// e.g. `riscv32` and `riscv64`, which couldn't be told apart
+    pub fn validate_assemblers_by_arch(&self) -> Result<()> {
+        let Some(ref assemblers) = self.opts.assemblers_by_arch else {
+            return Ok(());
+        };
+        let mut seen: HashMap<Arch, &str> = HashMap::new();
+        for key in assemblers.keys() {
+            let Some(arch) = parse_assembler_arch_key(key) else {
+                return Err(anyhow!(
+                    "Unknown assemblers_by_arch arch \"{key}\", expected one of: x86, x86_64, arm, arm64, riscv, z80, 6502, m68k"
+                ));
+            };
+            if let Some(other) = seen.insert(arch, key) {
+                return Err(anyhow!(
+                    "assemblers_by_arch keys \"{other}\" and \"{key}\" both name the {arch} arch, only one can be given"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the arches listed by the `arch_priority` option, highest priority first
+    #[must_use]
+    pub fn get_arch_priority(&self) -> Vec<Arch> {
+        self.opts
+            .arch_priority
+            .iter()
+            .flatten()
+            .filter_map(|name| parse_arch_name(name))
+            .collect()
+    }
+
+    /// Returns the assembler the `assemblers_by_arch` option maps the first enabled
+    /// arch with an entry to, checking the arches in `arch_priority` first
+    #[must_use]
+    pub fn get_arch_assembler(&self) -> Option<&str> {
+        let assemblers = self.opts.assemblers_by_arch.as_ref()?;
+        let mut arches = self.get_arch_priority();
+        let unlisted: Vec<Arch> = Arch::iter().filter(|arch| !arches.contains(arch)).collect();
+        arches.extend(unlisted);
+
+        arches
+            .into_iter()
+            .filter(|&arch| self.is_isa_enabled(arch))
+            .find_map(|arch| {
+                // `validate_assemblers_by_arch` ensures at most one key names `arch`
+                assemblers
+                    .iter()
+                    .find(|(key, _)| parse_assembler_arch_key(key) == Some(arch))
+                    .map(|(_, assembler)| assembler.as_str())
+            })
+    }
+
+    /// Returns whether instructions should be documented by their Go names, i.e.
+    /// the `prefer_go_names` option is set and the Go assembler is enabled
+    #[must_use]
+    pub fn prefers_go_names(&self) -> bool {
+        self.opts.prefer_go_names.unwrap_or(false) && self.assemblers.go.unwrap_or(false)
+    }
+
+    /// Returns the string to indent instructions with, taken from the `indent_unit`
+    /// option or else built from the client's formatting `options`
+    #[must_use]
+    pub fn get_indent_unit(&self, options: &FormattingOptions) -> String {
+        self.opts.indent_unit.clone().unwrap_or_else(|| {
+            if options.insert_spaces {
+                " ".repeat(options.tab_size as usize)
+            } else {
+                String::from("\t")
+            }
+        })
+    }
+
+    /// Returns the level to report an assembler message with the severity `keyword`
+    /// at, as configured by the `severity_map` option or the natural mapping
+    /// otherwise
+    #[must_use]
+    pub fn get_diagnostic_level(&self, keyword: &str) -> DiagnosticLevel {
+        self.opts
+            .severity_map
+            .as_ref()
+            .and_then(|severity_map| severity_map.get(keyword))
+            .and_then(|level| DiagnosticLevel::from_str(level).ok())
+            .unwrap_or(match keyword {
+                "error" => DiagnosticLevel::Error,
+                "warning" => DiagnosticLevel::Warning,
+                _ => DiagnosticLevel::Information,
+            })
+    }
+
+    /// Returns the config in effect for the file at `uri`, i.e. its modeline
+    /// override if it has one, and `self` otherwise
+    #[must_use]
+    pub fn get_config<'a>(&'a self, uri: &Uri, overrides: &'a ConfigOverrides) -> &'a Self {
+        overrides.get(uri).unwrap_or(self)
+    }
+
+    /// Returns `true` if `arch` is enabled in the `instruction_sets` table
+    #[must_use]
+    pub fn is_isa_enabled(&self, arch: Arch) -> bool {
+        let sets = &self.instruction_sets;
+        match arch {
+            Arch::X86 => sets.x86,
+            Arch::X86_64 => sets.x86_64,
+            Arch::ARM => sets.arm,
+            Arch::ARM64 => sets.arm64,
+            Arch::RISCV => sets.riscv,
+            Arch::Z80 => sets.z80,
+            Arch::Mos6502 => sets.mos6502,
+            Arch::M68k => sets.m68k,
+        }
+        .unwrap_or(false)
+    }
+
+    /// Returns `true` if `assembler` is enabled in the `assemblers` table
+    #[must_use]
+    pub fn is_assembler_enabled(&self, assembler: Assembler) -> bool {
+        let assemblers = &self.assemblers;
+        match assembler {
+            Assembler::Gas => assemblers.gas,
+            Assembler::Go => assemblers.go,
+            Assembler::Masm => assemblers.masm,
+            Assembler::Nasm => assemblers.nasm,
+        }
+        .unwrap_or(false)
+    }
+
+    /// Enables or disables `assembler` in the `assemblers` table
+    pub const fn set_assembler_enabled(&mut self, assembler: Assembler, enabled: bool) {
+        let assemblers = &mut self.assemblers;
+        let entry = match assembler {
+            Assembler::Gas => &mut assemblers.gas,
+            Assembler::Go => &mut assemblers.go,
+            Assembler::Masm => &mut assemblers.masm,
+            Assembler::Nasm => &mut assemblers.nasm,
+        };
+        *entry = Some(enabled);
+    }
+
+    /// Returns `true` if the config doesn't specify any assemblers, either in the
+    /// `assemblers` table or via the `assembler` shorthand
+    #[must_use]
+    pub fn is_assembler_unset(&self) -> bool {
+        let assemblers = &self.assemblers;
+        self.assembler.is_none()
+            && [
+                assemblers.gas,
+                assemblers.go,
+                assemblers.masm,
+                assemblers.nasm,
+            ]
+            .iter()
+            .all(Option::is_none)
+    }
+
+    /// Returns `true` if no instruction set or assembler is enabled, leaving the
+    /// server with no documentation to serve
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        let sets = &self.instruction_sets;
+        let assemblers = &self.assemblers;
+        [
+            sets.x86,
+            sets.x86_64,
+            sets.z80,
+            sets.arm,
+            sets.arm64,
+            sets.riscv,
+            sets.mos6502,
+            sets.m68k,
+            assemblers.gas,
+            assemblers.go,
+            assemblers.masm,
+            assemblers.nasm,
+            assemblers.z80,
+        ]
+        .iter()
+        .all(|enabled| !enabled.unwrap_or(false))
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             version: String::from("0.1"),
+            arch: None,
+            assembler: None,
             assemblers: Assemblers::default(),
             instruction_sets: InstructionSets::default(),
             opts: ConfigOptions::default(),
             client: None,
+            project_root: None,
+            config_path: None,
+            disabled: false,
+        }
+    }
+}
+
+/// The severity keywords assemblers prefix their messages with, as recognized in
+/// the `severity_map` option
+pub const DIAGNOSTIC_KEYWORDS: [&str; 3] = ["error", "warning", "note"];
+
+/// The level a diagnostic is reported at, as set in the `severity_map` option
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumString, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum DiagnosticLevel {
+    Error,
+    Warning,
+    Information,
+    Hint,
+    /// Drop the diagnostic entirely
+    None,
+}
+
+impl DiagnosticLevel {
+    /// Returns the corresponding LSP severity, or `None` if diagnostics at this
+    /// level shouldn't be reported
+    #[must_use]
+    pub const fn to_lsp_severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Self::Error => Some(DiagnosticSeverity::ERROR),
+            Self::Warning => Some(DiagnosticSeverity::WARNING),
+            Self::Information => Some(DiagnosticSeverity::INFORMATION),
+            Self::Hint => Some(DiagnosticSeverity::HINT),
+            Self::None => None,
         }
     }
 }
 
+/// How much of a demangled C++ symbol name is shown on hover, as set in the
+/// `demangle_options` option
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DemangleStyle {
+    /// The full signature, including parameter and return types
+    #[default]
+    Complete,
+    /// Just the (qualified) name, e.g. `std::endl<char, std::char_traits<char> >`
+    NameOnly,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LspClient {
     Helix,
 }
 
+// Custom requests --------------------------------------------------------------------------------
+/// The custom `asm-lsp/listBackends` request, which lists every supported
+/// instruction set and assembler, and whether each is enabled in the server's
+/// effective config. Takes no parameters
+pub enum ListBackends {}
+
+impl lsp_types::request::Request for ListBackends {
+    type Params = ();
+    type Result = ListBackendsResult;
+    const METHOD: &'static str = "asm-lsp/listBackends";
+}
+
+/// Response to the `asm-lsp/listBackends` request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListBackendsResult {
+    pub instruction_sets: Vec<BackendInfo>,
+    pub assemblers: Vec<BackendInfo>,
+}
+
+/// The custom `asm-lsp/assembleProject` request
+///
+/// Runs the compile command of every assembly source file in the project's
+/// compilation database and reports the resulting diagnostics. Takes no parameters
+pub enum AssembleProject {}
+
+impl lsp_types::request::Request for AssembleProject {
+    type Params = ();
+    type Result = AssembleProjectResult;
+    const METHOD: &'static str = "asm-lsp/assembleProject";
+}
+
+/// Response to the `asm-lsp/assembleProject` request
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AssembleProjectResult {
+    /// Diagnostics for each assembled file, keyed by its URI. Files that
+    /// assembled cleanly have an empty list
+    pub diagnostics: HashMap<Uri, Vec<Diagnostic>>,
+}
+
+/// The custom `asm-lsp/explainRange` request
+///
+/// Explains each instruction within a selected range of a document, returning a
+/// markdown list of their summaries in order
+pub enum ExplainRange {}
+
+impl lsp_types::request::Request for ExplainRange {
+    type Params = ExplainRangeParams;
+    type Result = Option<MarkupContent>;
+    const METHOD: &'static str = "asm-lsp/explainRange";
+}
+
+/// Parameters of the `asm-lsp/explainRange` request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainRangeParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+/// The custom `asm-lsp/documentOutline` request
+///
+/// Returns the labels and repetition blocks of a document, as shown in an editor's
+/// outline, as plain JSON for use outside of an editor. Documents that aren't open
+/// are read from disk
+pub enum DocumentOutline {}
+
+impl lsp_types::request::Request for DocumentOutline {
+    type Params = DocumentOutlineParams;
+    type Result = Option<DocumentOutlineResult>;
+    const METHOD: &'static str = "asm-lsp/documentOutline";
+}
+
+/// Parameters of the `asm-lsp/documentOutline` request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentOutlineParams {
+    pub text_document: TextDocumentIdentifier,
+    /// List every symbol at the top level, naming its enclosing symbol in
+    /// `container`, rather than nesting symbols in their parents' `children`
+    #[serde(default)]
+    pub flat: bool,
+}
+
+/// Response to the `asm-lsp/documentOutline` request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentOutlineResult {
+    pub uri: Uri,
+    /// The document's symbols, in document order
+    pub symbols: Vec<OutlineSymbol>,
+}
+
+/// A single symbol in a `DocumentOutlineResult`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlineSymbol {
+    pub name: String,
+    pub kind: OutlineSymbolKind,
+    /// The range of the whole symbol, e.g. a label and the statements after it
+    pub range: Range,
+    /// The range of the symbol's declaration, e.g. a label's name
+    pub selection_range: Range,
+    /// The name of the enclosing symbol, in flat outlines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    /// The symbols nested within this one, in nested outlines
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Self>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlineSymbolKind {
+    Label,
+    /// A `.rept`, `.irp`, or `.irpc` block
+    Block,
+}
+
+/// The custom `asm-lsp/instructionsWritingRegister` request
+///
+/// Lists the instructions with a form that writes a given register, either
+/// implicitly (e.g. `mul` writing `rdx:rax`) or as a fixed operand. Only the x86
+/// and x86-64 documentation has this information
+pub enum InstructionsWritingRegister {}
+
+impl lsp_types::request::Request for InstructionsWritingRegister {
+    type Params = InstructionsWritingRegisterParams;
+    type Result = Vec<RegisterWriter>;
+    const METHOD: &'static str = "asm-lsp/instructionsWritingRegister";
+}
+
+/// Parameters of the `asm-lsp/instructionsWritingRegister` request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstructionsWritingRegisterParams {
+    /// The arch's key in the `instruction_sets` config table, e.g. `x86_64`
+    pub arch: String,
+    pub register: String,
+}
+
+/// A single instruction in the `asm-lsp/instructionsWritingRegister` response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterWriter {
+    pub name: String,
+    /// Which of the instruction's forms write the register, and how
+    pub note: String,
+}
+
+/// A single instruction set or assembler in a `ListBackendsResult`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackendInfo {
+    /// The entry's key in the `instruction_sets` or `assemblers` config table
+    pub id: String,
+    /// Human-readable name, suitable for display
+    pub name: String,
+    pub enabled: bool,
+}
+
 // Instruction Set Architecture -------------------------------------------------------------------
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, EnumString, AsRefStr, Serialize, Deserialize)]
 pub enum ISA {
@@ -1004,6 +1942,14 @@ pub struct Operand {
     pub extended_size: Option<usize>,
 }
 
+/// A register an instruction form reads or writes implicitly
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+pub struct ImplicitOperand {
+    pub id: String,
+    pub input: Option<bool>,
+    pub output: Option<bool>,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, EnumString, AsRefStr, Serialize, Deserialize)]
 pub enum OperandType {
@@ -1144,7 +2090,117 @@ pub type Column = usize;
 pub struct TreeEntry {
     pub tree: Option<Tree>,
     pub parser: Parser,
+    /// Labels and references extracted from `tree`, built on demand
+    pub symbols: Option<SymbolIndex>,
+}
+
+/// The labels declared in and identifiers referenced by a source file, cached
+/// for goto definition and references requests
+pub struct SymbolIndex {
+    /// The version of the document the index was built from
+    pub version: i32,
+    /// Each label's name and the range of its declaration, in document order
+    pub labels: Vec<(String, Range)>,
+    pub idents: Vec<IdentRef>,
+}
+
+/// A single identifier token within a source file
+pub struct IdentRef {
+    pub name: String,
+    pub range: Range,
+    /// Whether this is a label's own identifier, i.e. its declaration
+    pub is_decl: bool,
+}
+
+/// A structure type declared by a NASM `struc`/`endstruc` block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NasmStruc {
+    pub name: String,
+    /// The range of the structure's name in its `struc` line
+    pub range: Range,
+    /// The structure's total size in bytes, if all of its members' sizes are known
+    pub size: Option<u64>,
+    pub members: Vec<NasmStrucMember>,
+}
+
+impl NasmStruc {
+    /// Returns the member whose symbol is `name`, e.g. `.x` declared in `struc point`
+    /// is `point.x`
+    #[must_use]
+    pub fn get_member(&self, name: &str) -> Option<&NasmStrucMember> {
+        self.members
+            .iter()
+            .find(|member| self.member_symbol(member) == name)
+    }
+
+    /// Returns the symbol NASM defines for `member`. Members declared as local labels
+    /// are prefixed with the structure's name
+    #[must_use]
+    pub fn member_symbol(&self, member: &NasmStrucMember) -> String {
+        if member.name.starts_with('.') {
+            format!("{}{}", self.name, member.name)
+        } else {
+            member.name.clone()
+        }
+    }
+}
+
+/// A single member of a NASM structure type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NasmStrucMember {
+    /// The member's name as declared, e.g. `.x`
+    pub name: String,
+    /// The range of the member's name in its declaration
+    pub range: Range,
+    /// The member's byte offset from the start of the structure, if known
+    pub offset: Option<u64>,
+    /// The member's size in bytes, if known
+    pub size: Option<u64>,
 }
 
 /// Associates URIs with their corresponding tree-sitter tree and parser
 pub type TreeStore = BTreeMap<Uri, TreeEntry>;
+
+/// The trees of recently closed documents, along with the text they were parsed
+/// from, so a document reopened unchanged needn't be parsed again
+#[derive(Default)]
+pub struct ClosedTrees {
+    /// Least recently closed first
+    entries: VecDeque<(Uri, String, TreeEntry)>,
+}
+
+impl ClosedTrees {
+    /// Keeps `entry`, parsed from `text`, evicting the least recently closed
+    /// trees beyond `capacity`
+    pub fn insert(&mut self, uri: Uri, text: String, entry: TreeEntry, capacity: usize) {
+        self.entries.retain(|(closed_uri, _, _)| *closed_uri != uri);
+        self.entries.push_back((uri, text, entry));
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Takes the tree kept for `uri`, or `None` if there isn't one or the
+    /// document's text is no longer `text`
+    pub fn take(&mut self, uri: &Uri, text: &str) -> Option<TreeEntry> {
+        let idx = self
+            .entries
+            .iter()
+            .position(|(closed_uri, _, _)| closed_uri == uri)?;
+        let (_, closed_text, entry) = self.entries.remove(idx)?;
+        (closed_text == text).then_some(entry)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Associates URIs with the config set by a modeline in the file, if any
+pub type ConfigOverrides = BTreeMap<Uri, Config>;
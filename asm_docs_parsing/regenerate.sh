@@ -9,9 +9,13 @@ cargo run --release -- ../docs_store/opcodes/raw/x86.xml -o ../asm-lsp/serialize
 cargo run --release -- ../docs_store/opcodes/raw/x86_64.xml -o ../asm-lsp/serialized/opcodes/x86_64 --doc-type instruction
 # TODO: get the official arm32 opcode files
 cargo run --release -- ../docs_store/opcodes/raw/ARM/ -o ../asm-lsp/serialized/opcodes/arm --doc-type instruction --arch arm
+cargo run --release -- ../docs_store/opcodes/raw/arm_pseudo.xml -o ../asm-lsp/serialized/opcodes/arm_pseudo --doc-type instruction
 cargo run --release -- ../docs_store/opcodes/raw/ARM/ -o ../asm-lsp/serialized/opcodes/arm64 --doc-type instruction --arch arm64
 cargo run --release -- ../docs_store/opcodes/raw/RISCV/ -o ../asm-lsp/serialized/opcodes/riscv --doc-type instruction --arch riscv
+cargo run --release -- ../docs_store/opcodes/raw/riscv_pseudo.xml -o ../asm-lsp/serialized/opcodes/riscv_pseudo --doc-type instruction
 cargo run --release -- ../docs_store/opcodes/raw/z80.xml -o ../asm-lsp/serialized/opcodes/z80 --doc-type instruction
+cargo run --release -- ../docs_store/opcodes/raw/6502.xml -o ../asm-lsp/serialized/opcodes/6502 --doc-type instruction
+cargo run --release -- ../docs_store/opcodes/raw/m68k.xml -o ../asm-lsp/serialized/opcodes/m68k --doc-type instruction
 
 # register binaries
 cargo run --release -- ../docs_store/registers/raw/x86.xml -o ../asm-lsp/serialized/registers/x86 --doc-type register --arch x86
@@ -20,6 +24,8 @@ cargo run --release -- ../docs_store/registers/raw/arm.xml -o ../asm-lsp/seriali
 cargo run --release -- ../docs_store/registers/raw/arm64.xml -o ../asm-lsp/serialized/registers/arm64 --doc-type register --arch arm64
 cargo run --release -- ../docs_store/registers/raw/riscv.rst.txt -o ../asm-lsp/serialized/registers/riscv --doc-type register --arch riscv
 cargo run --release -- ../docs_store/registers/raw/z80.xml -o ../asm-lsp/serialized/registers/z80 --doc-type register --arch z80
+cargo run --release -- ../docs_store/registers/raw/6502.xml -o ../asm-lsp/serialized/registers/6502 --doc-type register --arch 6502
+cargo run --release -- ../docs_store/registers/raw/m68k.xml -o ../asm-lsp/serialized/registers/m68k --doc-type register --arch m68k
 
 # directive binaries
 cargo run --release -- ../docs_store/directives/raw/gas.xml -o ../asm-lsp/serialized/directives/gas --doc-type directive --assembler gas